@@ -1,62 +1,365 @@
-use std::sync::Arc;
+use std::{
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use cpu_6502::Cpu;
 use nes_rom_parser::Rom;
 use nessy::{
-    mapper::{get_mapper, DynMapper},
-    nesbus::NesBus,
+    debugger::{pending_jam, Jam},
+    emulator::Emulator,
+    mapper::{DynMapper, Mapper},
+    movie::Movie,
+    nes::Nes,
+    rom::{
+        archive,
+        db::Db,
+        expansion::ExpansionDevice,
+        fds::Disk,
+        hash::RomHashes,
+        header::Header,
+        patch, unif,
+        validate::{clean_header, validate},
+    },
+    saves::FileSaveStore,
 };
 use winit::{
     event_loop::EventLoop,
     window::{Window, WindowBuilder},
 };
 
-use crate::ROM_FILE;
+use crate::{
+    args::Args,
+    audio::{Audio, AudioSink},
+    BATCHED_PPU, DPAD_POLICY, FDS_BIOS_FILE, HEADER_DB_FILE, POWER_UP_RAM, PPU_WARM_UP, TURBO_RATE,
+    ZAPPER,
+};
+
+const TITLE: &str = "nessy";
+// At most this many frames are emulated per update, so a stall can't snowball.
+const MAX_FRAMES_PER_UPDATE: usize = 5;
 
+// Everything emulation needs, owned by the worker thread once the window is up.
 pub struct App {
     pub window: Arc<Window>,
-    pub cpu: Cpu,
-    pub nesbus: NesBus<DynMapper>,
+    pub nes: Nes<DynMapper>,
+    pub audio: Option<AudioSink>,
+    // Whether the running game has a Family BASIC keyboard, so the window knows if typing makes sense.
+    pub has_keyboard: Arc<AtomicBool>,
+    // Games loaded later, like dropped files, are set up with these too.
+    args: Args,
+    // The ROM file of the running game, which states and movies are kept next to.
+    rom_path: String,
+    // The window title, naming the game.
+    title: String,
+    last_frame: Instant,
+    frame_time: Duration,
+    jam: Option<Jam>,
 }
 impl App {
-    pub fn init() -> (App, EventLoop<()>) {
+    // Nothing can run without the game from the command line, so failing to load it ends
+    // the program. The patch and cheats only apply to that game.
+    pub fn init(args: &Args) -> (App, Option<Audio>, EventLoop<()>) {
+        let (name, mut nes) =
+            open_rom(&args.rom, args.patch.as_deref(), args).unwrap_or_else(|err| {
+                eprintln!("{err}");
+                process::exit(1);
+            });
+        let title = format!("{TITLE} - {name}");
         let ev_loop = EventLoop::new().unwrap();
-        let window = Arc::new(WindowBuilder::new().build(&ev_loop).unwrap());
+        let window = WindowBuilder::new().with_title(&title);
+        let window = Arc::new(window.build(&ev_loop).unwrap());
 
-        let (cpu, bus) = start_nes();
+        for code in &args.cheats {
+            match nes.cheats_mut().add(code) {
+                Ok(()) => eprintln!("Enabled cheat {code}"),
+                Err(err) => eprintln!("Ignoring cheat {code}: {err}"),
+            }
+        }
+        let (audio, sink) = Audio::init(nes.bus.sample_rate()).unzip();
+        let frame_time = Duration::from_secs_f64(1.0 / nes.bus.region().frames_per_second());
+        if audio.is_none() {
+            eprintln!("No audio output device, running without sound");
+        }
 
+        let has_keyboard = Arc::new(AtomicBool::new(nes.bus.input().has_keyboard()));
         let app = Self {
             window,
-            cpu,
-            nesbus: bus,
+            nes,
+            audio: sink,
+            has_keyboard,
+            args: args.clone(),
+            rom_path: args.rom.clone(),
+            title,
+            last_frame: Instant::now(),
+            frame_time,
+            jam: None,
         };
 
-        (app, ev_loop)
+        (app, audio, ev_loop)
     }
 
-    pub fn run_nes_until_vsync(&mut self) {
-        let mut last_blank = self.nesbus.ppu().is_vblank();
+    // Swaps in another game, like one dropped on the window. The old one's battery save is
+    // written out and a movie being recorded is kept first. If the new one can't be loaded,
+    // the old one keeps running.
+    pub fn load_rom(&mut self, path: &str) -> Result<(), String> {
+        let (name, nes) = open_rom(path, None, &self.args)?;
+        if self.nes.movie_recording() {
+            self.toggle_movie_recording();
+        }
+        if let Err(err) = self.nes.flush_saves() {
+            eprintln!("Can't write battery save: {err}");
+        }
 
-        loop {
-            let blank = self.nesbus.ppu().is_vblank();
-            let pos_edge = blank && !last_blank;
-            if pos_edge {
+        self.nes = nes;
+        let has_keyboard = self.nes.bus.input().has_keyboard();
+        self.has_keyboard.store(has_keyboard, Ordering::Relaxed);
+        let region = self.nes.bus.region();
+        if let Some(audio) = &mut self.audio {
+            audio.set_input_rate(self.nes.bus.sample_rate());
+        }
+        self.frame_time = Duration::from_secs_f64(1.0 / region.frames_per_second());
+        self.last_frame = Instant::now();
+        self.rom_path = path.to_string();
+        self.title = format!("{TITLE} - {name}");
+        self.jam = None;
+        self.window.set_title(&self.title);
+        eprintln!("Loaded {path}");
+        Ok(())
+    }
+
+    // Paces emulation by how much audio the device has consumed,
+    // or by the wall clock if there is no audio device. Returns whether any frames ran.
+    pub fn update(&mut self) -> bool {
+        let mut ran = false;
+        for _ in 0..MAX_FRAMES_PER_UPDATE {
+            let behind = match &self.audio {
+                Some(audio) => audio.wants_samples(),
+                None => self.last_frame.elapsed() >= self.frame_time,
+            };
+            if !behind {
                 break;
             };
-            last_blank = blank;
-            self.cpu.exec(&mut self.nesbus);
+            if self.audio.is_none() {
+                self.last_frame += self.frame_time;
+            }
+            self.run_frame();
+            ran = true;
+        }
+        ran
+    }
+
+    pub fn run_frame(&mut self) {
+        let frame = self.nes.run_frame();
+        if let Some(audio) = &mut self.audio {
+            audio.push_samples(frame.samples);
+        }
+        if self.jam.is_none() {
+            self.check_jam();
+        }
+    }
+    // A jammed CPU leaves the picture frozen, so say why instead of looking hung.
+    fn check_jam(&mut self) {
+        let Some(jam) = pending_jam(&self.nes.cpu, &self.nes.bus) else {
+            return;
+        };
+        let message = format!(
+            "CPU jammed by opcode ${:02X} at ${:04X}, press Backspace to reset",
+            jam.opcode, jam.pc
+        );
+        eprintln!("{message}");
+        let title = format!("{} - {message}", self.title);
+        self.window.set_title(&title);
+        self.jam = Some(jam);
+    }
+
+    pub fn flip_disk(&mut self) {
+        let Some(drive) = self.nes.bus.mapper_mut().disk_drive() else {
+            return;
+        };
+        if let Some(side) = drive.flip() {
+            eprintln!("Inserting disk side {}", side + 1);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.nes.reset();
+        self.clear_jam();
+    }
+    pub fn power_cycle(&mut self) {
+        self.nes.power_cycle();
+        self.clear_jam();
+    }
+    fn clear_jam(&mut self) {
+        if self.jam.take().is_some() {
+            self.window.set_title(&self.title);
+        }
+    }
+
+    pub fn save_state(&mut self) {
+        let path = self.state_file();
+        match std::fs::write(&path, self.nes.save_state()) {
+            Ok(()) => eprintln!("Saved state to {path}"),
+            Err(err) => eprintln!("Can't save state to {path}: {err}"),
         }
     }
+    pub fn load_state(&mut self) {
+        let path = self.state_file();
+        let result = std::fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| self.nes.load_state(&state).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => {
+                eprintln!("Loaded state from {path}");
+                self.clear_jam();
+            }
+            Err(err) => eprintln!("Can't load state from {path}: {err}"),
+        }
+    }
+
+    // Starts recording from power on, or writes out the movie being recorded.
+    pub fn toggle_movie_recording(&mut self) {
+        if !self.nes.movie_recording() {
+            self.nes.record_movie();
+            self.clear_jam();
+            eprintln!("Recording movie");
+            return;
+        };
+        let movie = self.nes.stop_movie().unwrap();
+        let path = self.movie_file();
+        match std::fs::write(&path, movie.to_fm2()) {
+            Ok(()) => eprintln!("Saved {} frames to {path}", movie.frames.len()),
+            Err(err) => eprintln!("Can't save movie to {path}: {err}"),
+        }
+    }
+    pub fn play_movie(&mut self) {
+        let path = self.movie_file();
+        let result = std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|src| Movie::parse(&src).map_err(|err| err.to_string()));
+        match result {
+            Ok(movie) => {
+                eprintln!("Playing {} frames from {path}", movie.frames.len());
+                self.nes.play_movie(movie);
+                self.clear_jam();
+            }
+            Err(err) => eprintln!("Can't play movie {path}: {err}"),
+        }
+    }
+
+    // Each game gets its own state next to the ROM.
+    fn state_file(&self) -> String {
+        format!("{}.state", self.rom_path)
+    }
+    fn movie_file(&self) -> String {
+        format!("{}.fm2", self.rom_path)
+    }
 }
 
-fn start_nes() -> (Cpu, NesBus<DynMapper>) {
-    let src = std::fs::read(ROM_FILE).unwrap();
-    let rom = Rom::parse(&src).unwrap();
-    eprintln!("{:#?}", rom.header);
-    let mapper = get_mapper(&rom);
+// Reads and sets up a game, returning it along with the name of its file.
+fn open_rom(
+    path: &str,
+    patch: Option<&str>,
+    args: &Args,
+) -> Result<(String, Nes<DynMapper>), String> {
+    let (name, src) = read_rom(path).map_err(|err| format!("Can't load {path}: {err}"))?;
+    let nes = start_nes(src, patch, args).map_err(|err| format!("Can't start {name}: {err}"))?;
+    Ok((name, nes))
+}
 
-    let cpu = Cpu::new();
-    let bus = NesBus::new(mapper);
+// For zip archives, the name is that of the .nes file inside.
+fn read_rom(path: &str) -> Result<(String, Vec<u8>), String> {
+    let src = std::fs::read(path).map_err(|err| err.to_string())?;
+    if archive::is_zip(&src) {
+        return archive::extract_nes(&src).map_err(|err| err.to_string());
+    }
+    let name = Path::new(path)
+        .file_name()
+        .map_or(path.into(), |name| name.to_string_lossy());
+    Ok((name.into_owned(), src))
+}
 
-    (cpu, bus)
+fn start_nes(mut src: Vec<u8>, patch: Option<&str>, args: &Args) -> Result<Nes<DynMapper>, String> {
+    if let Some(path) = patch {
+        let patch_src = std::fs::read(path).map_err(|err| format!("can't read {path}: {err}"))?;
+        patch::apply(&mut src, &patch_src).map_err(|err| format!("can't apply {path}: {err}"))?;
+        eprintln!("Applied {path}");
+    }
+    let mut builder = Emulator::builder()
+        .power_up(POWER_UP_RAM)
+        .ppu_warm_up(PPU_WARM_UP)
+        .batched_ppu(BATCHED_PPU)
+        .turbo_rate(TURBO_RATE[0], TURBO_RATE[1])
+        .dpad_policy(DPAD_POLICY)
+        .zapper(ZAPPER)
+        .save_store(FileSaveStore::new(&args.save_dir));
+    if let Some(region) = args.region {
+        builder = builder.region_override(region);
+    }
+
+    if src.starts_with(b"FDS\x1A") || src.starts_with(b"\x01*NINTENDO-HVC*") {
+        let bios = std::fs::read(FDS_BIOS_FILE)
+            .map_err(|err| format!("can't read the BIOS from {FDS_BIOS_FILE}: {err}"))?;
+        let disk = Disk::parse(&src).map_err(|err| err.to_string())?;
+        eprintln!("FDS disk with {} sides", disk.sides());
+        return builder
+            .fds_bios(bios)
+            .rom_bytes(src)
+            .build_nes()
+            .map_err(|err| err.to_string());
+    }
+    if src.starts_with(b"UNIF") {
+        src = unif::parse(&src).map_err(|err| err.to_string())?;
+    }
+    for warning in validate(&src) {
+        eprintln!("Warning: {warning}");
+    }
+    clean_header(&mut src);
+    correct_header(&mut src);
+    if let Ok(rom) = Rom::parse(&src) {
+        eprintln!("{:#?}", rom.header);
+        eprintln!("PRG+CHR CRC32: {:08X}", rom.rom_crc32());
+    }
+    let header = Header::parse(&src).ok();
+    let device = header
+        .as_ref()
+        .map_or(ExpansionDevice::Unspecified, |header| {
+            header.expansion_device
+        });
+    let battery = header.is_some_and(|header| header.battery);
+
+    let nes = builder
+        .rom_bytes(src)
+        .build_nes()
+        .map_err(|err| err.to_string())?;
+    if !device.emulated() {
+        eprintln!("{device:?} isn't emulated, using standard controllers");
+    }
+    if device == ExpansionDevice::FamilyBasicKeyboard {
+        eprintln!("Family BASIC keyboard connected, Scroll Lock switches typing on and off");
+    }
+    if battery {
+        eprintln!("Battery saves go to {}", args.save_dir);
+    }
+    Ok(nes)
+}
+
+fn correct_header(src: &mut [u8]) {
+    let Some(path) = HEADER_DB_FILE else {
+        return;
+    };
+    let db = match Db::load(path) {
+        Ok(db) => db,
+        Err(err) => {
+            eprintln!("Keeping the ROM header: {err}");
+            return;
+        }
+    };
+    for change in db.apply(src).unwrap_or_default() {
+        eprintln!("Header database fixed {change}");
+    }
 }
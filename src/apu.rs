@@ -1,29 +1,61 @@
-use crate::nesbus::CpuBus;
-
-const SAMPLES_PER_SECOND: usize = 44100;
-const CYCLES_PER_SAMPLE: usize = 1_789773 / SAMPLES_PER_SECOND;
+use self::{noise::Noise, pulse::Pulse, triangle::Triangle};
+use crate::{
+    nesbus::CpuBus,
+    region::Region,
+    state::{SaveState, StateError, StateReader, StateWriter},
+};
+use std::collections::VecDeque;
+
+mod noise;
+mod pulse;
+pub mod recorder;
+pub mod resampler;
+mod triangle;
+mod units;
+
+// Samples that aren't drained are dropped after about a tenth of a second.
+const SAMPLE_BUFFER_LEN: usize = 178977;
 
 pub struct Apu {
+    region: Region,
+    pulses: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
     dmc: Dmc,
     status: Status,
     dma: Dma,
     frame_counter: FrameCounter,
+    expansion_audio: f32,
+    expansion_volume: f32,
+    channel_enabled: [bool; 5],
 
-    cycles_since_sample: usize,
+    samples: VecDeque<f32>,
 }
 impl Apu {
     pub fn init() -> Self {
+        Self::new(Region::Ntsc)
+    }
+    pub fn new(region: Region) -> Self {
         Self {
+            region,
+            pulses: [Pulse::init(true), Pulse::init(false)],
+            triangle: Triangle::init(),
+            noise: Noise::init(region),
             dmc: Dmc::init(),
             status: Status::init(),
             dma: Dma::init(),
             frame_counter: FrameCounter::init(),
+            expansion_audio: 0.0,
+            expansion_volume: 1.0,
+            channel_enabled: [true; 5],
 
-            cycles_since_sample: 0,
+            samples: VecDeque::with_capacity(SAMPLE_BUFFER_LEN),
         }
     }
 
     pub fn cycle(&mut self, cpu: &mut CpuBus) {
+        self.status.frame_irq_raised = false;
+        self.status.length_clocked = false;
         self.produce_sample();
         self.update_sound_channels();
         self.tick_frame_counter();
@@ -34,83 +66,158 @@ impl Apu {
         self.dma.tick_counters();
     }
 
+    pub fn set_expansion_audio(&mut self, level: f32) {
+        self.expansion_audio = level;
+    }
+    // Mappers scale their output to match the APU, so 1.0 is the volume most games were mixed at.
+    pub fn set_expansion_volume(&mut self, volume: f32) {
+        self.expansion_volume = volume;
+    }
+    pub fn drain_audio(&mut self, out: &mut Vec<f32>) {
+        out.extend(self.samples.drain(..));
+    }
+    // Muted channels keep running, they're only left out of the mix.
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.channel_enabled[channel as usize] = enabled;
+    }
+    pub fn channel_enabled(&self, channel: Channel) -> bool {
+        self.channel_enabled[channel as usize]
+    }
+    // One sample is produced per CPU cycle.
+    pub fn sample_rate(&self) -> f64 {
+        self.region.cpu_clock_hz()
+    }
+    // What a read of $4015 returns, apart from the open bus bit, without acknowledging the frame IRQ.
+    pub fn peek_status(&self) -> u8 {
+        let dmc_active = self.dmc.bytes_remaining != 0;
+        let dmc_active = if dmc_active { 1 << 4 } else { 0 };
+        let frame_irq = (self.status.frame_irq as u8) << 6;
+        let dmc_irq = (self.status.dmc_irq as u8) << 7;
+
+        let pulse_0 = self.pulses[0].length_active() as u8;
+        let pulse_1 = (self.pulses[1].length_active() as u8) << 1;
+
+        let triangle = (self.triangle.length_active() as u8) << 2;
+        let noise = (self.noise.length_active() as u8) << 3;
+
+        pulse_0 | pulse_1 | triangle | noise | dmc_active | dmc_irq | frame_irq
+    }
+
+    // The RESET button silences every channel as if $00 were written to $4015,
+    // and restarts the frame counter in the mode it was in. DMA in progress is dropped.
+    pub fn reset(&mut self) {
+        self.write_status(0);
+        self.status.frame_irq = false;
+        self.dmc.sample &= 1;
+        self.dma = Dma {
+            put_cycle: self.dma.put_cycle,
+            ..Dma::init()
+        };
+        self.restart_frame_counter();
+    }
+    // Everything starts over, except the mixer settings.
+    pub fn power_cycle(&mut self) {
+        *self = Self {
+            expansion_volume: self.expansion_volume,
+            channel_enabled: self.channel_enabled,
+            ..Self::new(self.region)
+        };
+    }
+
     fn update_sound_channels(&mut self) {
+        self.triangle.tick_timer();
+        self.noise.tick_timer();
+
         // An APU cycle occurs every 2 CPU cycles.
         // Repurpose dma cycle flag for fun and profit.
         if self.dma.put_cycle {
             return;
         };
+
+        self.pulses[0].tick_timer();
+        self.pulses[1].tick_timer();
     }
 
     fn tick_frame_counter(&mut self) {
-        if self.frame_counter.cycles_until_step < FrameCounter::CYCLES_PER_STEP {
-            self.frame_counter.cycles_until_step += 1;
-            return;
+        if self.frame_counter.reset_delay != 0 {
+            self.frame_counter.reset_delay -= 1;
+            if self.frame_counter.reset_delay == 0 {
+                self.reset_frame_counter();
+                return;
+            }
         }
-        self.frame_counter.cycles_until_step = 0;
 
+        self.frame_counter.cycle += 1;
+        let cycle = self.frame_counter.cycle;
+        let five_step = self.frame_counter.mode;
+
+        // Both sequences share their first three steps.
+        let [quarter_1, half_1, quarter_3, four_step_end, five_step_end] = frame_steps(self.region);
+        let end = if five_step { five_step_end } else { four_step_end };
+        if cycle == quarter_1 || cycle == quarter_3 {
+            self.tick_envelopes();
+        } else if cycle == half_1 || cycle == end {
+            self.tick_envelopes();
+            self.tick_length_counters();
+        }
+        // The four step sequence raises the IRQ flag on three consecutive cycles.
+        let irq_cycles = four_step_end - 1..=four_step_end + 1;
+        if !five_step && irq_cycles.contains(&cycle) && !self.frame_counter.irq_disable {
+            self.status.frame_irq = true;
+            self.status.frame_irq_raised = true;
+        }
+
+        if cycle == end + 1 {
+            self.frame_counter.cycle = 0;
+        }
+    }
+    // The five step sequence clocks everything as soon as it starts.
+    fn reset_frame_counter(&mut self) {
+        self.frame_counter.cycle = 0;
         if self.frame_counter.mode {
-            // Five step sequence
-            match self.frame_counter.step {
-                0 => self.tick_envelopes(),
-                1 => {
-                    self.tick_envelopes();
-                    self.tick_length_counters()
-                }
-                2 => self.tick_envelopes(),
-                3 => (),
-                4 => {
-                    self.tick_envelopes();
-                    self.tick_length_counters()
-                }
-                5.. => unreachable!(),
-            }
-            self.frame_counter.step += 1;
-            if self.frame_counter.step >= 5 {
-                self.frame_counter.step = 0;
-            }
-        } else {
-            // Four step sequence
-            match self.frame_counter.step {
-                0 => self.tick_envelopes(),
-                1 => {
-                    self.tick_envelopes();
-                    self.tick_length_counters()
-                }
-                2 => self.tick_envelopes(),
-                3 => {
-                    self.tick_envelopes();
-                    self.tick_length_counters();
-                    self.status.frame_irq |= !self.frame_counter.irq_disable;
-                }
-                4.. => unreachable!(),
-            }
-            self.frame_counter.step += 1;
-            if self.frame_counter.step >= 4 {
-                self.frame_counter.step = 0;
-            }
+            self.tick_envelopes();
+            self.tick_length_counters();
         }
     }
-    fn tick_length_counters(&mut self) {}
-    fn tick_envelopes(&mut self) {}
+    // Length counters are clocked together with the sweep units.
+    fn tick_length_counters(&mut self) {
+        self.status.length_clocked = true;
+        self.pulses[0].tick_length_and_sweep();
+        self.pulses[1].tick_length_and_sweep();
+        self.triangle.tick_length();
+        self.noise.tick_length();
+    }
+    // The triangle's linear counter is clocked together with the envelopes.
+    fn tick_envelopes(&mut self) {
+        self.pulses[0].tick_envelope();
+        self.pulses[1].tick_envelope();
+        self.triangle.tick_linear_counter();
+        self.noise.tick_envelope();
+    }
 
     fn produce_sample(&mut self) {
-        if self.cycles_since_sample < CYCLES_PER_SAMPLE {
-            self.cycles_since_sample += 1;
-            return;
+        if self.samples.len() == SAMPLE_BUFFER_LEN {
+            self.samples.pop_front();
         }
-        self.cycles_since_sample = 0;
-
         let sample = self.mix();
-        // This is where I'd put my audio output..
-        // If I HAD ANY!!!
+        self.samples.push_back(sample);
+    }
+    pub fn channel_outputs(&self) -> ChannelOutputs {
+        ChannelOutputs {
+            pulse: [self.pulses[0].output(), self.pulses[1].output()],
+            triangle: self.triangle.output(),
+            noise: self.noise.output(),
+            dmc: self.dmc.sample,
+        }
     }
+
     fn mix(&mut self) -> f32 {
-        let pulse_0 = 0.0;
-        let pulse_1 = 0.0;
-        let triangle = 0.0;
-        let noise = 0.0;
-        let dmc = self.dmc.sample as f64;
+        let outputs = self.channel_outputs().masked(self.channel_enabled);
+        let pulse_0 = outputs.pulse[0] as f64;
+        let pulse_1 = outputs.pulse[1] as f64;
+        let triangle = outputs.triangle as f64;
+        let noise = outputs.noise as f64;
+        let dmc = outputs.dmc as f64;
 
         let pulse_zero = pulse_0 == 0.0 && pulse_1 == 0.0;
         let tnd_zero = triangle == 0.0 && noise == 0.0 && dmc == 0.0;
@@ -128,7 +235,9 @@ impl Apu {
         let tnd_denom = 1.0 / (triangle + noise + dmc) + 100.0;
         let tnd_out = if tnd_zero { 0.0 } else { 159.79 / tnd_denom };
 
-        let output = square_out + tnd_out;
+        let expansion = (self.expansion_audio * self.expansion_volume) as f64;
+
+        let output = square_out + tnd_out + expansion;
         let sample = ((output * 2.0) - 1.0) as f32;
         sample
     }
@@ -147,7 +256,11 @@ impl Apu {
         self.update_dmc_dma();
     }
     fn update_dmc_output(&mut self) {
-        if self.dmc.cycles_since_last < self.dmc.wait_cycles {
+        // The rate table counts CPU cycles, but the timer is only clocked on APU cycles.
+        if self.dma.put_cycle {
+            return;
+        };
+        if self.dmc.cycles_since_last + 1 < self.dmc.wait_cycles / 2 {
             self.dmc.cycles_since_last += 1;
             return;
         }
@@ -163,13 +276,15 @@ impl Apu {
             self.dmc.bits_remaining = 8;
         }
 
+        // The delta counter is left alone rather than clamped when a step would leave 0..=127.
         if !self.dmc.silence {
             let bit = self.dmc.sample_shifter & 1 != 0;
-            let delta = if bit { 2 } else { -2 };
             let sample = self.dmc.sample;
-            let sample = sample.saturating_add_signed(delta);
-            let sample = sample.min(127);
-            self.dmc.sample = sample;
+            if bit && sample <= 125 {
+                self.dmc.sample = sample + 2;
+            } else if !bit && sample >= 2 {
+                self.dmc.sample = sample - 2;
+            }
         }
 
         self.dmc.sample_shifter >>= 1;
@@ -186,38 +301,65 @@ impl Apu {
             return;
         };
 
-        let addr = self.dmc.start + self.dmc.byte_offset;
+        // The sample address wraps around from $FFFF to $8000.
+        let addr = self.dmc.start.wrapping_add(self.dmc.byte_offset) | 0x8000;
         self.dma.start_dmc_dma(addr);
         self.dmc.byte_offset += 1;
         self.dmc.bytes_remaining -= 1;
 
         if self.dmc.bytes_remaining == 0 {
-            self.status.dmc_irq |= self.dmc.irq_enable;
-
             if self.dmc.loop_playback {
                 self.dmc.bytes_remaining = self.dmc.length;
                 self.dmc.byte_offset = 0;
+            } else {
+                self.status.dmc_irq |= self.dmc.irq_enable;
             }
         }
     }
 
     fn handle_cpu(&mut self, cpu: &mut CpuBus) {
         match cpu.address() {
+            0x4000..=0x4007 => {
+                if cpu.read() {
+                    return;
+                };
+                let pulse = (cpu.address() as usize >> 2) & 1;
+                let enabled = self.status.pulse_enable[pulse];
+                let clocked = self.status.length_clocked;
+                self.pulses[pulse].write(cpu.address() % 4, cpu.data(), enabled, clocked);
+            }
+            0x4008..=0x400B => {
+                if cpu.read() {
+                    return;
+                };
+                let enabled = self.status.triangle_enable;
+                let clocked = self.status.length_clocked;
+                self.triangle.write(cpu.address() % 4, cpu.data(), enabled, clocked);
+            }
+            0x400C..=0x400F => {
+                if cpu.read() {
+                    return;
+                };
+                let enabled = self.status.noise_enable;
+                let clocked = self.status.length_clocked;
+                self.noise.write(cpu.address() % 4, cpu.data(), enabled, clocked);
+            }
             0x4010 => {
                 if cpu.read() {
                     return;
                 };
                 let data = cpu.data();
                 self.dmc.irq_enable = data & 128 != 0;
+                self.status.dmc_irq &= self.dmc.irq_enable;
                 self.dmc.loop_playback = data & 64 != 0;
                 let freq = data & 0xF;
-                self.dmc.wait_cycles = wait_cycles(freq);
+                self.dmc.wait_cycles = wait_cycles(freq, self.region);
             }
             0x4011 => {
                 if cpu.read() {
                     return;
                 };
-                self.dmc.sample = cpu.data() & 0x8F;
+                self.dmc.sample = cpu.data() & 0x7F;
             }
             0x4012 => {
                 if cpu.read() {
@@ -239,29 +381,13 @@ impl Apu {
             }
             0x4015 => {
                 if cpu.read() {
-                    let dmc_active = self.dmc.bytes_remaining != 0;
-                    let dmc_active = if dmc_active { 1 << 4 } else { 0 };
-                    let dmc_irq = (self.status.dmc_irq as u8) << 6;
-                    let frame_irq = (self.status.frame_irq as u8) << 7;
-
-                    let byte = dmc_active | dmc_irq | frame_irq;
-                    cpu.set_data(byte);
-                    self.status.frame_irq = false;
+                    // Bit 5 isn't connected and reads as open bus.
+                    let open_bus = cpu.data() & 0x20;
+                    cpu.set_internal_data(self.peek_status() | open_bus);
+                    // A flag raised on this very cycle survives the read.
+                    self.status.frame_irq &= self.status.frame_irq_raised;
                 } else {
-                    let data = cpu.data();
-                    self.status.pulse_enable[0] = data & 1 != 0;
-                    self.status.pulse_enable[1] = data & 2 != 0;
-                    self.status.triangle_enable = data & 4 != 0;
-                    self.status.noise_enable = data & 8 != 0;
-
-                    self.status.dmc_irq = false;
-                    let d = data & 16 != 0;
-                    if d {
-                        self.dmc.bytes_remaining = self.dmc.length;
-                        self.dmc.byte_offset = 0;
-                    } else {
-                        self.dmc.bytes_remaining = 0;
-                    }
+                    self.write_status(cpu.data());
                 }
             }
             0x4017 => {
@@ -270,23 +396,153 @@ impl Apu {
                 };
                 self.frame_counter.mode = cpu.data() & 128 != 0;
                 self.frame_counter.irq_disable = cpu.data() & 64 != 0;
-                self.frame_counter.step = 0;
-                self.frame_counter.cycles_until_step = 0;
+                self.status.frame_irq &= !self.frame_counter.irq_disable;
+                self.restart_frame_counter();
             }
             _ => (),
         }
     }
+    fn write_status(&mut self, data: u8) {
+        self.status.pulse_enable[0] = data & 1 != 0;
+        self.status.pulse_enable[1] = data & 2 != 0;
+        self.status.triangle_enable = data & 4 != 0;
+        self.status.noise_enable = data & 8 != 0;
+        for (pulse, enabled) in self.pulses.iter_mut().zip(self.status.pulse_enable) {
+            if !enabled {
+                pulse.disable();
+            }
+        }
+        if !self.status.triangle_enable {
+            self.triangle.disable();
+        }
+        if !self.status.noise_enable {
+            self.noise.disable();
+        }
+
+        self.status.dmc_irq = false;
+        let d = data & 16 != 0;
+        if d {
+            // A sample that is still playing is not restarted.
+            if self.dmc.bytes_remaining == 0 {
+                self.dmc.bytes_remaining = self.dmc.length;
+                self.dmc.byte_offset = 0;
+            }
+        } else {
+            self.dmc.bytes_remaining = 0;
+        }
+    }
+    // The restart happens 3 cycles later if requested on an APU cycle, 4 otherwise.
+    fn restart_frame_counter(&mut self) {
+        self.frame_counter.reset_delay = if self.dma.put_cycle { 4 } else { 3 };
+    }
     fn assert_irqs(&self, cpu: &mut CpuBus) {
         let irq = self.status.dmc_irq || self.status.frame_irq;
         cpu.or_irq(irq);
     }
 }
 
-fn wait_cycles(freq: u8) -> u16 {
-    static CYCLES: [u16; 16] = [
+impl SaveState for Apu {
+    // Buffered samples and the mixer settings belong to the frontend, so they aren't saved.
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.region as u8);
+        for pulse in &self.pulses {
+            pulse.save_state(out);
+        }
+        self.triangle.save_state(out);
+        self.noise.save_state(out);
+        self.dmc.save_state(out);
+        self.status.save_state(out);
+        self.dma.save_state(out);
+        self.frame_counter.save_state(out);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.region = match state.u8()? {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            _ => return Err(StateError::Invalid),
+        };
+        self.noise = Noise::init(self.region);
+
+        for pulse in &mut self.pulses {
+            pulse.load_state(state)?;
+        }
+        self.triangle.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.dmc.load_state(state)?;
+        self.status.load_state(state)?;
+        self.dma.load_state(state)?;
+        self.frame_counter.load_state(state)?;
+
+        // The sequence only wraps when it passes its last step, unless a $4017 write is
+        // about to restart it from wherever the old mode left it.
+        let [.., four_step_end, five_step_end] = frame_steps(self.region);
+        let counter = &self.frame_counter;
+        let end = match (counter.reset_delay, counter.mode) {
+            (0, false) => four_step_end,
+            (0, true) => five_step_end,
+            (1..=4, _) => five_step_end + 4,
+            _ => return Err(StateError::Invalid),
+        };
+        if counter.cycle > end {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
+
+// The current 4-bit output of each channel (7-bit for the DMC), before mixing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelOutputs {
+    pub pulse: [u8; 2],
+    pub triangle: u8,
+    pub noise: u8,
+    pub dmc: u8,
+}
+
+impl ChannelOutputs {
+    fn masked(self, enabled: [bool; 5]) -> Self {
+        let mask = |output, channel: Channel| if enabled[channel as usize] { output } else { 0 };
+        Self {
+            pulse: [
+                mask(self.pulse[0], Channel::Pulse1),
+                mask(self.pulse[1], Channel::Pulse2),
+            ],
+            triangle: mask(self.triangle, Channel::Triangle),
+            noise: mask(self.noise, Channel::Noise),
+            dmc: mask(self.dmc, Channel::Dmc),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+fn wait_cycles(freq: u8, region: Region) -> u16 {
+    static NTSC: [u16; 16] = [
         428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
     ];
-    CYCLES[freq as usize]
+    static PAL: [u16; 16] = [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+    ];
+    match region {
+        Region::Ntsc => NTSC[freq as usize],
+        Region::Pal => PAL[freq as usize],
+    }
+}
+
+// The CPU cycles of the first three steps, the last step of the four step sequence
+// and the last step of the five step sequence, counted from the start of the sequence.
+fn frame_steps(region: Region) -> [u16; 5] {
+    match region {
+        Region::Ntsc => [7457, 14913, 22371, 29829, 37281],
+        Region::Pal => [8313, 16627, 24939, 33253, 41565],
+    }
 }
 
 struct Dmc {
@@ -330,6 +586,43 @@ impl Dmc {
     }
 }
 
+impl SaveState for Dmc {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.irq_enable);
+        out.bool(self.loop_playback);
+        out.u16(self.wait_cycles);
+        out.u16(self.cycles_since_last);
+        out.u8(self.sample);
+        out.u16(self.start);
+        out.u16(self.length);
+        out.u16(self.bytes_remaining);
+        out.u16(self.byte_offset);
+        out.u8(self.bits_remaining);
+        out.bool(self.sample_buffer.is_some());
+        out.u8(self.sample_buffer.unwrap_or(0));
+        out.u8(self.sample_shifter);
+        out.bool(self.silence);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.irq_enable = state.bool()?;
+        self.loop_playback = state.bool()?;
+        self.wait_cycles = state.u16()?;
+        self.cycles_since_last = state.u16()?;
+        self.sample = state.u8()?;
+        self.start = state.u16()?;
+        self.length = state.u16()?;
+        self.bytes_remaining = state.u16()?;
+        self.byte_offset = state.u16()?;
+        self.bits_remaining = state.u8()?;
+        let buffered = state.bool()?;
+        let buffer = state.u8()?;
+        self.sample_buffer = buffered.then_some(buffer);
+        self.sample_shifter = state.u8()?;
+        self.silence = state.bool()?;
+        Ok(())
+    }
+}
+
 struct Status {
     pulse_enable: [bool; 2],
     triangle_enable: bool,
@@ -337,6 +630,8 @@ struct Status {
 
     dmc_irq: bool,
     frame_irq: bool,
+    frame_irq_raised: bool,
+    length_clocked: bool,
 }
 impl Status {
     fn init() -> Self {
@@ -346,28 +641,66 @@ impl Status {
             noise_enable: false,
             dmc_irq: false,
             frame_irq: false,
+            frame_irq_raised: false,
+            length_clocked: false,
         }
     }
 }
 
+// The flags that only last for one cycle are cleared before they're used again.
+impl SaveState for Status {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.pulse_enable[0]);
+        out.bool(self.pulse_enable[1]);
+        out.bool(self.triangle_enable);
+        out.bool(self.noise_enable);
+        out.bool(self.dmc_irq);
+        out.bool(self.frame_irq);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.pulse_enable[0] = state.bool()?;
+        self.pulse_enable[1] = state.bool()?;
+        self.triangle_enable = state.bool()?;
+        self.noise_enable = state.bool()?;
+        self.dmc_irq = state.bool()?;
+        self.frame_irq = state.bool()?;
+        Ok(())
+    }
+}
+
 struct FrameCounter {
     mode: bool,
     irq_disable: bool,
 
-    step: u8,
-    cycles_until_step: u16,
+    // CPU cycles since the sequence started.
+    cycle: u16,
+    reset_delay: u8,
 }
 impl FrameCounter {
     fn init() -> Self {
         Self {
             mode: false,
             irq_disable: true,
-            step: 0,
-            cycles_until_step: 0,
+            cycle: 0,
+            reset_delay: 0,
         }
     }
+}
 
-    const CYCLES_PER_STEP: u16 = 7457;
+impl SaveState for FrameCounter {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.mode);
+        out.bool(self.irq_disable);
+        out.u16(self.cycle);
+        out.u8(self.reset_delay);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.mode = state.bool()?;
+        self.irq_disable = state.bool()?;
+        self.cycle = state.u16()?;
+        self.reset_delay = state.u8()?;
+        Ok(())
+    }
 }
 
 struct Dma {
@@ -418,6 +751,7 @@ impl Dma {
                 cpu.set_address(self.oam_addr());
                 self.oam_dma = OamDma::ToWrite;
             }
+            // The PPU advances its own OAM address, so the copy starts wherever OAMADDR points.
             OamDma::ToWrite => {
                 cpu.set_not_ready(true);
                 cpu.set_read(false);
@@ -441,14 +775,13 @@ impl Dma {
     fn perform_dmc_dma(&mut self, cpu: &mut CpuBus) -> bool {
         match self.dmc_dma {
             DmcDma::Idle => false,
+            // The halt and dummy cycles can land on either kind of cycle. During OAM DMA they
+            // overlap its transfers, and only the get cycle of the fetch takes the bus from it.
             DmcDma::Started => {
                 cpu.set_not_ready(true);
                 if !cpu.halt() {
                     return false;
                 };
-                if self.put_cycle {
-                    return false;
-                };
                 self.dmc_dma = DmcDma::Dummy;
                 false
             }
@@ -459,6 +792,10 @@ impl Dma {
             }
             DmcDma::ToRead => {
                 cpu.set_not_ready(true);
+                // Reads only happen on get cycles, so a put cycle is spent aligning.
+                if self.put_cycle {
+                    return false;
+                };
                 cpu.set_address(self.dmc_address);
                 cpu.set_read(true);
                 //eprintln!("DMC read from {:x}", self.dmc_address);
@@ -490,6 +827,40 @@ impl Dma {
     }
 }
 
+impl SaveState for Dma {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.put_cycle);
+        out.u8(self.oam_dma as u8);
+        out.u8(self.oam_page);
+        out.u8(self.oam_step);
+        out.u8(self.dmc_dma as u8);
+        out.u16(self.dmc_address);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.put_cycle = state.bool()?;
+        self.oam_dma = match state.u8()? {
+            0 => OamDma::Idle,
+            1 => OamDma::Started,
+            2 => OamDma::ToRead,
+            3 => OamDma::ToWrite,
+            4 => OamDma::Align,
+            _ => return Err(StateError::Invalid),
+        };
+        self.oam_page = state.u8()?;
+        self.oam_step = state.u8()?;
+        self.dmc_dma = match state.u8()? {
+            0 => DmcDma::Idle,
+            1 => DmcDma::Started,
+            2 => DmcDma::Dummy,
+            3 => DmcDma::ToRead,
+            4 => DmcDma::ToReceive,
+            _ => return Err(StateError::Invalid),
+        };
+        self.dmc_address = state.u16()?;
+        Ok(())
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 enum DmcDma {
     Idle,
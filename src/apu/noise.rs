@@ -0,0 +1,117 @@
+use super::units::{Envelope, LengthCounter};
+use crate::{
+    region::Region,
+    state::{SaveState, StateError, StateReader, StateWriter},
+};
+
+pub struct Noise {
+    periods: &'static [u16; 16],
+    short_mode: bool,
+    period: u16,
+    timer: u16,
+    shift: u16,
+
+    envelope: Envelope,
+    length: LengthCounter,
+}
+impl Noise {
+    pub fn init(region: Region) -> Self {
+        let periods = match region {
+            Region::Ntsc => &NTSC_PERIODS,
+            Region::Pal => &PAL_PERIODS,
+        };
+
+        Self {
+            periods,
+            short_mode: false,
+            period: periods[0],
+            timer: 0,
+            shift: 1,
+
+            envelope: Envelope::init(),
+            length: LengthCounter::init(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, data: u8, enabled: bool, length_clocked: bool) {
+        match register {
+            0 => {
+                self.length.set_halt(data & 32 != 0);
+                self.envelope.write(data);
+            }
+            1 => (),
+            2 => {
+                self.short_mode = data & 128 != 0;
+                self.period = self.periods[data as usize & 0xF];
+            }
+            3 => {
+                self.length.load(data, enabled, length_clocked);
+                self.envelope.restart();
+            }
+            4.. => unreachable!(),
+        }
+    }
+    pub fn disable(&mut self) {
+        self.length.clear();
+    }
+
+    // The period table counts CPU cycles, so this is clocked on every CPU cycle.
+    pub fn tick_timer(&mut self) {
+        if self.timer != 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period - 1;
+
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift ^ self.shift >> tap) & 1;
+        self.shift = self.shift >> 1 | feedback << 14;
+    }
+    pub fn tick_envelope(&mut self) {
+        self.envelope.tick();
+    }
+    pub fn tick_length(&mut self) {
+        self.length.tick();
+    }
+
+    pub fn length_active(&self) -> bool {
+        self.length.active()
+    }
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 != 0 {
+            return 0;
+        };
+        self.envelope.output()
+    }
+}
+
+// The period table comes from the region, which the APU restores first.
+impl SaveState for Noise {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.short_mode);
+        out.u16(self.period);
+        out.u16(self.timer);
+        out.u16(self.shift);
+        self.envelope.save_state(out);
+        self.length.save_state(out);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.short_mode = state.bool()?;
+        self.period = state.u16()?;
+        self.timer = state.u16()?;
+        self.shift = state.u16()?;
+        if !self.periods.contains(&self.period) {
+            return Err(StateError::Invalid);
+        };
+        self.envelope.load_state(state)?;
+        self.length.load_state(state)
+    }
+}
+
+// Periods in CPU cycles.
+static NTSC_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+static PAL_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
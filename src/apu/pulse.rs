@@ -0,0 +1,195 @@
+use super::units::{Envelope, LengthCounter};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+pub struct Pulse {
+    // Pulse 1 negates its sweep with the ones' complement, pulse 2 with the two's complement.
+    ones_complement: bool,
+
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+
+    envelope: Envelope,
+    length: LengthCounter,
+    sweep: Sweep,
+}
+impl Pulse {
+    pub fn init(ones_complement: bool) -> Self {
+        Self {
+            ones_complement,
+
+            duty: 0,
+            step: 0,
+            period: 0,
+            timer: 0,
+
+            envelope: Envelope::init(),
+            length: LengthCounter::init(),
+            sweep: Sweep::init(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, data: u8, enabled: bool, length_clocked: bool) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.length.set_halt(data & 32 != 0);
+                self.envelope.write(data);
+            }
+            1 => self.sweep.write(data),
+            2 => self.period = (self.period & 0x700) | data as u16,
+            3 => {
+                self.period = (self.period & 0xFF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data, enabled, length_clocked);
+                self.step = 0;
+                self.envelope.restart();
+            }
+            4.. => unreachable!(),
+        }
+    }
+    pub fn disable(&mut self) {
+        self.length.clear();
+    }
+
+    // Clocked on every APU cycle.
+    pub fn tick_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    pub fn tick_envelope(&mut self) {
+        self.envelope.tick();
+    }
+    pub fn tick_length_and_sweep(&mut self) {
+        self.length.tick();
+
+        let target = self.sweep_target();
+        let adjust = self.sweep.divider == 0 && self.sweep.enable && self.sweep.shift != 0;
+        if adjust && !self.muted(target) {
+            self.period = target;
+        }
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep.shift;
+        if !self.sweep.negate {
+            self.period + change
+        } else if self.ones_complement {
+            self.period.saturating_sub(change + 1)
+        } else {
+            self.period.saturating_sub(change)
+        }
+    }
+    // The sweep unit silences the channel even while it is disabled.
+    fn muted(&self, target: u16) -> bool {
+        self.period < 8 || target > 0x7FF
+    }
+
+    pub fn length_active(&self) -> bool {
+        self.length.active()
+    }
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.muted(self.sweep_target()) {
+            return 0;
+        };
+        if DUTIES[self.duty as usize][self.step as usize] == 0 {
+            return 0;
+        };
+        self.envelope.output()
+    }
+}
+
+impl SaveState for Pulse {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.duty);
+        out.u8(self.step);
+        out.u16(self.period);
+        out.u16(self.timer);
+        self.envelope.save_state(out);
+        self.length.save_state(out);
+        self.sweep.save_state(out);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.duty = state.u8()?;
+        self.step = state.u8()?;
+        self.period = state.u16()?;
+        self.timer = state.u16()?;
+        if self.duty >= 4 || self.step >= 8 {
+            return Err(StateError::Invalid);
+        };
+        self.envelope.load_state(state)?;
+        self.length.load_state(state)?;
+        self.sweep.load_state(state)
+    }
+}
+
+struct Sweep {
+    enable: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+
+    divider: u8,
+    reload: bool,
+}
+impl Sweep {
+    fn init() -> Self {
+        Self {
+            enable: false,
+            period: 0,
+            negate: false,
+            shift: 0,
+
+            divider: 0,
+            reload: false,
+        }
+    }
+
+    fn write(&mut self, data: u8) {
+        self.enable = data & 128 != 0;
+        self.period = data >> 4 & 0b111;
+        self.negate = data & 8 != 0;
+        self.shift = data & 0b111;
+        self.reload = true;
+    }
+}
+
+impl SaveState for Sweep {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.enable);
+        out.u8(self.period);
+        out.bool(self.negate);
+        out.u8(self.shift);
+        out.u8(self.divider);
+        out.bool(self.reload);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enable = state.bool()?;
+        self.period = state.u8()?;
+        self.negate = state.bool()?;
+        self.shift = state.u8()?;
+        self.divider = state.u8()?;
+        self.reload = state.bool()?;
+        if self.shift > 7 {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
+
+static DUTIES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
@@ -0,0 +1,73 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const HEADER_LEN: u32 = 44;
+
+// Writes audio samples to a 16-bit mono PCM WAV file.
+// The sizes in the RIFF header are only correct once the recorder is finished.
+pub struct AudioRecorder<W: Write + Seek = BufWriter<File>> {
+    writer: W,
+    samples: u32,
+}
+impl AudioRecorder {
+    pub fn new(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Self::from_writer(BufWriter::new(file), sample_rate)
+    }
+}
+impl<W: Write + Seek> AudioRecorder<W> {
+    pub fn from_writer(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        writer.write_all(&header(sample_rate))?;
+        Ok(Self { writer, samples: 0 })
+    }
+
+    pub fn push_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    // Patches the chunk sizes into the header and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_len = self.samples * 2;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(HEADER_LEN - 8 + data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// The chunk sizes are left for `finish` to fill in.
+fn header(sample_rate: u32) -> [u8; HEADER_LEN as usize] {
+    let channels: u16 = 1;
+    let bits: u16 = 16;
+    let block_align = channels * bits / 8;
+    let byte_rate = sample_rate * block_align as u32;
+
+    let mut header = [0; HEADER_LEN as usize];
+    header[0..4].copy_from_slice(b"RIFF");
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    // Uncompressed PCM
+    header[20..22].copy_from_slice(&1u16.to_le_bytes());
+    header[22..24].copy_from_slice(&channels.to_le_bytes());
+    header[24..28].copy_from_slice(&sample_rate.to_le_bytes());
+    header[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+    header[32..34].copy_from_slice(&block_align.to_le_bytes());
+    header[34..36].copy_from_slice(&bits.to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header
+}
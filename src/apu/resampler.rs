@@ -0,0 +1,109 @@
+use std::{collections::VecDeque, f64::consts::PI};
+
+// How finely the filter kernel is tabulated between two input samples.
+const PHASES: usize = 32;
+// Kernel half-width in zero crossings, which sets the width of the transition band.
+// With a Blackman window this puts the band at roughly 40% to 60% of the output rate,
+// so whatever aliases does so above the audible range.
+const ZERO_CROSSINGS: f64 = 14.0;
+
+// Converts a sample stream between rates with a windowed-sinc low-pass filter.
+pub struct Resampler {
+    ratio: f64,
+    half_width: usize,
+    kernel: Vec<f32>,
+
+    history: VecDeque<f32>,
+    history_start: u64,
+    inputs: u64,
+    next_output: f64,
+    output: Vec<f32>,
+}
+impl Resampler {
+    pub fn new(input_hz: f64, output_hz: f64) -> Self {
+        let ratio = input_hz / output_hz;
+        // The cutoff is half the lower of the two rates, in cycles per input sample.
+        let scale = ratio.recip().min(1.0);
+        let cutoff = 0.5 * scale;
+        let half_width = (ZERO_CROSSINGS / scale).ceil() as usize;
+
+        let taps = 2 * half_width * PHASES + 1;
+        let kernel = (0..taps)
+            .map(|i| {
+                let x = i as f64 / PHASES as f64 - half_width as f64;
+                let window = blackman(x / half_width as f64);
+                (2.0 * cutoff * sinc(2.0 * cutoff * x) * window) as f32
+            })
+            .collect();
+
+        Self {
+            ratio,
+            half_width,
+            kernel,
+
+            history: VecDeque::new(),
+            history_start: 0,
+            inputs: 0,
+            next_output: half_width as f64,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn push_sample(&mut self, sample: f32) {
+        self.history.push_back(sample);
+        self.inputs += 1;
+
+        let last = (self.inputs - 1) as f64;
+        while self.next_output + self.half_width as f64 <= last {
+            let sample = self.filter(self.next_output);
+            self.output.push(sample);
+            self.next_output += self.ratio;
+            self.discard_history();
+        }
+    }
+    pub fn drain(&mut self, out: &mut Vec<f32>) {
+        out.append(&mut self.output);
+    }
+
+    fn filter(&self, time: f64) -> f32 {
+        let half_width = self.half_width as f64;
+        let first = (time - half_width).ceil() as u64;
+        let last = (time + half_width).floor() as u64;
+
+        let mut sum = 0.0;
+        for n in first..=last {
+            let sample = self.history[(n - self.history_start) as usize];
+            sum += sample * self.kernel_at(n as f64 - time);
+        }
+        sum
+    }
+    fn kernel_at(&self, x: f64) -> f32 {
+        let position = (x + self.half_width as f64) * PHASES as f64;
+        let index = position as usize;
+        let fraction = (position - index as f64) as f32;
+
+        let a = self.kernel[index];
+        let b = self.kernel.get(index + 1).copied().unwrap_or(0.0);
+        a + (b - a) * fraction
+    }
+    fn discard_history(&mut self) {
+        let needed = (self.next_output - self.half_width as f64).ceil() as u64;
+        while self.history_start < needed {
+            self.history.pop_front();
+            self.history_start += 1;
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+// Takes positions in -1..=1.
+fn blackman(x: f64) -> f64 {
+    let x = (x + 1.0) / 2.0;
+    0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos()
+}
@@ -0,0 +1,114 @@
+use super::units::LengthCounter;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+pub struct Triangle {
+    period: u16,
+    timer: u16,
+    step: u8,
+
+    linear_reload: u8,
+    linear_counter: u8,
+    linear_reload_flag: bool,
+    // Doubles as the length counter halt flag.
+    control: bool,
+    length: LengthCounter,
+}
+impl Triangle {
+    pub fn init() -> Self {
+        Self {
+            period: 0,
+            timer: 0,
+            step: 0,
+
+            linear_reload: 0,
+            linear_counter: 0,
+            linear_reload_flag: false,
+            control: false,
+            length: LengthCounter::init(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, data: u8, enabled: bool, length_clocked: bool) {
+        match register {
+            0 => {
+                self.control = data & 128 != 0;
+                self.length.set_halt(self.control);
+                self.linear_reload = data & 0x7F;
+            }
+            1 => (),
+            2 => self.period = (self.period & 0x700) | data as u16,
+            3 => {
+                self.period = (self.period & 0xFF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data, enabled, length_clocked);
+                self.linear_reload_flag = true;
+            }
+            4.. => unreachable!(),
+        }
+    }
+    pub fn disable(&mut self) {
+        self.length.clear();
+    }
+
+    // Unlike the other channels, the triangle timer is clocked on every CPU cycle.
+    pub fn tick_timer(&mut self) {
+        if self.timer != 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period;
+        if self.linear_counter != 0 && self.length.active() {
+            self.step = (self.step + 1) % 32;
+        }
+    }
+    pub fn tick_linear_counter(&mut self) {
+        if self.linear_reload_flag {
+            self.linear_counter = self.linear_reload;
+        } else if self.linear_counter != 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload_flag = false;
+        }
+    }
+    pub fn tick_length(&mut self) {
+        self.length.tick();
+    }
+
+    pub fn length_active(&self) -> bool {
+        self.length.active()
+    }
+    // A halted sequencer keeps outputting its current step.
+    pub fn output(&self) -> u8 {
+        if self.step < 16 {
+            15 - self.step
+        } else {
+            self.step - 16
+        }
+    }
+}
+
+impl SaveState for Triangle {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u16(self.period);
+        out.u16(self.timer);
+        out.u8(self.step);
+        out.u8(self.linear_reload);
+        out.u8(self.linear_counter);
+        out.bool(self.linear_reload_flag);
+        out.bool(self.control);
+        self.length.save_state(out);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.period = state.u16()?;
+        self.timer = state.u16()?;
+        self.step = state.u8()?;
+        self.linear_reload = state.u8()?;
+        self.linear_counter = state.u8()?;
+        self.linear_reload_flag = state.bool()?;
+        self.control = state.bool()?;
+        if self.step >= 32 {
+            return Err(StateError::Invalid);
+        };
+        self.length.load_state(state)
+    }
+}
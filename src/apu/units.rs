@@ -0,0 +1,146 @@
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+pub struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    volume: u8,
+
+    divider: u8,
+    decay: u8,
+}
+impl Envelope {
+    pub fn init() -> Self {
+        Self {
+            start: false,
+            looping: false,
+            constant: false,
+            volume: 0,
+
+            divider: 0,
+            decay: 0,
+        }
+    }
+
+    // Takes the low six bits of $4000, $4004 or $400C.
+    pub fn write(&mut self, data: u8) {
+        self.looping = data & 32 != 0;
+        self.constant = data & 16 != 0;
+        self.volume = data & 0xF;
+    }
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn tick(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+            return;
+        }
+
+        if self.divider != 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.volume;
+        if self.decay != 0 {
+            self.decay -= 1;
+        } else if self.looping {
+            self.decay = 15;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.start);
+        out.bool(self.looping);
+        out.bool(self.constant);
+        out.u8(self.volume);
+        out.u8(self.divider);
+        out.u8(self.decay);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.start = state.bool()?;
+        self.looping = state.bool()?;
+        self.constant = state.bool()?;
+        self.volume = state.u8()?;
+        self.divider = state.u8()?;
+        self.decay = state.u8()?;
+        Ok(())
+    }
+}
+
+pub struct LengthCounter {
+    halt: bool,
+    counter: u8,
+    decremented: bool,
+}
+impl LengthCounter {
+    pub fn init() -> Self {
+        Self {
+            halt: false,
+            counter: 0,
+            decremented: false,
+        }
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+    // Takes the upper five bits of $4003, $4007, $400B or $400F.
+    // Disabled channels keep their counter at zero.
+    // A reload on the same cycle as a clock is lost if that clock decremented the counter.
+    pub fn load(&mut self, data: u8, enabled: bool, clocked: bool) {
+        if !enabled {
+            return;
+        };
+        if clocked && self.decremented {
+            return;
+        };
+        self.counter = LENGTHS[data as usize >> 3];
+    }
+    pub fn clear(&mut self) {
+        self.counter = 0;
+    }
+
+    pub fn tick(&mut self) {
+        self.decremented = self.counter != 0 && !self.halt;
+        if self.decremented {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.counter != 0
+    }
+}
+
+impl SaveState for LengthCounter {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.halt);
+        out.u8(self.counter);
+        out.bool(self.decremented);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.halt = state.bool()?;
+        self.counter = state.u8()?;
+        self.decremented = state.bool()?;
+        Ok(())
+    }
+}
+
+static LENGTHS: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
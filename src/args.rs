@@ -0,0 +1,96 @@
+use std::{error::Error, fmt};
+
+use nessy::region::Region;
+
+use crate::SAVE_DIR;
+
+pub const USAGE: &str = "\
+usage: nessy <rom> [options]
+
+<rom> is an iNES, NES 2.0, UNIF or FDS image, or a zip archive with a .nes file in it.
+
+options:
+    --region ntsc|pal   runs at this region's timing, whatever the header says
+    --palette <file>    a .pal file to use instead of the built-in palette
+    --save-dir <dir>    where battery saves go, saves by default
+    --patch <file>      an IPS or BPS patch to apply to the ROM
+    --cheat <code>      a Game Genie code to enable, may be given more than once";
+
+// What the emulator was started with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Args {
+    pub rom: String,
+    pub region: Option<Region>,
+    pub palette: Option<String>,
+    pub save_dir: String,
+    pub patch: Option<String>,
+    pub cheats: Vec<String>,
+}
+impl Args {
+    // Takes the arguments after the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut rom = None;
+        let mut region = None;
+        let mut palette = None;
+        let mut save_dir = None;
+        let mut patch = None;
+        let mut cheats = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if rom.replace(arg).is_some() {
+                    return Err(ArgsError::ExtraRom);
+                };
+                continue;
+            };
+            let value = args.next().ok_or(ArgsError::MissingValue(arg.clone()))?;
+            match arg.as_str() {
+                "--region" => region = Some(parse_region(&value)?),
+                "--palette" => palette = Some(value),
+                "--save-dir" => save_dir = Some(value),
+                "--patch" => patch = Some(value),
+                "--cheat" => cheats.push(value),
+                _ => return Err(ArgsError::Unknown(arg)),
+            }
+        }
+
+        Ok(Self {
+            rom: rom.ok_or(ArgsError::NoRom)?,
+            region,
+            palette,
+            save_dir: save_dir.unwrap_or_else(|| SAVE_DIR.to_string()),
+            patch,
+            cheats,
+        })
+    }
+}
+
+fn parse_region(name: &str) -> Result<Region, ArgsError> {
+    match name.to_ascii_lowercase().as_str() {
+        "ntsc" => Ok(Region::Ntsc),
+        "pal" => Ok(Region::Pal),
+        _ => Err(ArgsError::Region(name.to_string())),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArgsError {
+    NoRom,
+    ExtraRom,
+    MissingValue(String),
+    Unknown(String),
+    Region(String),
+}
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoRom => write!(f, "no ROM file given"),
+            Self::ExtraRom => write!(f, "only one ROM file can be given"),
+            Self::MissingValue(flag) => write!(f, "{flag} needs a value"),
+            Self::Unknown(flag) => write!(f, "unknown option {flag}"),
+            Self::Region(name) => write!(f, "{name} isn't a region, try ntsc or pal"),
+        }
+    }
+}
+impl Error for ArgsError {}
@@ -0,0 +1,176 @@
+use std::{
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SampleFormat, SizedSample, Stream, StreamConfig,
+};
+use crossbeam::queue::ArrayQueue;
+use nessy::apu::{recorder::AudioRecorder, resampler::Resampler};
+
+// How much audio is kept queued ahead of the device, in seconds.
+const LATENCY: f64 = 0.05;
+const DEFAULT_VOLUME: f32 = 0.5;
+
+// The output stream, which has to stay on the thread that opened it.
+pub struct Audio {
+    _stream: Stream,
+    volume: Arc<AtomicU32>,
+}
+impl Audio {
+    // Returns None if there is no usable output device.
+    pub fn init(input_rate: f64) -> Option<(Self, AudioSink)> {
+        let host = cpal::default_host();
+        let device = host.default_output_device()?;
+        let config = device.default_output_config().ok()?;
+        let format = config.sample_format();
+        let config: StreamConfig = config.into();
+
+        let sample_rate = config.sample_rate.0 as f64;
+        let target_len = (sample_rate * LATENCY) as usize;
+        let queue = Arc::new(ArrayQueue::new(target_len * 4));
+        let volume = Arc::new(AtomicU32::new(DEFAULT_VOLUME.to_bits()));
+
+        let shared = (Arc::clone(&queue), Arc::clone(&volume));
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, shared),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, shared),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, shared),
+            _ => None,
+        }?;
+        stream.play().ok()?;
+
+        let audio = Self {
+            _stream: stream,
+            volume,
+        };
+        let sink = AudioSink {
+            queue,
+            resampler: Resampler::new(input_rate, sample_rate),
+            dc_blocker: DcBlocker::init(),
+            target_len,
+            resampled: Vec::new(),
+            sample_rate: config.sample_rate.0,
+            recorder: None,
+        };
+        Some((audio, sink))
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+    pub fn set_volume(&self, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+}
+
+// Feeds the stream from the emulation thread.
+pub struct AudioSink {
+    queue: Arc<ArrayQueue<f32>>,
+    resampler: Resampler,
+    dc_blocker: DcBlocker,
+    target_len: usize,
+    resampled: Vec<f32>,
+    sample_rate: u32,
+    recorder: Option<AudioRecorder>,
+}
+impl AudioSink {
+    // For when the console changes, since PAL and NTSC ones make samples at different rates.
+    pub fn set_input_rate(&mut self, input_rate: f64) {
+        self.resampler = Resampler::new(input_rate, self.sample_rate as f64);
+    }
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.resampler.push_sample(sample);
+        }
+        self.resampler.drain(&mut self.resampled);
+        for sample in &mut self.resampled {
+            *sample = self.dc_blocker.filter(*sample);
+            // Samples that don't fit are dropped; the queue refills once the emulator slows down.
+            let _ = self.queue.push(*sample);
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.push_samples(&self.resampled) {
+                eprintln!("Stopped recording audio: {err}");
+                self.recorder = None;
+            }
+        }
+        self.resampled.clear();
+    }
+
+    // Records what is played from now on, at the output device's sample rate.
+    pub fn start_recording(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.recorder = Some(AudioRecorder::new(path, self.sample_rate)?);
+        Ok(())
+    }
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish()?;
+        }
+        Ok(())
+    }
+    pub fn recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+    // The emulator should run another frame while this holds.
+    pub fn wants_samples(&self) -> bool {
+        self.queue.len() < self.target_len
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    (queue, volume): (Arc<ArrayQueue<f32>>, Arc<AtomicU32>),
+) -> Option<Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    // Underruns repeat the last sample instead of snapping back to zero.
+    let mut last = 0.0;
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let volume = f32::from_bits(volume.load(Ordering::Relaxed));
+            for frame in data.chunks_mut(channels) {
+                last = queue.pop().unwrap_or(last);
+                frame.fill(T::from_sample(last * volume));
+            }
+        },
+        |err| eprintln!("Audio stream error: {err}"),
+        None,
+    );
+    stream.ok()
+}
+
+// The mixer output sits well below zero while silent, which the NES removes with a high-pass filter.
+struct DcBlocker {
+    last_input: Option<f32>,
+    last_output: f32,
+}
+impl DcBlocker {
+    fn init() -> Self {
+        Self {
+            last_input: None,
+            last_output: 0.0,
+        }
+    }
+
+    fn filter(&mut self, sample: f32) -> f32 {
+        let last_input = self.last_input.unwrap_or(sample);
+        let output = sample - last_input + 0.995 * self.last_output;
+        self.last_input = Some(sample);
+        self.last_output = output;
+        output
+    }
+}
@@ -0,0 +1,196 @@
+use crate::nesbus::CpuBus;
+use std::{error::Error, fmt};
+
+// Game Genie letters, in the order of the values they stand for.
+const LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+// What a Game Genie code does: reads of `address` return `value` instead,
+// but only when the cartridge put `compare` on the bus, if the code has one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+impl GameGenieCode {
+    // Six letter codes replace a byte, eight letter ones also check what they replace.
+    pub fn decode(code: &str) -> Result<Self, CheatError> {
+        let mut n = [0u8; 8];
+        let len = code.chars().count();
+        if len != 6 && len != 8 {
+            return Err(CheatError::Length(len));
+        };
+        for (digit, letter) in n.iter_mut().zip(code.chars()) {
+            let upper = letter.to_ascii_uppercase();
+            let value = LETTERS.iter().position(|&known| known as char == upper);
+            *digit = value.ok_or(CheatError::Letter(letter))? as u8;
+        }
+
+        // The bits of every field are scattered over the letters.
+        let address = 0x8000
+            | (n[3] as u16 & 7) << 12
+            | (n[5] as u16 & 7) << 8
+            | (n[4] as u16 & 8) << 8
+            | (n[2] as u16 & 7) << 4
+            | (n[1] as u16 & 8) << 4
+            | n[4] as u16 & 7
+            | n[3] as u16 & 8;
+        let value = |high: u8, low: u8| (high & 7) << 4 | (low & 8) << 4 | low & 7;
+        let code = if len == 6 {
+            Self {
+                address,
+                value: value(n[1], n[0]) | n[5] & 8,
+                compare: None,
+            }
+        } else {
+            Self {
+                address,
+                value: value(n[1], n[0]) | n[7] & 8,
+                compare: Some(value(n[7], n[6]) | n[5] & 8),
+            }
+        };
+        Ok(code)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Cheat {
+    // As it was entered, in upper case.
+    pub name: String,
+    pub code: GameGenieCode,
+    pub enabled: bool,
+}
+
+// Holds a byte of console RAM at one value, the way a Pro Action Replay does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RamFreeze {
+    pub addr: u16,
+    pub value: u8,
+}
+
+// Patches what the CPU reads from the cartridge, like a Game Genie plugged in between,
+// and keeps frozen RAM at its values.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CheatEngine {
+    cheats: Vec<Cheat>,
+    freezes: Vec<RamFreeze>,
+}
+impl CheatEngine {
+    pub fn init() -> Self {
+        Self {
+            cheats: Vec::new(),
+            freezes: Vec::new(),
+        }
+    }
+
+    // Adds a code, enabled. Adding one that's already there only enables it again.
+    pub fn add(&mut self, code: &str) -> Result<(), CheatError> {
+        let name = code.to_ascii_uppercase();
+        let code = GameGenieCode::decode(&name)?;
+        match self.cheats.iter_mut().find(|cheat| cheat.name == name) {
+            Some(cheat) => cheat.enabled = true,
+            None => self.cheats.push(Cheat {
+                name,
+                code,
+                enabled: true,
+            }),
+        }
+        Ok(())
+    }
+    pub fn remove(&mut self, code: &str) -> bool {
+        let len = self.cheats.len();
+        self.cheats
+            .retain(|cheat| !cheat.name.eq_ignore_ascii_case(code));
+        self.cheats.len() != len
+    }
+    // Returns whether the code is enabled now, or None if it was never added.
+    pub fn toggle(&mut self, code: &str) -> Option<bool> {
+        let cheat = self.find_mut(code)?;
+        cheat.enabled = !cheat.enabled;
+        Some(cheat.enabled)
+    }
+    pub fn set_enabled(&mut self, code: &str, enabled: bool) -> bool {
+        let Some(cheat) = self.find_mut(code) else {
+            return false;
+        };
+        cheat.enabled = enabled;
+        true
+    }
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+        self.freezes.clear();
+    }
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty() && self.freezes.is_empty()
+    }
+
+    // Only the 2K of console RAM can be frozen, through any of its mirrors below $2000.
+    // Returns false for anything else. Freezing an address again changes its value.
+    pub fn freeze(&mut self, addr: u16, value: u8) -> bool {
+        if addr >= 0x2000 {
+            return false;
+        };
+        let addr = addr % 0x800;
+        match self.freezes.iter_mut().find(|freeze| freeze.addr == addr) {
+            Some(freeze) => freeze.value = value,
+            None => self.freezes.push(RamFreeze { addr, value }),
+        }
+        true
+    }
+    pub fn unfreeze(&mut self, addr: u16) -> bool {
+        if addr >= 0x2000 {
+            return false;
+        };
+        let addr = addr % 0x800;
+        let len = self.freezes.len();
+        self.freezes.retain(|freeze| freeze.addr != addr);
+        self.freezes.len() != len
+    }
+    pub fn freezes(&self) -> &[RamFreeze] {
+        &self.freezes
+    }
+    fn find_mut(&mut self, code: &str) -> Option<&mut Cheat> {
+        self.cheats
+            .iter_mut()
+            .find(|cheat| cheat.name.eq_ignore_ascii_case(code))
+    }
+
+    // Runs after the cartridge has answered a read, so compare values see what it put on the bus.
+    pub fn apply(&self, cpu: &mut CpuBus) {
+        if !cpu.read() || cpu.address() < 0x8000 {
+            return;
+        };
+        let hit = self.cheats.iter().find(|cheat| {
+            let code = cheat.code;
+            let compared = code.compare.is_none_or(|compare| compare == cpu.data());
+            cheat.enabled && code.address == cpu.address() && compared
+        });
+        if let Some(cheat) = hit {
+            cpu.set_data(cheat.code.value);
+        }
+    }
+    // Runs after every CPU cycle, so whatever the game writes is gone before it reads it back.
+    pub fn freeze_ram(&self, ram: &mut [u8; 2048]) {
+        for freeze in &self.freezes {
+            ram[freeze.addr as usize] = freeze.value;
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CheatError {
+    Length(usize),
+    Letter(char),
+}
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Length(len) => write!(f, "Game Genie codes have 6 or 8 letters, not {len}"),
+            Self::Letter(letter) => write!(f, "'{letter}' isn't a Game Genie letter"),
+        }
+    }
+}
+impl Error for CheatError {}
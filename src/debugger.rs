@@ -0,0 +1,156 @@
+use crate::{disasm::opcode, mapper::Mapper, nesbus::NesBus};
+use cpu_6502::Cpu;
+use std::{collections::BTreeSet, ops::RangeInclusive};
+
+// Runs the CPU an instruction at a time, stopping at breakpoints.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+}
+impl Debugger {
+    pub fn init() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.insert(pc);
+    }
+    pub fn remove_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    // Runs until the next instruction is at a breakpoint or would jam the CPU,
+    // a watchpoint is hit, or the budget runs out.
+    // The instruction the CPU is stopped at always runs, so a breakpoint can be resumed from.
+    pub fn run_until_break<M: Mapper>(
+        &self,
+        cpu: &mut Cpu,
+        bus: &mut NesBus<M>,
+        max_cycles: u64,
+    ) -> Stop {
+        let start = bus.cycles();
+        loop {
+            step(cpu, bus);
+            if let Some(hit) = bus.take_watch_hit() {
+                return Stop::Watchpoint(hit);
+            };
+            if self.breakpoints.contains(&cpu.pc()) {
+                return Stop::Breakpoint(cpu.pc());
+            };
+            if let Some(jam) = pending_jam(cpu, bus) {
+                return Stop::Jammed(jam);
+            };
+            if bus.cycles() - start >= max_cycles {
+                return Stop::CycleBudget;
+            };
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Stop {
+    Breakpoint(u16),
+    Watchpoint(WatchHit),
+    Jammed(Jam),
+    CycleBudget,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StepResult {
+    // Where the instruction that ran was.
+    pub pc: u16,
+    pub cycles: u64,
+}
+
+// Runs exactly one instruction, or the reset or interrupt sequence the CPU has pending.
+pub fn step<M: Mapper>(cpu: &mut Cpu, bus: &mut NesBus<M>) -> StepResult {
+    let pc = cpu.pc();
+    let start = bus.cycles();
+    cpu.exec(bus);
+    StepResult {
+        pc,
+        cycles: bus.cycles() - start,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Jam {
+    pub opcode: u8,
+    pub pc: u16,
+}
+
+// The CPU is about to run one of the JAM opcodes, after which only a reset gets it going again.
+// An interrupt that is already pending would still run first.
+pub fn pending_jam<M: Mapper>(cpu: &Cpu, bus: &NesBus<M>) -> Option<Jam> {
+    let pc = cpu.pc();
+    let byte = bus.peek_cpu(pc);
+    let jams = opcode(byte).name == "JAM";
+    jams.then_some(Jam { opcode: byte, pc })
+}
+
+// Gets the cycle, address and data of a CPU access, and whether it was a read.
+pub type AccessHook = Box<dyn FnMut(u64, u16, u8, bool) + Send>;
+
+// Told about points in the emulation as they happen, for tools like auto-splitters.
+// Observers only get to see numbers; they can look at the console once the frame is done.
+pub trait NesObserver: Send {
+    // The PPU started a frame, right before the scanline call for line 0.
+    fn on_frame(&mut self, _frame: u64) {}
+    // The PPU is on the first dot of a line, counting the ones in vblank and the pre-render line.
+    fn on_scanline(&mut self, _frame: u64, _line: u16) {}
+    // The interrupt lines went up at the end of this CPU cycle.
+    fn on_nmi(&mut self, _cycle: u64) {}
+    fn on_irq(&mut self, _cycle: u64) {}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub on_read: bool,
+    pub on_write: bool,
+}
+
+// Addresses on the CPU bus to watch. Every access counts, DMA and dummy reads included.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Watchpoints(Vec<Watchpoint>);
+impl Watchpoints {
+    pub fn init() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn add(&mut self, watchpoint: Watchpoint) {
+        self.0.push(watchpoint);
+    }
+    pub fn remove(&mut self, range: &RangeInclusive<u16>) {
+        self.0.retain(|watchpoint| &watchpoint.range != range);
+    }
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn matches(&self, address: u16, read: bool) -> bool {
+        self.0.iter().any(|watchpoint| {
+            let access = if read {
+                watchpoint.on_read
+            } else {
+                watchpoint.on_write
+            };
+            access && watchpoint.range.contains(&address)
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WatchHit {
+    pub address: u16,
+    pub data: u8,
+    pub read: bool,
+    pub cycle: u64,
+}
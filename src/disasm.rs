@@ -0,0 +1,216 @@
+// Opcode names and addressing modes of the 2A03, for traces and debuggers.
+// The CPU core has its own decoder, this one only needs to know how instructions are spelled.
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+impl Mode {
+    // Bytes following the opcode.
+    pub fn operand_len(self) -> u16 {
+        match self {
+            Self::Implied | Self::Accumulator => 0,
+            Self::Absolute | Self::AbsoluteX | Self::AbsoluteY | Self::Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Opcode {
+    pub name: &'static str,
+    pub mode: Mode,
+    // Unofficial opcodes are marked with a * in nestest style traces.
+    pub official: bool,
+}
+impl Opcode {
+    // Bytes including the opcode.
+    pub fn size(self) -> u16 {
+        1 + self.mode.operand_len()
+    }
+}
+
+pub fn opcode(byte: u8) -> Opcode {
+    OPCODES[byte as usize]
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub opcode: Opcode,
+    // In the usual syntax, with branch targets already resolved.
+    pub operand: String,
+}
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.operand.is_empty() {
+            f.write_str(self.opcode.name)
+        } else {
+            write!(f, "{} {}", self.opcode.name, self.operand)
+        }
+    }
+}
+
+// Decodes instructions back to back, as if the bytes were loaded at origin.
+// An instruction cut off by the end of the bytes is left out.
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let opcode = opcode(bytes[offset]);
+        let Some(bytes) = bytes.get(offset..offset + opcode.size() as usize) else {
+            break;
+        };
+        let address = origin.wrapping_add(offset as u16);
+        instructions.push(Instruction {
+            address,
+            bytes: bytes.to_vec(),
+            opcode,
+            operand: format_operand(opcode.mode, address, bytes),
+        });
+        offset += bytes.len();
+    }
+    instructions
+}
+// The operand as written in assembly, for the instruction at `address` made of `bytes`.
+pub(crate) fn format_operand(mode: Mode, address: u16, bytes: &[u8]) -> String {
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+    match mode {
+        Mode::Implied => String::new(),
+        Mode::Accumulator => "A".to_string(),
+        Mode::Immediate => format!("#${byte:02X}"),
+        Mode::ZeroPage => format!("${byte:02X}"),
+        Mode::ZeroPageX => format!("${byte:02X},X"),
+        Mode::ZeroPageY => format!("${byte:02X},Y"),
+        Mode::Absolute => format!("${word:04X}"),
+        Mode::AbsoluteX => format!("${word:04X},X"),
+        Mode::AbsoluteY => format!("${word:04X},Y"),
+        Mode::Indirect => format!("(${word:04X})"),
+        Mode::IndirectX => format!("(${byte:02X},X)"),
+        Mode::IndirectY => format!("(${byte:02X}),Y"),
+        Mode::Relative => {
+            let target = address.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            format!("${target:04X}")
+        }
+    }
+}
+
+const fn op(name: &'static str, mode: Mode, official: bool) -> Opcode {
+    Opcode {
+        name,
+        mode,
+        official,
+    }
+}
+const IMP: Mode = Mode::Implied;
+const ACC: Mode = Mode::Accumulator;
+const IMM: Mode = Mode::Immediate;
+const ZP: Mode = Mode::ZeroPage;
+const ZPX: Mode = Mode::ZeroPageX;
+const ZPY: Mode = Mode::ZeroPageY;
+const ABS: Mode = Mode::Absolute;
+const ABX: Mode = Mode::AbsoluteX;
+const ABY: Mode = Mode::AbsoluteY;
+const IND: Mode = Mode::Indirect;
+const IZX: Mode = Mode::IndirectX;
+const IZY: Mode = Mode::IndirectY;
+const REL: Mode = Mode::Relative;
+
+#[rustfmt::skip]
+static OPCODES: [Opcode; 256] = [
+    // $00
+    op("BRK", IMP, true), op("ORA", IZX, true), op("JAM", IMP, false), op("SLO", IZX, false),
+    op("NOP", ZP, false), op("ORA", ZP, true), op("ASL", ZP, true), op("SLO", ZP, false),
+    op("PHP", IMP, true), op("ORA", IMM, true), op("ASL", ACC, true), op("ANC", IMM, false),
+    op("NOP", ABS, false), op("ORA", ABS, true), op("ASL", ABS, true), op("SLO", ABS, false),
+    // $10
+    op("BPL", REL, true), op("ORA", IZY, true), op("JAM", IMP, false), op("SLO", IZY, false),
+    op("NOP", ZPX, false), op("ORA", ZPX, true), op("ASL", ZPX, true), op("SLO", ZPX, false),
+    op("CLC", IMP, true), op("ORA", ABY, true), op("NOP", IMP, false), op("SLO", ABY, false),
+    op("NOP", ABX, false), op("ORA", ABX, true), op("ASL", ABX, true), op("SLO", ABX, false),
+    // $20
+    op("JSR", ABS, true), op("AND", IZX, true), op("JAM", IMP, false), op("RLA", IZX, false),
+    op("BIT", ZP, true), op("AND", ZP, true), op("ROL", ZP, true), op("RLA", ZP, false),
+    op("PLP", IMP, true), op("AND", IMM, true), op("ROL", ACC, true), op("ANC", IMM, false),
+    op("BIT", ABS, true), op("AND", ABS, true), op("ROL", ABS, true), op("RLA", ABS, false),
+    // $30
+    op("BMI", REL, true), op("AND", IZY, true), op("JAM", IMP, false), op("RLA", IZY, false),
+    op("NOP", ZPX, false), op("AND", ZPX, true), op("ROL", ZPX, true), op("RLA", ZPX, false),
+    op("SEC", IMP, true), op("AND", ABY, true), op("NOP", IMP, false), op("RLA", ABY, false),
+    op("NOP", ABX, false), op("AND", ABX, true), op("ROL", ABX, true), op("RLA", ABX, false),
+    // $40
+    op("RTI", IMP, true), op("EOR", IZX, true), op("JAM", IMP, false), op("SRE", IZX, false),
+    op("NOP", ZP, false), op("EOR", ZP, true), op("LSR", ZP, true), op("SRE", ZP, false),
+    op("PHA", IMP, true), op("EOR", IMM, true), op("LSR", ACC, true), op("ALR", IMM, false),
+    op("JMP", ABS, true), op("EOR", ABS, true), op("LSR", ABS, true), op("SRE", ABS, false),
+    // $50
+    op("BVC", REL, true), op("EOR", IZY, true), op("JAM", IMP, false), op("SRE", IZY, false),
+    op("NOP", ZPX, false), op("EOR", ZPX, true), op("LSR", ZPX, true), op("SRE", ZPX, false),
+    op("CLI", IMP, true), op("EOR", ABY, true), op("NOP", IMP, false), op("SRE", ABY, false),
+    op("NOP", ABX, false), op("EOR", ABX, true), op("LSR", ABX, true), op("SRE", ABX, false),
+    // $60
+    op("RTS", IMP, true), op("ADC", IZX, true), op("JAM", IMP, false), op("RRA", IZX, false),
+    op("NOP", ZP, false), op("ADC", ZP, true), op("ROR", ZP, true), op("RRA", ZP, false),
+    op("PLA", IMP, true), op("ADC", IMM, true), op("ROR", ACC, true), op("ARR", IMM, false),
+    op("JMP", IND, true), op("ADC", ABS, true), op("ROR", ABS, true), op("RRA", ABS, false),
+    // $70
+    op("BVS", REL, true), op("ADC", IZY, true), op("JAM", IMP, false), op("RRA", IZY, false),
+    op("NOP", ZPX, false), op("ADC", ZPX, true), op("ROR", ZPX, true), op("RRA", ZPX, false),
+    op("SEI", IMP, true), op("ADC", ABY, true), op("NOP", IMP, false), op("RRA", ABY, false),
+    op("NOP", ABX, false), op("ADC", ABX, true), op("ROR", ABX, true), op("RRA", ABX, false),
+    // $80
+    op("NOP", IMM, false), op("STA", IZX, true), op("NOP", IMM, false), op("SAX", IZX, false),
+    op("STY", ZP, true), op("STA", ZP, true), op("STX", ZP, true), op("SAX", ZP, false),
+    op("DEY", IMP, true), op("NOP", IMM, false), op("TXA", IMP, true), op("ANE", IMM, false),
+    op("STY", ABS, true), op("STA", ABS, true), op("STX", ABS, true), op("SAX", ABS, false),
+    // $90
+    op("BCC", REL, true), op("STA", IZY, true), op("JAM", IMP, false), op("SHA", IZY, false),
+    op("STY", ZPX, true), op("STA", ZPX, true), op("STX", ZPY, true), op("SAX", ZPY, false),
+    op("TYA", IMP, true), op("STA", ABY, true), op("TXS", IMP, true), op("TAS", ABY, false),
+    op("SHY", ABX, false), op("STA", ABX, true), op("SHX", ABY, false), op("SHA", ABY, false),
+    // $A0
+    op("LDY", IMM, true), op("LDA", IZX, true), op("LDX", IMM, true), op("LAX", IZX, false),
+    op("LDY", ZP, true), op("LDA", ZP, true), op("LDX", ZP, true), op("LAX", ZP, false),
+    op("TAY", IMP, true), op("LDA", IMM, true), op("TAX", IMP, true), op("LXA", IMM, false),
+    op("LDY", ABS, true), op("LDA", ABS, true), op("LDX", ABS, true), op("LAX", ABS, false),
+    // $B0
+    op("BCS", REL, true), op("LDA", IZY, true), op("JAM", IMP, false), op("LAX", IZY, false),
+    op("LDY", ZPX, true), op("LDA", ZPX, true), op("LDX", ZPY, true), op("LAX", ZPY, false),
+    op("CLV", IMP, true), op("LDA", ABY, true), op("TSX", IMP, true), op("LAS", ABY, false),
+    op("LDY", ABX, true), op("LDA", ABX, true), op("LDX", ABY, true), op("LAX", ABY, false),
+    // $C0
+    op("CPY", IMM, true), op("CMP", IZX, true), op("NOP", IMM, false), op("DCP", IZX, false),
+    op("CPY", ZP, true), op("CMP", ZP, true), op("DEC", ZP, true), op("DCP", ZP, false),
+    op("INY", IMP, true), op("CMP", IMM, true), op("DEX", IMP, true), op("SBX", IMM, false),
+    op("CPY", ABS, true), op("CMP", ABS, true), op("DEC", ABS, true), op("DCP", ABS, false),
+    // $D0
+    op("BNE", REL, true), op("CMP", IZY, true), op("JAM", IMP, false), op("DCP", IZY, false),
+    op("NOP", ZPX, false), op("CMP", ZPX, true), op("DEC", ZPX, true), op("DCP", ZPX, false),
+    op("CLD", IMP, true), op("CMP", ABY, true), op("NOP", IMP, false), op("DCP", ABY, false),
+    op("NOP", ABX, false), op("CMP", ABX, true), op("DEC", ABX, true), op("DCP", ABX, false),
+    // $E0
+    op("CPX", IMM, true), op("SBC", IZX, true), op("NOP", IMM, false), op("ISB", IZX, false),
+    op("CPX", ZP, true), op("SBC", ZP, true), op("INC", ZP, true), op("ISB", ZP, false),
+    op("INX", IMP, true), op("SBC", IMM, true), op("NOP", IMP, true), op("SBC", IMM, false),
+    op("CPX", ABS, true), op("SBC", ABS, true), op("INC", ABS, true), op("ISB", ABS, false),
+    // $F0
+    op("BEQ", REL, true), op("SBC", IZY, true), op("JAM", IMP, false), op("ISB", IZY, false),
+    op("NOP", ZPX, false), op("SBC", ZPX, true), op("INC", ZPX, true), op("ISB", ZPX, false),
+    op("SED", IMP, true), op("SBC", ABY, true), op("NOP", IMP, false), op("ISB", ABY, false),
+    op("NOP", ABX, false), op("SBC", ABX, true), op("INC", ABX, true), op("ISB", ABX, false),
+];
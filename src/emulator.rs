@@ -0,0 +1,257 @@
+use crate::{
+    input::{keyboard::Keyboard, ControllerState, DpadPolicy, Zapper},
+    mapper::{fds::FdsSystem, try_get_mapper, DynMapper},
+    nes::Nes,
+    nesbus::{NesBus, PowerUpState},
+    ppu::pixel_buffer::PixelBuffer,
+    region::Region,
+    rom::{
+        self,
+        expansion::ExpansionDevice,
+        fds::{Disk, FdsError},
+        header::Header,
+        unif::{self, UnifError},
+        validate::clean_header,
+    },
+    saves::{self, SaveStore},
+    state::StateError,
+};
+use nes_rom_parser::Rom;
+use std::{error::Error, fmt, io};
+
+// A console with a cartridge in it, for frontends that just want frames, sound and input.
+// Everything else is still reachable through `nes`.
+pub struct Emulator {
+    pub nes: Nes<DynMapper>,
+    samples: Vec<f32>,
+}
+impl Emulator {
+    pub fn builder() -> EmulatorBuilder {
+        EmulatorBuilder::new()
+    }
+
+    // Runs until the PPU has drawn a whole picture.
+    pub fn run_frame(&mut self) {
+        let frame = self.nes.run_frame();
+        self.samples.clear();
+        self.samples.extend_from_slice(frame.samples);
+    }
+    pub fn reset(&mut self) {
+        self.nes.reset();
+    }
+    pub fn power_cycle(&mut self) {
+        self.nes.power_cycle();
+    }
+
+    // Port 0 is controller 1. Takes effect from the next frame on.
+    pub fn set_controller(&mut self, port: usize, state: impl Into<ControllerState>) {
+        self.nes.set_controller(port, state.into());
+    }
+    pub fn framebuffer(&self) -> &PixelBuffer {
+        self.nes.bus.ppu().pixels()
+    }
+    // What the APU produced during the last frame, at `sample_rate`.
+    pub fn audio(&self) -> &[f32] {
+        &self.samples
+    }
+    pub fn sample_rate(&self) -> f64 {
+        self.nes.bus.sample_rate()
+    }
+    pub fn region(&self) -> Region {
+        self.nes.bus.region()
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.nes.save_state()
+    }
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        self.nes.load_state(state)
+    }
+    // Writes out battery RAM now instead of waiting for the next flush.
+    pub fn flush_saves(&mut self) -> io::Result<()> {
+        self.nes.flush_saves()
+    }
+}
+// Catches changes made since the last frame, which the console's own flush on drop can't see.
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        let _ = self.flush_saves();
+    }
+}
+
+// The one place images get turned into a console, for frontends and tests alike.
+pub struct EmulatorBuilder {
+    rom: Option<Vec<u8>>,
+    fds_bios: Option<Vec<u8>>,
+    region: Option<Region>,
+    power_up: PowerUpState,
+    ppu_warm_up: bool,
+    batched_ppu: bool,
+    save_store: Option<Box<dyn SaveStore>>,
+    dpad_policy: DpadPolicy,
+    turbo_rate: [u8; 2],
+    zapper: bool,
+}
+impl EmulatorBuilder {
+    pub fn new() -> Self {
+        Self {
+            rom: None,
+            fds_bios: None,
+            region: None,
+            power_up: PowerUpState::Zeroed,
+            ppu_warm_up: false,
+            batched_ppu: false,
+            save_store: None,
+            dpad_policy: DpadPolicy::Neutral,
+            turbo_rate: [2, 2],
+            zapper: false,
+        }
+    }
+
+    // An iNES, NES 2.0, UNIF or FDS image.
+    pub fn rom_bytes(mut self, src: Vec<u8>) -> Self {
+        self.rom = Some(src);
+        self
+    }
+    // The Famicom Disk System BIOS, only needed for FDS images.
+    pub fn fds_bios(mut self, bios: Vec<u8>) -> Self {
+        self.fds_bios = Some(bios);
+        self
+    }
+    // Without one, the region comes from the header. Disk images are NTSC.
+    pub fn region_override(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+    pub fn power_up(mut self, power_up: PowerUpState) -> Self {
+        self.power_up = power_up;
+        self
+    }
+    pub fn ppu_warm_up(mut self, warm_up: bool) -> Self {
+        self.ppu_warm_up = warm_up;
+        self
+    }
+    pub fn batched_ppu(mut self, batched: bool) -> Self {
+        self.batched_ppu = batched;
+        self
+    }
+    // Where battery backed RAM is kept. Only used if the header says there's a battery.
+    pub fn save_store(mut self, store: impl SaveStore + 'static) -> Self {
+        self.save_store = Some(Box::new(store));
+        self
+    }
+    // What happens to controller states with opposite directions held. Neutral by default.
+    pub fn dpad_policy(mut self, policy: DpadPolicy) -> Self {
+        self.dpad_policy = policy;
+        self
+    }
+    pub fn turbo_rate(mut self, frames_on: u8, frames_off: u8) -> Self {
+        self.turbo_rate = [frames_on, frames_off];
+        self
+    }
+    // Plugs a Zapper into port 2 even if the header doesn't ask for one.
+    pub fn zapper(mut self, zapper: bool) -> Self {
+        self.zapper = zapper;
+        self
+    }
+
+    pub fn build(self) -> Result<Emulator, EmulatorError> {
+        Ok(Emulator {
+            nes: self.build_nes()?,
+            samples: Vec::new(),
+        })
+    }
+    // For frontends that drive the console themselves.
+    pub fn build_nes(mut self) -> Result<Nes<DynMapper>, EmulatorError> {
+        let mut src = self.rom.take().ok_or(EmulatorError::NoRom)?;
+        if src.starts_with(b"FDS\x1A") || src.starts_with(b"\x01*NINTENDO-HVC*") {
+            return self.build_fds(&src);
+        }
+        if src.starts_with(b"UNIF") {
+            src = unif::parse(&src).map_err(EmulatorError::Unif)?;
+        }
+        clean_header(&mut src);
+        let rom = Rom::parse(&src).map_err(|_| EmulatorError::Invalid)?;
+        let mapper = try_get_mapper(&rom, rom::trainer(&src))
+            .ok_or(EmulatorError::Mapper(rom.header.mapper))?;
+
+        let mut bus = self.bus(mapper);
+        bus.set_region(self.region.unwrap_or(rom::region(&src)));
+        if let Some(vs_ppu) = rom::vs_ppu(&src) {
+            bus.enable_vs_system(vs_ppu);
+        }
+        let header = Header::parse(&src).ok();
+        let device = header
+            .as_ref()
+            .map_or(ExpansionDevice::Unspecified, |header| {
+                header.expansion_device
+            });
+        bus.input_mut().set_swapped_ports(device.swaps_ports());
+        if self.zapper || device == ExpansionDevice::Zapper {
+            bus.input_mut().set_zapper(Some(Zapper::init()));
+        }
+        if device == ExpansionDevice::FamilyBasicKeyboard {
+            bus.input_mut().set_keyboard(Some(Keyboard::init()));
+        }
+
+        let mut nes = Nes::new(bus);
+        let battery = header.is_some_and(|header| header.battery);
+        if let Some(store) = self.save_store.filter(|_| battery) {
+            nes.attach_save_store(store, saves::game_id(&rom));
+        }
+        Ok(nes)
+    }
+
+    fn build_fds(self, src: &[u8]) -> Result<Nes<DynMapper>, EmulatorError> {
+        let disk = Disk::parse(src).map_err(EmulatorError::Fds)?;
+        let bios = self.fds_bios.as_ref().ok_or(EmulatorError::NoBios)?;
+        let mut fds = FdsSystem::new(bios).map_err(EmulatorError::Fds)?;
+        fds.insert_disk(disk);
+
+        let mut bus = self.bus(DynMapper::new(fds));
+        bus.set_region(self.region.unwrap_or(Region::Ntsc));
+        if self.zapper {
+            bus.input_mut().set_zapper(Some(Zapper::init()));
+        }
+        Ok(Nes::new(bus))
+    }
+    // Settings that don't depend on the image.
+    fn bus(&self, mapper: DynMapper) -> NesBus<DynMapper> {
+        let mut bus = NesBus::new_with(mapper, &self.power_up);
+        bus.set_ppu_warm_up(self.ppu_warm_up);
+        bus.set_batched_ppu(self.batched_ppu);
+        let [on, off] = self.turbo_rate;
+        bus.input_mut().set_turbo_rate(on, off);
+        bus.input_mut().set_dpad_policy(self.dpad_policy);
+        bus
+    }
+}
+impl Default for EmulatorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EmulatorError {
+    NoRom,
+    Unif(UnifError),
+    Invalid,
+    Mapper(u16),
+    Fds(FdsError),
+    // A disk image was given without the BIOS to boot it.
+    NoBios,
+}
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoRom => write!(f, "no ROM was given"),
+            Self::Unif(err) => write!(f, "{err}"),
+            Self::Invalid => write!(f, "ROM isn't a valid iNES image"),
+            Self::Mapper(mapper) => write!(f, "mapper {mapper} isn't emulated"),
+            Self::Fds(err) => write!(f, "{err}"),
+            Self::NoBios => write!(f, "FDS images need the disk system BIOS"),
+        }
+    }
+}
+impl Error for EmulatorError {}
@@ -0,0 +1,306 @@
+use std::{error::Error, fmt, io};
+
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use nessy::input::Controller;
+
+// How far a stick has to be pushed before it counts as a d-pad press.
+const DEFAULT_DEADZONE: f32 = 0.5;
+
+// Up to two gamepads, the first one connected playing on port 1 and the second on port 2.
+// Pads plugged in later take whichever port is free. If the mapping names a pad for port 2,
+// that one gets port 2 and the others only play on port 1.
+pub struct Gamepads {
+    gilrs: Gilrs,
+    mapping: PadMapping,
+    ports: [Option<GamepadId>; 2],
+    controllers: [Controller; 2],
+}
+impl Gamepads {
+    // Returns None if the platform's gamepad support can't be opened.
+    pub fn init(mapping: PadMapping) -> Option<Self> {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => gilrs,
+            Err(err) => {
+                eprintln!("Running without gamepads: {err}");
+                return None;
+            }
+        };
+        let mut pads = Self {
+            gilrs,
+            mapping,
+            ports: [None; 2],
+            controllers: [Controller(0); 2],
+        };
+        let connected: Vec<GamepadId> = pads.gilrs.gamepads().map(|(id, _)| id).collect();
+        for id in connected {
+            pads.connect(id);
+        }
+        Some(pads)
+    }
+
+    // Handles pending events and hot-plugging. Returns whether any button changed.
+    pub fn poll(&mut self) -> bool {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::Connected => self.connect(event.id),
+                EventType::Disconnected => self.disconnect(event.id),
+                _ => (),
+            }
+        }
+        let controllers = [0, 1].map(|port| self.read(port));
+        let changed = controllers != self.controllers;
+        self.controllers = controllers;
+        changed
+    }
+    pub fn controllers(&self) -> [Controller; 2] {
+        self.controllers
+    }
+
+    fn connect(&mut self, id: GamepadId) {
+        if self.ports.contains(&Some(id)) {
+            return;
+        };
+        let name = self.gilrs.gamepad(id).name().to_string();
+        let port = match &self.mapping.port2 {
+            Some(pad) if pad.matches(id, &name) => 1,
+            Some(_) => 0,
+            None => match self.ports.iter().position(Option::is_none) {
+                Some(port) => port,
+                None => return,
+            },
+        };
+        if self.ports[port].is_some() {
+            return;
+        };
+        self.ports[port] = Some(id);
+        eprintln!("Gamepad {name} plays on port {}", port + 1);
+    }
+    fn disconnect(&mut self, id: GamepadId) {
+        for port in 0..2 {
+            if self.ports[port] == Some(id) {
+                self.ports[port] = None;
+                eprintln!("Gamepad on port {} disconnected", port + 1);
+            }
+        }
+    }
+
+    // The d-pad and the left stick both steer.
+    fn read(&self, port: usize) -> Controller {
+        let mut controller = Controller(0);
+        let Some(pad) = self.ports[port].and_then(|id| self.gilrs.connected_gamepad(id)) else {
+            return controller;
+        };
+        let mapping = &self.mapping;
+        let deadzone = mapping.deadzone;
+        let x = pad.value(Axis::LeftStickX);
+        let y = pad.value(Axis::LeftStickY);
+
+        controller.set_up(pad.is_pressed(Button::DPadUp) || y > deadzone);
+        controller.set_down(pad.is_pressed(Button::DPadDown) || y < -deadzone);
+        controller.set_left(pad.is_pressed(Button::DPadLeft) || x < -deadzone);
+        controller.set_right(pad.is_pressed(Button::DPadRight) || x > deadzone);
+        controller.set_a(pad.is_pressed(mapping.a));
+        controller.set_b(pad.is_pressed(mapping.b));
+        controller.set_select(pad.is_pressed(mapping.select));
+        controller.set_start(pad.is_pressed(mapping.start));
+        controller
+    }
+}
+
+// Which pad buttons act as the NES buttons. Read from a file of `name = value` lines, like
+//
+//     a = East
+//     b = South
+//     deadzone = 0.3
+//     port2 = Xbox Controller
+//
+// where buttons go by their gilrs names, and port2 takes a pad's name or its number, counting
+// from 0 in the order they were connected. Lines starting with # are comments, and anything
+// not mentioned keeps its default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PadMapping {
+    pub a: Button,
+    pub b: Button,
+    pub select: Button,
+    pub start: Button,
+    pub deadzone: f32,
+    pub port2: Option<PadChoice>,
+}
+impl PadMapping {
+    // A and B sit where they do on a NES pad: B on the left, A to its right.
+    pub fn init() -> Self {
+        Self {
+            a: Button::South,
+            b: Button::West,
+            select: Button::Select,
+            start: Button::Start,
+            deadzone: DEFAULT_DEADZONE,
+            port2: None,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, MappingError> {
+        let src = std::fs::read_to_string(path).map_err(MappingError::Io)?;
+        Self::parse(&src)
+    }
+    pub fn parse(src: &str) -> Result<Self, MappingError> {
+        let mut mapping = Self::init();
+        for (index, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            };
+            let number = index + 1;
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(MappingError::Line(number));
+            };
+            let value = value.trim();
+            let button = match key.trim() {
+                "a" => &mut mapping.a,
+                "b" => &mut mapping.b,
+                "select" => &mut mapping.select,
+                "start" => &mut mapping.start,
+                "deadzone" => {
+                    let deadzone = value.parse().map_err(|_| MappingError::Line(number))?;
+                    if !(0.0..1.0).contains(&deadzone) {
+                        return Err(MappingError::Line(number));
+                    };
+                    mapping.deadzone = deadzone;
+                    continue;
+                }
+                "port2" => {
+                    mapping.port2 =
+                        Some(PadChoice::parse(value).ok_or(MappingError::Line(number))?);
+                    continue;
+                }
+                _ => return Err(MappingError::Line(number)),
+            };
+            *button = parse_button(value).ok_or(MappingError::Button(value.to_string()))?;
+        }
+        Ok(mapping)
+    }
+}
+
+// A gamepad picked out in the mapping file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PadChoice {
+    Index(usize),
+    Name(String),
+}
+impl PadChoice {
+    fn parse(value: &str) -> Option<Self> {
+        if value.is_empty() {
+            return None;
+        };
+        let choice = match value.parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(value.to_string()),
+        };
+        Some(choice)
+    }
+    fn matches(&self, id: GamepadId, name: &str) -> bool {
+        match self {
+            Self::Index(index) => usize::from(id) == *index,
+            Self::Name(pad) => pad == name,
+        }
+    }
+}
+
+fn parse_button(name: &str) -> Option<Button> {
+    let button = match name {
+        "South" => Button::South,
+        "East" => Button::East,
+        "North" => Button::North,
+        "West" => Button::West,
+        "C" => Button::C,
+        "Z" => Button::Z,
+        "LeftTrigger" => Button::LeftTrigger,
+        "LeftTrigger2" => Button::LeftTrigger2,
+        "RightTrigger" => Button::RightTrigger,
+        "RightTrigger2" => Button::RightTrigger2,
+        "Select" => Button::Select,
+        "Start" => Button::Start,
+        "Mode" => Button::Mode,
+        "LeftThumb" => Button::LeftThumb,
+        "RightThumb" => Button::RightThumb,
+        _ => return None,
+    };
+    Some(button)
+}
+
+#[derive(Debug)]
+pub enum MappingError {
+    Io(io::Error),
+    // Line numbers, starting at 1.
+    Line(usize),
+    Button(String),
+}
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "can't read gamepad mapping: {err}"),
+            Self::Line(line) => write!(f, "malformed gamepad mapping on line {line}"),
+            Self::Button(name) => write!(f, "{name} isn't a gamepad button"),
+        }
+    }
+}
+impl Error for MappingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        assert_eq!(PadMapping::parse("").unwrap(), PadMapping::init());
+        let src = "# nothing but comments\n\n   # and blank lines\n";
+        assert_eq!(PadMapping::parse(src).unwrap(), PadMapping::init());
+    }
+
+    #[test]
+    fn overrides() {
+        let src = "a = East\n  b=North  \nstart = Mode\ndeadzone = 0.25\nport2 = Xbox Controller\n";
+        let mapping = PadMapping::parse(src).unwrap();
+        assert_eq!(mapping.a, Button::East);
+        assert_eq!(mapping.b, Button::North);
+        assert_eq!(mapping.select, Button::Select);
+        assert_eq!(mapping.start, Button::Mode);
+        assert_eq!(mapping.deadzone, 0.25);
+        assert_eq!(
+            mapping.port2,
+            Some(PadChoice::Name("Xbox Controller".to_string()))
+        );
+
+        let mapping = PadMapping::parse("port2 = 1").unwrap();
+        assert_eq!(mapping.port2, Some(PadChoice::Index(1)));
+    }
+
+    #[test]
+    fn bad_lines() {
+        let src = "a = East\nthis isn't a setting\n";
+        assert!(matches!(PadMapping::parse(src), Err(MappingError::Line(2))));
+        assert!(matches!(
+            PadMapping::parse("turbo = South"),
+            Err(MappingError::Line(1))
+        ));
+        assert!(matches!(
+            PadMapping::parse("port2 ="),
+            Err(MappingError::Line(1))
+        ));
+        match PadMapping::parse("a = Triangle") {
+            Err(MappingError::Button(name)) => assert_eq!(name, "Triangle"),
+            result => panic!("unexpected {result:?}"),
+        }
+    }
+
+    #[test]
+    fn deadzone_out_of_range() {
+        for deadzone in ["1.0", "1.5", "-0.1", "half"] {
+            let src = format!("# comment\ndeadzone = {deadzone}");
+            assert!(matches!(
+                PadMapping::parse(&src),
+                Err(MappingError::Line(2))
+            ));
+        }
+    }
+}
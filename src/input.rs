@@ -1,10 +1,35 @@
-use crate::{nesbus::CpuBus, util::set_flag_u8};
+use self::keyboard::Keyboard;
+use crate::{
+    nesbus::CpuBus,
+    ppu::{
+        pixel_buffer::{HEIGHT, WIDTH},
+        Ppu,
+    },
+    state::{SaveState, StateError, StateReader, StateWriter},
+    util::{get_flag_u8, set_flag_u8},
+};
+
+pub mod keyboard;
 
 pub struct Input {
     controllers: [Controller; 2],
     indices: [u8; 2],
     strobe: bool,
-
+    last_read: Option<usize>,
+    vs_switches: Option<VsSwitches>,
+    swapped_ports: bool,
+    zapper: Option<Zapper>,
+    keyboard: Option<Keyboard>,
+    // States waiting for the next frame to start.
+    pending: [Option<Controller>; 2],
+    // The last states latched, before the D-pad policy cleaned them up.
+    raw: [Controller; 2],
+    dpad_policy: DpadPolicy,
+    // Buttons held down through their turbo key, and those applied last frame.
+    turbo: [Controller; 2],
+    applied_turbo: [Controller; 2],
+    // Frames pressed, then frames released.
+    turbo_rate: [u8; 2],
 }
 impl Input {
     pub fn init() -> Self {
@@ -12,39 +37,80 @@ impl Input {
             controllers: [Controller(0); 2],
             indices: [0; 2],
             strobe: false,
+            last_read: None,
+            vs_switches: None,
+            swapped_ports: false,
+            zapper: None,
+            keyboard: None,
+            pending: [None; 2],
+            raw: [Controller(0); 2],
+            dpad_policy: DpadPolicy::Neutral,
+            turbo: [Controller(0); 2],
+            applied_turbo: [Controller(0); 2],
+            turbo_rate: [2, 2],
         }
     }
 
-    pub fn cycle(&mut self, cpu: &mut CpuBus) {
+    pub fn cycle(&mut self, cpu: &mut CpuBus, ppu: &Ppu) {
         self.strobe();
-        self.handle_cpu(cpu);
+        self.handle_cpu(cpu, ppu);
     }
+    // The shift registers keep reloading while the strobe bit is high, so every read returns A.
     fn strobe(&mut self) {
         if self.strobe {
             self.indices = [0; 2];
         }
     }
 
-    fn handle_cpu(&mut self, cpu: &mut CpuBus) {
+    fn handle_cpu(&mut self, cpu: &mut CpuBus, ppu: &Ppu) {
+        self.clock_shift_registers(cpu);
+
         if !cpu.read() {
             if cpu.address() != 0x4016 {
                 return;
             };
             let strobe = cpu.data() & 1 != 0;
             self.strobe = strobe;
+            if let Some(keyboard) = &mut self.keyboard {
+                keyboard.write(cpu.data());
+            }
         } else {
             if cpu.address() != 0x4016 && cpu.address() != 0x4017 {
                 return;
             };
+            // Only the low bits are driven, the rest are open bus.
+            // Vs. System cabinets put their switches on most of those.
             let port = (cpu.address() % 2) as usize;
-            let index = self.indices[port];
-            if index >= 8 {
-                cpu.set_data(0x41);
+            if let (1, Some(zapper)) = (port, self.zapper) {
+                cpu.set_data(cpu.data() & 0xE0 | zapper.bits(ppu));
                 return;
-            }
-            let bit = self.controllers[port].0 & (1 << index) != 0;
-            cpu.set_data(if bit { 0x41 } else { 0x40 });
-            self.indices[port] += 1;
+            };
+            let open_bus = match self.vs_switches {
+                Some(switches) => (cpu.data() & 0x80) | switches.bits(port),
+                None => cpu.data() & 0xE0,
+            };
+            let index = self.indices[port];
+            let controller = self.controllers[port ^ self.swapped_ports as usize];
+            let bit = index >= 8 || controller.0 & (1 << index) != 0;
+            let keys = match (port, self.keyboard) {
+                (1, Some(keyboard)) => keyboard.bits(),
+                _ => 0,
+            };
+            cpu.set_data(open_bus | keys | bit as u8);
+            self.last_read = Some(port);
+        }
+    }
+    // A shift register is clocked once its port stops being read,
+    // so back-to-back reads (like the halted ones during DMA) only clock it once.
+    fn clock_shift_registers(&mut self, cpu: &CpuBus) {
+        let Some(port) = self.last_read.take() else {
+            return;
+        };
+        let reading = cpu.read() && cpu.address() == 0x4016 + port as u16;
+        if reading {
+            self.last_read = Some(port);
+        } else if !self.strobe {
+            self.indices[port] = (self.indices[port] + 1).min(8);
         }
     }
 
@@ -54,11 +120,260 @@ impl Input {
     pub fn controller_mut(&mut self, controller: u8) -> &mut Controller {
         &mut self.controllers[controller as usize]
     }
+
+    // Takes effect when the PPU starts the next frame, so a game sees the same input all frame
+    // no matter when the frontend sends it. The last state sent before then wins.
+    pub fn set_controller(&mut self, port: usize, state: ControllerState) {
+        self.pending[port] = Some(state.into());
+    }
+    pub fn latch_controllers(&mut self) {
+        for port in 0..2 {
+            if let Some(state) = self.pending[port].take() {
+                let controller = &mut self.controllers[port];
+                *controller = self.dpad_policy.apply(state, self.raw[port], *controller);
+                self.raw[port] = state;
+            }
+        }
+    }
+    // How states given to set_controller that hold opposite directions are cleaned up.
+    pub fn set_dpad_policy(&mut self, policy: DpadPolicy) {
+        self.dpad_policy = policy;
+    }
+
+    // Buttons set here are pressed and released on their own, following the turbo rate.
+    pub fn turbo_mut(&mut self) -> &mut [Controller; 2] {
+        &mut self.turbo
+    }
+    pub fn set_turbo_rate(&mut self, frames_on: u8, frames_off: u8) {
+        assert!(frames_on > 0 && frames_off > 0);
+        self.turbo_rate = [frames_on, frames_off];
+    }
+    // Called at the start of every frame. Going by the frame counter rather than by when
+    // the key went down keeps it the same on every run, so movies can record it.
+    pub fn apply_turbo(&mut self, frame: u64) {
+        let [on, off] = self.turbo_rate.map(u64::from);
+        let pressed = frame % (on + off) < on;
+        for port in 0..2 {
+            // Buttons whose turbo key was let go are released, the others follow the rate.
+            let turbo = self.turbo[port].0;
+            let mask = turbo | self.applied_turbo[port].0;
+            let bits = if pressed { turbo } else { 0 };
+            self.controllers[port].0 = self.controllers[port].0 & !mask | bits;
+        }
+        self.applied_turbo = self.turbo;
+    }
+
+    pub fn set_vs_switches(&mut self, switches: Option<VsSwitches>) {
+        self.vs_switches = switches;
+    }
+    pub fn vs_switches_mut(&mut self) -> Option<&mut VsSwitches> {
+        self.vs_switches.as_mut()
+    }
+    // Connects controller 1 to $4017 and controller 2 to $4016, like some Vs. System cabinets.
+    pub fn set_swapped_ports(&mut self, swapped: bool) {
+        self.swapped_ports = swapped;
+    }
+    // A Zapper takes the place of controller 2.
+    pub fn set_zapper(&mut self, zapper: Option<Zapper>) {
+        self.zapper = zapper;
+    }
+    pub fn zapper_mut(&mut self) -> Option<&mut Zapper> {
+        self.zapper.as_mut()
+    }
+    pub fn has_zapper(&self) -> bool {
+        self.zapper.is_some()
+    }
+    // The Family BASIC keyboard shares $4017 with controller 2, on the bits above it.
+    pub fn set_keyboard(&mut self, keyboard: Option<Keyboard>) {
+        self.keyboard = keyboard;
+    }
+    pub fn keyboard_mut(&mut self) -> Option<&mut Keyboard> {
+        self.keyboard.as_mut()
+    }
+    pub fn has_keyboard(&self) -> bool {
+        self.keyboard.is_some()
+    }
+}
+
+// The cabinet switches, the Zapper and the keyboard are configuration and stay as they are.
+impl SaveState for Input {
+    fn save_state(&self, out: &mut StateWriter) {
+        for (controller, index) in self.controllers.iter().zip(self.indices) {
+            out.u8(controller.0);
+            out.u8(index);
+        }
+        out.bool(self.strobe);
+        out.bool(self.last_read.is_some());
+        out.u8(self.last_read.unwrap_or(0) as u8);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        for (controller, index) in self.controllers.iter_mut().zip(&mut self.indices) {
+            controller.0 = state.u8()?;
+            *index = state.u8()?;
+        }
+        self.strobe = state.bool()?;
+        let reading = state.bool()?;
+        let port = state.u8()? as usize;
+        if self.indices.iter().any(|&index| index > 8) || port > 1 {
+            return Err(StateError::Invalid);
+        };
+        self.last_read = reading.then_some(port);
+        Ok(())
+    }
+}
+
+// The coin slots, service button and DIP switches of a Vs. System cabinet.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VsSwitches {
+    pub dip: u8,
+    pub coins: [bool; 2],
+    pub service: bool,
+}
+impl VsSwitches {
+    fn bits(self, port: usize) -> u8 {
+        if port == 0 {
+            let service = (self.service as u8) << 2;
+            let dip = (self.dip & 0b11) << 3;
+            let coins = (self.coins[0] as u8) << 5 | (self.coins[1] as u8) << 6;
+            service | dip | coins
+        } else {
+            self.dip & 0xFC
+        }
+    }
+}
+
+// The light gun, read through $4017. Its photodiode stays lit for a while after the beam
+// passes a bright spot, so light is seen for this many lines after the one aimed at.
+const LIGHT_LINES: u16 = 20;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Zapper {
+    // None while it's aimed away from the screen.
+    position: Option<[u8; 2]>,
+    trigger: bool,
+}
+impl Zapper {
+    pub fn init() -> Self {
+        Self {
+            position: None,
+            trigger: false,
+        }
+    }
+
+    // Lines below the picture are off the screen, the same as aiming away.
+    pub fn set_position(&mut self, x: u8, y: u8) {
+        self.position = (usize::from(y) < HEIGHT).then_some([x, y]);
+    }
+    pub fn aim_away(&mut self) {
+        self.position = None;
+    }
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+
+    // Bit 3 is clear while light is seen, bit 4 is set while the trigger is pulled.
+    fn bits(self, ppu: &Ppu) -> u8 {
+        let dark = !self.senses_light(ppu) as u8;
+        dark << 3 | (self.trigger as u8) << 4
+    }
+    // Only pixels drawn recently count, those further down the screen are from the last frame.
+    pub fn senses_light(self, ppu: &Ppu) -> bool {
+        let Some([x, y]) = self.position else {
+            return false;
+        };
+        let (x, y) = (x as u16, y as u16);
+        let [dot, line] = ppu.dot();
+        // Pixels come out a dot behind the one the PPU is on.
+        let drawn = y < line || (y == line && x + 1 < dot);
+        if !drawn || line - y > LIGHT_LINES {
+            return false;
+        };
+        let color = ppu.pixels().0[y as usize * WIDTH + x as usize] as u8 & 0x3F;
+        bright(color)
+    }
+}
+// The lighter half of the palette, leaving out the greys and blacks at the end of each row.
+fn bright(color: u8) -> bool {
+    color >= 0x20 && color & 0x0F <= 0x0C
 }
 
+// A real pad's rocker can't press opposite directions at once, and some games break when they
+// see it, which a keyboard makes easy to do.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DpadPolicy {
+    // Both directions are let go.
+    Neutral,
+    // The direction pressed most recently wins. If both went down at once, both are let go.
+    LastWins,
+    Allow,
+}
+impl DpadPolicy {
+    // `raw_previous` is the state given before this one, as it came in, to tell which direction
+    // is the new one. `previous` is what the game saw, whose direction stays while both are held.
+    pub fn apply(
+        self,
+        state: Controller,
+        raw_previous: Controller,
+        previous: Controller,
+    ) -> Controller {
+        let mut state = state;
+        let axes = [
+            [Controller::UP, Controller::DOWN],
+            [Controller::LEFT, Controller::RIGHT],
+        ];
+        for [first, second] in axes {
+            let held =
+                |controller: Controller| [first, second].map(|bit| get_flag_u8(controller.0, bit));
+            if held(state) != [true, true] {
+                continue;
+            };
+            let [keep_first, keep_second] = match self {
+                Self::Allow => continue,
+                Self::Neutral => [false, false],
+                Self::LastWins => match held(raw_previous) {
+                    [false, true] => [true, false],
+                    [true, false] => [false, true],
+                    [true, true] => held(previous),
+                    [false, false] => [false, false],
+                },
+            };
+            set_flag_u8(&mut state.0, first, keep_first);
+            set_flag_u8(&mut state.0, second, keep_second);
+        }
+        state
+    }
+}
+
+// The buttons of one controller, one bit each from A in bit 0 up to Right in bit 7.
+// The setters change them one at a time, as keys go up and down.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Controller(pub u8);
 impl Controller {
+    pub fn a(self) -> bool {
+        get_flag_u8(self.0, Self::A)
+    }
+    pub fn b(self) -> bool {
+        get_flag_u8(self.0, Self::B)
+    }
+    pub fn select(self) -> bool {
+        get_flag_u8(self.0, Self::SELECT)
+    }
+    pub fn start(self) -> bool {
+        get_flag_u8(self.0, Self::START)
+    }
+    pub fn up(self) -> bool {
+        get_flag_u8(self.0, Self::UP)
+    }
+    pub fn down(self) -> bool {
+        get_flag_u8(self.0, Self::DOWN)
+    }
+    pub fn left(self) -> bool {
+        get_flag_u8(self.0, Self::LEFT)
+    }
+    pub fn right(self) -> bool {
+        get_flag_u8(self.0, Self::RIGHT)
+    }
+
     pub fn set_a(&mut self, a: bool) {
         set_flag_u8(&mut self.0, Self::A, a)
     }
@@ -93,3 +408,44 @@ impl Controller {
     const LEFT: u8 = 6;
     const RIGHT: u8 = 7;
 }
+
+// All of a controller's buttons at once, for handing whole states to Input::set_controller.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ControllerState {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+impl From<ControllerState> for Controller {
+    fn from(state: ControllerState) -> Self {
+        let mut controller = Controller(0);
+        controller.set_a(state.a);
+        controller.set_b(state.b);
+        controller.set_select(state.select);
+        controller.set_start(state.start);
+        controller.set_up(state.up);
+        controller.set_down(state.down);
+        controller.set_left(state.left);
+        controller.set_right(state.right);
+        controller
+    }
+}
+impl From<Controller> for ControllerState {
+    fn from(controller: Controller) -> Self {
+        Self {
+            a: controller.a(),
+            b: controller.b(),
+            select: controller.select(),
+            start: controller.start(),
+            up: controller.up(),
+            down: controller.down(),
+            left: controller.left(),
+            right: controller.right(),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// The Family BASIC keyboard, on the expansion port. Writes to $4016 pick a row and one of its
+// two columns, and $4017 reads the four keys there on bits 1 to 4, low while pressed.
+const ROWS: usize = 9;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Keyboard {
+    // Bits 1 to 4 of each column, set while the key is down.
+    pressed: [[u8; 2]; ROWS],
+    row: u8,
+    column: u8,
+    enabled: bool,
+}
+impl Keyboard {
+    pub fn init() -> Self {
+        Self {
+            pressed: [[0; 2]; ROWS],
+            row: 0,
+            column: 0,
+            enabled: false,
+        }
+    }
+
+    pub fn set_key(&mut self, key: Key, pressed: bool) {
+        let (row, column, bit) = key.position();
+        let keys = &mut self.pressed[row][column];
+        if pressed {
+            *keys |= bit;
+        } else {
+            *keys &= !bit;
+        }
+    }
+    pub fn release_all(&mut self) {
+        self.pressed = [[0; 2]; ROWS];
+    }
+
+    // Bit 0 goes back to the first row, bit 1 selects the column, and bit 2 enables the matrix.
+    // Dropping from column 1 to column 0 moves on to the next row.
+    pub(super) fn write(&mut self, data: u8) {
+        let column = data >> 1 & 1;
+        if self.column == 1 && column == 0 {
+            // A decade counter picks the row, so there are ten of them, the last without keys.
+            self.row = (self.row + 1) % 10;
+        };
+        if data & 1 != 0 {
+            self.row = 0;
+        };
+        self.column = column;
+        self.enabled = data & 4 != 0;
+    }
+    // A disabled matrix reads as all zeros, the row past the last one as nothing pressed.
+    pub(super) fn bits(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        };
+        let pressed = self
+            .pressed
+            .get(self.row as usize)
+            .map_or(0, |row| row[self.column as usize]);
+        !pressed & 0x1E
+    }
+}
+
+// In the order of the matrix: each row has four keys in column 0 then four in column 1,
+// from bit 1 up to bit 4.
+#[rustfmt::skip]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    RightBracket, LeftBracket, Return, F8, Stop, Yen, RightShift, Kana,
+    Semicolon, Colon, At, F7, Caret, Minus, Slash, Underscore,
+    K, L, O, F6, Digit0, P, Comma, Period,
+    J, U, I, F5, Digit8, Digit9, N, M,
+    H, G, Y, F4, Digit6, Digit7, V, B,
+    D, R, T, F3, Digit4, Digit5, C, F,
+    A, S, W, F2, Digit3, E, Z, X,
+    Ctr, Q, Escape, F1, Digit2, Digit1, Grph, LeftShift,
+    Left, Right, Up, ClrHome, Ins, Del, Space, Down,
+}
+impl Key {
+    // Row, column and the bit the key shows up on.
+    pub fn position(self) -> (usize, usize, u8) {
+        let index = self as usize;
+        (index / 8, index / 4 % 2, 2 << (index % 4))
+    }
+}
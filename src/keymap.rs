@@ -0,0 +1,63 @@
+use nessy::input::Controller;
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+// Which keys press controller 1's buttons. Keys go by where they sit, not what they're labeled,
+// so Z and X stay next to each other on any layout.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Keymap {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub a: KeyCode,
+    pub b: KeyCode,
+    pub select: KeyCode,
+    pub start: KeyCode,
+}
+impl Keymap {
+    // B sits left of A, like on the pad.
+    pub fn init() -> Self {
+        Self {
+            up: KeyCode::ArrowUp,
+            down: KeyCode::ArrowDown,
+            left: KeyCode::ArrowLeft,
+            right: KeyCode::ArrowRight,
+            a: KeyCode::KeyX,
+            b: KeyCode::KeyZ,
+            select: KeyCode::ShiftRight,
+            start: KeyCode::Enter,
+        }
+    }
+
+    pub fn button(&self, key: PhysicalKey) -> Option<fn(&mut Controller, bool)> {
+        let PhysicalKey::Code(code) = key else {
+            return None;
+        };
+        let button: fn(&mut Controller, bool) = match code {
+            code if code == self.up => Controller::set_up,
+            code if code == self.down => Controller::set_down,
+            code if code == self.left => Controller::set_left,
+            code if code == self.right => Controller::set_right,
+            code if code == self.a => Controller::set_a,
+            code if code == self.b => Controller::set_b,
+            code if code == self.select => Controller::set_select,
+            code if code == self.start => Controller::set_start,
+            _ => return None,
+        };
+        Some(button)
+    }
+
+    // Returns whether the key was one of the buttons. Repeats of a held key change nothing.
+    pub fn apply(&self, controller: &mut Controller, event: &KeyEvent) -> bool {
+        let Some(button) = self.button(event.physical_key) else {
+            return false;
+        };
+        if !event.repeat {
+            button(controller, event.state == ElementState::Pressed);
+        }
+        true
+    }
+}
@@ -6,11 +6,25 @@ use cpu_6502::{instruction::decode, Cpu};
 use mapper::MapperBus;
 use nesbus::CpuBus;
 use ppu::{Ppu, PpuBus};
+pub mod cheats;
+pub mod debugger;
+pub mod disasm;
+pub mod emulator;
 pub mod input;
 pub mod mapper;
+pub mod movie;
+pub mod nes;
 pub mod nesbus;
+pub mod palette;
+pub mod player;
 pub mod ppu;
+pub mod region;
 pub mod apu;
+pub mod rom;
+pub mod saves;
+pub mod state;
+pub mod test_rom;
+pub mod trace;
 mod util;
 
 pub fn simple_debug(
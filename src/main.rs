@@ -1,59 +1,163 @@
 use app::App;
-use nessy::input::Controller;
+use args::{Args, USAGE};
+use audio::Audio;
+use gamepad::{Gamepads, PadMapping};
+use keymap::Keymap;
+use nessy::{
+    apu::Channel,
+    input::{keyboard::Key, Controller, DpadPolicy, Input},
+    nesbus::{NesBus, PowerUpState},
+    palette::Palette,
+    ppu::pixel_buffer::{HEIGHT, WIDTH},
+};
 use renderer::Renderer;
-use std::sync::Arc;
-use std::time::Duration;
-use std::time::Instant;
+use std::process;
+use std::sync::{atomic::Ordering, Arc};
+use std::time::{SystemTime, UNIX_EPOCH};
 use winit::{
-    event::{ElementState, Event, WindowEvent},
-    event_loop::ControlFlow,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, KeyEvent, MouseButton, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
 };
+use worker::Worker;
 
-const ROM_FILE: &str = "roms/SuperMarioBros.nes";
+// Ignores PPU writes right after power on, which some test ROMs and a few games rely on.
+const PPU_WARM_UP: bool = false;
+// Runs the PPU in batches behind the CPU, drawing untouched lines in one go. Same picture, faster.
+const BATCHED_PPU: bool = false;
+// What RAM starts out as. Real hardware is closer to PowerUpState::Random.
+const POWER_UP_RAM: PowerUpState = PowerUpState::Zeroed;
+// A nes20db XML file whose headers replace the ones of images it knows.
+const HEADER_DB_FILE: Option<&str> = None;
+// The Famicom Disk System BIOS, needed to run .fds disk images.
+const FDS_BIOS_FILE: &str = "roms/disksys.rom";
+// Battery backed cartridge RAM is kept here, one file per game, unless --save-dir says otherwise.
+const SAVE_DIR: &str = "saves";
+// Frames pressed and frames released for the turbo buttons.
+const TURBO_RATE: [u8; 2] = [2, 2];
+// What opposite directions held at once turn into. Some games glitch if they see both.
+const DPAD_POLICY: DpadPolicy = DpadPolicy::Neutral;
+// Which gamepad buttons act as A, B, Select and Start, and the stick's deadzone.
+// See PadMapping for the format.
+const GAMEPAD_FILE: Option<&str> = None;
+// Plugs a Zapper into port 2, aimed with the mouse. NES 2.0 images that ask for one get it anyway.
+const ZAPPER: bool = false;
 
 mod app;
+mod args;
+mod audio;
+mod gamepad;
+mod keymap;
 mod renderer;
+mod worker;
 
 fn main() {
     env_logger::init();
 
-    let (mut app, ev_loop) = App::init();
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n\n{USAGE}");
+        process::exit(2);
+    });
+    let (app, audio, ev_loop) = App::init(&args);
     let window = Arc::clone(&app.window);
-    let mut renderer = Renderer::init(Arc::clone(&window));
-
-    let nes_frame_time = Duration::from_secs_f64(1.0 / 60.0);
-    let mut last_nes_frame = Instant::now();
+    let palette = load_palette(args.palette.as_deref());
+    let mut renderer = Renderer::init(Arc::clone(&window), &palette);
+    let keymap = Keymap::init();
+    let mut controllers = [Controller(0); 2];
+    let mut turbo = [Controller(0); 2];
+    let mut pads = Gamepads::init(load_pad_mapping());
+    // Whether keys go to the Family BASIC keyboard instead of controllers and hotkeys.
+    let mut typing = false;
+    let has_keyboard = Arc::clone(&app.has_keyboard);
+    let mut worker = Worker::spawn(app);
 
+    // The worker asks for a redraw whenever it has a new picture,
+    // which also gets gamepads polled about once a frame.
     let res = ev_loop.run(move |ev, loop_target| match ev {
         Event::WindowEvent { event, .. } => {
             renderer.window_event(&event);
             match event {
                 WindowEvent::CloseRequested => {
+                    worker.stop();
                     loop_target.exit();
                 }
                 WindowEvent::KeyboardInput { event, .. } => {
-                    handle_keyboard(app.nesbus.controllers_mut(), event)
+                    // A game dropped on the window since typing started may have no keyboard.
+                    let keyboard = has_keyboard.load(Ordering::Relaxed);
+                    typing &= keyboard;
+                    if toggle_typing(&mut typing, keyboard, &event) {
+                        // Keys held as buttons would never see their release while typing.
+                        if typing {
+                            controllers = [Controller(0); 2];
+                            turbo = [Controller(0); 2];
+                            worker.set_controllers(merge_pads(controllers, pads.as_ref()), turbo);
+                        };
+                        worker.send(move |app| release_family_keys(app, typing));
+                        return;
+                    };
+                    if typing {
+                        worker.send(move |app| handle_family_keyboard(app, &event));
+                        return;
+                    };
+                    handle_volume_keyboard(audio.as_ref(), &event);
+                    if keymap.apply(&mut controllers[0], &event)
+                        || handle_turbo_keyboard(&mut turbo, &event)
+                    {
+                        if !event.repeat {
+                            worker.set_controllers(merge_pads(controllers, pads.as_ref()), turbo);
+                        };
+                    } else {
+                        worker.send(move |app| handle_app_keyboard(app, &event));
+                    }
+                }
+                // Keys let go while another window has focus never tell us, so they'd stick.
+                WindowEvent::Focused(false) => {
+                    controllers = [Controller(0); 2];
+                    turbo = [Controller(0); 2];
+                    worker.set_controllers(merge_pads(controllers, pads.as_ref()), turbo);
+                    worker.send(|app| {
+                        if let Some(keyboard) = app.nes.bus.input_mut().keyboard_mut() {
+                            keyboard.release_all();
+                        }
+                    });
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    let size = window.inner_size();
+                    worker.send(move |app| {
+                        aim_zapper(app.nes.bus.input_mut(), Some((position, size)));
+                    });
+                }
+                WindowEvent::CursorLeft { .. } => {
+                    worker.send(|app| aim_zapper(app.nes.bus.input_mut(), None));
+                }
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => worker.send(move |app| {
+                    if let Some(zapper) = app.nes.bus.input_mut().zapper_mut() {
+                        zapper.set_trigger(state == ElementState::Pressed);
+                    }
+                }),
+                WindowEvent::DroppedFile(path) => {
+                    let path = path.to_string_lossy().into_owned();
+                    worker.send(move |app| {
+                        if let Err(err) = app.load_rom(&path) {
+                            eprintln!("{err}, keeping the current game");
+                        }
+                    });
                 }
                 WindowEvent::RedrawRequested => {
-                    for _ in 0..5 {
-                        if last_nes_frame.elapsed() < nes_frame_time {
-                            break;
-                        };
-                        last_nes_frame += nes_frame_time;
-                        app.run_nes_until_vsync();
+                    if let Some(pixels) = worker.latest_frame() {
+                        renderer.upload_pixels(&pixels);
                     }
-
-                    let pixels = app.nesbus.ppu().pixels();
-                    renderer.upload_pixels(pixels);
                     renderer.render();
-                    loop_target.set_control_flow(ControlFlow::Poll);
                 }
                 _ => (),
             }
         }
-        Event::AboutToWait => {
-            app.window.request_redraw();
+        Event::AboutToWait if pads.as_mut().is_some_and(Gamepads::poll) => {
+            worker.set_controllers(merge_pads(controllers, pads.as_ref()), turbo);
         }
         _ => (),
     });
@@ -61,24 +165,285 @@ fn main() {
     res.unwrap();
 }
 
-fn handle_keyboard(inputs: &mut [Controller; 2], input: winit::event::KeyEvent) {
-    let keycode = input.physical_key;
-    let function = match keycode {
-        PhysicalKey::Code(KeyCode::KeyI) => Controller::set_up,
-        PhysicalKey::Code(KeyCode::KeyK) => Controller::set_down,
-        PhysicalKey::Code(KeyCode::KeyJ) => Controller::set_left,
-        PhysicalKey::Code(KeyCode::KeyL) => Controller::set_right,
-        PhysicalKey::Code(KeyCode::KeyD) => Controller::set_a,
-        PhysicalKey::Code(KeyCode::KeyF) => Controller::set_b,
-        PhysicalKey::Code(KeyCode::KeyS) => Controller::set_select,
-        PhysicalKey::Code(KeyCode::Enter) => Controller::set_start,
+fn load_palette(path: Option<&str>) -> Palette {
+    let Some(path) = path else {
+        return Palette::init();
+    };
+    Palette::load(path).unwrap_or_else(|err| {
+        eprintln!("Using the built-in palette: {err}");
+        Palette::init()
+    })
+}
+
+fn load_pad_mapping() -> PadMapping {
+    let Some(path) = GAMEPAD_FILE else {
+        return PadMapping::init();
+    };
+    PadMapping::load(path).unwrap_or_else(|err| {
+        eprintln!("Using the default gamepad mapping: {err}");
+        PadMapping::init()
+    })
+}
+
+// Buttons count as pressed if either the keyboard or the pad on that port presses them.
+fn merge_pads(keys: [Controller; 2], pads: Option<&Gamepads>) -> [Controller; 2] {
+    let Some(pads) = pads else {
+        return keys;
+    };
+    let pads = pads.controllers();
+    [0, 1].map(|port| Controller(keys[port].0 | pads[port].0))
+}
+
+fn handle_turbo_keyboard(turbo: &mut [Controller; 2], input: &KeyEvent) -> bool {
+    let function = match input.physical_key {
+        PhysicalKey::Code(KeyCode::KeyE) => Controller::set_a,
+        PhysicalKey::Code(KeyCode::KeyW) => Controller::set_b,
+        _ => return false,
+    };
+    if !input.repeat {
+        function(&mut turbo[0], input.state == ElementState::Pressed);
+    }
+    true
+}
+
+// Scroll Lock switches typing on and off, since typing would set off every hotkey otherwise.
+// Without a keyboard to type on, it stays off.
+fn toggle_typing(typing: &mut bool, has_keyboard: bool, event: &KeyEvent) -> bool {
+    let toggle = event.physical_key == PhysicalKey::Code(KeyCode::ScrollLock);
+    if toggle && event.state == ElementState::Pressed && !event.repeat {
+        if has_keyboard {
+            *typing = !*typing;
+        } else {
+            eprintln!("No Family BASIC keyboard connected");
+        }
+    };
+    toggle
+}
+fn release_family_keys(app: &mut App, typing: bool) {
+    let Some(keyboard) = app.nes.bus.input_mut().keyboard_mut() else {
+        return;
+    };
+    keyboard.release_all();
+    eprintln!("Typing {}", if typing { "on" } else { "off" });
+}
+
+// Keys sit where they are on a Japanese layout, so symbols land on the keys printed with them.
+fn handle_family_keyboard(app: &mut App, event: &KeyEvent) {
+    let Some(keyboard) = app.nes.bus.input_mut().keyboard_mut() else {
+        return;
+    };
+    let PhysicalKey::Code(code) = event.physical_key else {
+        return;
+    };
+    let key = match code {
+        KeyCode::KeyA => Key::A,
+        KeyCode::KeyB => Key::B,
+        KeyCode::KeyC => Key::C,
+        KeyCode::KeyD => Key::D,
+        KeyCode::KeyE => Key::E,
+        KeyCode::KeyF => Key::F,
+        KeyCode::KeyG => Key::G,
+        KeyCode::KeyH => Key::H,
+        KeyCode::KeyI => Key::I,
+        KeyCode::KeyJ => Key::J,
+        KeyCode::KeyK => Key::K,
+        KeyCode::KeyL => Key::L,
+        KeyCode::KeyM => Key::M,
+        KeyCode::KeyN => Key::N,
+        KeyCode::KeyO => Key::O,
+        KeyCode::KeyP => Key::P,
+        KeyCode::KeyQ => Key::Q,
+        KeyCode::KeyR => Key::R,
+        KeyCode::KeyS => Key::S,
+        KeyCode::KeyT => Key::T,
+        KeyCode::KeyU => Key::U,
+        KeyCode::KeyV => Key::V,
+        KeyCode::KeyW => Key::W,
+        KeyCode::KeyX => Key::X,
+        KeyCode::KeyY => Key::Y,
+        KeyCode::KeyZ => Key::Z,
+        KeyCode::Digit0 => Key::Digit0,
+        KeyCode::Digit1 => Key::Digit1,
+        KeyCode::Digit2 => Key::Digit2,
+        KeyCode::Digit3 => Key::Digit3,
+        KeyCode::Digit4 => Key::Digit4,
+        KeyCode::Digit5 => Key::Digit5,
+        KeyCode::Digit6 => Key::Digit6,
+        KeyCode::Digit7 => Key::Digit7,
+        KeyCode::Digit8 => Key::Digit8,
+        KeyCode::Digit9 => Key::Digit9,
+        KeyCode::F1 => Key::F1,
+        KeyCode::F2 => Key::F2,
+        KeyCode::F3 => Key::F3,
+        KeyCode::F4 => Key::F4,
+        KeyCode::F5 => Key::F5,
+        KeyCode::F6 => Key::F6,
+        KeyCode::F7 => Key::F7,
+        KeyCode::F8 => Key::F8,
+        KeyCode::Minus => Key::Minus,
+        KeyCode::Equal => Key::Caret,
+        KeyCode::IntlYen => Key::Yen,
+        KeyCode::BracketLeft => Key::At,
+        KeyCode::BracketRight => Key::LeftBracket,
+        KeyCode::Backslash => Key::RightBracket,
+        KeyCode::Semicolon => Key::Semicolon,
+        KeyCode::Quote => Key::Colon,
+        KeyCode::Comma => Key::Comma,
+        KeyCode::Period => Key::Period,
+        KeyCode::Slash => Key::Slash,
+        KeyCode::IntlRo => Key::Underscore,
+        KeyCode::Enter => Key::Return,
+        KeyCode::Space => Key::Space,
+        KeyCode::Escape => Key::Escape,
+        KeyCode::ControlLeft => Key::Ctr,
+        KeyCode::AltLeft => Key::Grph,
+        KeyCode::AltRight | KeyCode::KanaMode => Key::Kana,
+        KeyCode::ShiftLeft => Key::LeftShift,
+        KeyCode::ShiftRight => Key::RightShift,
+        KeyCode::Pause => Key::Stop,
+        KeyCode::Home => Key::ClrHome,
+        KeyCode::Insert => Key::Ins,
+        KeyCode::Backspace | KeyCode::Delete => Key::Del,
+        KeyCode::ArrowUp => Key::Up,
+        KeyCode::ArrowDown => Key::Down,
+        KeyCode::ArrowLeft => Key::Left,
+        KeyCode::ArrowRight => Key::Right,
+        _ => return,
+    };
+    keyboard.set_key(key, event.state == ElementState::Pressed);
+}
+
+// Keys handled on the worker thread, since they act on the emulator itself.
+fn handle_app_keyboard(app: &mut App, event: &KeyEvent) {
+    handle_vs_keyboard(app.nes.bus.input_mut(), event);
+    handle_channel_keyboard(&mut app.nes.bus, event);
+    handle_recording_keyboard(app, event);
+    handle_reset_keyboard(app, event);
+    handle_disk_keyboard(app, event);
+    handle_state_keyboard(app, event);
+}
+
+// The picture is stretched over the whole window.
+fn aim_zapper(input: &mut Input, cursor: Option<(PhysicalPosition<f64>, PhysicalSize<u32>)>) {
+    let Some(zapper) = input.zapper_mut() else {
+        return;
+    };
+    let Some((position, size)) = cursor else {
+        zapper.aim_away();
+        return;
+    };
+    let x = position.x / size.width as f64 * WIDTH as f64;
+    let y = position.y / size.height as f64 * HEIGHT as f64;
+    if (0.0..WIDTH as f64).contains(&x) && (0.0..HEIGHT as f64).contains(&y) {
+        zapper.set_position(x as u8, y as u8);
+    } else {
+        zapper.aim_away();
+    }
+}
+
+fn handle_vs_keyboard(input: &mut Input, event: &KeyEvent) {
+    let Some(switches) = input.vs_switches_mut() else {
+        return;
+    };
+    let pressed = event.state == ElementState::Pressed;
+
+    match event.physical_key {
+        PhysicalKey::Code(KeyCode::KeyC) => switches.coins[0] = pressed,
+        PhysicalKey::Code(KeyCode::KeyV) => switches.coins[1] = pressed,
+        PhysicalKey::Code(KeyCode::KeyB) => switches.service = pressed,
+        _ => (),
+    }
+}
+
+fn handle_volume_keyboard(audio: Option<&Audio>, event: &KeyEvent) {
+    let Some(audio) = audio else {
+        return;
+    };
+    if event.state != ElementState::Pressed {
+        return;
+    };
+
+    let step = match event.physical_key {
+        PhysicalKey::Code(KeyCode::Minus) => -0.1,
+        PhysicalKey::Code(KeyCode::Equal) => 0.1,
+        _ => return,
+    };
+    audio.set_volume(audio.volume() + step);
+}
+
+fn handle_channel_keyboard<M>(nes: &mut NesBus<M>, event: &KeyEvent) {
+    if event.state != ElementState::Pressed || event.repeat {
+        return;
+    };
+
+    let channel = match event.physical_key {
+        PhysicalKey::Code(KeyCode::Digit1) => Channel::Pulse1,
+        PhysicalKey::Code(KeyCode::Digit2) => Channel::Pulse2,
+        PhysicalKey::Code(KeyCode::Digit3) => Channel::Triangle,
+        PhysicalKey::Code(KeyCode::Digit4) => Channel::Noise,
+        PhysicalKey::Code(KeyCode::Digit5) => Channel::Dmc,
         _ => return,
     };
+    let enabled = !nes.channel_enabled(channel);
+    nes.set_channel_enabled(channel, enabled);
+    eprintln!("{channel:?} {}", if enabled { "enabled" } else { "muted" });
+}
+
+fn handle_reset_keyboard(app: &mut App, event: &KeyEvent) {
+    if event.state != ElementState::Pressed || event.repeat {
+        return;
+    };
+    match event.physical_key {
+        PhysicalKey::Code(KeyCode::Backspace) => app.reset(),
+        PhysicalKey::Code(KeyCode::Delete) => app.power_cycle(),
+        _ => (),
+    }
+}
+
+fn handle_disk_keyboard(app: &mut App, event: &KeyEvent) {
+    if event.state != ElementState::Pressed || event.repeat {
+        return;
+    };
+    if event.physical_key == PhysicalKey::Code(KeyCode::Insert) {
+        app.flip_disk();
+    }
+}
 
-    let state = match input.state {
-        ElementState::Pressed => true,
-        ElementState::Released => false,
+fn handle_state_keyboard(app: &mut App, event: &KeyEvent) {
+    if event.state != ElementState::Pressed || event.repeat {
+        return;
     };
+    match event.physical_key {
+        PhysicalKey::Code(KeyCode::F5) => app.save_state(),
+        PhysicalKey::Code(KeyCode::F7) => app.load_state(),
+        PhysicalKey::Code(KeyCode::F9) => app.toggle_movie_recording(),
+        PhysicalKey::Code(KeyCode::F10) => app.play_movie(),
+        _ => (),
+    }
+}
 
-    function(&mut inputs[0], state);
+fn handle_recording_keyboard(app: &mut App, event: &KeyEvent) {
+    if event.state != ElementState::Pressed || event.repeat {
+        return;
+    };
+    if event.physical_key != PhysicalKey::Code(KeyCode::KeyR) {
+        return;
+    };
+    let Some(audio) = &mut app.audio else {
+        eprintln!("Can't record without an audio device");
+        return;
+    };
+
+    let result = if audio.recording() {
+        eprintln!("Stopped recording");
+        audio.stop_recording()
+    } else {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let path = format!("recording-{}.wav", time.as_secs());
+        eprintln!("Recording to {path}");
+        audio.start_recording(path)
+    };
+    if let Err(err) = result {
+        eprintln!("Recording failed: {err}");
+    }
 }
@@ -1,16 +1,117 @@
-use self::mapper0::Mapper0;
+use self::{fds::FdsSystem, mapper0::Mapper0, mapper24::Mapper24, mapper99::Mapper99};
 use crate::{
     nesbus::CpuBus,
     ppu::PpuBus,
+    state::{SaveState, StateError, StateReader, StateWriter},
     util::{get_flag_u8, set_flag_u8},
 };
 use nes_rom_parser::Rom;
 
+pub mod fds;
 pub mod mapper0;
+pub mod mapper24;
+pub mod mapper99;
+pub mod nsf;
 
+// With the PPU batched, cycle_with_ppu isn't called on every dot: idle dots in vblank are
+// skipped, and a line drawn at once only calls it for each fetch, in order, and once after.
+// Boards must answer whatever is on the PPU bus without counting dots or keeping time by them.
+// Anything timed has to run off cycle, which sees every CPU cycle.
 pub trait Mapper {
     fn cycle(&mut self, bus: &mut MapperBus, cpu: &mut CpuBus, ppu: &mut PpuBus);
     fn cycle_with_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus);
+
+    // Output level of any expansion audio on the cartridge, summed into the APU mix.
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    // Copies a 512 byte trainer to $7000-$71FF. Boards without PRG RAM there ignore it.
+    fn load_trainer(&mut self, _trainer: &[u8]) {}
+
+    // Currently mapped banks, for debuggers. Unbanked boards can rely on the default.
+    fn describe(&self) -> MapperState {
+        MapperState::init()
+    }
+    // Reads pattern memory through the current banking, without any of the side effects of a real fetch.
+    // Boards without any read as 0.
+    fn peek_chr(&self, _addr: u16) -> u8 {
+        0
+    }
+    // The same for the CPU side, or None where the cartridge doesn't drive the bus.
+    fn peek_cpu(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+    // Writes cartridge RAM directly, for cheat tools. Registers and ROM are left alone.
+    fn poke_cpu(&mut self, _addr: u16, _data: u8) {}
+    // PPU reads, or None where console VRAM answers through the mirroring.
+    fn peek_ppu(&self, addr: u16) -> Option<u8> {
+        (addr < 0x2000).then(|| self.peek_chr(addr))
+    }
+
+    // Cartridge RAM a battery could keep through power off, for save files.
+    // Whether there really is a battery is up to the header.
+    fn save_ram(&self) -> Option<&[u8]> {
+        None
+    }
+    // Restores it from a save file. Saves of the wrong size are ignored.
+    fn load_save_ram(&mut self, _data: &[u8]) {}
+
+    // Most boards don't see the RESET button. Turning the power off clears their registers,
+    // but battery backed RAM is kept, so boards leave their RAM alone either way.
+    fn reset(&mut self) {}
+    fn power_cycle(&mut self) {}
+
+    // The disk drive of the Famicom Disk System, for swapping disks. Cartridges have none.
+    fn disk_drive(&mut self) -> Option<&mut FdsSystem> {
+        None
+    }
+
+    // Writes out everything that can change while running, like registers and cartridge RAM.
+    // ROM isn't part of it, as the state is only ever loaded into the same cartridge.
+    fn save_state(&self, _out: &mut StateWriter) {}
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), StateError> {
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MapperState {
+    pub mapper: u16,
+    // Bank numbers in units of 8K, for the windows at $8000, $A000, $C000 and $E000.
+    pub prg_banks: [u16; 4],
+    // Bank numbers in units of 1K, for each window from $0000 to $1C00.
+    pub chr_banks: [u16; 8],
+    pub mirroring: Mirroring,
+}
+impl MapperState {
+    pub fn init() -> Self {
+        Self {
+            mapper: 0,
+            prg_banks: [0, 1, 2, 3],
+            chr_banks: [0, 1, 2, 3, 4, 5, 6, 7],
+            mirroring: Mirroring::Horizontal,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLow,
+    SingleScreenHigh,
+}
+impl Mirroring {
+    // Which of the two 1K pages of console VRAM a nametable ends up in.
+    pub fn vram_page(self, nametable: u8) -> usize {
+        match self {
+            Self::Horizontal => (nametable >> 1 & 1) as usize,
+            Self::Vertical => (nametable & 1) as usize,
+            Self::SingleScreenLow => 0,
+            Self::SingleScreenHigh => 1,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -52,6 +153,15 @@ impl MapperBus {
     const VRAM_A10: u8 = 1;
     const IRQ: u8 = 2;
 }
+impl SaveState for MapperBus {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.flags);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.flags = state.u8()?;
+        Ok(())
+    }
+}
 
 pub struct DynMapper(Box<dyn Mapper + Send>);
 impl DynMapper {
@@ -67,12 +177,74 @@ impl Mapper for DynMapper {
     fn cycle_with_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus) {
         self.0.cycle_with_ppu(bus, ppu);
     }
+
+    fn audio_output(&self) -> f32 {
+        self.0.audio_output()
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        self.0.load_trainer(trainer);
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.0.save_ram()
+    }
+    fn load_save_ram(&mut self, data: &[u8]) {
+        self.0.load_save_ram(data);
+    }
+
+    fn describe(&self) -> MapperState {
+        self.0.describe()
+    }
+
+    fn peek_chr(&self, addr: u16) -> u8 {
+        self.0.peek_chr(addr)
+    }
+    fn peek_cpu(&self, addr: u16) -> Option<u8> {
+        self.0.peek_cpu(addr)
+    }
+    fn poke_cpu(&mut self, addr: u16, data: u8) {
+        self.0.poke_cpu(addr, data)
+    }
+    fn peek_ppu(&self, addr: u16) -> Option<u8> {
+        self.0.peek_ppu(addr)
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+    fn power_cycle(&mut self) {
+        self.0.power_cycle();
+    }
+
+    fn disk_drive(&mut self) -> Option<&mut FdsSystem> {
+        self.0.disk_drive()
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        self.0.save_state(out);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.0.load_state(state)
+    }
 }
 
-pub fn get_mapper(rom: &Rom) -> DynMapper {
+pub fn get_mapper(rom: &Rom, trainer: Option<&[u8]>) -> DynMapper {
     let mapper = rom.header.mapper;
-    match rom.header.mapper {
+    try_get_mapper(rom, trainer)
+        .unwrap_or_else(|| unimplemented!("Mapper {mapper} is not implemented"))
+}
+// None for boards that aren't emulated. The trainer, from rom::trainer, is copied to $7000
+// before the game starts.
+pub fn try_get_mapper(rom: &Rom, trainer: Option<&[u8]>) -> Option<DynMapper> {
+    let mut mapper = match rom.header.mapper {
         0 => DynMapper::new(Mapper0::new(rom)),
-        _ => unimplemented!("Mapper {mapper} is not implemented"),
+        24 | 26 => DynMapper::new(Mapper24::new(rom)),
+        99 => DynMapper::new(Mapper99::new(rom)),
+        _ => return None,
+    };
+    if let Some(trainer) = trainer {
+        mapper.load_trainer(trainer);
     }
+    Some(mapper)
 }
@@ -0,0 +1,549 @@
+use super::{Mapper, MapperBus, MapperState, Mirroring};
+use crate::{
+    nesbus::CpuBus,
+    ppu::PpuBus,
+    rom::fds::{Disk, FdsError, SIDE_SIZE},
+    state::{SaveState, StateError, StateReader, StateWriter},
+};
+
+pub const BIOS_SIZE: usize = 0x2000;
+// Gaps are counted in bits on the real disk.
+const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+// CPU cycles to spin the disk back to the start, and to move the head by one byte.
+const REWIND_CYCLES: u32 = 50000;
+const BYTE_CYCLES: u32 = 150;
+// How long a disk stays out of the drive while it's flipped, so the BIOS notices the change.
+const SWAP_CYCLES: u32 = 1_000_000;
+
+// The Famicom Disk System: the RAM adapter with its BIOS, 32K of PRG RAM and 8K of CHR RAM,
+// the timer IRQ, and a disk drive. FDS audio isn't emulated.
+pub struct FdsSystem {
+    bios: Box<[u8; BIOS_SIZE]>,
+    prg_ram: Box<[u8; 0x8000]>,
+    chr_ram: Box<[u8; 0x2000]>,
+    disk_registers: bool,
+    horizontal_mirroring: bool,
+    external: u8,
+    timer: Timer,
+    drive: Drive,
+}
+impl FdsSystem {
+    pub fn new(bios: &[u8]) -> Result<Self, FdsError> {
+        let bios = bios.try_into().map_err(|_| FdsError::Bios(bios.len()))?;
+        Ok(Self {
+            bios: Box::new(bios),
+            prg_ram: Box::new([0; 0x8000]),
+            chr_ram: Box::new([0; 0x2000]),
+            disk_registers: false,
+            horizontal_mirroring: false,
+            external: 0,
+            timer: Timer::init(),
+            drive: Drive::init(),
+        })
+    }
+
+    pub fn insert_disk(&mut self, disk: Disk) {
+        self.drive.tracks = (0..disk.sides())
+            .map(|side| track(disk.side(side)))
+            .collect();
+        self.drive.side = None;
+        self.drive.swap = Some((0, SWAP_CYCLES));
+    }
+    pub fn sides(&self) -> usize {
+        self.drive.tracks.len()
+    }
+    // The side in the drive, which is None while a disk is being swapped.
+    pub fn inserted_side(&self) -> Option<usize> {
+        self.drive.side
+    }
+    pub fn insert_side(&mut self, side: usize) {
+        assert!(
+            side < self.sides(),
+            "the disk only has {} sides",
+            self.sides()
+        );
+        self.drive.side = None;
+        self.drive.swap = Some((side, SWAP_CYCLES));
+    }
+    pub fn eject(&mut self) {
+        self.drive.side = None;
+        self.drive.swap = None;
+    }
+    // Turns the disk over, or puts in the next one of a multi-disk game. Returns the side going in.
+    pub fn flip(&mut self) -> Option<usize> {
+        if self.sides() == 0 {
+            return None;
+        };
+        let next = match (self.drive.side, self.drive.swap) {
+            (Some(side), _) | (None, Some((side, _))) => side + 1,
+            (None, None) => 0,
+        };
+        let next = next % self.sides();
+        self.insert_side(next);
+        Some(next)
+    }
+
+    fn handle_cpu(&mut self, cpu: &mut CpuBus) {
+        let addr = cpu.address();
+        if cpu.read() {
+            if let Some(data) = self.read_register(addr) {
+                cpu.set_data(data);
+            } else if let Some(data) = self.peek_cpu(addr) {
+                cpu.set_data(data);
+            }
+            return;
+        };
+
+        let data = cpu.data();
+        match addr {
+            0x4020 => self.timer.reload = self.timer.reload & 0xFF00 | data as u16,
+            0x4021 => self.timer.reload = self.timer.reload & 0x00FF | (data as u16) << 8,
+            0x4022 => self.timer.write_control(data, self.disk_registers),
+            0x4023 => {
+                self.disk_registers = data & 1 != 0;
+                if !self.disk_registers {
+                    self.timer.enabled = false;
+                    self.timer.pending = false;
+                    self.drive.irq = false;
+                }
+            }
+            0x4024 if self.disk_registers => {
+                self.drive.write_data = data;
+                self.drive.transfer_complete = false;
+                self.drive.irq = false;
+            }
+            0x4025 if self.disk_registers => {
+                self.horizontal_mirroring = data & 8 != 0;
+                self.drive.write_control(data);
+            }
+            0x4026 if self.disk_registers => self.external = data,
+            0x6000..=0xDFFF => self.prg_ram[addr as usize - 0x6000] = data,
+            _ => (),
+        }
+    }
+    fn read_register(&mut self, addr: u16) -> Option<u8> {
+        if !self.disk_registers {
+            return None;
+        };
+        let drive = &mut self.drive;
+        let data = match addr {
+            0x4030 => {
+                let mut status = self.timer.pending as u8;
+                status |= (drive.transfer_complete as u8) << 1;
+                status |= (drive.end_of_head as u8) << 6;
+                self.timer.pending = false;
+                drive.transfer_complete = false;
+                drive.irq = false;
+                status
+            }
+            0x4031 => {
+                drive.transfer_complete = false;
+                drive.irq = false;
+                drive.read_data
+            }
+            0x4032 => {
+                let inserted = drive.side.is_some();
+                let not_ready = !inserted || !drive.scanning;
+                // Disks are never write protected, so only a missing one reads as protected.
+                !inserted as u8 | (not_ready as u8) << 1 | (!inserted as u8) << 2
+            }
+            // Bit 7 is the battery check of the drive.
+            0x4033 => 0x80 | self.external & 0x7F,
+            _ => return None,
+        };
+        Some(data)
+    }
+
+    fn handle_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus) {
+        let addr = ppu.address();
+        if addr < 0x2000 {
+            if ppu.read_enable() {
+                ppu.set_data(self.chr_ram[addr as usize]);
+            }
+            if ppu.write_enable() {
+                self.chr_ram[addr as usize] = ppu.data();
+            }
+        }
+
+        let a10 = addr >> 10 & 1 != 0;
+        let a11 = addr >> 11 & 1 != 0;
+        bus.set_vram_a10(if self.horizontal_mirroring { a11 } else { a10 });
+        bus.set_vram_enable((0x2000..0x3000).contains(&addr));
+    }
+}
+impl Mapper for FdsSystem {
+    fn cycle(&mut self, bus: &mut MapperBus, cpu: &mut CpuBus, ppu: &mut PpuBus) {
+        self.handle_cpu(cpu);
+        self.handle_ppu(bus, ppu);
+        self.timer.clock();
+        self.drive.clock();
+        cpu.or_irq(self.timer.pending || self.drive.irq);
+    }
+
+    fn cycle_with_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus) {
+        self.handle_ppu(bus, ppu);
+    }
+
+    fn describe(&self) -> MapperState {
+        let mirroring = if self.horizontal_mirroring {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+        MapperState {
+            mirroring,
+            ..MapperState::init()
+        }
+    }
+
+    fn peek_chr(&self, addr: u16) -> u8 {
+        self.chr_ram[addr as usize % 0x2000]
+    }
+    fn peek_cpu(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0xDFFF => Some(self.prg_ram[addr as usize - 0x6000]),
+            0xE000..=0xFFFF => Some(self.bios[addr as usize - 0xE000]),
+            _ => None,
+        }
+    }
+    fn poke_cpu(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0xDFFF = addr {
+            self.prg_ram[addr as usize - 0x6000] = data;
+        };
+    }
+
+    fn disk_drive(&mut self) -> Option<&mut FdsSystem> {
+        Some(self)
+    }
+    // The disk stays in the drive.
+    fn power_cycle(&mut self) {
+        self.disk_registers = false;
+        self.horizontal_mirroring = false;
+        self.external = 0;
+        self.timer = Timer::init();
+        let drive = std::mem::replace(&mut self.drive, Drive::init());
+        self.drive = Drive {
+            tracks: drive.tracks,
+            side: drive.side,
+            swap: drive.swap,
+            ..Drive::init()
+        };
+    }
+
+    // The BIOS is ROM, but the disk is written to, so its contents are part of the state.
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bytes(&*self.prg_ram);
+        out.bytes(&*self.chr_ram);
+        out.bool(self.disk_registers);
+        out.bool(self.horizontal_mirroring);
+        out.u8(self.external);
+        self.timer.save_state(out);
+        self.drive.save_state(out);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes(&mut *self.prg_ram)?;
+        state.bytes(&mut *self.chr_ram)?;
+        self.disk_registers = state.bool()?;
+        self.horizontal_mirroring = state.bool()?;
+        self.external = state.u8()?;
+        self.timer.load_state(state)?;
+        self.drive.load_state(state)
+    }
+}
+
+struct Timer {
+    reload: u16,
+    counter: u16,
+    repeat: bool,
+    enabled: bool,
+    pending: bool,
+}
+impl Timer {
+    fn init() -> Self {
+        Self {
+            reload: 0,
+            counter: 0,
+            repeat: false,
+            enabled: false,
+            pending: false,
+        }
+    }
+
+    fn write_control(&mut self, data: u8, disk_registers: bool) {
+        self.repeat = data & 1 != 0;
+        self.enabled = data & 2 != 0 && disk_registers;
+        if self.enabled {
+            self.counter = self.reload;
+        } else {
+            self.pending = false;
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        };
+        if self.counter != 0 {
+            self.counter -= 1;
+            return;
+        };
+        self.pending = true;
+        self.counter = self.reload;
+        self.enabled = self.repeat;
+    }
+}
+
+impl SaveState for Timer {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u16(self.reload);
+        out.u16(self.counter);
+        out.bool(self.repeat);
+        out.bool(self.enabled);
+        out.bool(self.pending);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.reload = state.u16()?;
+        self.counter = state.u16()?;
+        self.repeat = state.bool()?;
+        self.enabled = state.bool()?;
+        self.pending = state.bool()?;
+        Ok(())
+    }
+}
+
+struct Drive {
+    // Each side as the head sees it, with gaps, start marks and CRCs.
+    tracks: Vec<Vec<u8>>,
+    side: Option<usize>,
+    // The side that goes in once the countdown runs out.
+    swap: Option<(usize, u32)>,
+
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    disk_ready: bool,
+    irq_enabled: bool,
+
+    position: usize,
+    delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    transfer_complete: bool,
+    irq: bool,
+    read_data: u8,
+    write_data: u8,
+}
+impl Drive {
+    fn init() -> Self {
+        Self {
+            tracks: Vec::new(),
+            side: None,
+            swap: None,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: false,
+            crc_control: false,
+            disk_ready: false,
+            irq_enabled: false,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            transfer_complete: false,
+            irq: false,
+            read_data: 0,
+            write_data: 0,
+        }
+    }
+
+    fn write_control(&mut self, data: u8) {
+        self.motor_on = data & 1 != 0;
+        self.reset_transfer = data & 2 != 0;
+        self.read_mode = data & 4 != 0;
+        self.crc_control = data & 0x10 != 0;
+        self.disk_ready = data & 0x40 != 0;
+        self.irq_enabled = data & 0x80 != 0;
+        self.irq = false;
+    }
+
+    fn clock(&mut self) {
+        if let Some((side, countdown)) = &mut self.swap {
+            *countdown -= 1;
+            if *countdown == 0 {
+                self.side = Some(*side);
+                self.swap = None;
+            }
+        }
+
+        let Some(side) = self.side else {
+            self.stop();
+            return;
+        };
+        if !self.motor_on {
+            self.stop();
+            return;
+        };
+        if self.reset_transfer && !self.scanning {
+            return;
+        };
+        if self.end_of_head {
+            self.end_of_head = false;
+            self.delay = REWIND_CYCLES;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        };
+        if self.delay != 0 {
+            self.delay -= 1;
+            return;
+        };
+
+        self.scanning = true;
+        if self.read_mode {
+            self.read_byte(self.tracks[side][self.position]);
+        } else {
+            let data = if self.disk_ready { self.write_data } else { 0 };
+            if !self.crc_control {
+                self.transfer_complete = true;
+                self.irq |= self.irq_enabled;
+                self.tracks[side][self.position] = data;
+            }
+            self.gap_ended = false;
+        }
+
+        self.position += 1;
+        if self.position >= self.tracks[side].len() {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+    fn stop(&mut self) {
+        self.end_of_head = true;
+        self.scanning = false;
+    }
+
+    // Nothing is transferred until the first non-zero byte after a gap, the block's start mark.
+    fn read_byte(&mut self, data: u8) {
+        let mut irq = self.irq_enabled;
+        if !self.disk_ready {
+            self.gap_ended = false;
+        } else if data != 0 && !self.gap_ended {
+            self.gap_ended = true;
+            irq = false;
+        }
+        if self.gap_ended {
+            self.transfer_complete = true;
+            self.read_data = data;
+            self.irq |= irq;
+        }
+    }
+}
+
+// States only load into the same disk, so the number of sides has to match.
+impl SaveState for Drive {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.tracks.len() as u8);
+        for track in &self.tracks {
+            out.bytes(track);
+        }
+        out.bool(self.side.is_some());
+        out.u8(self.side.unwrap_or(0) as u8);
+        let (swap_side, countdown) = self.swap.unwrap_or((0, 0));
+        out.bool(self.swap.is_some());
+        out.u8(swap_side as u8);
+        out.u32(countdown);
+
+        out.bool(self.motor_on);
+        out.bool(self.reset_transfer);
+        out.bool(self.read_mode);
+        out.bool(self.crc_control);
+        out.bool(self.disk_ready);
+        out.bool(self.irq_enabled);
+
+        out.u32(self.position as u32);
+        out.u32(self.delay);
+        out.bool(self.end_of_head);
+        out.bool(self.scanning);
+        out.bool(self.gap_ended);
+        out.bool(self.transfer_complete);
+        out.bool(self.irq);
+        out.u8(self.read_data);
+        out.u8(self.write_data);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        if state.u8()? as usize != self.tracks.len() {
+            return Err(StateError::Invalid);
+        };
+        for track in &mut self.tracks {
+            state.bytes(track)?;
+        }
+        let inserted = state.bool()?;
+        let side = state.u8()? as usize;
+        self.side = inserted.then_some(side);
+        let swapping = state.bool()?;
+        let swap_side = state.u8()? as usize;
+        let countdown = state.u32()?;
+        self.swap = swapping.then_some((swap_side, countdown));
+
+        self.motor_on = state.bool()?;
+        self.reset_transfer = state.bool()?;
+        self.read_mode = state.bool()?;
+        self.crc_control = state.bool()?;
+        self.disk_ready = state.bool()?;
+        self.irq_enabled = state.bool()?;
+
+        self.position = state.u32()? as usize;
+        self.delay = state.u32()?;
+        self.end_of_head = state.bool()?;
+        self.scanning = state.bool()?;
+        self.gap_ended = state.bool()?;
+        self.transfer_complete = state.bool()?;
+        self.irq = state.bool()?;
+        self.read_data = state.u8()?;
+        self.write_data = state.u8()?;
+
+        let sides = self.tracks.len();
+        let swap_ok = self
+            .swap
+            .is_none_or(|(side, countdown)| side < sides && countdown != 0);
+        let track_len = match self.side {
+            Some(side) => self.tracks.get(side).ok_or(StateError::Invalid)?.len(),
+            None => usize::MAX,
+        };
+        if !swap_ok || self.position > track_len {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
+
+// Lays a side out like it is on disk. The CRCs aren't checked, so they're left as a fixed value.
+fn track(side: &[u8]) -> Vec<u8> {
+    let mut track = vec![0; LEADING_GAP];
+    let mut pos = 0;
+    while pos < SIDE_SIZE {
+        let len = match side[pos] {
+            1 => 56,
+            2 => 2,
+            3 => 16,
+            // A file's data follows its header, which ends with the file size.
+            4 if pos >= 3 => 1 + u16::from_le_bytes([side[pos - 3], side[pos - 2]]) as usize,
+            _ => break,
+        };
+        let Some(block) = side.get(pos..pos + len) else {
+            break;
+        };
+        track.push(0x80);
+        track.extend_from_slice(block);
+        track.extend_from_slice(&[0x4D, 0x62]);
+        track.extend(std::iter::repeat_n(0, BLOCK_GAP));
+        pos += len;
+    }
+    track.resize(track.len().max(SIDE_SIZE + LEADING_GAP), 0);
+    track
+}
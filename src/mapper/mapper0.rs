@@ -1,10 +1,15 @@
-use super::{Mapper, MapperBus};
-use crate::{nesbus::CpuBus, ppu::PpuBus};
+use super::{Mapper, MapperBus, MapperState, Mirroring};
+use crate::{
+    nesbus::CpuBus,
+    ppu::PpuBus,
+    state::{StateError, StateReader, StateWriter},
+};
 use nes_rom_parser::Rom;
 
 pub struct Mapper0 {
     prg: Vec<u8>,
     chr: Vec<u8>,
+    prg_ram: Box<[u8; 0x2000]>,
     large_prg: bool,
     vertical_mirror: bool,
 }
@@ -14,6 +19,7 @@ impl Mapper0 {
         Self {
             prg: rom.prg_rom.to_vec(),
             chr: rom.chr_rom.to_vec(),
+            prg_ram: Box::new([0; 0x2000]),
             large_prg,
             vertical_mirror: rom.header.vertical_mirroring,
         }
@@ -21,6 +27,10 @@ impl Mapper0 {
 
     fn handle_cpu(&mut self, cpu: &mut CpuBus) {
         let addr = cpu.address() as usize;
+        if (0x6000..0x8000).contains(&addr) {
+            self.handle_prg_ram(cpu);
+            return;
+        }
         if addr < 0x8000 {
             return;
         };
@@ -31,6 +41,14 @@ impl Mapper0 {
             cpu.set_data(self.prg[addr]);
         }
     }
+    fn handle_prg_ram(&mut self, cpu: &mut CpuBus) {
+        let addr = cpu.address() as usize % 0x2000;
+        if cpu.read() {
+            cpu.set_data(self.prg_ram[addr]);
+        } else {
+            self.prg_ram[addr] = cpu.data();
+        }
+    }
     fn handle_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus) {
         if ppu.address() < 0x2000 && ppu.read_enable() {
             ppu.set_data(self.chr[ppu.address() as usize]);
@@ -61,4 +79,62 @@ impl Mapper for Mapper0 {
     fn cycle_with_ppu(&mut self, bus: &mut super::MapperBus, ppu: &mut PpuBus) {
         self.handle_ppu(bus, ppu);
     }
+
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        self.prg_ram[0x1000..0x1200].copy_from_slice(trainer);
+    }
+
+    fn describe(&self) -> MapperState {
+        let prg_banks = if self.large_prg {
+            [0, 1, 2, 3]
+        } else {
+            [0, 1, 0, 1]
+        };
+        let mirroring = if self.vertical_mirror {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        MapperState {
+            prg_banks,
+            mirroring,
+            ..MapperState::init()
+        }
+    }
+
+    fn peek_chr(&self, addr: u16) -> u8 {
+        self.chr.get(addr as usize).copied().unwrap_or(0)
+    }
+    fn peek_cpu(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF => Some(self.prg_ram[addr as usize % 0x2000]),
+            0x8000..=0xFFFF => {
+                let addr = addr as usize % if self.large_prg { 0x8000 } else { 0x4000 };
+                Some(self.prg[addr])
+            }
+            _ => None,
+        }
+    }
+    fn poke_cpu(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[addr as usize % 0x2000] = data;
+        };
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        Some(&*self.prg_ram)
+    }
+    fn load_save_ram(&mut self, data: &[u8]) {
+        if data.len() == self.prg_ram.len() {
+            self.prg_ram.copy_from_slice(data);
+        };
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bytes(&*self.prg_ram);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes(&mut *self.prg_ram)
+    }
 }
@@ -0,0 +1,551 @@
+use super::{Mapper, MapperBus, MapperState, Mirroring};
+use crate::{
+    nesbus::CpuBus,
+    ppu::PpuBus,
+    state::{SaveState, StateError, StateReader, StateWriter},
+};
+use nes_rom_parser::Rom;
+
+// A full-volume VRC6 pulse is roughly as loud as a full-volume APU pulse.
+const AUDIO_SCALE: f32 = 0.00996;
+
+// VRC6, covering both mapper 24 (VRC6a) and mapper 26 (VRC6b).
+// The two only differ in that VRC6b has the A0 and A1 register lines swapped.
+// Only the common PPU banking mode (eight 1K CHR banks, nametables from CIRAM) is implemented.
+pub struct Mapper24 {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: Box<[u8; 0x2000]>,
+    swap_lines: bool,
+
+    prg_16k: u8,
+    prg_8k: u8,
+    chr_banks: [u8; 8],
+    banking_style: u8,
+
+    irq: Irq,
+    frequency_control: u8,
+    pulses: [Pulse; 2],
+    saw: Saw,
+}
+impl Mapper24 {
+    pub fn new(rom: &Rom) -> Self {
+        let chr_ram = rom.chr_rom.is_empty();
+        let chr = if chr_ram {
+            vec![0; 0x2000]
+        } else {
+            rom.chr_rom.to_vec()
+        };
+
+        Self {
+            prg: rom.prg_rom.to_vec(),
+            chr,
+            chr_ram,
+            prg_ram: Box::new([0; 0x2000]),
+            swap_lines: rom.header.mapper == 26,
+
+            prg_16k: 0,
+            prg_8k: 0,
+            chr_banks: [0; 8],
+            banking_style: 0,
+
+            irq: Irq::init(),
+            frequency_control: 0,
+            pulses: [Pulse::init(), Pulse::init()],
+            saw: Saw::init(),
+        }
+    }
+
+    fn handle_cpu(&mut self, cpu: &mut CpuBus) {
+        let addr = cpu.address();
+        match addr {
+            0x6000..=0x7FFF => {
+                if !self.prg_ram_enabled() {
+                    return;
+                };
+                let addr = addr as usize % 0x2000;
+                if cpu.read() {
+                    cpu.set_data(self.prg_ram[addr]);
+                } else {
+                    self.prg_ram[addr] = cpu.data();
+                }
+            }
+            0x8000..=0xFFFF => {
+                if cpu.read() {
+                    cpu.set_data(self.prg[self.prg_index(addr)]);
+                } else {
+                    self.write_register(addr, cpu.data());
+                }
+            }
+            _ => (),
+        }
+    }
+    fn prg_index(&self, addr: u16) -> usize {
+        let addr = addr as usize;
+        let index = match addr {
+            0x8000..=0xBFFF => (self.prg_16k as usize & 0xF) * 0x4000 + addr % 0x4000,
+            0xC000..=0xDFFF => (self.prg_8k as usize & 0x1F) * 0x2000 + addr % 0x2000,
+            _ => self.prg.len() - 0x2000 + addr % 0x2000,
+        };
+        index % self.prg.len()
+    }
+    fn write_register(&mut self, addr: u16, data: u8) {
+        let line_0 = addr & 1;
+        let line_1 = addr >> 1 & 1;
+        let low = if self.swap_lines {
+            line_1 | line_0 << 1
+        } else {
+            line_0 | line_1 << 1
+        };
+        let register = (addr & 0xF000) | low;
+
+        match register {
+            0x8000..=0x8003 => self.prg_16k = data,
+            0x9000 => self.pulses[0].write_control(data),
+            0x9001 => self.pulses[0].write_period_low(data),
+            0x9002 => self.pulses[0].write_period_high(data),
+            0x9003 => self.frequency_control = data,
+            0xA000 => self.pulses[1].write_control(data),
+            0xA001 => self.pulses[1].write_period_low(data),
+            0xA002 => self.pulses[1].write_period_high(data),
+            0xB000 => self.saw.write_rate(data),
+            0xB001 => self.saw.write_period_low(data),
+            0xB002 => self.saw.write_period_high(data),
+            0xB003 => self.banking_style = data,
+            0xC000..=0xC003 => self.prg_8k = data,
+            0xD000..=0xD003 => self.chr_banks[low as usize] = data,
+            0xE000..=0xE003 => self.chr_banks[4 + low as usize] = data,
+            0xF000 => self.irq.latch = data,
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    fn handle_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus) {
+        let addr = ppu.address();
+        if addr < 0x2000 {
+            let index = self.chr_index(addr);
+            if ppu.read_enable() {
+                ppu.set_data(self.chr[index]);
+            }
+            if ppu.write_enable() && self.chr_ram {
+                self.chr[index] = ppu.data();
+            }
+        }
+
+        let a10 = addr >> 10 & 1 != 0;
+        let a11 = addr >> 11 & 1 != 0;
+        let a10 = match self.mirroring() {
+            Mirroring::Vertical => a10,
+            Mirroring::Horizontal => a11,
+            Mirroring::SingleScreenLow => false,
+            Mirroring::SingleScreenHigh => true,
+        };
+        bus.set_vram_a10(a10);
+        let enable = (0x2000..0x3000).contains(&addr);
+        bus.set_vram_enable(enable);
+    }
+    fn mirroring(&self) -> Mirroring {
+        match self.banking_style >> 2 & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLow,
+            3 => Mirroring::SingleScreenHigh,
+            4.. => unreachable!(),
+        }
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[addr as usize >> 10 & 7] as usize;
+        (bank * 0x400 + addr as usize % 0x400) % self.chr.len()
+    }
+
+    fn clock_audio(&mut self) {
+        if self.frequency_control & 1 != 0 {
+            return;
+        };
+        let shift = if self.frequency_control & 4 != 0 {
+            8
+        } else if self.frequency_control & 2 != 0 {
+            4
+        } else {
+            0
+        };
+
+        self.pulses[0].clock(shift);
+        self.pulses[1].clock(shift);
+        self.saw.clock(shift);
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.banking_style & 0x80 != 0
+    }
+}
+impl Mapper for Mapper24 {
+    fn cycle(&mut self, bus: &mut MapperBus, cpu: &mut CpuBus, ppu: &mut PpuBus) {
+        self.handle_cpu(cpu);
+        self.handle_ppu(bus, ppu);
+        self.clock_audio();
+        self.irq.clock();
+        cpu.or_irq(self.irq.pending);
+    }
+
+    fn cycle_with_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus) {
+        self.handle_ppu(bus, ppu);
+    }
+
+    fn audio_output(&self) -> f32 {
+        let pulse_0 = self.pulses[0].output();
+        let pulse_1 = self.pulses[1].output();
+        let saw = self.saw.output();
+        (pulse_0 + pulse_1 + saw) as f32 * AUDIO_SCALE
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        self.prg_ram[0x1000..0x1200].copy_from_slice(trainer);
+    }
+
+    fn describe(&self) -> MapperState {
+        let prg_bank = |i: u16| (self.prg_index(0x8000 + i * 0x2000) / 0x2000) as u16;
+        let chr_bank = |i: u16| (self.chr_index(i * 0x400) / 0x400) as u16;
+
+        MapperState {
+            mapper: if self.swap_lines { 26 } else { 24 },
+            prg_banks: [0, 1, 2, 3].map(prg_bank),
+            chr_banks: [0, 1, 2, 3, 4, 5, 6, 7].map(chr_bank),
+            mirroring: self.mirroring(),
+        }
+    }
+
+    fn peek_chr(&self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+    fn peek_cpu(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => Some(self.prg_ram[addr as usize % 0x2000]),
+            0x8000..=0xFFFF => Some(self.prg[self.prg_index(addr)]),
+            _ => None,
+        }
+    }
+    fn poke_cpu(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[addr as usize % 0x2000] = data;
+        };
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        Some(&*self.prg_ram)
+    }
+    fn load_save_ram(&mut self, data: &[u8]) {
+        if data.len() == self.prg_ram.len() {
+            self.prg_ram.copy_from_slice(data);
+        };
+    }
+
+    fn power_cycle(&mut self) {
+        self.prg_16k = 0;
+        self.prg_8k = 0;
+        self.chr_banks = [0; 8];
+        self.banking_style = 0;
+        self.irq = Irq::init();
+        self.frequency_control = 0;
+        self.pulses = [Pulse::init(), Pulse::init()];
+        self.saw = Saw::init();
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bytes(&*self.prg_ram);
+        if self.chr_ram {
+            out.bytes(&self.chr);
+        }
+        out.u8(self.prg_16k);
+        out.u8(self.prg_8k);
+        out.bytes(&self.chr_banks);
+        out.u8(self.banking_style);
+        self.irq.save_state(out);
+        out.u8(self.frequency_control);
+        for pulse in &self.pulses {
+            pulse.save_state(out);
+        }
+        self.saw.save_state(out);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes(&mut *self.prg_ram)?;
+        if self.chr_ram {
+            state.bytes(&mut self.chr)?;
+        }
+        self.prg_16k = state.u8()?;
+        self.prg_8k = state.u8()?;
+        state.bytes(&mut self.chr_banks)?;
+        self.banking_style = state.u8()?;
+        self.irq.load_state(state)?;
+        self.frequency_control = state.u8()?;
+        for pulse in &mut self.pulses {
+            pulse.load_state(state)?;
+        }
+        self.saw.load_state(state)
+    }
+}
+
+struct Irq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enable: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+impl Irq {
+    fn init() -> Self {
+        Self {
+            latch: 0,
+            counter: 0,
+            prescaler: 341,
+            enable: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data & 1 != 0;
+        self.enable = data & 2 != 0;
+        self.cycle_mode = data & 4 != 0;
+        self.pending = false;
+
+        if self.enable {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+    }
+    fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enable = self.enable_after_ack;
+    }
+
+    fn clock(&mut self) {
+        if !self.enable {
+            return;
+        };
+
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            // Scanline mode approximates 341 PPU dots by stepping three dots per CPU cycle.
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += 341;
+                self.clock_counter();
+            }
+        }
+    }
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+impl SaveState for Irq {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.latch);
+        out.u8(self.counter);
+        out.u16(self.prescaler as u16);
+        out.bool(self.enable);
+        out.bool(self.enable_after_ack);
+        out.bool(self.cycle_mode);
+        out.bool(self.pending);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.latch = state.u8()?;
+        self.counter = state.u8()?;
+        self.prescaler = state.u16()? as i16;
+        self.enable = state.bool()?;
+        self.enable_after_ack = state.bool()?;
+        self.cycle_mode = state.bool()?;
+        self.pending = state.bool()?;
+        if !(1..=341).contains(&self.prescaler) {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
+
+struct Pulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    period: u16,
+    enable: bool,
+
+    timer: u16,
+    step: u8,
+}
+impl Pulse {
+    fn init() -> Self {
+        Self {
+            volume: 0,
+            duty: 0,
+            ignore_duty: false,
+            period: 0,
+            enable: false,
+
+            timer: 0,
+            step: 0,
+        }
+    }
+
+    fn write_control(&mut self, data: u8) {
+        self.ignore_duty = data & 128 != 0;
+        self.duty = data >> 4 & 0b111;
+        self.volume = data & 0xF;
+    }
+    fn write_period_low(&mut self, data: u8) {
+        self.period = (self.period & 0xF00) | data as u16;
+    }
+    fn write_period_high(&mut self, data: u8) {
+        self.period = (self.period & 0xFF) | ((data as u16 & 0xF) << 8);
+        self.enable = data & 128 != 0;
+        if !self.enable {
+            self.step = 0;
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enable {
+            return;
+        };
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = (self.step + 1) % 16;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    fn output(&self) -> u8 {
+        if !self.enable {
+            return 0;
+        };
+        if self.ignore_duty || self.step <= self.duty {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+impl SaveState for Pulse {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.volume);
+        out.u8(self.duty);
+        out.bool(self.ignore_duty);
+        out.u16(self.period);
+        out.bool(self.enable);
+        out.u16(self.timer);
+        out.u8(self.step);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.volume = state.u8()?;
+        self.duty = state.u8()?;
+        self.ignore_duty = state.bool()?;
+        self.period = state.u16()?;
+        self.enable = state.bool()?;
+        self.timer = state.u16()?;
+        self.step = state.u8()?;
+        if self.step >= 16 {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
+
+struct Saw {
+    rate: u8,
+    period: u16,
+    enable: bool,
+
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+impl Saw {
+    fn init() -> Self {
+        Self {
+            rate: 0,
+            period: 0,
+            enable: false,
+
+            timer: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+
+    fn write_rate(&mut self, data: u8) {
+        self.rate = data & 0b111111;
+    }
+    fn write_period_low(&mut self, data: u8) {
+        self.period = (self.period & 0xF00) | data as u16;
+    }
+    fn write_period_high(&mut self, data: u8) {
+        self.period = (self.period & 0xFF) | ((data as u16 & 0xF) << 8);
+        self.enable = data & 128 != 0;
+        if !self.enable {
+            self.step = 0;
+            self.accumulator = 0;
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enable {
+            return;
+        };
+        if self.timer != 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+
+        // The accumulator is only added to on every second step, and reset on the seventh even step,
+        // after six additions.
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 1 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+impl SaveState for Saw {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.rate);
+        out.u16(self.period);
+        out.bool(self.enable);
+        out.u16(self.timer);
+        out.u8(self.step);
+        out.u8(self.accumulator);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rate = state.u8()?;
+        self.period = state.u16()?;
+        self.enable = state.bool()?;
+        self.timer = state.u16()?;
+        self.step = state.u8()?;
+        self.accumulator = state.u8()?;
+        if self.step >= 14 {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
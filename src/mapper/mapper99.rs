@@ -0,0 +1,162 @@
+use super::{Mapper, MapperBus, MapperState, Mirroring};
+use crate::{
+    nesbus::CpuBus,
+    ppu::PpuBus,
+    state::{StateError, StateReader, StateWriter},
+};
+use nes_rom_parser::Rom;
+
+// Vs. System boards. Bit 2 of writes to $4016 selects the CHR bank,
+// and on boards with more than 32K of PRG also the first 8K PRG bank.
+pub struct Mapper99 {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+    prg_ram: Box<[u8; 0x800]>,
+    vertical_mirror: bool,
+
+    bank: bool,
+    coin_counter: u8,
+}
+impl Mapper99 {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            prg: rom.prg_rom.to_vec(),
+            chr: rom.chr_rom.to_vec(),
+            prg_ram: Box::new([0; 0x800]),
+            vertical_mirror: rom.header.vertical_mirroring,
+
+            bank: false,
+            coin_counter: 0,
+        }
+    }
+
+    fn handle_cpu(&mut self, cpu: &mut CpuBus) {
+        let addr = cpu.address();
+        match addr {
+            0x4016 => {
+                if cpu.read() {
+                    return;
+                };
+                self.bank = cpu.data() & 4 != 0;
+            }
+            0x4020 => {
+                if cpu.read() {
+                    return;
+                };
+                self.coin_counter = cpu.data();
+            }
+            0x6000..=0x7FFF => {
+                let addr = addr as usize % 0x800;
+                if cpu.read() {
+                    cpu.set_data(self.prg_ram[addr]);
+                } else {
+                    self.prg_ram[addr] = cpu.data();
+                }
+            }
+            0x8000..=0xFFFF if cpu.read() => {
+                cpu.set_data(self.prg[self.prg_index(addr)]);
+            }
+            _ => (),
+        }
+    }
+    fn prg_index(&self, addr: u16) -> usize {
+        let addr = addr as usize % 0x8000;
+        let switchable = self.prg.len() > 0x8000 && addr < 0x2000;
+        if switchable && self.bank {
+            0x8000 + addr
+        } else {
+            addr % self.prg.len()
+        }
+    }
+
+    fn handle_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus) {
+        if ppu.address() < 0x2000 && ppu.read_enable() {
+            ppu.set_data(self.chr[self.chr_index(ppu.address())]);
+        }
+
+        let a10 = ppu.address() >> 10 & 1 != 0;
+        let a11 = ppu.address() >> 11 & 1 != 0;
+        bus.set_vram_a10(if self.vertical_mirror { a10 } else { a11 });
+        let enable = (0x2000..0x3000).contains(&ppu.address());
+
+        bus.set_vram_enable(enable);
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let base = if self.bank { 0x2000 } else { 0 };
+        (base + addr as usize) % self.chr.len()
+    }
+
+    // The last value written to the coin counter and lockout register at $4020.
+    pub fn coin_counter(&self) -> u8 {
+        self.coin_counter
+    }
+}
+impl Mapper for Mapper99 {
+    fn cycle(&mut self, bus: &mut MapperBus, cpu: &mut CpuBus, ppu: &mut PpuBus) {
+        self.handle_cpu(cpu);
+        self.handle_ppu(bus, ppu);
+    }
+
+    fn cycle_with_ppu(&mut self, bus: &mut MapperBus, ppu: &mut PpuBus) {
+        self.handle_ppu(bus, ppu);
+    }
+
+    fn describe(&self) -> MapperState {
+        let prg_bank = |i: u16| (self.prg_index(0x8000 + i * 0x2000) / 0x2000) as u16;
+        let chr_bank = |i: u16| (self.chr_index(i * 0x400) / 0x400) as u16;
+        let mirroring = if self.vertical_mirror {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        MapperState {
+            mapper: 99,
+            prg_banks: [0, 1, 2, 3].map(prg_bank),
+            chr_banks: [0, 1, 2, 3, 4, 5, 6, 7].map(chr_bank),
+            mirroring,
+        }
+    }
+
+    fn peek_chr(&self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+    fn peek_cpu(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF => Some(self.prg_ram[addr as usize % 0x800]),
+            0x8000..=0xFFFF => Some(self.prg[self.prg_index(addr)]),
+            _ => None,
+        }
+    }
+    fn poke_cpu(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[addr as usize % 0x800] = data;
+        };
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        Some(&*self.prg_ram)
+    }
+    fn load_save_ram(&mut self, data: &[u8]) {
+        if data.len() == self.prg_ram.len() {
+            self.prg_ram.copy_from_slice(data);
+        };
+    }
+
+    fn power_cycle(&mut self) {
+        self.bank = false;
+        self.coin_counter = 0;
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bytes(&*self.prg_ram);
+        out.bool(self.bank);
+        out.u8(self.coin_counter);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes(&mut *self.prg_ram)?;
+        self.bank = state.bool()?;
+        self.coin_counter = state.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use super::{Mapper, MapperBus, MapperState};
+use crate::{
+    nesbus::CpuBus,
+    ppu::PpuBus,
+    region::Region,
+    rom::nsf::Nsf,
+    state::{StateError, StateReader, StateWriter},
+};
+
+const DRIVER_ADDRESS: u16 = 0x5000;
+const TIMER_REGISTER: u16 = 0x5100;
+
+// Runs the file's INIT routine, then calls PLAY every time the timer register at $5100 says so.
+// Before INIT, the APU registers get the values the NSF spec promises.
+#[rustfmt::skip]
+const DRIVER: [u8; 0x33] = [
+    0x78,             // $5000 SEI
+    0xD8,             // $5001 CLD
+    0xA2, 0xFF,       // $5002 LDX #$FF
+    0x9A,             // $5004 TXS
+    0xE8,             // $5005 INX
+    0xA9, 0x00,       // $5006 LDA #$00
+    0x9D, 0x00, 0x40, // $5008 STA $4000,X
+    0xE8,             // $500B INX
+    0xE0, 0x14,       // $500C CPX #$14
+    0xD0, 0xF8,       // $500E BNE $5008
+    0x8D, 0x15, 0x40, // $5010 STA $4015
+    0xA9, 0x0F,       // $5013 LDA #$0F
+    0x8D, 0x15, 0x40, // $5015 STA $4015
+    0xA9, 0x40,       // $5018 LDA #$40
+    0x8D, 0x17, 0x40, // $501A STA $4017
+    0xA9, 0x00,       // $501D LDA #song
+    0xA2, 0x00,       // $501F LDX #region
+    0x20, 0x00, 0x00, // $5021 JSR init
+    0x8D, 0x00, 0x51, // $5024 STA $5100
+    0x2C, 0x00, 0x51, // $5027 BIT $5100
+    0x10, 0xFB,       // $502A BPL $5027
+    0x20, 0x00, 0x00, // $502C JSR play
+    0x4C, 0x27, 0x50, // $502F JMP $5027
+    0x40,             // $5032 RTI
+];
+const SONG: usize = 0x1E;
+const REGION: usize = 0x20;
+const INIT: usize = 0x22;
+const PLAY: usize = 0x2D;
+const RTI: u16 = DRIVER_ADDRESS + 0x32;
+
+// The cartridge side of an NSF player: 4K PRG banks switched at $5FF8-$5FFF,
+// 8K of PRG RAM, and a driver at $5000 that the vectors point into.
+pub struct NsfMapper {
+    prg: Vec<u8>,
+    banks: [u8; 8],
+    bankable: bool,
+    prg_ram: Box<[u8; 0x2000]>,
+    driver: [u8; DRIVER.len()],
+    // CPU cycles between PLAY calls, and how far along the current one is.
+    period: f64,
+    timer: f64,
+    timer_running: bool,
+    play_due: bool,
+}
+impl NsfMapper {
+    // Sets the driver up to play a song, with `period` CPU cycles between PLAY calls.
+    pub fn new(nsf: &Nsf, song: u8, period: f64) -> Self {
+        // Files without banking are laid out as if bank n were in slot n.
+        let (padding, banks) = match nsf.banks {
+            Some(banks) => (nsf.load_address as usize & 0xFFF, banks),
+            None => (nsf.load_address as usize - 0x8000, [0, 1, 2, 3, 4, 5, 6, 7]),
+        };
+        let mut prg = vec![0; padding];
+        prg.extend_from_slice(&nsf.data);
+        prg.resize(prg.len().next_multiple_of(0x1000).max(0x1000), 0);
+
+        let mut driver = DRIVER;
+        driver[SONG] = song;
+        driver[REGION] = (nsf.region == Region::Pal) as u8;
+        driver[INIT..INIT + 2].copy_from_slice(&nsf.init_address.to_le_bytes());
+        driver[PLAY..PLAY + 2].copy_from_slice(&nsf.play_address.to_le_bytes());
+
+        Self {
+            prg,
+            banks,
+            bankable: nsf.banks.is_some(),
+            prg_ram: Box::new([0; 0x2000]),
+            driver,
+            period,
+            timer: 0.0,
+            timer_running: false,
+            play_due: false,
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_running {
+            return;
+        };
+        self.timer += 1.0;
+        if self.timer >= self.period {
+            self.timer -= self.period;
+            self.play_due = true;
+        }
+    }
+
+    fn handle_cpu(&mut self, cpu: &mut CpuBus) {
+        let addr = cpu.address();
+        if cpu.read() {
+            if addr == TIMER_REGISTER {
+                cpu.set_data((self.play_due as u8) << 7);
+                self.play_due = false;
+            } else if let Some(data) = self.peek_cpu(addr) {
+                cpu.set_data(data);
+            }
+            return;
+        };
+
+        match addr {
+            TIMER_REGISTER => {
+                self.timer = 0.0;
+                self.timer_running = true;
+            }
+            0x5FF8..=0x5FFF if self.bankable => self.banks[addr as usize - 0x5FF8] = cpu.data(),
+            0x6000..=0x7FFF => self.prg_ram[addr as usize % 0x2000] = cpu.data(),
+            _ => (),
+        }
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank = self.banks[(addr as usize - 0x8000) / 0x1000] as usize;
+        let banks = self.prg.len() / 0x1000;
+        bank % banks * 0x1000 + addr as usize % 0x1000
+    }
+}
+impl Mapper for NsfMapper {
+    fn cycle(&mut self, _bus: &mut MapperBus, cpu: &mut CpuBus, _ppu: &mut PpuBus) {
+        self.clock_timer();
+        self.handle_cpu(cpu);
+    }
+    fn cycle_with_ppu(&mut self, _bus: &mut MapperBus, _ppu: &mut PpuBus) {}
+
+    fn describe(&self) -> MapperState {
+        MapperState {
+            // In units of 8K, so only the even slots are shown.
+            prg_banks: [0, 2, 4, 6].map(|slot| self.banks[slot] as u16 / 2),
+            ..MapperState::init()
+        }
+    }
+
+    fn peek_cpu(&self, addr: u16) -> Option<u8> {
+        let driver = DRIVER_ADDRESS..DRIVER_ADDRESS + DRIVER.len() as u16;
+        let vector = |vector: u16| vector.to_le_bytes()[addr as usize & 1];
+        match addr {
+            _ if driver.contains(&addr) => Some(self.driver[(addr - DRIVER_ADDRESS) as usize]),
+            0x6000..=0x7FFF => Some(self.prg_ram[addr as usize % 0x2000]),
+            0xFFFA..=0xFFFB | 0xFFFE..=0xFFFF => Some(vector(RTI)),
+            0xFFFC..=0xFFFD => Some(vector(DRIVER_ADDRESS)),
+            0x8000..=0xFFFF => Some(self.prg[self.prg_index(addr)]),
+            _ => None,
+        }
+    }
+    fn poke_cpu(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[addr as usize % 0x2000] = data;
+        };
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bytes(&self.banks);
+        out.bytes(&*self.prg_ram);
+        out.u64(self.timer.to_bits());
+        out.bool(self.timer_running);
+        out.bool(self.play_due);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes(&mut self.banks)?;
+        state.bytes(&mut *self.prg_ram)?;
+        self.timer = f64::from_bits(state.u64()?);
+        self.timer_running = state.bool()?;
+        self.play_due = state.bool()?;
+        if !(0.0..self.period.max(1.0)).contains(&self.timer) {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{input::Controller, nesbus::PowerUpState};
+use std::{error::Error, fmt, fmt::Write};
+
+// FCEUX's frame commands. Disk and coin commands aren't supported and get ignored.
+pub const COMMAND_RESET: u8 = 1;
+pub const COMMAND_POWER: u8 = 2;
+
+// Header keys this emulator reads and writes itself. Others are kept as they are.
+const OWN_KEYS: [&str; 6] = [
+    "version",
+    "palFlag",
+    "port0",
+    "port1",
+    "port2",
+    "powerUpRam",
+];
+// The order FM2 lists the buttons in, which is also their bits in Controller from 7 down to 0.
+const BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+// Input for one frame, in the order the frames ran.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MovieFrame {
+    pub commands: u8,
+    pub controllers: [Controller; 2],
+}
+
+// An FCEUX .fm2 movie with two standard controllers, played from power on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Movie {
+    pub pal: bool,
+    // Written as a `powerUpRam` line, which FCEUX ignores. Other emulators fill RAM their own way,
+    // so their movies may desync without one.
+    pub power_up: PowerUpState,
+    pub header: Vec<(String, String)>,
+    pub frames: Vec<MovieFrame>,
+}
+impl Movie {
+    pub fn new(pal: bool, power_up: PowerUpState) -> Self {
+        Self {
+            pal,
+            power_up,
+            header: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn parse(src: &str) -> Result<Self, MovieError> {
+        let mut movie = Self::new(false, PowerUpState::Zeroed);
+        let mut ports = [true, true];
+        for (index, line) in src.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                movie
+                    .frames
+                    .push(parse_frame(line, ports).ok_or(MovieError::Frame(number))?);
+                continue;
+            };
+            if line.trim().is_empty() {
+                continue;
+            };
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "version" if value != "3" => return Err(MovieError::Unsupported(line.to_string())),
+                "binary" | "fourscore" | "savestate" if !matches!(value, "" | "0") => {
+                    return Err(MovieError::Unsupported(line.to_string()));
+                }
+                "palFlag" => movie.pal = value == "1",
+                "port0" | "port1" => {
+                    let port = (key == "port1") as usize;
+                    ports[port] = match value {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(MovieError::Unsupported(line.to_string())),
+                    };
+                }
+                "port2" if value != "0" => return Err(MovieError::Unsupported(line.to_string())),
+                "powerUpRam" => {
+                    movie.power_up = parse_power_up(value).ok_or(MovieError::Header(number))?;
+                }
+                _ if OWN_KEYS.contains(&key) => (),
+                _ => movie.header.push((key.to_string(), value.to_string())),
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "version 3").unwrap();
+        for (key, value) in &self.header {
+            writeln!(out, "{key} {value}").unwrap();
+        }
+        writeln!(out, "palFlag {}", self.pal as u8).unwrap();
+        writeln!(out, "powerUpRam {}", write_power_up(&self.power_up)).unwrap();
+        writeln!(out, "port0 1\nport1 1\nport2 0").unwrap();
+        for frame in &self.frames {
+            let [first, second] = frame.controllers.map(write_controller);
+            writeln!(out, "|{}|{first}|{second}||", frame.commands).unwrap();
+        }
+        out
+    }
+}
+
+// `|commands|port0|port1|port2|`, where an unplugged port has an empty field.
+fn parse_frame(line: &str, ports: [bool; 2]) -> Option<MovieFrame> {
+    let mut fields = line.split('|').skip(1);
+    let commands = fields.next()?.trim().parse().ok()?;
+    let mut controllers = [Controller(0); 2];
+    for (controller, plugged) in controllers.iter_mut().zip(ports) {
+        let field = fields.next()?;
+        if plugged {
+            *controller = parse_controller(field)?;
+        }
+    }
+    Some(MovieFrame {
+        commands,
+        controllers,
+    })
+}
+// Anything other than a dot or a space is a pressed button.
+fn parse_controller(field: &str) -> Option<Controller> {
+    if field.len() != BUTTONS.len() {
+        return None;
+    };
+    let bits = field.bytes().fold(0, |bits, button| {
+        let pressed = button != b'.' && button != b' ';
+        bits << 1 | pressed as u8
+    });
+    Some(Controller(bits))
+}
+fn write_controller(controller: Controller) -> String {
+    let bit = |index: usize| controller.0 & (0x80 >> index) != 0;
+    (0..8)
+        .map(|index| {
+            if bit(index) {
+                BUTTONS[index] as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+fn parse_power_up(value: &str) -> Option<PowerUpState> {
+    let (kind, arg) = value.split_once(' ').unwrap_or((value, ""));
+    let state = match kind {
+        "zeroed" => PowerUpState::Zeroed,
+        "filled" => PowerUpState::Filled(u8::from_str_radix(arg, 16).ok()?),
+        "random" => PowerUpState::Random(arg.parse().ok()?),
+        "pattern" if arg.len() % 2 == 0 => {
+            let byte = |i: usize| u8::from_str_radix(arg.get(i..i + 2)?, 16).ok();
+            PowerUpState::Pattern((0..arg.len()).step_by(2).map(byte).collect::<Option<_>>()?)
+        }
+        _ => return None,
+    };
+    Some(state)
+}
+fn write_power_up(state: &PowerUpState) -> String {
+    match state {
+        PowerUpState::Zeroed => "zeroed".to_string(),
+        PowerUpState::Filled(value) => format!("filled {value:02X}"),
+        PowerUpState::Random(seed) => format!("random {seed}"),
+        PowerUpState::Pattern(pattern) => {
+            let hex: String = pattern.iter().map(|byte| format!("{byte:02X}")).collect();
+            format!("pattern {hex}")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MovieError {
+    // Line numbers, starting at 1.
+    Header(usize),
+    Frame(usize),
+    // A header line asking for something only FCEUX itself can play, like a Four Score.
+    Unsupported(String),
+}
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Header(line) => write!(f, "malformed header on line {line}"),
+            Self::Frame(line) => write!(f, "malformed input on line {line}"),
+            Self::Unsupported(line) => write!(f, "movies with \"{line}\" aren't supported"),
+        }
+    }
+}
+impl Error for MovieError {}
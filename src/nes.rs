@@ -0,0 +1,394 @@
+use crate::{
+    cheats::CheatEngine,
+    debugger::NesObserver,
+    input::ControllerState,
+    mapper::Mapper,
+    movie::{Movie, MovieFrame, COMMAND_POWER, COMMAND_RESET},
+    nesbus::NesBus,
+    ppu::pixel_buffer::PixelBuffer,
+    region::Region,
+    saves::{BatterySaves, SaveStore},
+    state::{SaveState, StateError, StateReader, StateWriter},
+    trace::status,
+};
+use cpu_6502::{Bus, Cpu};
+use std::io;
+
+const STATE_MAGIC: &[u8; 4] = b"NSST";
+// Bumped whenever anything changes what goes into a state, so older ones are turned away.
+pub const STATE_VERSION: u16 = 2;
+
+// The CPU and everything connected to it.
+pub struct Nes<M> {
+    pub cpu: Cpu,
+    pub bus: NesBus<M>,
+    samples: Vec<f32>,
+    movie: MovieMode,
+    saves: Option<BatterySaves>,
+}
+impl<M> Nes<M> {
+    pub fn new(bus: NesBus<M>) -> Self {
+        Self {
+            cpu: Cpu::new(),
+            bus,
+            samples: Vec::new(),
+            movie: MovieMode::Off,
+            saves: None,
+        }
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut CheatEngine {
+        self.bus.cheats_mut()
+    }
+    pub fn set_observer(&mut self, observer: Option<Box<dyn NesObserver>>) {
+        self.bus.set_observer(observer);
+    }
+    // Port 0 is controller 1. The game sees the new state from the start of the next frame,
+    // so it can't change halfway through one.
+    pub fn set_controller(&mut self, port: usize, state: ControllerState) {
+        self.bus.set_controller(port, state);
+    }
+}
+impl<M> Nes<M>
+where
+    M: Mapper,
+{
+    // Memory access for cheat searches and the like, between frames. Neither runs a bus cycle.
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek_cpu(addr)
+    }
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.bus.poke_cpu(addr, value);
+    }
+    // Identifies the last picture the PPU drew, for comparing against known good frames.
+    pub fn framebuffer_hash(&self) -> u64 {
+        self.bus.ppu().pixels().hash()
+    }
+    // For debuggers: reads anywhere in either address space, registers included, without side effects.
+    pub fn peek_cpu(&self, addr: u16) -> u8 {
+        self.bus.peek_cpu(addr)
+    }
+    pub fn peek_ppu(&self, addr: u16) -> u8 {
+        self.bus.peek_ppu(addr)
+    }
+
+    // Runs until the PPU starts the next frame. That doesn't depend on NMI or the vblank flag,
+    // so games that turn NMI off still get their frames. The CPU finishes the instruction
+    // it's in, so a frame can end a few cycles late.
+    pub fn run_frame(&mut self) -> FrameOutput<'_> {
+        // Whatever was set between frames belongs to this one.
+        self.bus.input_mut().latch_controllers();
+        let frame = self.bus.ppu().frame_count();
+        self.bus.input_mut().apply_turbo(frame);
+        self.advance_movie();
+        let cycles = self.bus.cycles();
+        let dots = self.bus.ppu_dots();
+        let frame = self.bus.ppu().frame_count();
+        while self.bus.ppu().frame_count() == frame {
+            self.cpu.exec(&mut self.bus);
+        }
+        self.bus.catch_up_ppu();
+        if let Some(saves) = &mut self.saves {
+            saves.frame(self.bus.mapper());
+        }
+
+        self.samples.clear();
+        self.bus.drain_audio(&mut self.samples);
+        FrameOutput {
+            pixels: self.bus.ppu().pixels(),
+            samples: &self.samples,
+            cpu_cycles: self.bus.cycles() - cycles,
+            ppu_dots: self.bus.ppu_dots() - dots,
+        }
+    }
+    // Runs whole instructions until at least this many CPU cycles have passed.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let end = self.bus.cycles() + cycles;
+        while self.bus.cycles() < end {
+            self.cpu.exec(&mut self.bus);
+        }
+    }
+    // Runs until the PPU is this many scanlines further along, counting the ones in vblank.
+    pub fn run_scanlines(&mut self, lines: u64) {
+        let end = self.scanline() + lines;
+        while self.scanline() < end {
+            self.cpu.exec(&mut self.bus);
+            self.bus.catch_up_ppu();
+        }
+    }
+    fn scanline(&self) -> u64 {
+        let ppu = self.bus.ppu();
+        let lines = self.bus.region().ppu_lines() as u64;
+        ppu.frame_count() * lines + ppu.dot()[1] as u64
+    }
+
+    // Presses the RESET button. The CPU jumps through the reset vector once it sees the line.
+    pub fn reset(&mut self) {
+        self.record_command(COMMAND_RESET);
+        self.bus.reset();
+    }
+    // Memory on the cartridge survives, like battery backed saves do.
+    pub fn power_cycle(&mut self) {
+        self.record_command(COMMAND_POWER);
+        self.cpu = Cpu::new();
+        self.bus.power_cycle();
+    }
+
+    // Keeps the cartridge's battery RAM in the store from now on, starting from the game's
+    // last save. Changes are written out every few seconds and when the console is dropped.
+    pub fn attach_save_store(&mut self, store: Box<dyn SaveStore>, game_id: String) {
+        self.saves = None;
+        self.saves = Some(BatterySaves::attach(store, game_id, self.bus.mapper_mut()));
+    }
+    pub fn flush_saves(&mut self) -> io::Result<()> {
+        match &mut self.saves {
+            Some(saves) => saves.flush(self.bus.mapper()),
+            None => Ok(()),
+        }
+    }
+
+    // Movies start from power on, in the region and with the RAM they were recorded with.
+    // While one plays, run_frame takes the controllers from it, and live input comes back
+    // once it's over.
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.movie = MovieMode::Off;
+        let region = if movie.pal { Region::Pal } else { Region::Ntsc };
+        // Changing it starts the PPU over, which would lose Vs. System settings.
+        if self.bus.region() != region {
+            self.bus.set_region(region);
+        }
+        self.bus.set_power_up(&movie.power_up);
+        self.power_cycle();
+        self.movie = MovieMode::Playing { movie, frame: 0 };
+    }
+    // Power cycles, then records the controllers at the start of every frame.
+    pub fn record_movie(&mut self) {
+        self.movie = MovieMode::Off;
+        self.power_cycle();
+        let pal = self.bus.region() == Region::Pal;
+        let movie = Movie::new(pal, self.bus.power_up().clone());
+        self.movie = MovieMode::Recording { movie, commands: 0 };
+    }
+    // Stops playing or recording, and hands back the movie.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        match std::mem::replace(&mut self.movie, MovieMode::Off) {
+            MovieMode::Off => None,
+            MovieMode::Playing { movie, .. } | MovieMode::Recording { movie, .. } => Some(movie),
+        }
+    }
+    pub fn movie_playing(&self) -> bool {
+        match &self.movie {
+            MovieMode::Playing { movie, frame } => *frame < movie.frames.len(),
+            _ => false,
+        }
+    }
+    pub fn movie_recording(&self) -> bool {
+        matches!(self.movie, MovieMode::Recording { .. })
+    }
+    fn advance_movie(&mut self) {
+        match &mut self.movie {
+            MovieMode::Off => (),
+            MovieMode::Playing { movie, frame } => {
+                let Some(&input) = movie.frames.get(*frame) else {
+                    return;
+                };
+                *frame += 1;
+                // Commands are replayed the way FCEUX does, before the frame's input.
+                if input.commands & COMMAND_POWER != 0 {
+                    self.cpu = Cpu::new();
+                    self.bus.power_cycle();
+                } else if input.commands & COMMAND_RESET != 0 {
+                    self.bus.reset();
+                }
+                *self.bus.controllers_mut() = input.controllers;
+            }
+            MovieMode::Recording { movie, commands } => {
+                movie.frames.push(MovieFrame {
+                    commands: std::mem::take(commands),
+                    controllers: *self.bus.controllers_mut(),
+                });
+            }
+        }
+    }
+    fn record_command(&mut self, command: u8) {
+        if let MovieMode::Recording { commands, .. } = &mut self.movie {
+            *commands |= command;
+        }
+    }
+
+    // States are meant to be taken between instructions, which is the only time the frontend runs.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = StateWriter::init();
+        out.bytes(STATE_MAGIC);
+        out.u16(STATE_VERSION);
+        CpuRegisters::of(&self.cpu).save_state(&mut out);
+        self.bus.save_state(&mut out);
+        out.finish()
+    }
+    // A state that fails to load leaves the console as it was.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let Some(state) = state.strip_prefix(STATE_MAGIC) else {
+            return Err(StateError::NotAState);
+        };
+        let mut reader = StateReader::new(state);
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::Version(version));
+        };
+        let mut registers = CpuRegisters::init();
+        registers.load_state(&mut reader)?;
+
+        let backup = self.save_state();
+        let loaded = self
+            .bus
+            .load_state(&mut reader)
+            .and_then(|_| reader.finish());
+        if let Err(err) = loaded {
+            let mut reader = StateReader::new(&backup[STATE_MAGIC.len() + 2..]);
+            CpuRegisters::init().load_state(&mut reader).unwrap();
+            self.bus.load_state(&mut reader).unwrap();
+            return Err(err);
+        };
+        self.cpu = registers.restore();
+        Ok(())
+    }
+}
+
+enum MovieMode {
+    Off,
+    Playing { movie: Movie, frame: usize },
+    // Commands given since the last frame, recorded with the next one.
+    Recording { movie: Movie, commands: u8 },
+}
+
+// What one call of run_frame produced. Audio that wasn't drained before is included.
+pub struct FrameOutput<'a> {
+    pub pixels: &'a PixelBuffer,
+    pub samples: &'a [f32],
+    pub cpu_cycles: u64,
+    pub ppu_dots: u64,
+}
+
+// The core has no setters for its registers, so they're restored by having a fresh CPU
+// run a few instructions on a bus of its own. Interrupts it had latched but not yet taken are lost.
+struct CpuRegisters {
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: u8,
+    pc: u16,
+    p: u8,
+}
+impl CpuRegisters {
+    fn init() -> Self {
+        Self {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0,
+            pc: 0,
+            p: 0,
+        }
+    }
+    fn of(cpu: &Cpu) -> Self {
+        Self {
+            a: cpu.a(),
+            x: cpu.x(),
+            y: cpu.y(),
+            sp: cpu.sp() as u8,
+            pc: cpu.pc(),
+            p: status(cpu),
+        }
+    }
+
+    fn restore(&self) -> Cpu {
+        let mut cpu = Cpu::new();
+        let mut bus = LoaderBus::new(self);
+        // Wherever the fresh CPU starts, it ends up in the loader before long.
+        for _ in 0..8 {
+            if cpu.pc() == LoaderBus::ADDRESS {
+                break;
+            };
+            cpu.exec(&mut bus);
+        }
+        for _ in 0..LoaderBus::INSTRUCTIONS {
+            cpu.exec(&mut bus);
+        }
+        cpu
+    }
+}
+impl SaveState for CpuRegisters {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.a);
+        out.u8(self.x);
+        out.u8(self.y);
+        out.u8(self.sp);
+        out.u16(self.pc);
+        out.u8(self.p);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.a = state.u8()?;
+        self.x = state.u8()?;
+        self.y = state.u8()?;
+        self.sp = state.u8()?;
+        self.pc = state.u16()?;
+        self.p = state.u8()?;
+        Ok(())
+    }
+}
+
+// Every byte outside the loader reads as $20, so running anywhere is JSR $2020,
+// and every vector points at $2020 too. The loader sets the registers and returns
+// through RTI, which pulls P and PC from where the stack will be once it's done.
+struct LoaderBus {
+    program: [u8; 10],
+    // The three bytes RTI pulls, and their addresses.
+    stack: [(u16, u8); 3],
+}
+impl LoaderBus {
+    const ADDRESS: u16 = 0x2020;
+    const INSTRUCTIONS: usize = 6;
+
+    fn new(registers: &CpuRegisters) -> Self {
+        let sp = registers.sp.wrapping_sub(3);
+        let [pc_low, pc_high] = registers.pc.to_le_bytes();
+        let stack = |offset: u8| 0x100 | sp.wrapping_add(offset) as u16;
+        #[rustfmt::skip]
+        let program = [
+            0xA2, sp,          // LDX #sp-3
+            0x9A,              // TXS
+            0xA9, registers.a, // LDA #a
+            0xA2, registers.x, // LDX #x
+            0xA0, registers.y, // LDY #y
+            0x40,              // RTI
+        ];
+        Self {
+            program,
+            stack: [
+                (stack(1), registers.p),
+                (stack(2), pc_low),
+                (stack(3), pc_high),
+            ],
+        }
+    }
+}
+impl Bus for LoaderBus {
+    fn rst(&self) -> bool {
+        false
+    }
+    fn nmi(&self) -> bool {
+        false
+    }
+    fn irq(&self) -> bool {
+        false
+    }
+    fn read(&mut self, addr: u16, _sync: bool, _halt: bool) -> (u8, bool) {
+        let offset = addr.wrapping_sub(Self::ADDRESS) as usize;
+        if let Some(&data) = self.program.get(offset) {
+            return (data, false);
+        };
+        let pulled = self.stack.iter().find(|&&(at, _)| at == addr);
+        (pulled.map_or(0x20, |&(_, data)| data), false)
+    }
+    fn write(&mut self, _addr: u16, _data: u8) {}
+}
@@ -1,13 +1,31 @@
-
 use crate::{
-    apu::Apu, input::{Controller, Input}, mapper::{Mapper, MapperBus}, ppu::{Ppu, PpuBus}, util::{get_flag_u8, set_flag_u8}
+    apu::{Apu, Channel},
+    cheats::CheatEngine,
+    debugger::{AccessHook, NesObserver, WatchHit, Watchpoints},
+    input::{Controller, ControllerState, Input, VsSwitches},
+    mapper::{Mapper, MapperBus, MapperState},
+    ppu::{vs_ppu::VsPpu, Ppu, PpuBus},
+    region::Region,
+    state::{SaveState, StateError, StateReader, StateWriter},
+    util::{get_flag_u8, set_flag_u8},
 };
 use cpu_6502::Bus;
 
+// Longer than any instruction, so the CPU sees the line at an instruction boundary.
+const RESET_CYCLES: u8 = 8;
 
 pub struct NesBus<M> {
     cycle: u64,
+    dots: u64,
+    reset_cycles: u8,
+    region: Region,
+    ppu_clock: u32,
+    batched_ppu: bool,
+    ppu_debt: u32,
     cpu_bus: CpuBus,
+    // The last value driven onto the CPU data bus. It's deliberately kept forever instead of
+    // decaying: the CPU drives the bus every cycle, so no value sits long enough to fade.
+    open_bus: u8,
     ppu_bus: PpuBus,
     mapper_bus: MapperBus,
     apu: Apu,
@@ -16,67 +34,388 @@ pub struct NesBus<M> {
     input: Input,
     ram: Box<[u8; 2048]>,
     vram: Box<[u8; 2048]>,
+    power_up: PowerUpState,
+    cheats: CheatEngine,
+    watchpoints: Watchpoints,
+    watch_hit: Option<WatchHit>,
+    access_hook: Option<AccessHook>,
+    observer: Option<Box<dyn NesObserver>>,
+    // NMI and IRQ as the observer last saw them.
+    observed_lines: [bool; 2],
 }
 impl<M> NesBus<M> {
     pub fn new(mapper: M) -> Self {
+        Self::new_with(mapper, &PowerUpState::Zeroed)
+    }
+    pub fn new_with(mapper: M, power_up: &PowerUpState) -> Self {
+        let mut ram = Box::new([0; 2048]);
+        power_up.fill(&mut *ram);
         Self {
             cycle: 0,
+            dots: 0,
+            reset_cycles: 0,
+            region: Region::Ntsc,
+            ppu_clock: 0,
+            batched_ppu: false,
+            ppu_debt: 0,
             cpu_bus: CpuBus::init(),
+            open_bus: 0,
             ppu_bus: PpuBus::init(),
             mapper_bus: MapperBus::init(),
             apu: Apu::init(),
             ppu: Ppu::init(),
             mapper,
             input: Input::init(),
-            ram: Box::new([0; 2048]),
+            ram,
             vram: Box::new([0; 2048]),
+            power_up: power_up.clone(),
+            cheats: CheatEngine::init(),
+            watchpoints: Watchpoints::init(),
+            watch_hit: None,
+            access_hook: None,
+            observer: None,
+            observed_lines: [false; 2],
         }
     }
 
     pub fn ppu(&self) -> &Ppu {
         &self.ppu
     }
+    pub fn take_frame_complete(&mut self) -> bool {
+        self.ppu.take_frame_complete()
+    }
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
     pub fn input_mut(&mut self) -> &mut Input {
         &mut self.input
     }
+    pub fn ram(&self) -> &[u8] {
+        &*self.ram
+    }
     pub fn vram(&self) -> &[u8] {
         &*self.vram
     }
     pub fn cycles(&self) -> u64 {
         self.cycle
     }
+    // PPU dots since power on, including ones the PPU hasn't caught up on yet.
+    pub fn ppu_dots(&self) -> u64 {
+        self.dots
+    }
+    pub fn power_up(&self) -> &PowerUpState {
+        &self.power_up
+    }
+    // Takes effect with the next power cycle.
+    pub fn set_power_up(&mut self, power_up: &PowerUpState) {
+        self.power_up = power_up.clone();
+    }
     pub fn controllers_mut(&mut self) -> &mut [Controller; 2] {
         self.input.controllers_mut()
     }
+    pub fn set_controller(&mut self, port: usize, state: ControllerState) {
+        self.input.set_controller(port, state);
+    }
+    pub fn drain_audio(&mut self, out: &mut Vec<f32>) {
+        self.apu.drain_audio(out);
+    }
+    pub fn sample_rate(&self) -> f64 {
+        self.apu.sample_rate()
+    }
+    pub fn set_expansion_volume(&mut self, volume: f32) {
+        self.apu.set_expansion_volume(volume);
+    }
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.apu.set_channel_enabled(channel, enabled);
+    }
+    pub fn channel_enabled(&self, channel: Channel) -> bool {
+        self.apu.channel_enabled(channel)
+    }
+
+    // Replaces the APU and PPU with ones running on the timing of the given region.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu_clock = 0;
+        self.apu = Apu::new(region);
+        self.ppu = Ppu::new(region);
+    }
+    pub fn region(&self) -> Region {
+        self.region
+    }
+    // Lets the PPU fall behind the CPU and catch up a line at a time, or whenever the CPU
+    // does something that could see or change PPU state. Lines the CPU left alone are drawn
+    // in one go and idle dots in vblank are skipped. For mappers that follow the rule on the
+    // Mapper trait, the picture and everything the CPU sees, NMI timing included, is identical
+    // to the dot loop. Only ppu() can lag behind until catch_up_ppu.
+    pub fn set_batched_ppu(&mut self, enabled: bool) {
+        self.batched_ppu = enabled;
+    }
+    pub fn set_ppu_warm_up(&mut self, enabled: bool) {
+        self.ppu.set_warm_up(enabled);
+    }
+    pub fn cheats_mut(&mut self) -> &mut CheatEngine {
+        &mut self.cheats
+    }
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+    // The first access that matched a watchpoint since the last call.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+    // Called with every CPU access once the devices have answered it.
+    pub fn set_access_hook(&mut self, hook: Option<AccessHook>) {
+        self.access_hook = hook;
+    }
+    // With the PPU batched, scanlines are reported when the PPU catches up, a little late.
+    pub fn set_observer(&mut self, observer: Option<Box<dyn NesObserver>>) {
+        self.observer = observer;
+    }
+    pub fn enable_vs_system(&mut self, ppu: VsPpu) {
+        self.ppu.set_vs_ppu(Some(ppu));
+        self.input.set_vs_switches(Some(VsSwitches::default()));
+    }
 }
 impl<M> NesBus<M>
 where
     M: Mapper,
 {
+    // Presses the RESET button, holding the CPU's reset line long enough for it to notice.
+    pub fn reset(&mut self) {
+        self.catch_up_ppu();
+        self.ppu.reset();
+        self.apu.reset();
+        self.mapper.reset();
+        self.reset_cycles = RESET_CYCLES;
+        self.cpu_bus.set_rst(true);
+    }
+    // Turns the console off and on again. RAM comes back as the power-up state says,
+    // and only the cartridge keeps its memory. The CPU has to be replaced separately.
+    pub fn power_cycle(&mut self) {
+        self.reset_cycles = 0;
+        self.ppu_clock = 0;
+        self.ppu_debt = 0;
+        self.cpu_bus = CpuBus::init();
+        self.open_bus = 0;
+        self.ppu_bus = PpuBus::init();
+        self.mapper_bus = MapperBus::init();
+        self.apu.power_cycle();
+        self.ppu.power_cycle();
+        self.mapper.power_cycle();
+        self.power_up.fill(&mut *self.ram);
+        self.vram.fill(0);
+    }
+    pub fn mapper_state(&self) -> MapperState {
+        self.mapper.describe()
+    }
+    pub fn mapper(&self) -> &M {
+        &self.mapper
+    }
+    pub fn mapper_mut(&mut self) -> &mut M {
+        &mut self.mapper
+    }
+
+    // Snapshots for debuggers. None of these touch the buses, so they can be polled at any time.
+    pub fn debug_nametable(&self, index: u8) -> [u8; 1024] {
+        let page = self.mapper.describe().mirroring.vram_page(index);
+        let mut nametable = [0; 1024];
+        nametable.copy_from_slice(&self.vram[page * 1024..][..1024]);
+        nametable
+    }
+    pub fn debug_pattern_table(&self, half: u8) -> [u8; 4096] {
+        let base = half as u16 % 2 * 0x1000;
+        std::array::from_fn(|i| self.mapper.peek_chr(base + i as u16))
+    }
+    pub fn debug_oam(&self) -> &[u8; 256] {
+        self.ppu.debug_oam()
+    }
+    pub fn debug_palette(&self) -> &[u8; 32] {
+        self.ppu.debug_palette()
+    }
+    // What a CPU read would return, without clocking anything or touching any register.
+    // Controller ports aren't modelled and show the open bus value.
+    pub fn peek_cpu(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x07FF => self.ram[addr as usize],
+            0x2000..=0x3FFF => self.ppu.peek_register(addr),
+            0x4015 => self.apu.peek_status() | self.open_bus & 0x20,
+            _ => self.mapper.peek_cpu(addr).unwrap_or(self.open_bus),
+        }
+    }
+    // The same for the PPU's address space.
+    pub fn peek_ppu(&self, addr: u16) -> u8 {
+        let addr = addr % 0x4000;
+        if addr >= 0x3F00 {
+            return self.ppu.peek_palette(addr);
+        };
+        self.mapper.peek_ppu(addr).unwrap_or_else(|| {
+            let page = self
+                .mapper
+                .describe()
+                .mirroring
+                .vram_page((addr >> 10) as u8 % 4);
+            self.vram[page * 1024 + addr as usize % 1024]
+        })
+    }
+    // Writes RAM or cartridge RAM without running a cycle. Registers aren't touched.
+    pub fn poke_cpu(&mut self, addr: u16, data: u8) {
+        if addr < 2048 {
+            self.ram[addr as usize] = data;
+        } else {
+            self.mapper.poke_cpu(addr, data);
+        }
+    }
+
     fn cycle(&mut self) {
         self.cpu_bus.set_irq(false);
+        self.reset_cycles = self.reset_cycles.saturating_sub(1);
+        self.cpu_bus.set_rst(self.reset_cycles != 0);
+        // The PPU runs off the same master clock, one of its dots always lines up with the CPU cycle.
+        self.ppu_clock += self.region.cpu_divider() - self.region.ppu_divider();
         self.cpu_cycle();
-        self.ppu_cycle();
-        self.ppu_cycle();
+        self.dots += 1;
+        while self.ppu_clock >= self.region.ppu_divider() {
+            self.ppu_clock -= self.region.ppu_divider();
+            self.dots += 1;
+            if self.ppu_debt != 0 {
+                self.ppu_debt += 1;
+            } else {
+                self.ppu_cycle();
+            }
+        }
+        // One cycle can finish a line and reach the NMI dot of the next.
+        let mut sync = self.ppu.dots_until_sync();
+        while self.ppu_debt >= sync {
+            self.pay_ppu_debt(sync);
+            sync = self.ppu.dots_until_sync();
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints();
+        }
+        if let Some(hook) = &mut self.access_hook {
+            let cpu = &self.cpu_bus;
+            hook(self.cycle, cpu.address(), cpu.data(), cpu.read());
+        }
+        if let Some(observer) = &mut self.observer {
+            let lines = [self.cpu_bus.nmi(), self.cpu_bus.irq()];
+            if lines[0] && !self.observed_lines[0] {
+                observer.on_nmi(self.cycle);
+            }
+            if lines[1] && !self.observed_lines[1] {
+                observer.on_irq(self.cycle);
+            }
+            self.observed_lines = lines;
+        }
 
         self.cycle += 1;
     }
     fn cpu_cycle(&mut self) {
+        self.apu.set_expansion_audio(self.mapper.audio_output());
         self.apu.cycle(&mut self.cpu_bus);
-        self.ppu.cycle(&mut self.ppu_bus, &mut self.cpu_bus);
+        if self.batched_ppu && !self.cpu_reaches_ppu() {
+            self.ppu_debt += 1;
+        } else {
+            self.catch_up_ppu();
+            let line = self.ppu.dot()[1];
+            self.ppu.cycle(&mut self.ppu_bus, &mut self.cpu_bus);
+            self.observe_line(line);
+        }
         self.mapper
             .cycle(&mut self.mapper_bus, &mut self.cpu_bus, &mut self.ppu_bus);
-        self.input.cycle(&mut self.cpu_bus);
+        if !self.cheats.is_empty() {
+            self.cheats.apply(&mut self.cpu_bus);
+        }
+        self.input.cycle(&mut self.cpu_bus, &self.ppu);
         self.update_ram();
+        if !self.cheats.is_empty() {
+            self.cheats.freeze_ram(&mut self.ram);
+        }
         self.update_vram();
     }
+    fn check_watchpoints(&mut self) {
+        let address = self.cpu_bus.address();
+        let read = self.cpu_bus.read();
+        if self.watch_hit.is_some() || !self.watchpoints.matches(address, read) {
+            return;
+        };
+        self.watch_hit = Some(WatchHit {
+            address,
+            data: self.cpu_bus.data(),
+            read,
+            cycle: self.cycle,
+        });
+    }
+    // PPU registers, any write that might reach the cartridge and switch CHR banks,
+    // and controller reads, which depend on the frame for latched input and the Zapper.
+    fn cpu_reaches_ppu(&self) -> bool {
+        let addr = self.cpu_bus.address();
+        let input = (addr == 0x4016 || addr == 0x4017) && self.cpu_bus.read();
+        (0x2000..0x4000).contains(&addr) || (addr >= 0x2000 && !self.cpu_bus.read()) || input
+    }
+    pub fn catch_up_ppu(&mut self) {
+        self.pay_ppu_debt(self.ppu_debt);
+    }
+    // Runs the given number of the dots the PPU is behind by. Lines owed in full are drawn
+    // at once, the rest goes through the dot loop.
+    fn pay_ppu_debt(&mut self, dots: u32) {
+        let target = self.ppu_debt - dots;
+        while self.ppu_debt != target {
+            let max = self.ppu_debt - target;
+            let line = self.ppu.dot()[1];
+            let ran = match self.run_scanline(max) {
+                0 => self.ppu.skip_idle_dots(max, &self.ppu_bus),
+                ran => ran,
+            };
+            self.observe_line(line);
+            if ran != 0 {
+                self.ppu_debt -= ran;
+                continue;
+            };
+            self.ppu_cycle();
+            self.ppu_debt -= 1;
+        }
+    }
+    fn run_scanline(&mut self, max: u32) -> u32 {
+        let (mapper, mapper_bus, vram) = (&mut self.mapper, &mut self.mapper_bus, &mut self.vram);
+        let memory = |ppu_bus: &mut PpuBus| {
+            mapper.cycle_with_ppu(mapper_bus, ppu_bus);
+            update_vram(vram, mapper_bus, ppu_bus);
+        };
+        self.ppu
+            .run_scanline(max, &mut self.ppu_bus, &mut self.cpu_bus, memory)
+    }
     fn ppu_cycle(&mut self) {
+        let line = self.ppu.dot()[1];
         self.ppu.cycle_alone(&mut self.ppu_bus, &mut self.cpu_bus);
+        self.observe_line(line);
         self.mapper
             .cycle_with_ppu(&mut self.mapper_bus, &mut self.ppu_bus);
         self.update_vram();
     }
 
+    // Called whenever the PPU may have left the given line. It never moves more than a line at a time.
+    // Starting a new frame latches controller states, then the observer hears about it.
+    fn observe_line(&mut self, line: u16) {
+        let [_, now] = self.ppu.dot();
+        if now == line {
+            return;
+        };
+        if now == 0 {
+            self.input.latch_controllers();
+        }
+        let Some(observer) = &mut self.observer else {
+            return;
+        };
+        let frame = self.ppu.frame_count();
+        if now == 0 {
+            observer.on_frame(frame);
+        }
+        observer.on_scanline(frame, now);
+    }
+
     fn update_ram(&mut self) {
         let addr = self.cpu_bus.address() as usize;
         if addr < 2048 {
@@ -88,20 +427,23 @@ where
         }
     }
     fn update_vram(&mut self) {
-        if !self.mapper_bus.vram_enable() {
-            return;
-        };
-        let a10 = self.mapper_bus.vram_a10();
-        let mask = 1 << 10;
-        let addr = ((self.ppu_bus.address() % 0x800) & !mask) | if a10 { mask } else { 0 };
-        let addr = addr as usize;
+        update_vram(&mut self.vram, &self.mapper_bus, &mut self.ppu_bus);
+    }
+}
+fn update_vram(vram: &mut [u8; 2048], mapper_bus: &MapperBus, ppu_bus: &mut PpuBus) {
+    if !mapper_bus.vram_enable() {
+        return;
+    };
+    let a10 = mapper_bus.vram_a10();
+    let mask = 1 << 10;
+    let addr = ((ppu_bus.address() % 0x800) & !mask) | if a10 { mask } else { 0 };
+    let addr = addr as usize;
 
-        if self.ppu_bus.read_enable() {
-            self.ppu_bus.set_data(self.vram[addr]);
-        }
-        if self.ppu_bus.write_enable() {
-            self.vram[addr] = self.ppu_bus.data();
-        }
+    if ppu_bus.read_enable() {
+        ppu_bus.set_data(vram[addr]);
+    }
+    if ppu_bus.write_enable() {
+        vram[addr] = ppu_bus.data();
     }
 }
 impl<M> Bus for NesBus<M>
@@ -125,7 +467,12 @@ where
         self.cpu_bus.set_halt(halt);
         self.cpu_bus.set_address(addr);
         self.cpu_bus.set_read(true);
+        // Devices that don't respond leave the last driven value on the bus.
+        self.cpu_bus.float(self.open_bus);
         self.cycle();
+        if self.cpu_bus.driven() {
+            self.open_bus = self.cpu_bus.data();
+        }
         let data = self.cpu_bus.data;
         let not_ready = self.cpu_bus.not_ready();
         (data, not_ready)
@@ -136,10 +483,101 @@ where
         self.cpu_bus.set_sync(false);
         self.cpu_bus.set_halt(false);
         self.cpu_bus.set_read(false);
+        self.open_bus = data;
         self.cycle();
     }
 }
 
+// Cheats, debugger watchpoints and hooks, and PPU batching belong to the frontend and aren't saved.
+impl<M> SaveState for NesBus<M>
+where
+    M: Mapper,
+{
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u64(self.cycle);
+        out.u64(self.dots);
+        out.u8(self.reset_cycles);
+        out.u8(self.region as u8);
+        out.u32(self.ppu_clock);
+        out.u32(self.ppu_debt);
+        self.cpu_bus.save_state(out);
+        out.u8(self.open_bus);
+        self.ppu_bus.save_state(out);
+        self.mapper_bus.save_state(out);
+        self.apu.save_state(out);
+        self.ppu.save_state(out);
+        self.mapper.save_state(out);
+        self.input.save_state(out);
+        out.bytes(&*self.ram);
+        out.bytes(&*self.vram);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.cycle = state.u64()?;
+        self.dots = state.u64()?;
+        self.reset_cycles = state.u8()?;
+        self.region = match state.u8()? {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            _ => return Err(StateError::Invalid),
+        };
+        self.ppu_clock = state.u32()?;
+        self.ppu_debt = state.u32()?;
+        self.cpu_bus.load_state(state)?;
+        self.open_bus = state.u8()?;
+        self.ppu_bus.load_state(state)?;
+        self.mapper_bus.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.mapper.load_state(state)?;
+        self.input.load_state(state)?;
+        state.bytes(&mut *self.ram)?;
+        state.bytes(&mut *self.vram)?;
+        if self.ppu_clock >= self.region.ppu_divider() || self.reset_cycles > RESET_CYCLES {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
+
+// What RAM holds at power on. Real consoles start with whatever the chips settle to,
+// and a few games end up depending on it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PowerUpState {
+    #[default]
+    Zeroed,
+    Filled(u8),
+    // Repeated over all of RAM.
+    Pattern(Vec<u8>),
+    // Pseudo-random bytes, the same every time for the same seed.
+    Random(u64),
+}
+impl PowerUpState {
+    pub fn fill(&self, ram: &mut [u8]) {
+        match self {
+            Self::Zeroed => ram.fill(0),
+            Self::Filled(value) => ram.fill(*value),
+            Self::Pattern(pattern) if pattern.is_empty() => ram.fill(0),
+            Self::Pattern(pattern) => {
+                for (byte, &value) in ram.iter_mut().zip(pattern.iter().cycle()) {
+                    *byte = value;
+                }
+            }
+            // SplitMix64, which is plenty for garbage.
+            Self::Random(seed) => {
+                let mut state = *seed;
+                for chunk in ram.chunks_mut(8) {
+                    state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                    z ^= z >> 31;
+                    chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct CpuBus {
     address: u16,
@@ -192,6 +630,20 @@ impl CpuBus {
     }
     pub fn set_data(&mut self, data: u8) {
         self.data = data;
+        self.set_flag(Self::FLAG_DRIVEN, true);
+    }
+    // Registers inside the CPU package answer reads without driving the external bus.
+    pub fn set_internal_data(&mut self, data: u8) {
+        self.data = data;
+        self.set_flag(Self::FLAG_DRIVEN, false);
+    }
+    // Leaves a value on the bus without any device having driven it.
+    pub fn float(&mut self, open_bus: u8) {
+        self.data = open_bus;
+        self.set_flag(Self::FLAG_DRIVEN, false);
+    }
+    pub fn driven(self) -> bool {
+        self.get_flag(Self::FLAG_DRIVEN)
     }
 
     fn set_flag(&mut self, flag: u8, value: bool) {
@@ -231,4 +683,18 @@ impl CpuBus {
     const FLAG_SYNC: u8 = 4;
     const FLAG_NOT_READY: u8 = 5;
     const FLAG_HALT: u8 = 6;
+    const FLAG_DRIVEN: u8 = 7;
+}
+impl SaveState for CpuBus {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u16(self.address);
+        out.u8(self.data);
+        out.u8(self.flags);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.address = state.u16()?;
+        self.data = state.u8()?;
+        self.flags = state.u8()?;
+        Ok(())
+    }
 }
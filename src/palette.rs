@@ -0,0 +1,130 @@
+use std::{error::Error, f64::consts::PI, fmt, fs, io, path::Path};
+
+pub const COLORS: usize = 64;
+pub const EMPHASIS_ROWS: usize = 8;
+// Each emphasized channel dims the other two by this much, for palettes without emphasis rows.
+pub const EMPHASIS_DIM: f64 = 0.816;
+
+static DEFAULT_PALETTE: &[u8; COLORS * 3] = include_bytes!("ntscpalette.pal");
+
+// RGB colors for every combination of a 6 bit color and the three emphasis bits,
+// with emphasis in the upper bits of the index like the PPU outputs them.
+pub struct Palette {
+    colors: Box<[[u8; 3]; COLORS * EMPHASIS_ROWS]>,
+}
+impl Palette {
+    // The palette shipped with the emulator.
+    pub fn init() -> Self {
+        Self::from_bytes(DEFAULT_PALETTE).unwrap()
+    }
+    // Reads a .pal file, either with just the 64 base colors or with all 8 emphasis rows.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PaletteError> {
+        let bytes = fs::read(path).map_err(PaletteError::Io)?;
+        Self::from_bytes(&bytes)
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaletteError> {
+        let mut colors = Box::new([[0; 3]; COLORS * EMPHASIS_ROWS]);
+        match bytes.len() {
+            192 => {
+                for (index, color) in colors.iter_mut().enumerate() {
+                    let base = &bytes[index % COLORS * 3..][..3];
+                    *color = emphasize(base, (index / COLORS) as u8);
+                }
+            }
+            1536 => {
+                for (color, rgb) in colors.iter_mut().zip(bytes.chunks_exact(3)) {
+                    color.copy_from_slice(rgb);
+                }
+            }
+            len => return Err(PaletteError::Size(len)),
+        }
+        Ok(Self { colors })
+    }
+    // Decodes the composite signal the PPU would output for each color, emphasis included.
+    pub fn generate() -> Self {
+        let mut colors = Box::new([[0; 3]; COLORS * EMPHASIS_ROWS]);
+        for (index, color) in colors.iter_mut().enumerate() {
+            *color = decode_signal(index as u16);
+        }
+        Self { colors }
+    }
+
+    pub fn rgb(&self, color: u8, emphasis: u8) -> [u8; 3] {
+        let index = (emphasis as usize & 7) * COLORS + (color as usize & 0x3F);
+        self.colors[index]
+    }
+}
+
+// The red, green and blue emphasis bits each dim the channels they don't name.
+fn emphasize(base: &[u8], emphasis: u8) -> [u8; 3] {
+    let mut color = [0; 3];
+    for (channel, (out, &value)) in color.iter_mut().zip(base).enumerate() {
+        let others = emphasis & !(1 << channel);
+        let dim = EMPHASIS_DIM.powi(others.count_ones() as i32);
+        *out = (value as f64 * dim).round() as u8;
+    }
+    color
+}
+
+// Signal voltages for the four luma levels, at the low and high point of the color wave.
+const LOW_LEVELS: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+const HIGH_LEVELS: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f64 = 0.518;
+const WHITE: f64 = 1.962;
+const EMPHASIS_ATTENUATION: f64 = 0.746;
+// Lines the hues up with how TVs decode them.
+const HUE_OFFSET: f64 = 4.0;
+
+// Averages one color cycle of the square wave a pixel puts out, and converts that from YIQ.
+fn decode_signal(index: u16) -> [u8; 3] {
+    let hue = index & 0x0F;
+    let level = if hue > 13 { 1 } else { index >> 4 & 3 } as usize;
+    let emphasis = index >> 6;
+
+    let low = if hue == 0 {
+        HIGH_LEVELS[level]
+    } else {
+        LOW_LEVELS[level]
+    };
+    let high = if hue > 12 { low } else { HIGH_LEVELS[level] };
+    let in_phase = |hue: u16, phase: u16| (hue + phase) % 12 < 6;
+
+    let [mut y, mut i, mut q] = [0.0; 3];
+    for phase in 0..12 {
+        let mut signal = if in_phase(hue, phase) { high } else { low };
+        let emphasized = (emphasis & 1 != 0 && in_phase(0, phase))
+            || (emphasis & 2 != 0 && in_phase(4, phase))
+            || (emphasis & 4 != 0 && in_phase(8, phase));
+        if emphasized {
+            signal *= EMPHASIS_ATTENUATION;
+        }
+
+        let signal = (signal - BLACK) / (WHITE - BLACK) / 12.0;
+        let angle = PI * (phase as f64 + HUE_OFFSET) / 6.0;
+        y += signal;
+        i += signal * angle.cos();
+        q += signal * angle.sin();
+    }
+
+    let rgb = [
+        y + 0.956 * i + 0.621 * q,
+        y - 0.272 * i - 0.647 * q,
+        y - 1.106 * i + 1.703 * q,
+    ];
+    rgb.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    Size(usize),
+}
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "can't read palette: {err}"),
+            Self::Size(len) => write!(f, "palette is {len} bytes, expected 192 or 1536"),
+        }
+    }
+}
+impl Error for PaletteError {}
@@ -0,0 +1,76 @@
+use crate::{mapper::nsf::NsfMapper, nesbus::NesBus, rom::nsf::Nsf};
+use cpu_6502::Cpu;
+use std::time::Duration;
+
+// Plays the songs of an NSF file on the emulated console, one at a time.
+pub struct NsfPlayer {
+    nsf: Nsf,
+    cpu: Cpu,
+    bus: NesBus<NsfMapper>,
+    song: u8,
+    // The current song stops after this many CPU cycles.
+    limit: Option<u64>,
+}
+impl NsfPlayer {
+    pub fn new(nsf: Nsf) -> Self {
+        let song = nsf.first_song;
+        let (cpu, bus) = start(&nsf, song);
+        Self {
+            nsf,
+            cpu,
+            bus,
+            song,
+            limit: None,
+        }
+    }
+
+    pub fn nsf(&self) -> &Nsf {
+        &self.nsf
+    }
+    pub fn song(&self) -> u8 {
+        self.song
+    }
+    // Restarts the console on another song. Songs past the last one wrap around.
+    pub fn select_song(&mut self, song: u8) {
+        self.song = song % self.nsf.songs;
+        (self.cpu, self.bus) = start(&self.nsf, self.song);
+    }
+
+    // Applies to every song from the start, including the current one.
+    pub fn set_duration_limit(&mut self, limit: Option<Duration>) {
+        let hz = self.nsf.region.cpu_clock_hz();
+        self.limit = limit.map(|limit| (limit.as_secs_f64() * hz) as u64);
+    }
+    pub fn finished(&self) -> bool {
+        self.limit.is_some_and(|limit| self.bus.cycles() >= limit)
+    }
+    pub fn elapsed(&self) -> Duration {
+        let hz = self.nsf.region.cpu_clock_hz();
+        Duration::from_secs_f64(self.bus.cycles() as f64 / hz)
+    }
+
+    // Runs the CPU for about this many cycles, or until the song is over.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let end = self.bus.cycles() + cycles;
+        while self.bus.cycles() < end && !self.finished() {
+            self.cpu.exec(&mut self.bus);
+        }
+    }
+
+    pub fn drain_audio(&mut self, out: &mut Vec<f32>) {
+        self.bus.drain_audio(out);
+    }
+    pub fn sample_rate(&self) -> f64 {
+        self.bus.sample_rate()
+    }
+    pub fn bus(&self) -> &NesBus<NsfMapper> {
+        &self.bus
+    }
+}
+
+fn start(nsf: &Nsf, song: u8) -> (Cpu, NesBus<NsfMapper>) {
+    let period = nsf.speed(nsf.region) as f64 * nsf.region.cpu_clock_hz() / 1_000_000.0;
+    let mut bus = NesBus::new(NsfMapper::new(nsf, song, period));
+    bus.set_region(nsf.region);
+    (Cpu::new(), bus)
+}
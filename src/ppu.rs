@@ -1,14 +1,22 @@
 use crate::{
     nesbus::CpuBus,
+    region::Region,
+    state::{SaveState, StateError, StateReader, StateWriter},
     util::{get_flag_u16, get_flag_u8, set_flag_u16, set_flag_u8},
 };
+use std::ops::RangeInclusive;
 
-use self::pixel_buffer::PixelBuffer;
+use self::{
+    pixel_buffer::{PixelBuffer, WIDTH},
+    vs_ppu::VsPpu,
+};
 
 const DOTS: u16 = 341;
-const LINES: u16 = 262;
+const VBLANK_START: [u16; 2] = [1, 241];
+const LATCH_DECAY_FRAMES: u8 = 36;
 
 pub mod pixel_buffer;
+pub mod vs_ppu;
 
 pub struct Ppu {
     meta: Meta,
@@ -17,19 +25,32 @@ pub struct Ppu {
     v: V,
     t: V,
     dot: [u16; 2],
+    frame_count: u64,
+    frame_complete: bool,
 
     data_latch: u8,
+    io_latch: IoLatch,
     oam_addr: u8,
     oam: Box<[u8; 256]>,
     palette: Box<[u8; 32]>,
 
     shifters: Shifters,
     sprites: Box<Sprites>,
+    sprite_zero_hit_dot: Option<[u16; 2]>,
+    render_was_enabled: bool,
+    // One bit per 8 byte OAM row that gets overwritten with row 0 once rendering starts again.
+    oam_corruption: u32,
 
     pixels: Box<PixelBuffer>,
+    vs_ppu: Option<VsPpu>,
+    region: Region,
+    warm_up: bool,
 }
 impl Ppu {
     pub fn init() -> Self {
+        Self::new(Region::Ntsc)
+    }
+    pub fn new(region: Region) -> Self {
         Self {
             meta: Meta::init(),
             control: Control::init(),
@@ -37,19 +58,59 @@ impl Ppu {
             v: V::init(),
             t: V::init(),
             dot: [0; 2],
+            frame_count: 0,
+            frame_complete: false,
 
             data_latch: 0,
+            io_latch: IoLatch::init(),
             oam_addr: 0,
             oam: Box::new([0; 256]),
             palette: Box::new([0; 32]),
 
             shifters: Shifters::init(),
             sprites: Box::new(Sprites::init()),
+            sprite_zero_hit_dot: None,
+            render_was_enabled: false,
+            oam_corruption: 0,
 
             pixels: Box::new(PixelBuffer::new()),
+            vs_ppu: None,
+            region,
+            warm_up: false,
         }
     }
 
+    // After power on or reset, the real PPU ignores writes to some registers until the end of the first vblank.
+    // Off by default, since most software doesn't care and it only delays startup.
+    pub fn set_warm_up(&mut self, enabled: bool) {
+        self.warm_up = enabled;
+        self.meta.set_warming_up(enabled);
+    }
+    // The RESET button clears the registers and scroll, but leaves memory and the VRAM address alone.
+    pub fn reset(&mut self) {
+        self.control = Control::init();
+        self.mask = Mask::init();
+        self.t = V::init();
+        self.meta.set_x(0);
+        self.meta.set_w(false);
+        self.meta.set_odd_frame(false);
+        self.data_latch = 0;
+        self.meta.set_warming_up(self.warm_up);
+    }
+    // Back to how the PPU comes out of power on, except for the Vs. System palette and warm-up setting.
+    pub fn power_cycle(&mut self) {
+        let warm_up = self.warm_up;
+        *self = Self {
+            vs_ppu: self.vs_ppu,
+            ..Self::new(self.region)
+        };
+        self.set_warm_up(warm_up);
+    }
+
+    pub fn set_vs_ppu(&mut self, vs_ppu: Option<VsPpu>) {
+        self.vs_ppu = vs_ppu;
+    }
+
     pub fn cycle(&mut self, bus: &mut PpuBus, cpu: &mut CpuBus) {
         self.common_cycle(cpu, bus);
         self.handle_cpu(bus, cpu);
@@ -86,28 +147,208 @@ impl Ppu {
         self.meta.set_write_pending(false);
     }
     fn decide_vblank(&mut self, cpu: &mut CpuBus) {
-        let start = [1, 241];
-        let end = [1, 261];
+        let end = [1, self.prerender_line()];
 
-        if self.dot == start {
-            self.meta.set_vblank(true);
+        if self.dot == VBLANK_START {
+            self.meta.set_vblank(!self.meta.vblank_suppressed());
+            self.meta.set_vblank_suppressed(false);
         } else if self.dot == end {
             self.meta.set_vblank(false);
+            self.meta.set_warming_up(false);
             self.meta.set_sprite_zero_hit(false);
+            self.sprite_zero_hit_dot = None;
             self.meta.set_sprite_overflow(false);
         }
 
-        cpu.set_nmi(self.meta.vblank() && self.control.nmi_enable());
+        self.update_nmi(cpu);
+    }
+    // The NMI output is a level, and the CPU triggers on its edges.
+    // Toggling the enable bit during vblank therefore causes another NMI.
+    fn update_nmi(&self, cpu: &mut CpuBus) {
+        // NMI follows the flag a dot late, so reading $2002 right as it's set still cancels the NMI.
+        let settling = self.dot == VBLANK_START;
+        cpu.set_nmi(self.meta.vblank() && self.control.nmi_enable() && !settling);
+    }
+    // How many dots can run before the CPU could notice, for running the PPU in batches.
+    // Outside of register accesses it only sees NMI rise the dot after vblank starts,
+    // and fall as vblank ends; batches also end with each line.
+    pub fn dots_until_sync(&self) -> u32 {
+        let [dot, line] = self.dot;
+        let nmi_dots = [
+            [VBLANK_START[0] + 1, VBLANK_START[1]],
+            [1, self.prerender_line()],
+        ];
+        for [nmi_dot, nmi_line] in nmi_dots {
+            if line == nmi_line && dot <= nmi_dot {
+                return (nmi_dot - dot + 1) as u32;
+            };
+        }
+        (DOTS - dot) as u32
+    }
+    // Skips up to the given number of dots in the middle of a vblank line, where nothing happens
+    // as long as no memory access is in flight. Returns how many were skipped.
+    pub fn skip_idle_dots(&mut self, max: u32, bus: &PpuBus) -> u32 {
+        let [dot, line] = self.dot;
+        let idle_line = (240..self.prerender_line()).contains(&line) && dot > VBLANK_START[0] + 1;
+        if !idle_line || self.memop_in_flight(bus) {
+            return 0;
+        };
+
+        let skipped = max.min((DOTS - dot) as u32);
+        self.dot[0] += skipped as u16;
+        if self.dot[0] == DOTS {
+            self.dot = [0, line + 1];
+        }
+        skipped
+    }
+    // Runs a whole visible line in one go, if it is owed in full and starts from its first dot
+    // with nothing the CPU did still in flight. That means the CPU didn't touch the PPU since the
+    // last line, so nothing can change halfway through. The picture, flags and fetches come out
+    // the same as from the dot loop, except that `memory` only hears about the dots that read,
+    // and once more at the end. It is called with the fetch on the bus, and has to answer it the
+    // way the bus would after a dot. Returns how many dots were run, 0 if the dot loop has to.
+    pub fn run_scanline(
+        &mut self,
+        max: u32,
+        bus: &mut PpuBus,
+        cpu: &mut CpuBus,
+        mut memory: impl FnMut(&mut PpuBus),
+    ) -> u32 {
+        let [dot, line] = self.dot;
+        let enabled = self.mask.render_enabled();
+        let settled = enabled == self.render_was_enabled && !self.meta.sprite_zero_hit_pending();
+        if max < DOTS as u32 || dot != 0 || line >= 240 || !settled || self.memop_in_flight(bus) {
+            return 0;
+        };
+
+        if enabled {
+            self.render_line(bus, &mut memory);
+        } else {
+            let color = self.backdrop_color();
+            for x in 0..WIDTH {
+                self.output_pixel(x, line as usize, color);
+            }
+        }
+        self.dot = [0, line + 1];
+        self.update_nmi(cpu);
+        DOTS as u32
+    }
+    fn memop_in_flight(&self, bus: &PpuBus) -> bool {
+        self.meta.read_pending()
+            || self.meta.write_pending()
+            || self.meta.data_latch_update_pending()
+            || bus.read_enable()
+            || bus.write_enable()
+    }
+    // visible_scanline for a whole line, with the fetches of each tile done together.
+    // Nothing drawn depends on the tile being fetched, so the order doesn't matter.
+    fn render_line(&mut self, bus: &mut PpuBus, memory: &mut impl FnMut(&mut PpuBus)) {
+        let y = self.dot[1] as usize;
+        if self.oam_corruption != 0 {
+            self.corrupt_oam();
+        }
+        let sprites = self.sprite_line();
+        // Evaluation for the next line replaces the overflow dot halfway through.
+        self.flag_overflow(1..=64);
+
+        let mut pattern_high = 0;
+        for (x, &sprite) in sprites.iter().enumerate() {
+            if x != 0 {
+                self.shifters.shift();
+            }
+            if x % 8 == 0 {
+                if x != 0 {
+                    self.shifters.shift_in_tile(pattern_high);
+                    self.v.increment_x();
+                }
+                pattern_high = self.fetch_tile(bus, memory);
+            }
+
+            self.mix_pixel(x, y, sprite);
+            if self.meta.sprite_zero_hit_pending() {
+                self.meta.set_sprite_zero_hit_pending(false);
+                self.meta.set_sprite_zero_hit(true);
+                self.sprite_zero_hit_dot = Some([x as u16 + 2, y as u16]);
+            }
+            if x == 64 {
+                self.evaluate_sprites();
+            }
+        }
+        self.v.increment_y();
+        self.flag_overflow(65..=256);
+
+        self.v.copy_horizontal_bits(self.t);
+        self.sprites.sprites = self.sprites.secondary;
+        self.sprites.fetch_index = 0;
+        for _ in 0..8 {
+            self.fetch(self.v.tile_address(), bus, memory);
+            self.fetch(self.v.attribute_address(), bus, memory);
+            let addr = self.sprites.pattern_low_address(self.control);
+            let low = self.fetch(addr, bus, memory);
+            self.sprites.fetch_low_pattern(low);
+            let high = self.fetch(addr + 8, bus, memory);
+            self.sprites.fetch_high_pattern(high);
+            self.sprites.next_fetch();
+        }
+
+        // The first two tiles of the next line, shifted along by dots 321 to 337.
+        for tile in 0..2 {
+            self.shifters.shift();
+            if tile != 0 {
+                self.shifters.shift_in_tile(pattern_high);
+                self.v.increment_x();
+            }
+            pattern_high = self.fetch_tile(bus, memory);
+            (0..7).for_each(|_| self.shifters.shift());
+        }
+        self.shifters.shift();
+        self.shifters.shift_in_tile(pattern_high);
+        self.v.increment_x();
+
+        self.sprites.oam_bus = self.sprites.secondary_oam[0];
+        memory(bus);
+    }
+    fn flag_overflow(&mut self, dots: RangeInclusive<u16>) {
+        let overflow_dot = self.sprites.overflow_dot;
+        if overflow_dot.is_some_and(|dot| dots.contains(&dot)) {
+            self.meta.set_sprite_overflow(true);
+        }
+    }
+    // fetch_background for a whole tile. Returns the high pattern byte, which the dot loop
+    // leaves on the bus until the tile is shifted in.
+    fn fetch_tile(&mut self, bus: &mut PpuBus, memory: &mut impl FnMut(&mut PpuBus)) -> u8 {
+        self.shifters.next_name = self.fetch(self.v.tile_address(), bus, memory);
+        let attribute = self.fetch(self.v.attribute_address(), bus, memory);
+        self.shifters.next_attribute = self.v.extract_attribute(attribute);
+        let addr = self
+            .shifters
+            .pattern_address(self.control.background_table(), self.v.fine_y());
+        self.shifters.next_pattern_low = self.fetch(addr, bus, memory);
+        self.fetch(addr + 8, bus, memory)
+    }
+    fn fetch(&self, addr: u16, bus: &mut PpuBus, memory: &mut impl FnMut(&mut PpuBus)) -> u8 {
+        bus.set_address(addr);
+        bus.set_read_enable(true);
+        memory(bus);
+        bus.set_read_enable(false);
+        bus.data()
     }
+
     fn tick_counter(&mut self) {
-        let last = if self.meta.odd_frame() {
-            [DOTS - 2, LINES - 1]
+        // Odd frames skip the last dot, decided by the background enable bit right on dot 339.
+        let lines = self.region.ppu_lines();
+        let skip = self.region.skips_odd_dot() && self.mask.background();
+        let last = if self.meta.odd_frame() && skip {
+            [DOTS - 2, lines - 1]
         } else {
-            [DOTS - 1, LINES - 1]
+            [DOTS - 1, lines - 1]
         };
         if self.dot == last {
             self.dot = [0, 0];
+            self.frame_count += 1;
+            self.frame_complete = true;
             self.meta.set_odd_frame(!self.meta.odd_frame());
+            self.io_latch.tick_frame();
         } else {
             self.dot[0] += 1;
             if self.dot[0] == DOTS {
@@ -118,17 +359,84 @@ impl Ppu {
     }
 
     fn render(&mut self, bus: &mut PpuBus) {
-        if !self.mask.render_enabled() {
+        // The flag shows up a dot after the pixel that caused it, so dot 2 at the earliest.
+        if self.meta.sprite_zero_hit_pending() {
+            self.meta.set_sprite_zero_hit_pending(false);
+            self.meta.set_sprite_zero_hit(true);
+            self.sprite_zero_hit_dot = Some(self.dot);
+        }
+        let enabled = self.mask.render_enabled();
+        if enabled != self.render_was_enabled {
+            self.render_was_enabled = enabled;
+            if !enabled && self.rendering_line() {
+                self.stop_rendering();
+            }
+        }
+        // With rendering off, v, the shifters and sprite evaluation all stand still.
+        if !enabled {
+            self.produce_backdrop();
             return;
         };
 
         match self.dot[1] {
             0..=239 => self.visible_scanline(false, bus),
-            261 => self.visible_scanline(true, bus),
+            line if line == self.prerender_line() => self.visible_scanline(true, bus),
             _ => (),
         }
     }
+    // Whether the PPU is currently using OAM for sprite evaluation and fetches.
+    fn rendering(&self) -> bool {
+        self.mask.render_enabled() && self.rendering_line()
+    }
+    fn rendering_line(&self) -> bool {
+        self.dot[1] < 240 || self.dot[1] == self.prerender_line()
+    }
+    // Turning rendering off in the middle of a line cuts sprite evaluation short,
+    // and corrupts the OAM row the PPU was addressing on that dot.
+    fn stop_rendering(&mut self) {
+        let dot = self.dot[0];
+        let row = match dot {
+            0..=63 => Some(dot / 2),
+            256..=319 => Some((dot - 256) / 8 * 4 + ((dot - 256) % 8).min(3)),
+            _ => None,
+        };
+        if let Some(row) = row {
+            self.oam_corruption |= 1 << row;
+        }
+
+        if self.dot[1] >= 240 || dot > 256 {
+            return;
+        };
+        // Before dot 65 this line's evaluation hasn't started, and won't if rendering stays off.
+        let sprites = &mut self.sprites;
+        for slot in 0..8 {
+            if dot < 65 || sprites.eval_dots[slot] > dot {
+                sprites.secondary[slot] = Sprite::default();
+                sprites.secondary_oam[slot * 4..slot * 4 + 4].fill(0xFF);
+            }
+        }
+        if sprites.overflow_dot.is_some_and(|overflow| dot < 65 || overflow > dot) {
+            sprites.overflow_dot = None;
+        }
+    }
+    // The first row is copied over every row that was corrupted.
+    fn corrupt_oam(&mut self) {
+        let row_zero: [u8; 8] = self.oam[..8].try_into().unwrap();
+        for row in 1..32 {
+            if self.oam_corruption & (1 << row) != 0 {
+                self.oam[row * 8..row * 8 + 8].copy_from_slice(&row_zero);
+            }
+        }
+        self.oam_corruption = 0;
+    }
+    // The last line of the frame, which prepares the first visible one. PAL has a longer vblank before it.
+    fn prerender_line(&self) -> u16 {
+        self.region.ppu_lines() - 1
+    }
     fn visible_scanline(&mut self, prerender: bool, bus: &mut PpuBus) {
+        if self.oam_corruption != 0 {
+            self.corrupt_oam();
+        }
         match self.dot[0] {
             0 => (),
             1..=256 => {
@@ -151,11 +459,25 @@ impl Ppu {
                 if x == 255 {
                     self.v.increment_y();
                 }
+
+                if self.dot[0] == 65 {
+                    if prerender {
+                        self.sprites.secondary = Default::default();
+                        self.sprites.secondary_oam = [0xFF; 32];
+                        self.sprites.overflow_dot = None;
+                    } else {
+                        self.evaluate_sprites();
+                    }
+                }
+                if self.sprites.overflow_dot == Some(self.dot[0]) {
+                    self.meta.set_sprite_overflow(true);
+                }
             }
             257..=320 => {
                 if self.dot[0] == 257 {
                     self.v.copy_horizontal_bits(self.t);
-                    self.evaluate_sprites();
+                    self.sprites.sprites = self.sprites.secondary;
+                    self.sprites.fetch_index = 0;
                 }
                 if (280..=304).contains(&self.dot[0]) && prerender {
                     self.v.copy_vertical_bits(self.t)
@@ -172,31 +494,83 @@ impl Ppu {
             337 => self.prefetch_tiles(bus), // Final pattern data is only now available
             _ => (),
         }
+        self.update_oam_bus();
+    }
+    // The byte sprite evaluation or sprite fetching is looking at on this dot,
+    // which is what $2004 reads return while rendering.
+    fn update_oam_bus(&mut self) {
+        let dot = self.dot[0];
+        self.sprites.oam_bus = match dot {
+            1..=64 => 0xFF, // Secondary OAM is being cleared
+            65..=256 => {
+                let addr = self.sprites.eval_reads[(dot - 65) as usize / 2];
+                self.oam[addr as usize]
+            }
+            257..=320 => {
+                let step = dot - 257;
+                let byte = (step % 8).min(3);
+                self.sprites.secondary_oam[(step / 8 * 4 + byte) as usize]
+            }
+            _ => self.sprites.secondary_oam[0],
+        };
     }
 
+    // Fills secondary OAM for the next line. The real PPU spreads this over dots 65 to 256,
+    // taking two dots to check a sprite and another six to copy one that is in range.
     fn evaluate_sprites(&mut self) {
+        self.sprites.secondary = Default::default();
+        self.sprites.secondary_oam = [0xFF; 32];
         self.sprites.eval_index = 0;
-        self.sprites.fetch_index = 0;
-        for i in (0..256).step_by(4) {
-            self.evaluate_sprite(i);
-        }
-        while self.sprites.eval_index < 8 {
-            self.sprites.sprites[self.sprites.eval_index as usize] = Sprite::default();
-            self.sprites.eval_index += 1;
+        self.sprites.overflow_dot = None;
+
+        // The OAM address read on each pair of dots, for $2004 reads during evaluation.
+        let mut reads = EvalReads { addrs: [0; 96], len: 0 };
+
+        let mut dot = 65;
+        let mut n = 0;
+        while n < 64 && self.sprites.eval_index < 8 {
+            if self.evaluate_sprite(n * 4) {
+                (0..4).for_each(|m| reads.push(n * 4 + m));
+                dot += 8;
+                self.sprites.eval_dots[self.sprites.eval_index as usize - 1] = dot;
+            } else {
+                reads.push(n * 4);
+                dot += 2;
+            }
+            n += 1;
         }
-    }
-    fn evaluate_sprite(&mut self, sprite: usize) {
-        if self.sprites.eval_index >= 8 {
-            self.meta.set_sprite_overflow(true); // Wrongly correct implementation, real hardware has bug. Important?
-            return;
+
+        // With secondary OAM full, the PPU keeps looking for a ninth sprite,
+        // but also steps through the bytes of each sprite and compares tile numbers,
+        // attributes and X coordinates as if they were Y coordinates.
+        let mut m = 0;
+        while n < 64 {
+            reads.push(n * 4 + m);
+            if self.sprite_in_range(self.oam[n * 4 + m]) {
+                self.sprites.overflow_dot = Some(dot);
+                (1..4).for_each(|i| reads.push(n * 4 + m + i));
+                n += 1;
+                break;
+            }
+            n += 1;
+            m = (m + 1) % 4;
+            dot += 2;
         }
 
+        // Once done, it keeps reading the Y coordinate of each following sprite.
+        while reads.len < reads.addrs.len() {
+            reads.push(n % 64 * 4);
+            n += 1;
+        }
+        self.sprites.eval_reads = reads.addrs;
+    }
+    fn evaluate_sprite(&mut self, sprite: usize) -> bool {
         let bytes = &self.oam[sprite..sprite + 4];
         let dot = self.dot();
         let y = bytes[0] as u16;
-        let ver_range = y..(y + 8);
-        if !ver_range.contains(&dot[1]) {
-            return;
+        let height = self.control.sprite_height();
+        if !self.sprite_in_range(bytes[0]) {
+            return false;
         };
         let x = bytes[3];
         let tile = bytes[1];
@@ -208,9 +582,11 @@ impl Ppu {
         let ver_flip = flags & (1 << 7) != 0;
 
         let y_offset = (dot[1] - y) as u8;
-        let y_offset = if ver_flip { 7 - y_offset } else { y_offset };
+        let y_offset = if ver_flip { height - 1 - y_offset } else { y_offset };
 
-        self.sprites.sprites[self.sprites.eval_index as usize] = Sprite {
+        let slot = self.sprites.eval_index as usize * 4;
+        self.sprites.secondary_oam[slot..slot + 4].copy_from_slice(bytes);
+        self.sprites.secondary[self.sprites.eval_index as usize] = Sprite {
             present: true,
             x,
             sprite_zero: sprite == 0,
@@ -222,6 +598,14 @@ impl Ppu {
             palette,
         };
         self.sprites.eval_index += 1;
+        true
+    }
+    // Evaluation only runs on lines 0 to 239, so sprites parked at Y 240 and beyond never show.
+    // The range is computed wide so that those don't wrap around into the top of the screen.
+    fn sprite_in_range(&self, y: u8) -> bool {
+        let y = y as u16;
+        let height = self.control.sprite_height() as u16;
+        (y..y + height).contains(&self.dot[1])
     }
     fn fetch_sprites(&mut self, bus: &mut PpuBus) {
         if self.sprites.fetch_index >= 8 { return }; // If rendering is enabled in the middle of a scanline, the counter is not reset
@@ -241,19 +625,11 @@ impl Ppu {
             1 => (),
             2 => self.read(self.v.attribute_address(), bus),
             3 => (),
-            4 => self.read(
-                self.sprites
-                    .pattern_low_address(self.control.sprite_table()),
-                bus,
-            ),
+            4 => self.read(self.sprites.pattern_low_address(self.control), bus),
             5 => (),
             6 => {
                 self.sprites.fetch_low_pattern(bus.data());
-                self.read(
-                    self.sprites
-                        .pattern_high_address(self.control.sprite_table()),
-                    bus,
-                );
+                self.read(self.sprites.pattern_high_address(self.control), bus);
             }
             7 => (),
             _ => (),
@@ -304,16 +680,19 @@ impl Ppu {
         }
     }
     fn produce_pixel(&mut self) {
-        let x = self.dot()[0] as usize - 1;
-        let y = self.dot()[1] as usize;
-
+        let x = self.dot[0] as usize - 1;
+        let y = self.dot[1] as usize;
+        let sprite = self.generate_sprite_pixel();
+        self.mix_pixel(x, y, sprite);
+    }
+    fn mix_pixel(&mut self, x: usize, y: usize, sprite: SpritePixel) {
         let bg_pattern = self.shifters.pattern(self.meta.x());
         let bg_palette = self.shifters.palette(self.meta.x());
         let bg_opague =
             bg_pattern != 0 && self.mask.background() && (x >= 8 || self.mask.left_background());
         let bg_color = self.background_color(bg_palette, bg_pattern);
 
-        let (sp_pattern, sp_palette, sp_zero, sp_priority) = self.generate_sprite_pixel();
+        let (sp_pattern, sp_palette, sp_zero, sp_priority) = sprite;
         let sp_opague =
             sp_pattern != 0 && self.mask.sprites() && (x >= 8 || self.mask.left_sprites());
         let sp_color = self.sprite_color(sp_palette, sp_pattern);
@@ -329,13 +708,41 @@ impl Ppu {
             }
         };
 
-        if hit && sp_zero {
-            self.meta.set_sprite_zero_hit(true);
+        // Disabled or clipped layers are never opaque, and the last column never hits.
+        if hit && sp_zero && x != 255 && !self.meta.sprite_zero_hit() {
+            self.meta.set_sprite_zero_hit_pending(true);
         }
 
-        self.pixels.set_color(x, y, color);
+        self.output_pixel(x, y, color);
     }
-    fn generate_sprite_pixel(&self) -> (u8, u8, bool, bool) {
+    // With rendering off the PPU shows the backdrop color,
+    // unless v points into palette RAM, in which case that entry shows instead.
+    fn produce_backdrop(&mut self) {
+        let [dot, line] = self.dot;
+        if line >= 240 || !(1..=256).contains(&dot) {
+            return;
+        };
+        self.output_pixel(dot as usize - 1, line as usize, self.backdrop_color());
+    }
+    fn backdrop_color(&self) -> u8 {
+        let index = if is_palette_address(self.v.0) {
+            normalize_palette_address(self.v.0)
+        } else {
+            0
+        };
+        self.palette[index]
+    }
+    fn output_pixel(&mut self, x: usize, y: usize, color: u8) {
+        let color = if self.mask.greyscale() { color & 0x30 } else { color };
+        let color = match self.vs_ppu.and_then(VsPpu::palette_lut) {
+            Some(lut) => lut[color as usize % 64],
+            None => color,
+        };
+        self.pixels.set_color(x, y, color, self.mask.emphasis());
+    }
+    // The lowest sprite with an opaque pixel wins, even one that is behind the background.
+    // Empty slots and transparent pixels don't stop the search.
+    fn generate_sprite_pixel(&self) -> SpritePixel {
         for sprite in &self.sprites.sprites {
             if !sprite.present {
                 continue;
@@ -346,19 +753,7 @@ impl Ppu {
             if !hor_range.contains(&x) {
                 continue;
             };
-            let offset = (x - sp_x) as u8;
-            let offset = if !sprite.hor_flip { 7 - offset } else { offset };
-            let pattern_low = if sprite.pattern[0] & (1 << offset) != 0 {
-                1
-            } else {
-                0
-            };
-            let pattern_high = if sprite.pattern[1] & (1 << offset) != 0 {
-                2
-            } else {
-                0
-            };
-            let pattern = pattern_low | pattern_high;
+            let pattern = sprite.pattern_at((x - sp_x) as u8);
             if pattern == 0 {
                 continue;
             };
@@ -370,6 +765,21 @@ impl Ppu {
 
         (0, 0, false, false)
     }
+    // generate_sprite_pixel for every pixel of the line at once.
+    fn sprite_line(&self) -> [SpritePixel; WIDTH] {
+        let mut line = [(0, 0, false, false); WIDTH];
+        for sprite in self.sprites.sprites.iter().filter(|sprite| sprite.present) {
+            for column in 0..8 {
+                let x = sprite.x as usize + column as usize;
+                let pattern = sprite.pattern_at(column);
+                if x >= WIDTH || pattern == 0 || line[x].0 != 0 {
+                    continue;
+                };
+                line[x] = (pattern, sprite.palette, sprite.sprite_zero, sprite.priority);
+            }
+        }
+        line
+    }
     fn background_color(&self, palette: u8, pattern: u8) -> u8 {
         let index = (palette << 2) | pattern;
         self.palette[index as usize]
@@ -385,6 +795,21 @@ impl Ppu {
         };
         let addr = cpu.address() % 8;
         let data = cpu.data();
+        let swap = self.vs_ppu.is_some_and(VsPpu::swaps_ctrl_and_mask);
+        let addr = match addr {
+            0 | 1 if swap => addr ^ 1,
+            _ => addr,
+        };
+
+        // Reads of write-only registers return whatever is left in the I/O latch.
+        if cpu.read() {
+            cpu.set_data(self.io_latch.value);
+        } else {
+            self.io_latch.refresh(data, 0xFF);
+        }
+        if !cpu.read() && self.meta.warming_up() && matches!(addr, 0 | 1 | 5 | 6) {
+            return;
+        };
 
         match addr {
             0 => {
@@ -394,6 +819,7 @@ impl Ppu {
                 let nametable = data & 0b11;
                 self.t.set_nametable(nametable);
                 self.control.0 = data;
+                self.update_nmi(cpu);
             }
             1 => {
                 if cpu.read() {
@@ -405,9 +831,19 @@ impl Ppu {
                 if !cpu.read() {
                     return;
                 };
-                cpu.set_data(self.meta.status_bits());
+                let status = self.meta.status_bits();
+                self.io_latch.refresh(status, 0xE0);
+                match self.vs_ppu.and_then(VsPpu::ppu_id) {
+                    Some(id) => cpu.set_data(status | id),
+                    None => cpu.set_data(self.io_latch.value),
+                }
                 self.meta.set_w(false);
                 self.meta.set_vblank(false);
+                // A read one dot before the flag is set keeps it from being set this frame.
+                if self.dot == VBLANK_START {
+                    self.meta.set_vblank_suppressed(true);
+                }
+                self.update_nmi(cpu);
             }
             3 => {
                 if cpu.read() {
@@ -417,8 +853,19 @@ impl Ppu {
             }
             4 => {
                 if cpu.read() {
-                    cpu.set_data(self.oam[self.oam_addr as usize]);
+                    let value = if self.rendering() {
+                        self.sprites.oam_bus
+                    } else {
+                        self.oam[self.oam_addr as usize]
+                    };
+                    self.io_latch.refresh(value, 0xFF);
+                    cpu.set_data(value);
+                } else if self.rendering() {
+                    // The write is lost, but bumps the sprite index in the upper six bits
+                    self.oam_addr = self.oam_addr.wrapping_add(4);
                 } else {
+                    // Bits 2 to 4 of the attribute byte don't exist
+                    let data = if self.oam_addr % 4 == 2 { data & 0xE3 } else { data };
                     self.oam[self.oam_addr as usize] = data;
                     self.oam_addr = self.oam_addr.wrapping_add(1);
                 }
@@ -460,16 +907,22 @@ impl Ppu {
                 let palette_index = normalize_palette_address(v);
 
                 if cpu.read() {
-                    self.read(v, bus);
+                    // Palette reads skip the buffer, which is filled from the nametable underneath instead.
+                    let buffered = if palette { v - 0x1000 } else { v };
+                    self.read(buffered, bus);
                     self.meta.set_data_latch_update_pending(true);
+                    // Palette entries are six bits wide, the top two come from the latch.
                     if palette {
-                        cpu.set_data(self.palette[palette_index]);
+                        let color = self.palette[palette_index];
+                        let color = if self.mask.greyscale() { color & 0x30 } else { color };
+                        self.io_latch.refresh(color, 0x3F);
                     } else {
-                        cpu.set_data(self.data_latch);
+                        self.io_latch.refresh(self.data_latch, 0xFF);
                     }
+                    cpu.set_data(self.io_latch.value);
                 } else {
                     if palette {
-                        self.palette[palette_index] = cpu.data();
+                        self.palette[palette_index] = cpu.data() & 0x3F;
                     } else {
                         self.write(v, cpu.data(), bus);
                     }
@@ -497,17 +950,139 @@ impl Ppu {
     pub fn dot(&self) -> [u16; 2] {
         self.dot
     }
+    // Frames are counted when the dot counter wraps back to the top of the screen,
+    // regardless of what the vblank flag and NMI are doing.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
     pub fn is_vblank(&self) -> bool {
         self.meta.vblank()
     }
+    pub fn sprite_overflow(&self) -> bool {
+        self.meta.sprite_overflow()
+    }
+    // The dot on which sprite zero hit was set this frame.
+    pub fn sprite_zero_hit_dot(&self) -> Option<[u16; 2]> {
+        self.sprite_zero_hit_dot
+    }
     pub fn palette(&self) -> &[u8] {
         &*self.palette
     }
+    pub fn debug_palette(&self) -> &[u8; 32] {
+        &self.palette
+    }
+    pub fn debug_oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+    // What a CPU read of a register would return, without clearing or bumping anything.
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        let latch = self.io_latch.value;
+        match addr % 8 {
+            2 => {
+                let status = self.meta.status_bits();
+                match self.vs_ppu.and_then(VsPpu::ppu_id) {
+                    Some(id) => status | id,
+                    None => status | latch & 0x1F,
+                }
+            }
+            4 if self.rendering() => self.sprites.oam_bus,
+            4 => self.oam[self.oam_addr as usize],
+            // Reads return the buffer filled by the previous one, except for the palette.
+            7 if is_palette_address(self.v.0) => latch & 0xC0 | self.peek_palette(self.v.0),
+            7 => self.data_latch,
+            _ => latch,
+        }
+    }
+    pub fn peek_palette(&self, addr: u16) -> u8 {
+        let color = self.palette[normalize_palette_address(addr)];
+        if self.mask.greyscale() {
+            color & 0x30
+        } else {
+            color
+        }
+    }
     pub fn pixels(&self) -> &PixelBuffer {
         &self.pixels
     }
 }
 
+// The picture is saved too, so a state loaded mid-frame finishes the frame it was saved in.
+// The Vs. System palette and warm-up setting are configuration and stay as they are.
+impl SaveState for Ppu {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.region as u8);
+        out.u16(self.meta.0);
+        out.u8(self.control.0);
+        out.u8(self.mask.0);
+        out.u16(self.v.0);
+        out.u16(self.t.0);
+        out.u16(self.dot[0]);
+        out.u16(self.dot[1]);
+        out.u64(self.frame_count);
+        out.bool(self.frame_complete);
+
+        out.u8(self.data_latch);
+        self.io_latch.save_state(out);
+        out.u8(self.oam_addr);
+        out.bytes(&*self.oam);
+        out.bytes(&*self.palette);
+
+        self.shifters.save_state(out);
+        self.sprites.save_state(out);
+        let hit = self.sprite_zero_hit_dot;
+        out.bool(hit.is_some());
+        out.u16(hit.map_or(0, |dot| dot[0]));
+        out.u16(hit.map_or(0, |dot| dot[1]));
+        out.bool(self.render_was_enabled);
+        out.u32(self.oam_corruption);
+
+        for &pixel in &self.pixels.0 {
+            out.u16(pixel as u16);
+        }
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.region = match state.u8()? {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            _ => return Err(StateError::Invalid),
+        };
+        self.meta = Meta(state.u16()?);
+        self.control = Control(state.u8()?);
+        self.mask = Mask(state.u8()?);
+        self.v = V(state.u16()?);
+        self.t = V(state.u16()?);
+        self.dot = [state.u16()?, state.u16()?];
+        self.frame_count = state.u64()?;
+        self.frame_complete = state.bool()?;
+
+        self.data_latch = state.u8()?;
+        self.io_latch.load_state(state)?;
+        self.oam_addr = state.u8()?;
+        state.bytes(&mut *self.oam)?;
+        state.bytes(&mut *self.palette)?;
+
+        self.shifters.load_state(state)?;
+        self.sprites.load_state(state)?;
+        let hit = state.bool()?;
+        let hit_dot = [state.u16()?, state.u16()?];
+        self.sprite_zero_hit_dot = hit.then_some(hit_dot);
+        self.render_was_enabled = state.bool()?;
+        self.oam_corruption = state.u32()?;
+
+        for pixel in &mut self.pixels.0 {
+            *pixel = state.u16()? as u32;
+        }
+
+        if self.dot[0] >= DOTS || self.dot[1] >= self.region.ppu_lines() || self.v.0 >= 0x4000 {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct PpuBus {
     address: u16,
@@ -566,6 +1141,19 @@ impl PpuBus {
     const WRITE_ENABLE: u8 = 1;
     const NMI: u8 = 2;
 }
+impl SaveState for PpuBus {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u16(self.address);
+        out.u8(self.data);
+        out.u8(self.flags);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.address = state.u16()?;
+        self.data = state.u8()?;
+        self.flags = state.u8()?;
+        Ok(())
+    }
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 struct Meta(u16);
@@ -607,6 +1195,9 @@ impl Meta {
         self.get_flag(Self::VBLANK)
     }
 
+    pub fn sprite_zero_hit(self) -> bool {
+        self.get_flag(Self::SPRITE_ZERO_HIT)
+    }
     pub fn set_sprite_zero_hit(&mut self, hit: bool) {
         self.set_flag(Self::SPRITE_ZERO_HIT, hit);
     }
@@ -641,6 +1232,24 @@ impl Meta {
     pub fn set_data_latch_update_pending(&mut self, pending: bool) {
         self.set_flag(Self::DATA_LATCH_UPDATE_PENDING, pending)
     }
+    pub fn vblank_suppressed(self) -> bool {
+        self.get_flag(Self::VBLANK_SUPPRESSED)
+    }
+    pub fn set_vblank_suppressed(&mut self, suppressed: bool) {
+        self.set_flag(Self::VBLANK_SUPPRESSED, suppressed)
+    }
+    pub fn warming_up(self) -> bool {
+        self.get_flag(Self::WARMING_UP)
+    }
+    pub fn set_warming_up(&mut self, warming_up: bool) {
+        self.set_flag(Self::WARMING_UP, warming_up)
+    }
+    pub fn sprite_zero_hit_pending(self) -> bool {
+        self.get_flag(Self::SPRITE_ZERO_HIT_PENDING)
+    }
+    pub fn set_sprite_zero_hit_pending(&mut self, pending: bool) {
+        self.set_flag(Self::SPRITE_ZERO_HIT_PENDING, pending)
+    }
 
     const X: u16 = 0;
     const W: u16 = 3;
@@ -651,7 +1260,13 @@ impl Meta {
     const READ_PENDING: u16 = 8;
     const WRITE_PENDING: u16 = 9;
     const DATA_LATCH_UPDATE_PENDING: u16 = 10;
+    const VBLANK_SUPPRESSED: u16 = 11;
+    const SPRITE_ZERO_HIT_PENDING: u16 = 12;
+    const WARMING_UP: u16 = 13;
 
+    pub fn sprite_overflow(self) -> bool {
+        self.get_flag(Self::SPRITE_OVERFLOW)
+    }
     pub fn set_sprite_overflow(&mut self, overflow: bool) {
         self.set_flag(Self::SPRITE_OVERFLOW, overflow);
     }
@@ -685,11 +1300,22 @@ impl Control {
     const INCREMENT: u8 = 2;
     const SPRITE_TABLE: u8 = 3;
     const BACKGROUND_TABLE: u8 = 4;
+    const SPRITE_SIZE: u8 = 5;
     const NMI_ENABLE: u8 = 7;
 
     pub fn sprite_table(&self) -> bool {
         get_flag_u8(self.0, Self::SPRITE_TABLE)
     }
+    pub fn tall_sprites(self) -> bool {
+        get_flag_u8(self.0, Self::SPRITE_SIZE)
+    }
+    pub fn sprite_height(self) -> u8 {
+        if self.tall_sprites() {
+            16
+        } else {
+            8
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -714,11 +1340,20 @@ impl Mask {
     fn render_enabled(self) -> bool {
         self.background() || self.sprites()
     }
+    fn greyscale(self) -> bool {
+        get_flag_u8(self.0, Self::GREYSCALE)
+    }
+    // The red, green and blue emphasis bits, in that order from the lowest bit.
+    fn emphasis(self) -> u8 {
+        self.0 >> Self::EMPHASIS_RED
+    }
 
+    const GREYSCALE: u8 = 0;
     const LEFT_BACKGROUND: u8 = 1;
     const LEFT_SPRITES: u8 = 2;
     const BACKGROUND: u8 = 3;
     const SPRITES: u8 = 4;
+    const EMPHASIS_RED: u8 = 5;
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -845,6 +1480,51 @@ fn normalize_palette_address(addr: u16) -> usize {
     }
 }
 
+// The PPU side of the data bus. Its bits fade to 0 about 600ms after they were last driven.
+struct IoLatch {
+    value: u8,
+    decay: [u8; 8],
+}
+impl IoLatch {
+    fn init() -> Self {
+        Self {
+            value: 0,
+            decay: [0; 8],
+        }
+    }
+
+    fn refresh(&mut self, value: u8, mask: u8) {
+        self.value = (self.value & !mask) | (value & mask);
+        for (bit, decay) in self.decay.iter_mut().enumerate() {
+            if mask & (1 << bit) != 0 {
+                *decay = LATCH_DECAY_FRAMES;
+            }
+        }
+    }
+    fn tick_frame(&mut self) {
+        for (bit, decay) in self.decay.iter_mut().enumerate() {
+            if *decay == 0 {
+                continue;
+            };
+            *decay -= 1;
+            if *decay == 0 {
+                self.value &= !(1 << bit);
+            }
+        }
+    }
+}
+
+impl SaveState for IoLatch {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u8(self.value);
+        out.bytes(&self.decay);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.value = state.u8()?;
+        state.bytes(&mut self.decay)
+    }
+}
+
 struct Shifters {
     pattern: [u16; 2],
     palette: [u8; 2],
@@ -903,29 +1583,85 @@ impl Shifters {
     }
 }
 
+impl SaveState for Shifters {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.u16(self.pattern[0]);
+        out.u16(self.pattern[1]);
+        out.bytes(&self.palette);
+        out.bool(self.attribute[0]);
+        out.bool(self.attribute[1]);
+        out.u8(self.next_name);
+        out.bool(self.next_attribute[0]);
+        out.bool(self.next_attribute[1]);
+        out.u8(self.next_pattern_low);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.pattern = [state.u16()?, state.u16()?];
+        state.bytes(&mut self.palette)?;
+        self.attribute = [state.bool()?, state.bool()?];
+        self.next_name = state.u8()?;
+        self.next_attribute = [state.bool()?, state.bool()?];
+        self.next_pattern_low = state.u8()?;
+        Ok(())
+    }
+}
+
+struct EvalReads {
+    addrs: [u8; 96],
+    len: usize,
+}
+impl EvalReads {
+    fn push(&mut self, addr: usize) {
+        if self.len < self.addrs.len() {
+            self.addrs[self.len] = addr as u8;
+            self.len += 1;
+        }
+    }
+}
+
 struct Sprites {
     sprites: [Sprite; 8],
+    secondary: [Sprite; 8],
     fetch_index: u8,
     eval_index: u8,
+    // The dot each slot of secondary OAM is done being filled.
+    eval_dots: [u16; 8],
+    overflow_dot: Option<u16>,
+    secondary_oam: [u8; 32],
+    eval_reads: [u8; 96],
+    oam_bus: u8,
 }
 impl Sprites {
     fn init() -> Sprites {
         Sprites {
             sprites: Default::default(),
+            secondary: Default::default(),
             fetch_index: 0,
             eval_index: 0,
+            eval_dots: [0; 8],
+            overflow_dot: None,
+            secondary_oam: [0xFF; 32],
+            eval_reads: [0; 96],
+            oam_bus: 0xFF,
         }
     }
 
-    fn pattern_low_address(&self, table: bool) -> u16 {
-        let i = self.fetch_index as usize;
-        let tile = self.sprites[i].tile as u16;
-        let offset = tile * 16;
+    // 8x16 sprites take their pattern table from bit 0 of the tile index,
+    // and use the following tile for their lower half.
+    fn pattern_low_address(&self, control: Control) -> u16 {
+        let sprite = &self.sprites[self.fetch_index as usize];
+        let (table, tile) = if control.tall_sprites() {
+            let tile = (sprite.tile & !1) + sprite.y_offset / 8;
+            (sprite.tile & 1 != 0, tile)
+        } else {
+            (control.sprite_table(), sprite.tile)
+        };
+        let offset = tile as u16 * 16;
         let base = if table { 0x1000 } else { 0 };
-        base + offset + self.sprites[i].y_offset as u16
+        base + offset + (sprite.y_offset % 8) as u16
     }
-    fn pattern_high_address(&self, table: bool) -> u16 {
-        self.pattern_low_address(table) + 8
+    fn pattern_high_address(&self, control: Control) -> u16 {
+        self.pattern_low_address(control) + 8
     }
 
     fn fetch_low_pattern(&mut self, pattern: u8) {
@@ -943,6 +1679,48 @@ impl Sprites {
     }
 }
 
+impl SaveState for Sprites {
+    fn save_state(&self, out: &mut StateWriter) {
+        for sprite in self.sprites.iter().chain(&self.secondary) {
+            sprite.save_state(out);
+        }
+        out.u8(self.fetch_index);
+        out.u8(self.eval_index);
+        for &dot in &self.eval_dots {
+            out.u16(dot);
+        }
+        out.bool(self.overflow_dot.is_some());
+        out.u16(self.overflow_dot.unwrap_or(0));
+        out.bytes(&self.secondary_oam);
+        out.bytes(&self.eval_reads);
+        out.u8(self.oam_bus);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        for sprite in self.sprites.iter_mut().chain(&mut self.secondary) {
+            sprite.load_state(state)?;
+        }
+        self.fetch_index = state.u8()?;
+        self.eval_index = state.u8()?;
+        for dot in &mut self.eval_dots {
+            *dot = state.u16()?;
+        }
+        let overflow = state.bool()?;
+        let overflow_dot = state.u16()?;
+        self.overflow_dot = overflow.then_some(overflow_dot);
+        state.bytes(&mut self.secondary_oam)?;
+        state.bytes(&mut self.eval_reads)?;
+        self.oam_bus = state.u8()?;
+        if self.fetch_index > 8 || self.eval_index > 8 {
+            return Err(StateError::Invalid);
+        };
+        Ok(())
+    }
+}
+
+// Pattern, palette, whether it's sprite zero and whether it's in front of the background.
+type SpritePixel = (u8, u8, bool, bool);
+
+#[derive(Copy, Clone)]
 struct Sprite {
     present: bool,
     x: u8,
@@ -954,6 +1732,15 @@ struct Sprite {
     pattern: [u8; 2],
     palette: u8,
 }
+impl Sprite {
+    // The 2-bit pattern of a column, counting from the left edge of the sprite as drawn.
+    fn pattern_at(&self, column: u8) -> u8 {
+        let bit = if self.hor_flip { column } else { 7 - column };
+        let low = self.pattern[0] >> bit & 1;
+        let high = self.pattern[1] >> bit & 1;
+        low | high << 1
+    }
+}
 impl Default for Sprite {
     fn default() -> Self {
         Self {
@@ -969,3 +1756,28 @@ impl Default for Sprite {
         }
     }
 }
+impl SaveState for Sprite {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.bool(self.present);
+        out.u8(self.x);
+        out.bool(self.sprite_zero);
+        out.bool(self.priority);
+        out.u8(self.tile);
+        out.u8(self.y_offset);
+        out.bool(self.hor_flip);
+        out.bytes(&self.pattern);
+        out.u8(self.palette);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.present = state.bool()?;
+        self.x = state.u8()?;
+        self.sprite_zero = state.bool()?;
+        self.priority = state.bool()?;
+        self.tile = state.u8()?;
+        self.y_offset = state.u8()?;
+        self.hor_flip = state.bool()?;
+        state.bytes(&mut self.pattern)?;
+        self.palette = state.u8()?;
+        Ok(())
+    }
+}
@@ -2,19 +2,33 @@ pub const WIDTH: usize = 256;
 pub const HEIGHT: usize = 240;
 pub const PIXELS: usize = WIDTH * HEIGHT;
 
-// Each u32 stores four horizontally adjacent pixels, each pixel taking 8 bits.
-// Lower-order bits corresponds to more-left pixels.
+// Each u32 stores one pixel: the 6-bit palette color,
+// with the red, green and blue emphasis bits of PPUMASK above it.
+// That makes it an index into a 512 entry palette.
+#[derive(Clone)]
 pub struct PixelBuffer(pub [u32; PIXELS]);
 impl PixelBuffer {
     pub fn new() -> Self {
         Self([0; PIXELS])
     }
 
-    pub fn set_color(&mut self, x: usize, y: usize, color: u8) {
+    pub fn set_color(&mut self, x: usize, y: usize, color: u8, emphasis: u8) {
         assert!(x < WIDTH);
         assert!(y < HEIGHT);
 
         let pixel_i = y * WIDTH + x;
-        self.0[pixel_i] = color as u32;
+        self.0[pixel_i] = (color as u32 & 0x3F) | (emphasis as u32 & 0b111) << 6;
+    }
+
+    // 64 bit FNV-1a over every pixel as two little endian bytes.
+    // Unlike a hash of the RGBA output, it doesn't change along with the color palette.
+    pub fn hash(&self) -> u64 {
+        let bytes = self
+            .0
+            .iter()
+            .flat_map(|&pixel| (pixel as u16).to_le_bytes());
+        bytes.fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+        })
     }
 }
@@ -0,0 +1,74 @@
+// The PPUs found in Vs. System arcade boards.
+// RP2C04s scramble their palette, RC2C05s swap $2000/$2001 and identify themselves in $2002.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VsPpu {
+    Rp2c03,
+    // Revisions 0001 through 0004
+    Rp2c04(u8),
+    // Revisions 01 through 05
+    Rc2c05(u8),
+}
+impl VsPpu {
+    // Decodes the PPU type nibble of NES 2.0 header byte 13.
+    pub fn from_header(ppu_type: u8) -> Option<Self> {
+        let ppu = match ppu_type {
+            0x0 | 0x1 | 0x6 | 0x7 => Self::Rp2c03,
+            0x2..=0x5 => Self::Rp2c04(ppu_type - 1),
+            0x8..=0xC => Self::Rc2c05(ppu_type - 7),
+            _ => return None,
+        };
+        Some(ppu)
+    }
+
+    // Maps the palette indices this PPU outputs onto the equivalent 2C02 colors.
+    pub fn palette_lut(self) -> Option<&'static [u8; 64]> {
+        match self {
+            Self::Rp2c04(1) => Some(&RP2C04_0001),
+            Self::Rp2c04(2) => Some(&RP2C04_0002),
+            Self::Rp2c04(3) => Some(&RP2C04_0003),
+            Self::Rp2c04(4) => Some(&RP2C04_0004),
+            _ => None,
+        }
+    }
+    // Value returned in the low bits of $2002.
+    pub fn ppu_id(self) -> Option<u8> {
+        match self {
+            Self::Rc2c05(1) | Self::Rc2c05(4) => Some(0x1B),
+            Self::Rc2c05(2) => Some(0x3D),
+            Self::Rc2c05(3) => Some(0x1C),
+            _ => None,
+        }
+    }
+    pub fn swaps_ctrl_and_mask(self) -> bool {
+        matches!(self, Self::Rc2c05(_))
+    }
+}
+
+#[rustfmt::skip]
+static RP2C04_0001: [u8; 64] = [
+    0x35, 0x23, 0x16, 0x22, 0x1C, 0x09, 0x1D, 0x15, 0x20, 0x00, 0x27, 0x05, 0x04, 0x28, 0x08, 0x20,
+    0x21, 0x3E, 0x1F, 0x29, 0x3C, 0x32, 0x36, 0x12, 0x3F, 0x2B, 0x2E, 0x1E, 0x3D, 0x2D, 0x24, 0x01,
+    0x0E, 0x31, 0x33, 0x2A, 0x2C, 0x0C, 0x1B, 0x14, 0x2E, 0x07, 0x34, 0x06, 0x13, 0x02, 0x26, 0x2E,
+    0x2E, 0x19, 0x10, 0x0A, 0x39, 0x03, 0x37, 0x17, 0x0F, 0x11, 0x0B, 0x0D, 0x38, 0x25, 0x18, 0x3A,
+];
+#[rustfmt::skip]
+static RP2C04_0002: [u8; 64] = [
+    0x2E, 0x27, 0x18, 0x39, 0x3A, 0x25, 0x1C, 0x31, 0x16, 0x13, 0x38, 0x34, 0x20, 0x23, 0x3C, 0x0B,
+    0x0F, 0x21, 0x06, 0x3D, 0x1B, 0x29, 0x1E, 0x22, 0x1D, 0x24, 0x0E, 0x2B, 0x32, 0x08, 0x2E, 0x03,
+    0x04, 0x36, 0x26, 0x33, 0x11, 0x1F, 0x10, 0x02, 0x14, 0x3F, 0x00, 0x09, 0x12, 0x2E, 0x28, 0x20,
+    0x3E, 0x0D, 0x2A, 0x17, 0x0C, 0x01, 0x15, 0x19, 0x2E, 0x2C, 0x07, 0x37, 0x35, 0x05, 0x0A, 0x2D,
+];
+#[rustfmt::skip]
+static RP2C04_0003: [u8; 64] = [
+    0x14, 0x25, 0x3A, 0x10, 0x0B, 0x20, 0x31, 0x09, 0x01, 0x2E, 0x36, 0x08, 0x15, 0x3D, 0x3E, 0x3C,
+    0x22, 0x1C, 0x05, 0x12, 0x19, 0x18, 0x17, 0x1B, 0x00, 0x03, 0x2E, 0x02, 0x16, 0x06, 0x34, 0x35,
+    0x23, 0x0F, 0x0E, 0x37, 0x0D, 0x27, 0x26, 0x20, 0x29, 0x04, 0x21, 0x24, 0x11, 0x2D, 0x2E, 0x1F,
+    0x2C, 0x1E, 0x39, 0x33, 0x07, 0x2A, 0x28, 0x1D, 0x0A, 0x2E, 0x32, 0x38, 0x13, 0x2B, 0x3F, 0x0C,
+];
+#[rustfmt::skip]
+static RP2C04_0004: [u8; 64] = [
+    0x18, 0x03, 0x1C, 0x28, 0x2E, 0x35, 0x01, 0x17, 0x10, 0x1F, 0x2A, 0x0E, 0x36, 0x37, 0x0B, 0x39,
+    0x25, 0x1E, 0x12, 0x34, 0x2E, 0x1D, 0x06, 0x26, 0x3E, 0x1B, 0x22, 0x19, 0x04, 0x2E, 0x3A, 0x21,
+    0x05, 0x0A, 0x07, 0x02, 0x13, 0x14, 0x00, 0x15, 0x0C, 0x3D, 0x11, 0x0F, 0x0D, 0x38, 0x2D, 0x24,
+    0x33, 0x20, 0x08, 0x16, 0x3F, 0x2B, 0x20, 0x3C, 0x2E, 0x27, 0x23, 0x31, 0x29, 0x32, 0x2C, 0x09,
+];
@@ -0,0 +1,51 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Region {
+    Ntsc,
+    Pal,
+}
+impl Region {
+    // CPU cycles per second, which is also the rate the APU produces samples at.
+    pub const fn cpu_clock_hz(self) -> f64 {
+        match self {
+            Self::Ntsc => 21_477_272.0 / 12.0,
+            Self::Pal => 26_601_712.0 / 16.0,
+        }
+    }
+    // Master clock ticks per CPU cycle and per PPU dot.
+    // PAL runs 3.2 dots per CPU cycle, so the two don't line up every cycle.
+    pub const fn cpu_divider(self) -> u32 {
+        match self {
+            Self::Ntsc => 12,
+            Self::Pal => 16,
+        }
+    }
+    pub const fn ppu_divider(self) -> u32 {
+        match self {
+            Self::Ntsc => 4,
+            Self::Pal => 5,
+        }
+    }
+    // Scanlines per frame, including vblank and the pre-render line.
+    pub const fn ppu_lines(self) -> u16 {
+        match self {
+            Self::Ntsc => 262,
+            Self::Pal => 312,
+        }
+    }
+    // Only the NTSC PPU drops a dot on odd frames.
+    pub const fn skips_odd_dot(self) -> bool {
+        matches!(self, Self::Ntsc)
+    }
+    // With the background enabled, odd frames on NTSC are a dot short.
+    pub fn frames_per_second(self) -> f64 {
+        let dots_per_frame = 341.0 * self.ppu_lines() as f64;
+        let dots_per_frame = if self.skips_odd_dot() {
+            dots_per_frame - 0.5
+        } else {
+            dots_per_frame
+        };
+        let dots_per_second =
+            self.cpu_clock_hz() * self.cpu_divider() as f64 / self.ppu_divider() as f64;
+        dots_per_second / dots_per_frame
+    }
+}
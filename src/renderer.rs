@@ -1,7 +1,10 @@
 use std::{num::NonZeroU64, sync::Arc};
 
 use futures::executor::block_on;
-use nessy::ppu::pixel_buffer::{PixelBuffer, PIXELS};
+use nessy::{
+    palette::Palette,
+    ppu::pixel_buffer::{PixelBuffer, PIXELS},
+};
 use wgpu::{
     include_wgsl, Adapter, Backends, BindGroup, BindGroupDescriptor, BindGroupEntry,
     BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
@@ -27,7 +30,7 @@ pub struct Renderer {
     pipeline: Pipeline,
 }
 impl Renderer {
-    pub fn init(window: Arc<Window>) -> Self {
+    pub fn init(window: Arc<Window>, palette: &Palette) -> Self {
         let backends = Backends::VULKAN;
         let size = window.inner_size();
 
@@ -74,20 +77,21 @@ impl Renderer {
             pipeline,
         };
 
-        renderer.upload_palette();
+        renderer.upload_palette(palette);
         renderer
     }
-    fn upload_palette(&self) {
+    fn upload_palette(&self, palette: &Palette) {
         fn u8_to_f32(val: u8) -> f32 {
             (val as f32 / 255.0).clamp(0.0, 1.0)
         }
 
-        let mut pped = Vec::with_capacity(64 * 4);
-        for chunk in PALETTE.chunks_exact(3) {
-            pped.push(u8_to_f32(chunk[0]));
-            pped.push(u8_to_f32(chunk[1]));
-            pped.push(u8_to_f32(chunk[2]));
-            pped.push(1.0);
+        let mut pped = Vec::with_capacity(PALETTE_ENTRIES * 4);
+        for emphasis in 0..8 {
+            for color in 0..64 {
+                let rgb = palette.rgb(color, emphasis);
+                pped.extend(rgb.map(u8_to_f32));
+                pped.push(1.0);
+            }
         }
 
         let as_bytes = bytemuck::cast_slice(&pped);
@@ -312,5 +316,4 @@ struct Pipeline {
     bind_group: BindGroup,
 }
 
-const PALETTE_ENTRIES: usize = 64;
-static PALETTE: &[u8] = include_bytes!("ntscpalette.pal");
+const PALETTE_ENTRIES: usize = 512;
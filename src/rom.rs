@@ -0,0 +1,54 @@
+use crate::{ppu::vs_ppu::VsPpu, region::Region};
+
+pub mod archive;
+pub mod builder;
+pub mod db;
+pub mod expansion;
+pub mod fds;
+pub mod hash;
+pub mod header;
+pub mod nsf;
+pub mod patch;
+pub mod unif;
+pub mod validate;
+
+pub const TRAINER_SIZE: usize = 512;
+
+// The trainer of an iNES or NES 2.0 image, if the header declares one.
+// It sits between the header and PRG ROM and is meant to be loaded at $7000.
+pub fn trainer(src: &[u8]) -> Option<&[u8]> {
+    let flags_6 = *src.get(6)?;
+    if flags_6 & 4 == 0 {
+        return None;
+    };
+    src.get(16..16 + TRAINER_SIZE)
+}
+
+// The timing an image asks for. Multi-region and Dendy images run as NTSC and PAL respectively.
+pub fn region(src: &[u8]) -> Region {
+    let flags_7 = src.get(7).copied().unwrap_or(0);
+    let nes2 = flags_7 & 0x0C == 0x08;
+    let pal = if nes2 {
+        src.get(12).is_some_and(|timing| timing & 0b11 == 1 || timing & 0b11 == 3)
+    } else {
+        src.get(9).is_some_and(|flags_9| flags_9 & 1 != 0)
+    };
+    if pal {
+        Region::Pal
+    } else {
+        Region::Ntsc
+    }
+}
+
+// The PPU of a Vs. System image. iNES 1.0 images can't specify it, so they get the RGB PPU.
+pub fn vs_ppu(src: &[u8]) -> Option<VsPpu> {
+    let flags_7 = *src.get(7)?;
+    if flags_7 & 0b11 != 1 {
+        return None;
+    };
+    let nes2 = flags_7 & 0x0C == 0x08;
+    if !nes2 {
+        return Some(VsPpu::Rp2c03);
+    };
+    VsPpu::from_header(*src.get(13)? & 0xF)
+}
@@ -0,0 +1,43 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{Cursor, Read},
+};
+use zip::{result::ZipError, ZipArchive};
+
+// Zip files start with a local file header.
+pub fn is_zip(src: &[u8]) -> bool {
+    src.starts_with(b"PK\x03\x04")
+}
+
+// Unpacks the first .nes file in a zip archive, in the order they're stored.
+// Returns its name along with its contents.
+pub fn extract_nes(src: &[u8]) -> Result<(String, Vec<u8>), ArchiveError> {
+    let mut archive = ZipArchive::new(Cursor::new(src)).map_err(ArchiveError::Zip)?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(ArchiveError::Zip)?;
+        if !file.is_file() || !file.name().to_ascii_lowercase().ends_with(".nes") {
+            continue;
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(|err| ArchiveError::Zip(err.into()))?;
+        return Ok((file.name().to_string(), data));
+    }
+    Err(ArchiveError::NoRom)
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Zip(ZipError),
+    NoRom,
+}
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Zip(err) => write!(f, "can't read zip archive: {err}"),
+            Self::NoRom => write!(f, "zip archive has no .nes file in it"),
+        }
+    }
+}
+impl Error for ArchiveError {}
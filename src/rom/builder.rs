@@ -0,0 +1,165 @@
+use super::{
+    expansion::ExpansionDevice,
+    header::{assemble, Header},
+    TRAINER_SIZE,
+};
+use crate::region::Region;
+
+// Builds in-memory NES 2.0 images, so mapper and CPU tests don't have to ship real ROM dumps.
+pub struct RomBuilder {
+    mapper: u16,
+    submapper: u8,
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+    vertical_mirroring: bool,
+    battery: bool,
+    region: Region,
+    expansion_device: ExpansionDevice,
+    trainer: Option<Vec<u8>>,
+}
+impl RomBuilder {
+    // 32K of PRG ROM, 8K of CHR ROM and mapper 0.
+    pub fn new() -> Self {
+        Self {
+            mapper: 0,
+            submapper: 0,
+            prg: vec![0; 0x8000],
+            chr: vec![0; 0x2000],
+            vertical_mirroring: false,
+            battery: false,
+            region: Region::Ntsc,
+            expansion_device: ExpansionDevice::Unspecified,
+            trainer: None,
+        }
+    }
+
+    pub fn mapper(mut self, mapper: u16) -> Self {
+        self.mapper = mapper;
+        self
+    }
+    pub fn submapper(mut self, submapper: u8) -> Self {
+        self.submapper = submapper;
+        self
+    }
+    pub fn vertical_mirroring(mut self, vertical: bool) -> Self {
+        self.vertical_mirroring = vertical;
+        self
+    }
+    pub fn battery(mut self, battery: bool) -> Self {
+        self.battery = battery;
+        self
+    }
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = region;
+        self
+    }
+    pub fn expansion_device(mut self, device: ExpansionDevice) -> Self {
+        self.expansion_device = device;
+        self
+    }
+
+    pub fn trainer(mut self, trainer: &[u8]) -> Self {
+        assert_eq!(trainer.len(), TRAINER_SIZE);
+        self.trainer = Some(trainer.to_vec());
+        self
+    }
+
+    // Sizes are in bytes. PRG must be a multiple of 16K and CHR a multiple of 8K;
+    // a CHR size of zero declares 8K of CHR RAM instead.
+    pub fn prg_size(mut self, size: usize) -> Self {
+        assert_eq!(size % 0x4000, 0, "PRG size must be a multiple of 16K");
+        self.prg.resize(size, 0);
+        self
+    }
+    pub fn chr_size(mut self, size: usize) -> Self {
+        assert_eq!(size % 0x2000, 0, "CHR size must be a multiple of 8K");
+        self.chr.resize(size, 0);
+        self
+    }
+    pub fn prg(self, prg: Vec<u8>) -> Self {
+        let mut builder = self.prg_size(prg.len());
+        builder.prg = prg;
+        builder
+    }
+    pub fn chr(self, chr: Vec<u8>) -> Self {
+        let mut builder = self.chr_size(chr.len());
+        builder.chr = chr;
+        builder
+    }
+
+    pub fn prg_mut(&mut self) -> &mut [u8] {
+        &mut self.prg
+    }
+    pub fn chr_mut(&mut self) -> &mut [u8] {
+        &mut self.chr
+    }
+
+    // Places bytes at a CPU address in $8000-$FFFF.
+    // Small images are mirrored like NROM; larger ones are assumed to have their last 32K mapped there,
+    // which matches the power-on state of most mappers with a fixed last bank.
+    pub fn write_cpu(mut self, addr: u16, bytes: &[u8]) -> Self {
+        for (i, &byte) in bytes.iter().enumerate() {
+            let addr = addr.wrapping_add(i as u16);
+            let index = self.prg_index(addr);
+            self.prg[index] = byte;
+        }
+        self
+    }
+    pub fn reset_vector(self, addr: u16) -> Self {
+        self.write_cpu(0xFFFC, &addr.to_le_bytes())
+    }
+    pub fn nmi_vector(self, addr: u16) -> Self {
+        self.write_cpu(0xFFFA, &addr.to_le_bytes())
+    }
+    pub fn irq_vector(self, addr: u16) -> Self {
+        self.write_cpu(0xFFFE, &addr.to_le_bytes())
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        assert!(addr >= 0x8000, "{addr:04x} is not in PRG ROM space");
+        let addr = addr as usize % 0x8000;
+        let len = self.prg.len();
+        if len <= 0x8000 {
+            addr % len
+        } else {
+            len - 0x8000 + addr
+        }
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let trainer = self.trainer.as_deref();
+        assemble(&self.header(), &self.prg, &self.chr, trainer, &[])
+    }
+    // 8K of PRG RAM, battery-backed if requested, and 8K of CHR RAM if there is no CHR ROM.
+    fn header(&self) -> Header {
+        let prg_ram = if self.battery { 0 } else { 0x2000 };
+        let timing = match self.region {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+        };
+        Header {
+            mapper: self.mapper,
+            submapper: self.submapper,
+            prg_rom_size: self.prg.len(),
+            chr_rom_size: self.chr.len(),
+            prg_ram_size: prg_ram,
+            prg_nvram_size: 0x2000 - prg_ram,
+            chr_ram_size: if self.chr.is_empty() { 0x2000 } else { 0 },
+            chr_nvram_size: 0,
+            vertical_mirroring: self.vertical_mirroring,
+            battery: self.battery,
+            trainer: self.trainer.is_some(),
+            four_screen: false,
+            console_type: 0,
+            timing,
+            console_info: 0,
+            misc_roms: 0,
+            expansion_device: self.expansion_device,
+        }
+    }
+}
+impl Default for RomBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
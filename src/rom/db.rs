@@ -0,0 +1,255 @@
+use super::{
+    expansion::ExpansionDevice,
+    hash::crc32,
+    header::{Header, HEADER_SIZE},
+    TRAINER_SIZE,
+};
+use std::{collections::HashMap, error::Error, fmt, fs, io, path::Path};
+
+// Corrected headers from a nes20db XML file, keyed by the CRC32 of the data after the header.
+pub struct Db {
+    games: HashMap<u32, Header>,
+}
+impl Db {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DbError> {
+        let xml = fs::read_to_string(path).map_err(DbError::Io)?;
+        Self::parse(&xml)
+    }
+    pub fn parse(xml: &str) -> Result<Self, DbError> {
+        let mut games = HashMap::new();
+        let mut game = None;
+        for tag in (Tags { xml, pos: 0 }) {
+            match (tag.name, &mut game) {
+                ("game", None) => game = Some(Game::init()),
+                ("/game", Some(_)) => {
+                    let done = game.take().unwrap();
+                    let crc = done.crc.ok_or(DbError::Invalid(tag.offset))?;
+                    done.header
+                        .try_to_bytes()
+                        .map_err(|_| DbError::Invalid(tag.offset))?;
+                    games.insert(crc, done.header);
+                }
+                (_, Some(game)) => game.read(&tag)?,
+                _ => (),
+            }
+        }
+        Ok(Self { games })
+    }
+
+    pub fn get(&self, crc: u32) -> Option<&Header> {
+        self.games.get(&crc)
+    }
+
+    // Replaces the header of an image the database knows, describing each field that changed.
+    // The trainer flag is kept, since dropping or adding one would move the data it hashed.
+    pub fn apply(&self, src: &mut [u8]) -> Option<Vec<String>> {
+        let old = Header::parse(src).ok()?;
+        let start = HEADER_SIZE + if old.trainer { TRAINER_SIZE } else { 0 };
+        let new = self.get(crc32(src.get(start..)?))?;
+        let new = Header {
+            trainer: old.trainer,
+            ..new.clone()
+        };
+        src[..HEADER_SIZE].copy_from_slice(&new.to_bytes());
+        Some(changes(&old, &new))
+    }
+}
+
+fn changes(old: &Header, new: &Header) -> Vec<String> {
+    let fields = [
+        ("mapper", old.mapper as usize, new.mapper as usize),
+        ("submapper", old.submapper as usize, new.submapper as usize),
+        ("PRG ROM size", old.prg_rom_size, new.prg_rom_size),
+        ("CHR ROM size", old.chr_rom_size, new.chr_rom_size),
+        ("PRG RAM size", old.prg_ram_size, new.prg_ram_size),
+        ("PRG NVRAM size", old.prg_nvram_size, new.prg_nvram_size),
+        ("CHR RAM size", old.chr_ram_size, new.chr_ram_size),
+        ("CHR NVRAM size", old.chr_nvram_size, new.chr_nvram_size),
+        (
+            "vertical mirroring",
+            old.vertical_mirroring as usize,
+            new.vertical_mirroring as usize,
+        ),
+        ("battery", old.battery as usize, new.battery as usize),
+        (
+            "four screen",
+            old.four_screen as usize,
+            new.four_screen as usize,
+        ),
+        (
+            "console type",
+            old.console_type as usize,
+            new.console_type as usize,
+        ),
+        ("timing", old.timing as usize, new.timing as usize),
+        (
+            "console info",
+            old.console_info as usize,
+            new.console_info as usize,
+        ),
+        ("misc ROMs", old.misc_roms as usize, new.misc_roms as usize),
+        (
+            "expansion device",
+            old.expansion_device.to_byte() as usize,
+            new.expansion_device.to_byte() as usize,
+        ),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(name, old, new)| format!("{name}: {old} -> {new}"))
+        .collect()
+}
+
+// A <game> entry being read. Fields the entry doesn't mention stay empty.
+struct Game {
+    crc: Option<u32>,
+    header: Header,
+}
+impl Game {
+    fn init() -> Self {
+        Self {
+            crc: None,
+            header: Header {
+                mapper: 0,
+                submapper: 0,
+                prg_rom_size: 0,
+                chr_rom_size: 0,
+                prg_ram_size: 0,
+                prg_nvram_size: 0,
+                chr_ram_size: 0,
+                chr_nvram_size: 0,
+                vertical_mirroring: false,
+                battery: false,
+                trainer: false,
+                four_screen: false,
+                console_type: 0,
+                timing: 0,
+                console_info: 0,
+                misc_roms: 0,
+                expansion_device: ExpansionDevice::Unspecified,
+            },
+        }
+    }
+
+    fn read(&mut self, tag: &Tag) -> Result<(), DbError> {
+        let header = &mut self.header;
+        let number = |name| tag.number(name);
+        match tag.name {
+            "rom" => {
+                let crc = tag.attribute("crc32").ok_or(tag.invalid())?;
+                self.crc = Some(u32::from_str_radix(crc, 16).map_err(|_| tag.invalid())?);
+            }
+            "prgrom" => header.prg_rom_size = number("size")?,
+            "chrrom" => header.chr_rom_size = number("size")?,
+            "prgram" => header.prg_ram_size = number("size")?,
+            "prgnvram" => header.prg_nvram_size = number("size")?,
+            "chrram" => header.chr_ram_size = number("size")?,
+            "chrnvram" => header.chr_nvram_size = number("size")?,
+            "trainer" => header.trainer = number("size")? != 0,
+            "miscrom" => header.misc_roms = number("number")? as u8,
+            "expansion" => {
+                let device = number("type")? as u8;
+                header.expansion_device =
+                    ExpansionDevice::from_byte(device).ok_or(tag.invalid())?;
+            }
+            "pcb" => {
+                header.mapper = number("mapper")? as u16;
+                header.submapper = number("submapper")? as u8;
+                header.battery = number("battery")? != 0;
+                let mirroring = tag.attribute("mirroring").ok_or(tag.invalid())?;
+                header.vertical_mirroring = mirroring == "V";
+                header.four_screen = mirroring == "4";
+            }
+            "console" => {
+                let console = number("type")? as u8;
+                header.console_type = console.min(3);
+                if console >= 3 {
+                    header.console_info = console;
+                }
+                header.timing = number("region")? as u8;
+            }
+            "vs" => {
+                let hardware = number("hardware")? as u8;
+                header.console_info = hardware << 4 | number("ppu")? as u8;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+// Just enough of XML for the database: tags and their quoted attributes, comments skipped.
+struct Tags<'a> {
+    xml: &'a str,
+    pos: usize,
+}
+impl<'a> Iterator for Tags<'a> {
+    type Item = Tag<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let offset = self.pos + self.xml[self.pos..].find('<')?;
+            let rest = &self.xml[offset + 1..];
+            if let Some(comment) = rest.strip_prefix("!--") {
+                let end = comment.find("-->").map_or(comment.len(), |end| end + 3);
+                self.pos = offset + 4 + end;
+                continue;
+            };
+            let end = rest.find('>')?;
+            self.pos = offset + end + 2;
+
+            let body = rest[..end].trim_end_matches(['/', '?']);
+            if body.starts_with(['?', '!']) {
+                continue;
+            };
+            let (name, attributes) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+            return Some(Tag {
+                name,
+                attributes,
+                offset,
+            });
+        }
+    }
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    attributes: &'a str,
+    offset: usize,
+}
+impl Tag<'_> {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        let mut rest = self.attributes;
+        while let Some((key, value)) = rest.split_once('=') {
+            let value = value.trim_start().strip_prefix('"')?;
+            let (value, after) = value.split_once('"')?;
+            if key.trim() == name {
+                return Some(value);
+            };
+            rest = after;
+        }
+        None
+    }
+    fn number(&self, name: &str) -> Result<usize, DbError> {
+        let value = self.attribute(name).ok_or(self.invalid())?;
+        value.parse().map_err(|_| self.invalid())
+    }
+    fn invalid(&self) -> DbError {
+        DbError::Invalid(self.offset)
+    }
+}
+
+#[derive(Debug)]
+pub enum DbError {
+    Io(io::Error),
+    Invalid(usize),
+}
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "can't read header database: {err}"),
+            Self::Invalid(offset) => write!(f, "header database is malformed at byte {offset}"),
+        }
+    }
+}
+impl Error for DbError {}
@@ -0,0 +1,130 @@
+// What NES 2.0 byte 15 says is plugged into the controller ports and expansion port by default.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ExpansionDevice {
+    Unspecified,
+    StandardControllers,
+    FourScore,
+    FourPlayersAdapter,
+    VsSystem4016,
+    VsSystem4017,
+    VsZapper,
+    Zapper,
+    TwoZappers,
+    BandaiHyperShot,
+    PowerPadSideA,
+    PowerPadSideB,
+    FamilyTrainerSideA,
+    FamilyTrainerSideB,
+    ArkanoidNes,
+    ArkanoidFamicom,
+    TwoArkanoidsAndDataRecorder,
+    KonamiHyperShot,
+    CoconutsPachinko,
+    PunchingBag,
+    JissenMahjong,
+    PartyTap,
+    OekaKidsTablet,
+    BarcodeBattler,
+    MiraclePiano,
+    PokkunMoguraa,
+    TopRider,
+    DoubleFisted,
+    Famicom3dSystem,
+    DoremikkoKeyboard,
+    RobGyro,
+    DataRecorder,
+    TurboFile,
+    StorageBattleBox,
+    FamilyBasicKeyboard,
+    Pec586Keyboard,
+    Bit79Keyboard,
+    SuborKeyboard,
+    SuborKeyboardMouse3x8,
+    SuborKeyboardMouse24Bit4016,
+    SnesMouse,
+    Multicart,
+    SnesControllers,
+    RacerMateBicycle,
+    UForce,
+    RobStackUp,
+    CityPatrolmanLightgun,
+    SharpC1Cassette,
+    SwappedController,
+    SudokuPad,
+    AblPinball,
+    GoldenNuggetCasino,
+    KedaKeyboard,
+    SuborKeyboardMouse24Bit4017,
+    PortTestController,
+    BandaiMultiGamePlayer,
+    VenomDanceMat,
+    LgTvRemote,
+    FamicomNetworkController,
+    KingFishing,
+    CroakyKaraoke,
+    KingwonKeyboard,
+    ZechengKeyboard,
+}
+impl ExpansionDevice {
+    // In the order of their byte values. $06 is reserved, so it's left out.
+    #[rustfmt::skip]
+    const DEVICES: [(u8, Self); 63] = {
+        use ExpansionDevice::*;
+        [
+            (0x00, Unspecified), (0x01, StandardControllers), (0x02, FourScore),
+            (0x03, FourPlayersAdapter), (0x04, VsSystem4016), (0x05, VsSystem4017),
+            (0x07, VsZapper), (0x08, Zapper), (0x09, TwoZappers), (0x0A, BandaiHyperShot),
+            (0x0B, PowerPadSideA), (0x0C, PowerPadSideB), (0x0D, FamilyTrainerSideA),
+            (0x0E, FamilyTrainerSideB), (0x0F, ArkanoidNes), (0x10, ArkanoidFamicom),
+            (0x11, TwoArkanoidsAndDataRecorder), (0x12, KonamiHyperShot),
+            (0x13, CoconutsPachinko), (0x14, PunchingBag), (0x15, JissenMahjong),
+            (0x16, PartyTap), (0x17, OekaKidsTablet), (0x18, BarcodeBattler),
+            (0x19, MiraclePiano), (0x1A, PokkunMoguraa), (0x1B, TopRider), (0x1C, DoubleFisted),
+            (0x1D, Famicom3dSystem), (0x1E, DoremikkoKeyboard), (0x1F, RobGyro),
+            (0x20, DataRecorder), (0x21, TurboFile), (0x22, StorageBattleBox),
+            (0x23, FamilyBasicKeyboard), (0x24, Pec586Keyboard), (0x25, Bit79Keyboard),
+            (0x26, SuborKeyboard), (0x27, SuborKeyboardMouse3x8),
+            (0x28, SuborKeyboardMouse24Bit4016), (0x29, SnesMouse), (0x2A, Multicart),
+            (0x2B, SnesControllers), (0x2C, RacerMateBicycle), (0x2D, UForce),
+            (0x2E, RobStackUp), (0x2F, CityPatrolmanLightgun), (0x30, SharpC1Cassette),
+            (0x31, SwappedController), (0x32, SudokuPad), (0x33, AblPinball),
+            (0x34, GoldenNuggetCasino), (0x35, KedaKeyboard),
+            (0x36, SuborKeyboardMouse24Bit4017), (0x37, PortTestController),
+            (0x38, BandaiMultiGamePlayer), (0x39, VenomDanceMat), (0x3A, LgTvRemote),
+            (0x3B, FamicomNetworkController), (0x3C, KingFishing), (0x3D, CroakyKaraoke),
+            (0x3E, KingwonKeyboard), (0x3F, ZechengKeyboard),
+        ]
+    };
+
+    // None for the reserved value, and anything that doesn't fit in byte 15's six bits.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::DEVICES
+            .iter()
+            .find(|(value, _)| *value == byte)
+            .map(|&(_, device)| device)
+    }
+    pub fn to_byte(self) -> u8 {
+        Self::DEVICES
+            .iter()
+            .find(|(_, device)| *device == self)
+            .map(|&(value, _)| value)
+            .unwrap()
+    }
+
+    // Whether the console's own input emulation covers this device.
+    pub fn emulated(self) -> bool {
+        matches!(
+            self,
+            Self::Unspecified
+                | Self::StandardControllers
+                | Self::VsSystem4016
+                | Self::VsSystem4017
+                | Self::Zapper
+                | Self::FamilyBasicKeyboard
+        )
+    }
+    // Some Vs. System games read player 1 from $4017 instead of $4016.
+    pub fn swaps_ports(self) -> bool {
+        self == Self::VsSystem4017
+    }
+}
@@ -0,0 +1,63 @@
+use std::{error::Error, fmt};
+
+pub const SIDE_SIZE: usize = 65500;
+const FWNES_HEADER_SIZE: usize = 16;
+
+// A Famicom Disk System disk image, as the blocks stored on each side without gaps or CRCs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Disk {
+    sides: Vec<Vec<u8>>,
+}
+impl Disk {
+    // Reads .fds images, with or without the 16 byte fwNES header.
+    pub fn parse(src: &[u8]) -> Result<Self, FdsError> {
+        let src = if src.starts_with(b"FDS\x1A") {
+            src.get(FWNES_HEADER_SIZE..).ok_or(FdsError::Truncated)?
+        } else {
+            src
+        };
+        if src.is_empty() {
+            return Err(FdsError::Truncated);
+        };
+
+        let mut sides = Vec::new();
+        for side in src.chunks(SIDE_SIZE) {
+            // Some dumps drop the unused end of the last side.
+            let mut side = side.to_vec();
+            side.resize(SIDE_SIZE, 0);
+            if !side.starts_with(b"\x01*NINTENDO-HVC*") {
+                return Err(FdsError::NotADisk(sides.len()));
+            };
+            sides.push(side);
+        }
+        Ok(Self { sides })
+    }
+
+    pub fn sides(&self) -> usize {
+        self.sides.len()
+    }
+    pub fn side(&self, side: usize) -> &[u8] {
+        &self.sides[side]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FdsError {
+    Truncated,
+    // The side with this index doesn't start with a disk info block.
+    NotADisk(usize),
+    // The BIOS has to be exactly 8K. Holds the size it had instead.
+    Bios(usize),
+}
+impl fmt::Display for FdsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "disk image is empty"),
+            Self::NotADisk(side) => {
+                write!(f, "side {side} of the disk image has no disk info block")
+            }
+            Self::Bios(size) => write!(f, "the FDS BIOS is 8K, not {size} bytes"),
+        }
+    }
+}
+impl Error for FdsError {}
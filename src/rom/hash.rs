@@ -0,0 +1,113 @@
+use nes_rom_parser::Rom;
+
+// Checksums of the cartridge data in the form ROM databases key on.
+// The header and trainer are left out, so they can be fixed without changing the hashes.
+pub trait RomHashes {
+    fn prg_crc32(&self) -> u32;
+    fn chr_crc32(&self) -> u32;
+    // PRG followed by CHR.
+    fn rom_crc32(&self) -> u32;
+    fn prg_sha1(&self) -> [u8; 20];
+    fn chr_sha1(&self) -> [u8; 20];
+    fn rom_sha1(&self) -> [u8; 20];
+}
+impl RomHashes for Rom<'_> {
+    fn prg_crc32(&self) -> u32 {
+        crc32(self.prg_rom)
+    }
+    fn chr_crc32(&self) -> u32 {
+        crc32(self.chr_rom)
+    }
+    fn rom_crc32(&self) -> u32 {
+        let mut crc = Crc32::init();
+        crc.update(self.prg_rom);
+        crc.update(self.chr_rom);
+        crc.finish()
+    }
+    fn prg_sha1(&self) -> [u8; 20] {
+        sha1(self.prg_rom)
+    }
+    fn chr_sha1(&self) -> [u8; 20] {
+        sha1(self.chr_rom)
+    }
+    fn rom_sha1(&self) -> [u8; 20] {
+        sha1(&[self.prg_rom, self.chr_rom].concat())
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::init();
+    crc.update(data);
+    crc.finish()
+}
+
+// The zlib CRC-32, one bit at a time. ROMs get hashed once when they're loaded.
+pub struct Crc32(u32);
+impl Crc32 {
+    pub fn init() -> Self {
+        Self(!0)
+    }
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB88320 & mask);
+            }
+        }
+    }
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.into_iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5A827999),
+                1 => (b ^ c ^ d, 0x6ED9EBA1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in state.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
@@ -0,0 +1,309 @@
+use super::expansion::ExpansionDevice;
+use std::{error::Error, fmt, ops::Range};
+
+pub const HEADER_SIZE: usize = 16;
+pub const PLAYCHOICE: u8 = 2;
+pub const PC10_INST_ROM_SIZE: usize = 0x2000;
+pub const PC10_PROM_SIZE: usize = 16;
+
+// Every field of a NES 2.0 header, with all sizes in bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Header {
+    pub mapper: u16,
+    pub submapper: u8,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub vertical_mirroring: bool,
+    pub battery: bool,
+    pub trainer: bool,
+    pub four_screen: bool,
+    pub console_type: u8,
+    pub timing: u8,
+    // Byte 13: the Vs. System PPU and hardware type, or the extended console type.
+    pub console_info: u8,
+    pub misc_roms: u8,
+    pub expansion_device: ExpansionDevice,
+}
+impl Header {
+    // Reads NES 2.0 headers completely. iNES 1.0 headers only get their mapper, PRG/CHR sizes,
+    // flags and timing, plus the 8K of PRG RAM and CHR RAM that's usually assumed.
+    // Bytes 7-15 are ignored if they hold garbage like "DiskDude!".
+    pub fn parse(src: &[u8]) -> Result<Self, HeaderError> {
+        let src = src.get(..HEADER_SIZE).ok_or(HeaderError::Truncated)?;
+        if src[0..4] != *b"NES\x1A" {
+            return Err(HeaderError::Magic);
+        };
+        let mut src: [u8; HEADER_SIZE] = src.try_into().unwrap();
+        if dirty(&src) {
+            src[7..].fill(0);
+        }
+        let flags_6 = src[6];
+        let flags_7 = src[7];
+        let mut header = Self {
+            mapper: (flags_6 >> 4 | flags_7 & 0xF0) as u16,
+            submapper: 0,
+            prg_rom_size: src[4] as usize * 0x4000,
+            chr_rom_size: src[5] as usize * 0x2000,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            vertical_mirroring: flags_6 & 1 != 0,
+            battery: flags_6 & 2 != 0,
+            trainer: flags_6 & 4 != 0,
+            four_screen: flags_6 & 8 != 0,
+            console_type: flags_7 & 3,
+            timing: src[9] & 1,
+            console_info: 0,
+            misc_roms: 0,
+            expansion_device: ExpansionDevice::Unspecified,
+        };
+
+        if flags_7 & 0x0C != 0x08 {
+            if header.battery {
+                header.prg_nvram_size = 0x2000;
+            } else {
+                header.prg_ram_size = 0x2000;
+            }
+            if header.chr_rom_size == 0 {
+                header.chr_ram_size = 0x2000;
+            }
+            return Ok(header);
+        };
+
+        header.mapper |= ((src[8] & 0xF) as u16) << 8;
+        header.submapper = src[8] >> 4;
+        header.prg_rom_size = rom_size(src[4], src[9] & 0xF, 0x4000)?;
+        header.chr_rom_size = rom_size(src[5], src[9] >> 4, 0x2000)?;
+        header.prg_ram_size = ram_size(src[10] & 0xF);
+        header.prg_nvram_size = ram_size(src[10] >> 4);
+        header.chr_ram_size = ram_size(src[11] & 0xF);
+        header.chr_nvram_size = ram_size(src[11] >> 4);
+        header.timing = src[12] & 3;
+        header.console_info = src[13];
+        header.misc_roms = src[14] & 3;
+        let device = src[15] & 0x3F;
+        header.expansion_device =
+            ExpansionDevice::from_byte(device).ok_or(HeaderError::ExpansionDevice(device))?;
+        Ok(header)
+    }
+
+    // Encodes the header as NES 2.0.
+    // Panics if a field doesn't fit, or a size can't be expressed in the header at all.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        self.try_to_bytes()
+            .unwrap_or_else(|err| panic!("{err}: {self:?}"))
+    }
+    pub fn try_to_bytes(&self) -> Result<[u8; HEADER_SIZE], HeaderError> {
+        let fits = self.mapper < 0x1000
+            && self.submapper < 0x10
+            && self.console_type < 4
+            && self.timing < 4
+            && self.misc_roms < 4;
+        if !fits {
+            return Err(HeaderError::Unencodable);
+        };
+        let mapper = self.mapper;
+
+        let mut flags_6 = (mapper as u8 & 0xF) << 4;
+        flags_6 |= (self.four_screen as u8) << 3;
+        flags_6 |= (self.trainer as u8) << 2;
+        flags_6 |= (self.battery as u8) << 1;
+        flags_6 |= self.vertical_mirroring as u8;
+        let flags_7 = (mapper as u8 & 0xF0) | 0x08 | self.console_type;
+        let mapper_msb = (self.submapper << 4) | (mapper >> 8) as u8;
+
+        let (prg_lsb, prg_msb) = encode_rom_size(self.prg_rom_size, 0x4000)?;
+        let (chr_lsb, chr_msb) = encode_rom_size(self.chr_rom_size, 0x2000)?;
+        let prg_ram =
+            encode_ram_size(self.prg_nvram_size)? << 4 | encode_ram_size(self.prg_ram_size)?;
+        let chr_ram =
+            encode_ram_size(self.chr_nvram_size)? << 4 | encode_ram_size(self.chr_ram_size)?;
+
+        Ok([
+            b'N',
+            b'E',
+            b'S',
+            0x1A,
+            prg_lsb,
+            chr_lsb,
+            flags_6,
+            flags_7,
+            mapper_msb,
+            chr_msb << 4 | prg_msb,
+            prg_ram,
+            chr_ram,
+            self.timing,
+            self.console_info,
+            self.misc_roms,
+            self.expansion_device.to_byte(),
+        ])
+    }
+}
+
+// Headers written before NES 2.0 existed sometimes have a ripper's name in bytes 7-15.
+// Those can't be iNES 1.0 either, which leaves bytes 12-15 zero.
+pub fn dirty(header: &[u8; HEADER_SIZE]) -> bool {
+    match header[7] & 0x0C {
+        0x00 => header[12..16] != [0; 4],
+        0x08 => false,
+        _ => true,
+    }
+}
+
+// A size nibble of $F switches the low byte to exponent-multiplier form: 2^E * (M*2 + 1).
+fn rom_size(lsb: u8, msb: u8, unit: usize) -> Result<usize, HeaderError> {
+    if msb != 0xF {
+        return Ok(((msb as usize) << 8 | lsb as usize) * unit);
+    };
+    let multiplier = (lsb & 3) as usize * 2 + 1;
+    let exponent = (lsb >> 2) as u32;
+    1usize
+        .checked_shl(exponent)
+        .and_then(|size| size.checked_mul(multiplier))
+        .ok_or(HeaderError::Size)
+}
+fn encode_rom_size(size: usize, unit: usize) -> Result<(u8, u8), HeaderError> {
+    let units = size / unit;
+    if units * unit == size && units < 0xF00 {
+        return Ok((units as u8, (units >> 8) as u8));
+    };
+    let exponent = size.trailing_zeros();
+    let multiplier = size >> exponent;
+    if multiplier > 7 || exponent >= 64 {
+        return Err(HeaderError::Unencodable);
+    };
+    Ok(((exponent as u8) << 2 | (multiplier / 2) as u8, 0xF))
+}
+
+// RAM sizes are shift counts, 64 << n bytes, and zero for none.
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+fn encode_ram_size(size: usize) -> Result<u8, HeaderError> {
+    if size == 0 {
+        return Ok(0);
+    };
+    let shift = size.trailing_zeros().saturating_sub(6);
+    if size != 64 << shift || !(1..16).contains(&shift) {
+        return Err(HeaderError::Unencodable);
+    };
+    Ok(shift as u8)
+}
+
+// An image made from its parts. The header's ROM sizes and trainer flag are set to match them.
+pub fn assemble(
+    header: &Header,
+    prg: &[u8],
+    chr: &[u8],
+    trainer: Option<&[u8]>,
+    misc: &[u8],
+) -> Vec<u8> {
+    let header = Header {
+        prg_rom_size: prg.len(),
+        chr_rom_size: chr.len(),
+        trainer: trainer.is_some(),
+        ..header.clone()
+    };
+    let mut image = header.to_bytes().to_vec();
+    if let Some(trainer) = trainer {
+        assert_eq!(trainer.len(), super::TRAINER_SIZE);
+        image.extend_from_slice(trainer);
+    }
+    image.extend_from_slice(prg);
+    image.extend_from_slice(chr);
+    image.extend_from_slice(misc);
+    image
+}
+
+// Where each part of an image is. PlayChoice-10 images have their INST-ROM and PROM after CHR ROM,
+// anything else there is miscellaneous ROM.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Layout {
+    pub trainer: Option<Range<usize>>,
+    pub prg_rom: Range<usize>,
+    pub chr_rom: Range<usize>,
+    pub pc10_inst_rom: Option<Range<usize>>,
+    pub pc10_prom: Option<Range<usize>>,
+    pub misc_rom: Option<Range<usize>>,
+}
+impl Layout {
+    pub fn of(src: &[u8]) -> Result<Self, HeaderError> {
+        let header = Header::parse(src)?;
+        let mut pos = HEADER_SIZE;
+        // Exponent-multiplier sizes can add up to more than fits in memory.
+        let mut next = |len: usize| {
+            let end = pos.checked_add(len).ok_or(HeaderError::Size)?;
+            let range = pos..end;
+            pos = end;
+            Ok(range)
+        };
+        let trainer = header
+            .trainer
+            .then(|| next(super::TRAINER_SIZE))
+            .transpose()?;
+        let prg_rom = next(header.prg_rom_size)?;
+        let chr_rom = next(header.chr_rom_size)?;
+        if chr_rom.end > src.len() {
+            return Err(HeaderError::ImageSize);
+        };
+
+        let mut layout = Self {
+            trainer,
+            prg_rom,
+            chr_rom,
+            pc10_inst_rom: None,
+            pc10_prom: None,
+            misc_rom: None,
+        };
+        let rest = layout.chr_rom.end..src.len();
+        if header.console_type == PLAYCHOICE {
+            // The PROM's CounterOut half is often left out of dumps, so only the data half is needed.
+            let inst_rom = rest.start..rest.start + PC10_INST_ROM_SIZE;
+            let prom = inst_rom.end..inst_rom.end + PC10_PROM_SIZE;
+            if prom.end > rest.end {
+                return Err(HeaderError::Pc10Size);
+            };
+            layout.pc10_inst_rom = Some(inst_rom);
+            layout.pc10_prom = Some(prom);
+        } else if header.misc_roms != 0 && !rest.is_empty() {
+            layout.misc_rom = Some(rest);
+        }
+        Ok(layout)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HeaderError {
+    Truncated,
+    Magic,
+    Size,
+    Unencodable,
+    ImageSize,
+    Pc10Size,
+    ExpansionDevice(u8),
+}
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "image is shorter than a header"),
+            Self::Magic => write!(f, "image doesn't start with NES<EOF>"),
+            Self::Size => write!(f, "header declares a ROM size that doesn't fit in memory"),
+            Self::Unencodable => write!(f, "header field can't be expressed in NES 2.0"),
+            Self::ImageSize => write!(f, "image is shorter than its header says"),
+            Self::Pc10Size => write!(f, "PlayChoice-10 image lacks its INST-ROM or PROM"),
+            Self::ExpansionDevice(device) => {
+                write!(f, "expansion device ${device:02X} is reserved")
+            }
+        }
+    }
+}
+impl Error for HeaderError {}
@@ -0,0 +1,115 @@
+use crate::region::Region;
+use std::{error::Error, fmt};
+
+const HEADER_SIZE: usize = 0x80;
+
+// An NSF music file. Songs are numbered from zero here, unlike in the header.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Nsf {
+    pub version: u8,
+    pub songs: u8,
+    pub first_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub name: String,
+    pub artist: String,
+    pub copyright: String,
+    // Microseconds between calls to PLAY.
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // The initial 4K bank for each slot from $8000 to $F000, if the file uses bank switching.
+    pub banks: Option<[u8; 8]>,
+    // Files that support both regions play as NTSC.
+    pub region: Region,
+    pub expansion_chips: u8,
+    pub data: Vec<u8>,
+}
+impl Nsf {
+    pub fn parse(src: &[u8]) -> Result<Self, NsfError> {
+        let header = src.get(..HEADER_SIZE).ok_or(NsfError::Truncated)?;
+        if header[0..5] != *b"NESM\x1A" {
+            return Err(NsfError::Magic);
+        };
+        let word = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]);
+        let text = |at: usize| {
+            let field = &header[at..at + 32];
+            let end = field.iter().position(|&byte| byte == 0).unwrap_or(32);
+            String::from_utf8_lossy(&field[..end]).into_owned()
+        };
+
+        let songs = header[6];
+        if songs == 0 {
+            return Err(NsfError::NoSongs);
+        };
+        let banks: [u8; 8] = header[0x70..0x78].try_into().unwrap();
+        let banks = banks.iter().any(|&bank| bank != 0).then_some(banks);
+        let load_address = word(0x08);
+        if load_address < 0x8000 {
+            return Err(NsfError::LoadAddress(load_address));
+        };
+        let region = if header[0x7A] & 3 == 1 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
+
+        // NSF2 files can have metadata after the program, which the header then gives the length of.
+        let mut data = &src[HEADER_SIZE..];
+        let data_len = u32::from_le_bytes([header[0x7D], header[0x7E], header[0x7F], 0]) as usize;
+        if header[5] >= 2 && data_len != 0 {
+            data = data.get(..data_len).ok_or(NsfError::Truncated)?;
+        };
+
+        Ok(Self {
+            version: header[5],
+            songs,
+            first_song: header[7].saturating_sub(1).min(songs - 1),
+            load_address,
+            init_address: word(0x0A),
+            play_address: word(0x0C),
+            name: text(0x0E),
+            artist: text(0x2E),
+            copyright: text(0x4E),
+            ntsc_speed: word(0x6E),
+            pal_speed: word(0x78),
+            banks,
+            region,
+            expansion_chips: header[0x7B],
+            data: data.to_vec(),
+        })
+    }
+
+    // How often PLAY gets called in the given region, in microseconds.
+    // Files that leave the rate out get the usual 60 or 50 calls a second.
+    pub fn speed(&self, region: Region) -> u16 {
+        let (speed, default) = match region {
+            Region::Ntsc => (self.ntsc_speed, 16639),
+            Region::Pal => (self.pal_speed, 19997),
+        };
+        if speed == 0 {
+            default
+        } else {
+            speed
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NsfError {
+    Magic,
+    Truncated,
+    NoSongs,
+    LoadAddress(u16),
+}
+impl fmt::Display for NsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Magic => write!(f, "file doesn't start with NESM"),
+            Self::Truncated => write!(f, "NSF file ends early"),
+            Self::NoSongs => write!(f, "NSF file has no songs"),
+            Self::LoadAddress(addr) => write!(f, "NSF data can't be loaded at ${addr:04X}"),
+        }
+    }
+}
+impl Error for NsfError {}
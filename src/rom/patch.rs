@@ -0,0 +1,179 @@
+use super::hash::crc32;
+use std::{error::Error, fmt};
+
+// Applies an IPS or BPS patch, whichever the patch's magic says it is.
+pub fn apply(rom: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::Magic)
+    }
+}
+
+// Records past the end of the image grow it, and the optional length after EOF truncates it.
+pub fn apply_ips(rom: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    let mut patch = patch.strip_prefix(b"PATCH").ok_or(PatchError::Magic)?;
+    loop {
+        let offset = take(&mut patch, 3)?;
+        // A record at offset $454F46 spells EOF too.
+        // It's only the end of the patch if nothing but a truncation length follows.
+        if offset == b"EOF" && (patch.is_empty() || patch.len() == 3) {
+            break;
+        };
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let len = take(&mut patch, 2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+
+        // A zero length means a run of one byte.
+        if len == 0 {
+            let run = take(&mut patch, 3)?;
+            let len = u16::from_be_bytes([run[0], run[1]]) as usize;
+            grow(rom, offset + len);
+            rom[offset..offset + len].fill(run[2]);
+        } else {
+            let data = take(&mut patch, len)?;
+            grow(rom, offset + len);
+            rom[offset..offset + len].copy_from_slice(data);
+        }
+    }
+
+    if let Ok(len) = take(&mut patch, 3) {
+        let len = u32::from_be_bytes([0, len[0], len[1], len[2]]) as usize;
+        rom.truncate(len);
+    }
+    Ok(())
+}
+
+// BPS patches carry checksums of the image they expect and the one they make, so both are checked.
+pub fn apply_bps(rom: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    if !patch.starts_with(b"BPS1") {
+        return Err(PatchError::Magic);
+    };
+    if patch.len() < 4 + 12 {
+        return Err(PatchError::Truncated);
+    };
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let checksum = |at: usize| u32::from_le_bytes(footer[at..at + 4].try_into().unwrap());
+    if crc32(&patch[..patch.len() - 4]) != checksum(8) {
+        return Err(PatchError::PatchChecksum);
+    };
+    if crc32(rom) != checksum(0) {
+        return Err(PatchError::SourceChecksum);
+    };
+
+    let mut body = &body[4..];
+    let source_size = number(&mut body)?;
+    let target_size = number(&mut body)?;
+    let metadata_size = number(&mut body)?;
+    take(&mut body, metadata_size)?;
+    if source_size != rom.len() {
+        return Err(PatchError::SourceChecksum);
+    };
+
+    let source = &rom[..];
+    let mut target = Vec::new();
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while !body.is_empty() {
+        let action = number(&mut body)?;
+        let len = (action >> 2) + 1;
+        match action & 3 {
+            0 => {
+                let at = target.len();
+                let data = source.get(at..at + len).ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(data);
+            }
+            1 => target.extend_from_slice(take(&mut body, len)?),
+            2 => {
+                source_offset = relative(source_offset, &mut body)?;
+                let data = source
+                    .get(source_offset..source_offset.saturating_add(len))
+                    .ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(data);
+                source_offset += len;
+            }
+            _ => {
+                target_offset = relative(target_offset, &mut body)?;
+                // The copy may overlap what it's writing, so it goes a byte at a time.
+                for _ in 0..len {
+                    let byte = *target.get(target_offset).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size || crc32(&target) != checksum(4) {
+        return Err(PatchError::TargetChecksum);
+    };
+    *rom = target;
+    Ok(())
+}
+
+fn grow(rom: &mut Vec<u8>, len: usize) {
+    if rom.len() < len {
+        rom.resize(len, 0);
+    }
+}
+fn take<'a>(src: &mut &'a [u8], len: usize) -> Result<&'a [u8], PatchError> {
+    if src.len() < len {
+        return Err(PatchError::Truncated);
+    };
+    let (taken, rest) = src.split_at(len);
+    *src = rest;
+    Ok(taken)
+}
+// BPS numbers are 7 bits per byte, with each continuation also adding one so no value has two encodings.
+fn number(src: &mut &[u8]) -> Result<usize, PatchError> {
+    let mut number = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = take(src, 1)?[0];
+        let digit = (byte & 0x7F) as usize;
+        number = digit
+            .checked_mul(shift)
+            .and_then(|digit| number.checked_add(digit))
+            .ok_or(PatchError::OutOfBounds)?;
+        if byte & 0x80 != 0 {
+            return Ok(number);
+        };
+        shift = shift.checked_mul(0x80).ok_or(PatchError::OutOfBounds)?;
+        number = number.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+    }
+}
+// Copy offsets are stored relative to the last one, with the sign in the low bit.
+fn relative(offset: usize, src: &mut &[u8]) -> Result<usize, PatchError> {
+    let delta = number(src)?;
+    let result = if delta & 1 != 0 {
+        offset.checked_sub(delta >> 1)
+    } else {
+        offset.checked_add(delta >> 1)
+    };
+    result.ok_or(PatchError::OutOfBounds)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PatchError {
+    Magic,
+    Truncated,
+    SourceChecksum,
+    TargetChecksum,
+    PatchChecksum,
+    OutOfBounds,
+}
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Magic => write!(f, "not an IPS or BPS patch"),
+            Self::Truncated => write!(f, "patch ends early"),
+            Self::SourceChecksum => write!(f, "patch is meant for a different image"),
+            Self::TargetChecksum => write!(f, "patched image doesn't match the patch's checksum"),
+            Self::PatchChecksum => write!(f, "patch is corrupted"),
+            Self::OutOfBounds => write!(f, "patch copies from outside the image"),
+        }
+    }
+}
+impl Error for PatchError {}
@@ -0,0 +1,145 @@
+use super::{
+    expansion::ExpansionDevice,
+    header::{assemble, Header},
+};
+use std::{error::Error, fmt};
+
+const HEADER_SIZE: usize = 32;
+
+// UNIF boards by their name without the NES-, HVC-, UNL-, BTL- or BMC- prefix.
+#[rustfmt::skip]
+const BOARDS: &[(&str, u16)] = &[
+    ("NROM", 0), ("NROM-128", 0), ("NROM-256", 0), ("RROM", 0), ("RROM-128", 0),
+    ("SAROM", 1), ("SBROM", 1), ("SCROM", 1), ("SEROM", 1), ("SFROM", 1), ("SGROM", 1),
+    ("SHROM", 1), ("SJROM", 1), ("SKROM", 1), ("SLROM", 1), ("SNROM", 1), ("SOROM", 1),
+    ("SUROM", 1), ("SXROM", 1),
+    ("UNROM", 2), ("UOROM", 2),
+    ("CNROM", 3),
+    ("TBROM", 4), ("TEROM", 4), ("TFROM", 4), ("TGROM", 4), ("TKROM", 4), ("TLROM", 4),
+    ("TNROM", 4), ("TR1ROM", 4), ("TSROM", 4), ("TVROM", 4),
+    ("EKROM", 5), ("ELROM", 5), ("ETROM", 5), ("EWROM", 5),
+    ("AMROM", 7), ("ANROM", 7), ("AN1ROM", 7), ("AOROM", 7),
+    ("PEEOROM", 9), ("PNROM", 9),
+    ("FJROM", 10), ("FKROM", 10),
+    ("CPROM", 13),
+    ("BNROM", 34), ("AVE-NINA-01", 34), ("AVE-NINA-02", 34),
+    ("GNROM", 66), ("MHROM", 66),
+    ("SACHEN-8259D", 137), ("SACHEN-8259B", 138), ("SACHEN-8259C", 139), ("SACHEN-8259A", 141),
+];
+const PREFIXES: [&str; 5] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-"];
+
+// Converts a UNIF image into an equivalent NES 2.0 image, which Rom::parse can read.
+// PRG and CHR chunks are joined in the order of their number, not the order they're stored in.
+pub fn parse(src: &[u8]) -> Result<Vec<u8>, UnifError> {
+    if src.len() < HEADER_SIZE {
+        return Err(UnifError::Truncated);
+    };
+    if src[0..4] != *b"UNIF" {
+        return Err(UnifError::Magic);
+    };
+
+    let mut board = None;
+    let mut prg: [&[u8]; 16] = [&[]; 16];
+    let mut chr: [&[u8]; 16] = [&[]; 16];
+    let mut mirroring = 0;
+    let mut battery = false;
+    let mut rest = &src[HEADER_SIZE..];
+    while !rest.is_empty() {
+        let (id, data, after) = chunk(rest)?;
+        rest = after;
+        match id {
+            "MAPR" => board = Some(string(data)),
+            "MIRR" => mirroring = *data.first().ok_or(UnifError::Truncated)?,
+            "BATR" => battery = true,
+            _ => {
+                if let Some(index) = bank(id, "PRG") {
+                    prg[index] = data;
+                } else if let Some(index) = bank(id, "CHR") {
+                    chr[index] = data;
+                }
+            }
+        }
+    }
+
+    let board = board.ok_or(UnifError::NoBoard)?;
+    let name = PREFIXES
+        .iter()
+        .find_map(|prefix| board.strip_prefix(prefix))
+        .unwrap_or(&board);
+    let &(_, mapper) = BOARDS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .ok_or_else(|| UnifError::UnknownBoard(board.clone()))?;
+
+    let prg = prg.concat();
+    let chr = chr.concat();
+    let prg_ram = if battery { 0 } else { 0x2000 };
+    let header = Header {
+        mapper,
+        submapper: 0,
+        prg_rom_size: prg.len(),
+        chr_rom_size: chr.len(),
+        prg_ram_size: prg_ram,
+        prg_nvram_size: 0x2000 - prg_ram,
+        chr_ram_size: if chr.is_empty() { 0x2000 } else { 0 },
+        chr_nvram_size: 0,
+        vertical_mirroring: mirroring == 1,
+        battery,
+        trainer: false,
+        four_screen: mirroring == 4,
+        console_type: 0,
+        timing: 0,
+        console_info: 0,
+        misc_roms: 0,
+        expansion_device: ExpansionDevice::Unspecified,
+    };
+    header.try_to_bytes().map_err(|_| UnifError::Size)?;
+    Ok(assemble(&header, &prg, &chr, None, &[]))
+}
+
+// A chunk's ID and data, and whatever follows it.
+fn chunk(src: &[u8]) -> Result<(&str, &[u8], &[u8]), UnifError> {
+    let id = src.get(0..4).ok_or(UnifError::Truncated)?;
+    let id = std::str::from_utf8(id).unwrap_or("");
+    let len = src.get(4..8).ok_or(UnifError::Truncated)?;
+    let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+    let data = src.get(8..8 + len).ok_or(UnifError::Truncated)?;
+    Ok((id, data, &src[8 + len..]))
+}
+// PRG0 to PRGF and CHR0 to CHRF.
+fn bank(id: &str, kind: &str) -> Option<usize> {
+    let digit = id.strip_prefix(kind)?;
+    usize::from_str_radix(digit, 16).ok()
+}
+// Strings are zero terminated, though not always.
+fn string(data: &[u8]) -> String {
+    let end = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UnifError {
+    Magic,
+    Truncated,
+    NoBoard,
+    UnknownBoard(String),
+    Size,
+}
+impl fmt::Display for UnifError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Magic => write!(f, "image doesn't start with UNIF"),
+            Self::Truncated => write!(f, "UNIF image has a broken chunk"),
+            Self::NoBoard => write!(f, "UNIF image has no MAPR chunk"),
+            Self::UnknownBoard(board) => write!(f, "UNIF board {board} isn't supported"),
+            Self::Size => write!(
+                f,
+                "UNIF image's PRG or CHR size can't be expressed in NES 2.0"
+            ),
+        }
+    }
+}
+impl Error for UnifError {}
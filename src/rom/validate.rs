@@ -0,0 +1,102 @@
+use super::{
+    header::{dirty, Header, HEADER_SIZE, PC10_INST_ROM_SIZE, PC10_PROM_SIZE, PLAYCHOICE},
+    TRAINER_SIZE,
+};
+use std::fmt;
+
+// Oddities of real dumps that don't stop an image from loading.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RomWarning {
+    // Bytes 7-15 hold garbage like "DiskDude!" and get ignored.
+    DirtyHeader,
+    // The header looks like NES 2.0, but declares more ROM than the image has.
+    FalseNes2,
+    TrailingBytes(usize),
+    PrgSize(usize),
+    ChrSize(usize),
+    // An iNES 1.0 header with the low nibble of byte 8 set, which NES 2.0 would read as this mapper.
+    ExtendedMapper(u16),
+}
+impl fmt::Display for RomWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DirtyHeader => write!(f, "header bytes 7-15 hold garbage and were ignored"),
+            Self::FalseNes2 => write!(f, "header claims NES 2.0 but its sizes don't match"),
+            Self::TrailingBytes(len) => write!(f, "{len} bytes after the end of CHR ROM"),
+            Self::PrgSize(size) => write!(f, "PRG ROM size {size} isn't a power of two"),
+            Self::ChrSize(size) => write!(f, "CHR ROM size {size} isn't a power of two"),
+            Self::ExtendedMapper(mapper) => write!(
+                f,
+                "iNES 1.0 header has mapper bits of NES 2.0 mapper {mapper} in byte 8"
+            ),
+        }
+    }
+}
+
+// Everything odd about an image, as read after clean_header.
+pub fn validate(src: &[u8]) -> Vec<RomWarning> {
+    let Some(bytes) = src.get(..HEADER_SIZE) else {
+        return Vec::new();
+    };
+    let bytes: [u8; HEADER_SIZE] = bytes.try_into().unwrap();
+    let mut warnings = Vec::new();
+    if dirty(&bytes) {
+        warnings.push(RomWarning::DirtyHeader);
+    } else if false_nes2(src) {
+        warnings.push(RomWarning::FalseNes2);
+    }
+    let mut cleaned = src.to_vec();
+    clean_header(&mut cleaned);
+    let Ok(header) = Header::parse(&cleaned) else {
+        return warnings;
+    };
+
+    let nes2 = cleaned[7] & 0x0C == 0x08;
+    if !nes2 && cleaned[8] & 0xF != 0 {
+        let mapper = header.mapper | ((cleaned[8] & 0xF) as u16) << 8;
+        warnings.push(RomWarning::ExtendedMapper(mapper));
+    }
+    if !header.prg_rom_size.is_power_of_two() {
+        warnings.push(RomWarning::PrgSize(header.prg_rom_size));
+    }
+    if header.chr_rom_size != 0 && !header.chr_rom_size.is_power_of_two() {
+        warnings.push(RomWarning::ChrSize(header.chr_rom_size));
+    }
+    // Miscellaneous ROMs take up whatever follows CHR ROM.
+    let mut len = image_size(&header);
+    if header.console_type == PLAYCHOICE {
+        len += PC10_INST_ROM_SIZE + 2 * PC10_PROM_SIZE;
+    }
+    if src.len() > len && header.misc_roms == 0 {
+        warnings.push(RomWarning::TrailingBytes(src.len() - len));
+    }
+    warnings
+}
+
+// Zeroes bytes 7-15 of a header that holds garbage there, or claims NES 2.0 with sizes
+// the image doesn't have. Returns whether anything changed.
+pub fn clean_header(src: &mut [u8]) -> bool {
+    let Some(bytes) = src.get(..HEADER_SIZE) else {
+        return false;
+    };
+    if !dirty(bytes.try_into().unwrap()) && !false_nes2(src) {
+        return false;
+    };
+    let changed = src[7..HEADER_SIZE] != [0; 9];
+    src[7..HEADER_SIZE].fill(0);
+    changed
+}
+
+fn false_nes2(src: &[u8]) -> bool {
+    if src.len() < HEADER_SIZE || src[7] & 0x0C != 0x08 {
+        return false;
+    };
+    match Header::parse(src) {
+        Ok(header) => image_size(&header) > src.len(),
+        Err(_) => true,
+    }
+}
+fn image_size(header: &Header) -> usize {
+    let trainer = if header.trainer { TRAINER_SIZE } else { 0 };
+    HEADER_SIZE + trainer + header.prg_rom_size + header.chr_rom_size
+}
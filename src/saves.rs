@@ -0,0 +1,144 @@
+use crate::{mapper::Mapper, rom::hash::RomHashes};
+use nes_rom_parser::Rom;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+// Changed save RAM gets written out this often, about every five seconds.
+// Games keep scribbling into it while they run, so writing on every change would be constant.
+const FLUSH_INTERVAL_FRAMES: u32 = 300;
+
+// Where battery saves are kept, keyed by `game_id`.
+pub trait SaveStore: Send {
+    fn load(&mut self, game_id: &str) -> Option<Vec<u8>>;
+    fn save(&mut self, game_id: &str, data: &[u8]) -> io::Result<()>;
+}
+
+// The SHA-1 of PRG and CHR in hex, so fixing a header doesn't lose the save.
+pub fn game_id(rom: &Rom) -> String {
+    rom.rom_sha1()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+// One `<game_id>.sav` per game in a directory, created on the first save.
+pub struct FileSaveStore {
+    dir: PathBuf,
+}
+impl FileSaveStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, game_id: &str) -> PathBuf {
+        self.dir.join(format!("{game_id}.sav"))
+    }
+}
+impl SaveStore for FileSaveStore {
+    fn load(&mut self, game_id: &str) -> Option<Vec<u8>> {
+        fs::read(self.path(game_id)).ok()
+    }
+    // Written next to the old save and renamed over it, so a crash can't leave half a file.
+    fn save(&mut self, game_id: &str, data: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(game_id);
+        let temp = path.with_extension("sav.tmp");
+        fs::write(&temp, data)?;
+        fs::rename(temp, path)
+    }
+}
+
+// Keeps saves in memory. Clones share them, so a test can keep one and look inside later.
+#[derive(Clone, Default)]
+pub struct MemorySaveStore(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+impl MemorySaveStore {
+    pub fn init() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, game_id: &str) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().get(game_id).cloned()
+    }
+}
+impl SaveStore for MemorySaveStore {
+    fn load(&mut self, game_id: &str) -> Option<Vec<u8>> {
+        self.get(game_id)
+    }
+    fn save(&mut self, game_id: &str, data: &[u8]) -> io::Result<()> {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(game_id.to_string(), data.to_vec());
+        Ok(())
+    }
+}
+
+// Keeps a cartridge's battery RAM in sync with a store.
+// Anything not yet written out is flushed when it's dropped.
+pub struct BatterySaves {
+    store: Box<dyn SaveStore>,
+    game_id: String,
+    saved: Vec<u8>,
+    // Copied every frame, since the cartridge is out of reach when this gets dropped.
+    latest: Vec<u8>,
+    frames: u32,
+}
+impl BatterySaves {
+    // Loads the game's save into the cartridge, if there is one.
+    pub fn attach(
+        mut store: Box<dyn SaveStore>,
+        game_id: String,
+        mapper: &mut impl Mapper,
+    ) -> Self {
+        if let Some(data) = store.load(&game_id) {
+            mapper.load_save_ram(&data);
+        }
+        let ram = mapper.save_ram().unwrap_or_default().to_vec();
+        Self {
+            store,
+            game_id,
+            saved: ram.clone(),
+            latest: ram,
+            frames: 0,
+        }
+    }
+
+    // Called once a frame. Failed writes are tried again on later frames.
+    pub fn frame(&mut self, mapper: &impl Mapper) {
+        self.update(mapper);
+        self.frames += 1;
+        if self.frames >= FLUSH_INTERVAL_FRAMES {
+            self.frames = 0;
+            let _ = self.write_out();
+        }
+    }
+    pub fn flush(&mut self, mapper: &impl Mapper) -> io::Result<()> {
+        self.update(mapper);
+        self.write_out()
+    }
+
+    fn update(&mut self, mapper: &impl Mapper) {
+        let ram = mapper.save_ram().unwrap_or_default();
+        if ram != self.latest {
+            self.latest.clear();
+            self.latest.extend_from_slice(ram);
+        };
+    }
+    fn write_out(&mut self) -> io::Result<()> {
+        if self.latest == self.saved {
+            return Ok(());
+        };
+        self.store.save(&self.game_id, &self.latest)?;
+        self.saved.clone_from(&self.latest);
+        Ok(())
+    }
+}
+impl Drop for BatterySaves {
+    fn drop(&mut self) {
+        let _ = self.write_out();
+    }
+}
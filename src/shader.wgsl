@@ -24,7 +24,7 @@ const NES_WIDTH: u32 = 256;
 const NES_HEIGHT: u32 = 240;
 const NES_PIXELS: u32 = NES_WIDTH * NES_HEIGHT;
 
-const PALETTE_ENTRIES: u32 = 64;
+const PALETTE_ENTRIES: u32 = 512;
 
 @group(0) @binding(0) var<storage> pixels: array<u32, NES_PIXELS>;
 @group(0) @binding(1) var<uniform> screen: vec2u;
@@ -0,0 +1,102 @@
+use crate::{
+    emulator::{Emulator, EmulatorError},
+    mapper::DynMapper,
+    nes::Nes,
+};
+use std::{error::Error, fmt, io, path::Path};
+
+// Blargg's test ROMs write this to $6001-$6003 once $6000 holds their status.
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const RUNNING: u8 = 0x80;
+// The test wants the RESET button pressed, no sooner than 100ms from now.
+const NEEDS_RESET: u8 = 0x81;
+const RESET_DELAY_FRAMES: u32 = 8;
+
+// Runs one of blargg's test ROMs until it reports a result through $6000,
+// returning the text it left at $6004 if it passed.
+pub fn run_blargg_rom(path: impl AsRef<Path>, timeout_frames: u32) -> Result<String, TestFailure> {
+    let mut nes = load(path)?;
+
+    let mut reset_at = None;
+    for frame in 0..timeout_frames {
+        nes.run_frame();
+        if reset_at == Some(frame) {
+            reset_at = None;
+            nes.reset();
+        };
+        if [0x6001, 0x6002, 0x6003].map(|addr| nes.peek(addr)) != SIGNATURE {
+            continue;
+        };
+        match nes.peek(0x6000) {
+            RUNNING => (),
+            NEEDS_RESET => {
+                reset_at.get_or_insert(frame + RESET_DELAY_FRAMES);
+            }
+            0 => return Ok(message(&nes)),
+            status => {
+                let message = message(&nes);
+                return Err(TestFailure::Failed { status, message });
+            }
+        }
+    }
+    Err(TestFailure::Timeout(message(&nes)))
+}
+
+// Runs a ROM headless from power on and hashes the picture it drew on the given frame, counting from 1.
+pub fn frame_hash(path: impl AsRef<Path>, frame_number: u32) -> Result<u64, TestFailure> {
+    let mut nes = load(path)?;
+    for _ in 0..frame_number {
+        nes.run_frame();
+    }
+    Ok(nes.framebuffer_hash())
+}
+// For golden image tests. Panics with the hash it got, so new goldens can be copied from the message.
+#[track_caller]
+pub fn assert_frame_hash(path: impl AsRef<Path>, frame_number: u32, expected: u64) {
+    let path = path.as_ref();
+    let hash =
+        frame_hash(path, frame_number).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+    assert!(
+        hash == expected,
+        "{} frame {frame_number}: expected {expected:#018X}, got {hash:#018X}",
+        path.display()
+    );
+}
+
+fn load(path: impl AsRef<Path>) -> Result<Nes<DynMapper>, TestFailure> {
+    let src = std::fs::read(path).map_err(TestFailure::Io)?;
+    Emulator::builder()
+        .rom_bytes(src)
+        .build_nes()
+        .map_err(TestFailure::Load)
+}
+
+// Zero terminated, and cut short if it runs off the end of PRG RAM.
+fn message(nes: &Nes<DynMapper>) -> String {
+    let bytes = (0x6004..0x8000)
+        .map(|addr| nes.peek(addr))
+        .take_while(|&byte| byte != 0);
+    bytes.map(char::from).collect()
+}
+
+#[derive(Debug)]
+pub enum TestFailure {
+    Io(io::Error),
+    Load(EmulatorError),
+    // No result within the given number of frames. Holds whatever text was there by then.
+    Timeout(String),
+    Failed { status: u8, message: String },
+}
+impl fmt::Display for TestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "can't read test ROM: {err}"),
+            Self::Load(err) => write!(f, "can't load test ROM: {err}"),
+            Self::Timeout(message) => write!(f, "timed out: {}", message.trim_end()),
+            Self::Failed { status, message } => {
+                write!(f, "failed with status {status}: {}", message.trim_end())
+            }
+        }
+    }
+}
+impl Error for TestFailure {}
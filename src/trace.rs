@@ -0,0 +1,98 @@
+use crate::{
+    disasm::{format_operand, opcode, Mode},
+    mapper::Mapper,
+    nesbus::NesBus,
+};
+use cpu_6502::Cpu;
+
+// One line of a log in the format of nestest.log, for the instruction the CPU is about to run.
+// Everything is peeked, so tracing doesn't change what the emulator does.
+pub fn nestest_line<M: Mapper>(cpu: &Cpu, bus: &NesBus<M>) -> String {
+    let pc = cpu.pc();
+    let op = opcode(bus.peek_cpu(pc));
+    let bytes: Vec<String> = (0..op.size())
+        .map(|i| format!("{:02X}", bus.peek_cpu(pc.wrapping_add(i))))
+        .collect();
+    let bytes = bytes.join(" ");
+    let mark = if op.official { ' ' } else { '*' };
+    let text = instruction_text(bus, pc, cpu.x(), cpu.y());
+    let [dot, line] = bus.ppu().dot();
+
+    format!(
+        "{pc:04X}  {bytes:<8} {mark}{text:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{line:>3},{dot:>3} CYC:{}",
+        cpu.a(),
+        cpu.x(),
+        cpu.y(),
+        status(cpu),
+        cpu.sp() as u8,
+        bus.cycles(),
+    )
+}
+
+// The instruction at PC with the addresses it works out and the values it will find there,
+// like "LDA ($80,X) @ 80 = 0200 = 5A".
+pub fn instruction_text<M: Mapper>(bus: &NesBus<M>, pc: u16, x: u8, y: u8) -> String {
+    let peek = |addr: u16| bus.peek_cpu(addr);
+    let peek_word = |low: u16, high: u16| u16::from_le_bytes([peek(low), peek(high)]);
+    let peek_zp_word = |addr: u8| peek_word(addr as u16, addr.wrapping_add(1) as u16);
+
+    let op = opcode(peek(pc));
+    let bytes = [pc, pc.wrapping_add(1), pc.wrapping_add(2)].map(peek);
+    let [_, byte, _] = bytes;
+    let word = u16::from_le_bytes([bytes[1], bytes[2]]);
+
+    // The operand as the disassembler writes it, followed by what it works out to.
+    let resolved = match op.mode {
+        Mode::ZeroPage => format!(" = {:02X}", peek(byte as u16)),
+        Mode::ZeroPageX | Mode::ZeroPageY => {
+            let index = if op.mode == Mode::ZeroPageX { x } else { y };
+            let addr = byte.wrapping_add(index);
+            format!(" @ {addr:02X} = {:02X}", peek(addr as u16))
+        }
+        Mode::Absolute if matches!(op.name, "JMP" | "JSR") => String::new(),
+        Mode::Absolute => format!(" = {:02X}", peek(word)),
+        Mode::AbsoluteX | Mode::AbsoluteY => {
+            let index = if op.mode == Mode::AbsoluteX { x } else { y };
+            let addr = word.wrapping_add(index as u16);
+            format!(" @ {addr:04X} = {:02X}", peek(addr))
+        }
+        // The pointer's high byte is read from the same page, even if the low byte is at its end.
+        Mode::Indirect => {
+            let high = word & 0xFF00 | (word as u8).wrapping_add(1) as u16;
+            format!(" = {:04X}", peek_word(word, high))
+        }
+        Mode::IndirectX => {
+            let pointer = byte.wrapping_add(x);
+            let addr = peek_zp_word(pointer);
+            format!(" @ {pointer:02X} = {addr:04X} = {:02X}", peek(addr))
+        }
+        Mode::IndirectY => {
+            let base = peek_zp_word(byte);
+            let addr = base.wrapping_add(y as u16);
+            format!(" = {base:04X} @ {addr:04X} = {:02X}", peek(addr))
+        }
+        Mode::Implied | Mode::Accumulator | Mode::Immediate | Mode::Relative => String::new(),
+    };
+    let operand = format_operand(op.mode, pc, &bytes) + &resolved;
+
+    if operand.is_empty() {
+        op.name.to_string()
+    } else {
+        format!("{} {operand}", op.name)
+    }
+}
+
+// The status register as PHP would push it, minus the B flag.
+pub(crate) fn status(cpu: &Cpu) -> u8 {
+    let flags = cpu.flags();
+    let bits = [
+        (flags.negative(), 7),
+        (flags.overflow(), 6),
+        (true, 5),
+        (flags.decimal(), 3),
+        (flags.irq_disable(), 2),
+        (flags.zero(), 1),
+        (flags.carry(), 0),
+    ];
+    bits.iter().map(|&(set, bit)| (set as u8) << bit).sum()
+}
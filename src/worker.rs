@@ -0,0 +1,108 @@
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use nessy::{input::Controller, ppu::pixel_buffer::PixelBuffer};
+
+use crate::app::App;
+
+// How long the worker naps when it's ahead, before checking for input again.
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+enum Message {
+    // Held buttons, then those held through their turbo key.
+    Controllers([Controller; 2], [Controller; 2]),
+    Run(Box<dyn FnOnce(&mut App) + Send>),
+    Exit,
+}
+
+// Runs the emulator on its own thread, so a slow frame can't hold up window events.
+// Input goes in through messages; finished pictures come back out, newest only.
+pub struct Worker {
+    messages: Sender<Message>,
+    frames: Receiver<Box<PixelBuffer>>,
+    thread: Option<JoinHandle<()>>,
+}
+impl Worker {
+    pub fn spawn(app: App) -> Self {
+        let (messages, inbox) = channel::unbounded();
+        let (outbox, frames) = channel::bounded(1);
+        let stale = frames.clone();
+        let thread = thread::Builder::new()
+            .name("emulation".to_string())
+            .spawn(move || run(app, inbox, outbox, stale))
+            .unwrap();
+
+        Self {
+            messages,
+            frames,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn set_controllers(&self, controllers: [Controller; 2], turbo: [Controller; 2]) {
+        let _ = self.messages.send(Message::Controllers(controllers, turbo));
+    }
+    // Runs on the worker between frames.
+    pub fn send(&self, f: impl FnOnce(&mut App) + Send + 'static) {
+        let _ = self.messages.send(Message::Run(Box::new(f)));
+    }
+    pub fn latest_frame(&self) -> Option<Box<PixelBuffer>> {
+        self.frames.try_iter().last()
+    }
+
+    // Waits for the worker to finish, so audio recordings get closed properly.
+    pub fn stop(&mut self) {
+        let _ = self.messages.send(Message::Exit);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                eprintln!("Emulation thread panicked");
+            }
+        }
+    }
+}
+
+fn run(
+    mut app: App,
+    inbox: Receiver<Message>,
+    outbox: Sender<Box<PixelBuffer>>,
+    stale: Receiver<Box<PixelBuffer>>,
+) {
+    'outer: loop {
+        for message in inbox.try_iter() {
+            match message {
+                Message::Controllers(controllers, turbo) => {
+                    for (port, controller) in controllers.into_iter().enumerate() {
+                        app.nes.set_controller(port, controller.into());
+                    }
+                    *app.nes.bus.input_mut().turbo_mut() = turbo;
+                }
+                Message::Run(f) => f(&mut app),
+                Message::Exit => break 'outer,
+            }
+        }
+
+        if !app.update() {
+            thread::sleep(IDLE_SLEEP);
+            continue;
+        };
+        // A picture the window hasn't shown yet is replaced rather than queued behind.
+        let frame = Box::new(app.nes.bus.ppu().pixels().clone());
+        if let Err(TrySendError::Full(frame)) = outbox.try_send(frame) {
+            let _ = stale.try_recv();
+            let _ = outbox.try_send(frame);
+        }
+        app.window.request_redraw();
+    }
+
+    if let Err(err) = app.nes.flush_saves() {
+        eprintln!("Can't write battery save: {err}");
+    }
+    if let Some(audio) = &mut app.audio {
+        if let Err(err) = audio.stop_recording() {
+            eprintln!("Recording failed: {err}");
+        }
+    }
+}
@@ -0,0 +1,681 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{
+    apu::{Apu, Channel},
+    mapper::mapper0::Mapper0,
+    nesbus::{CpuBus, NesBus},
+    region::Region,
+    rom::builder::RomBuilder,
+};
+
+#[test]
+pub fn pulse_duty_cycles() {
+    for (duty, high_steps) in [(0, 1), (1, 2), (2, 4), (3, 6)] {
+        let mut apu = Apu::init();
+        write(&mut apu, 0x4015, 0b01);
+        write(&mut apu, 0x4000, duty << 6 | 0b11_1111);
+        write(&mut apu, 0x4002, 8);
+        write(&mut apu, 0x4003, 0b1000);
+
+        // A timer period of 8 holds each of the 8 sequencer steps for 9 APU cycles.
+        let period = 8 * 9 * 2;
+        let outputs: Vec<u8> = (0..period).map(|_| pulse_output(&mut apu, 0)).collect();
+        let high = outputs.iter().filter(|&&o| o == 15).count();
+        assert_eq!(high, high_steps * 18, "duty {duty}");
+        assert!(outputs.iter().all(|&o| o == 0 || o == 15));
+    }
+}
+
+#[test]
+pub fn sweep_mutes_short_periods() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b10);
+    write(&mut apu, 0x4004, 0b1111_1111);
+    write(&mut apu, 0x4006, 7);
+    write(&mut apu, 0x4007, 0b1000);
+    assert!((0..200).all(|_| pulse_output(&mut apu, 1) == 0));
+
+    write(&mut apu, 0x4006, 8);
+    assert!((0..200).any(|_| pulse_output(&mut apu, 1) != 0));
+}
+
+#[test]
+pub fn sweep_mutes_overflowing_target() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b01);
+    write(&mut apu, 0x4000, 0b1111_1111);
+
+    // Even a disabled sweep unit with a shift of zero would double the period past $7FF.
+    write(&mut apu, 0x4002, 0x00);
+    write(&mut apu, 0x4003, 0b1000_0101);
+    assert!((0..4000).all(|_| pulse_output(&mut apu, 0) == 0));
+
+    write(&mut apu, 0x4003, 0b1000_0011);
+    assert!((0..4000).any(|_| pulse_output(&mut apu, 0) != 0));
+}
+
+#[test]
+pub fn disabled_channel_is_silent() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4000, 0b1111_1111);
+    write(&mut apu, 0x4002, 8);
+    write(&mut apu, 0x4003, 0b1000);
+    assert!((0..200).all(|_| pulse_output(&mut apu, 0) == 0));
+}
+
+#[test]
+pub fn noise_short_mode_period() {
+    let outputs = noise_outputs(true, 93 * 3);
+    assert_eq!(sequence_period(&outputs), 93);
+}
+
+#[test]
+pub fn noise_long_mode_period() {
+    let outputs = noise_outputs(false, 32767 * 2);
+    assert_eq!(sequence_period(&outputs), 32767);
+}
+
+#[test]
+pub fn triangle_sequence() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b100);
+    write(&mut apu, 0x4008, 0xFF);
+    write(&mut apu, 0x400A, 0);
+    write(&mut apu, 0x400B, 0b1000);
+
+    // The sequencer only starts once the frame counter has reloaded the linear counter.
+    while apu.channel_outputs().triangle == 15 {
+        idle(&mut apu);
+    }
+    let outputs: Vec<u8> = (0..32).map(|_| triangle_output(&mut apu)).collect();
+    let mut expected: Vec<u8> = (0..16).rev().chain(0..16).collect();
+    // The loop above already consumed the 15 and 14 at the start of the sequence.
+    expected.rotate_left(2);
+    assert_eq!(outputs, expected);
+}
+
+#[test]
+pub fn dmc_fetch_stalls_cpu() {
+    let mut bus = dmc_bus(0x0F);
+
+    let mut stalls = Vec::new();
+    let mut run = 0;
+    for _ in 0..54 * 8 * 4 {
+        let (_, not_ready) = bus.read(0x8000, false, true);
+        if not_ready {
+            run += 1;
+        } else if run != 0 {
+            stalls.push(run);
+            run = 0;
+        }
+    }
+
+    // Reload fetches always halt on a put cycle and need an extra alignment cycle.
+    assert_eq!(stalls.len(), 5);
+    assert!(stalls[0] == 3 || stalls[0] == 4);
+    assert!(stalls[1..].iter().all(|&stall| stall == 4));
+}
+
+#[test]
+pub fn dmc_halt_waits_for_read_cycle() {
+    let mut bus = dmc_bus(0x0F);
+
+    // The DMA can only halt the CPU on a read, so the fetch waits out consecutive writes.
+    for _ in 0..8 {
+        bus.write(0x0000, 0);
+    }
+    let (_, not_ready) = bus.read(0x8000, false, true);
+    assert!(not_ready);
+}
+
+#[test]
+pub fn dmc_plays_sample() {
+    let mut bus = dmc_bus(0x8F);
+    let mut irq = false;
+    for _ in 0..54 * 8 * 18 {
+        bus.read(0x8000, false, true);
+        irq |= bus.irq();
+    }
+
+    // The delta counter stops at 126 because another step would overflow it.
+    assert_eq!(bus.apu().channel_outputs().dmc, 126);
+    assert!(irq);
+}
+
+#[test]
+pub fn status_reports_length_counters() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b1111);
+    for channel in [0x4000, 0x4004, 0x4008, 0x400C] {
+        write(&mut apu, channel, 0b1000_0000 | 32);
+        write(&mut apu, channel + 3, 0b1000);
+    }
+    assert_eq!(read(&mut apu, 0x4015) & 0x1F, 0b1111);
+
+    write(&mut apu, 0x4015, 0b0101);
+    assert_eq!(read(&mut apu, 0x4015) & 0x1F, 0b0101);
+
+    // Length counters of disabled channels can't be loaded.
+    write(&mut apu, 0x4015, 0b0010);
+    write(&mut apu, 0x4007, 0b1000);
+    write(&mut apu, 0x4003, 0b1000);
+    assert_eq!(read(&mut apu, 0x4015) & 0x1F, 0b0010);
+}
+
+#[test]
+pub fn status_read_clears_frame_irq() {
+    let cycles = cycles_until_frame_irq();
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4017, 0);
+    for _ in 0..cycles + 2 {
+        idle(&mut apu);
+    }
+
+    assert_eq!(read(&mut apu, 0x4015) & 0x40, 0x40);
+    assert_eq!(read(&mut apu, 0x4015) & 0x40, 0);
+}
+
+#[test]
+pub fn status_read_keeps_frame_irq_raised_on_same_cycle() {
+    let cycles = cycles_until_frame_irq();
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4017, 0);
+    for _ in 0..cycles - 1 {
+        idle(&mut apu);
+    }
+
+    assert_eq!(read(&mut apu, 0x4015) & 0x40, 0x40);
+    assert_eq!(read(&mut apu, 0x4015) & 0x40, 0x40);
+}
+
+#[test]
+pub fn frame_irq_inhibit_clears_flag() {
+    let cycles = cycles_until_frame_irq();
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4017, 0);
+    for _ in 0..cycles {
+        idle(&mut apu);
+    }
+
+    write(&mut apu, 0x4017, 0x40);
+    assert_eq!(read(&mut apu, 0x4015) & 0x40, 0);
+}
+
+#[test]
+pub fn one_sample_per_cycle() {
+    let mut apu = Apu::init();
+    let mut samples = Vec::new();
+    for _ in 0..100 {
+        idle(&mut apu);
+    }
+    apu.drain_audio(&mut samples);
+    assert_eq!(samples.len(), 100);
+    assert!(samples.windows(2).all(|w| w[0] == w[1]));
+
+    write(&mut apu, 0x4015, 0b01);
+    write(&mut apu, 0x4000, 0b1011_1111);
+    write(&mut apu, 0x4002, 8);
+    write(&mut apu, 0x4003, 0b1000);
+    for _ in 0..100 {
+        idle(&mut apu);
+    }
+    samples.clear();
+    apu.drain_audio(&mut samples);
+    assert_eq!(samples.len(), 104);
+    assert!(samples.iter().any(|&s| s != samples[0]));
+
+    samples.clear();
+    apu.drain_audio(&mut samples);
+    assert!(samples.is_empty());
+}
+
+#[test]
+pub fn five_step_write_clocks_after_delay() {
+    let mut delays = Vec::new();
+    for alignment in 0..2 {
+        let mut apu = Apu::init();
+        for _ in 0..alignment {
+            idle(&mut apu);
+        }
+        // A length of 2 runs out on the second half frame clock.
+        write(&mut apu, 0x4015, 0b01);
+        write(&mut apu, 0x4003, 0b0001_1000);
+        write(&mut apu, 0x4017, 0x80);
+        for _ in 0..8 {
+            idle(&mut apu);
+        }
+        assert_eq!(read(&mut apu, 0x4015) & 1, 1);
+
+        write(&mut apu, 0x4017, 0x80);
+        let delay = (1..).find(|_| read(&mut apu, 0x4015) & 1 == 0).unwrap();
+        delays.push(delay);
+    }
+    delays.sort();
+    assert_eq!(delays, [3, 4]);
+}
+
+#[test]
+pub fn four_step_write_does_not_clock() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b01);
+    write(&mut apu, 0x4003, 0b0001_1000);
+    write(&mut apu, 0x4017, 0x00);
+    for _ in 0..8 {
+        idle(&mut apu);
+    }
+    write(&mut apu, 0x4017, 0x00);
+    for _ in 0..8 {
+        idle(&mut apu);
+    }
+    assert_eq!(read(&mut apu, 0x4015) & 1, 1);
+}
+
+#[test]
+pub fn frame_irq_timing() {
+    // The write lands on an APU cycle, so the sequence restarts 3 cycles later.
+    assert_eq!(cycles_until_frame_irq(), 3 + 29828);
+}
+
+#[test]
+pub fn frame_irq_flag_is_raised_three_times() {
+    let cycles = cycles_until_frame_irq();
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4017, 0);
+    for _ in 0..cycles + 1 {
+        idle(&mut apu);
+    }
+
+    // Reading on the third cycle doesn't clear the flag either.
+    assert_eq!(read(&mut apu, 0x4015) & 0x40, 0x40);
+    assert_eq!(read(&mut apu, 0x4015) & 0x40, 0x40);
+    assert_eq!(read(&mut apu, 0x4015) & 0x40, 0);
+}
+
+#[test]
+pub fn five_step_sequence_has_no_irq() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4017, 0x80);
+    for _ in 0..37282 * 2 {
+        let mut cpu = CpuBus::init();
+        cpu.set_read(true);
+        apu.cycle(&mut cpu);
+        assert!(!cpu.irq());
+    }
+}
+
+#[test]
+pub fn length_counters_clock_twice_per_frame() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b01);
+    write(&mut apu, 0x4017, 0x40);
+    // A length of 4 lasts for two frames.
+    write(&mut apu, 0x4003, 0b0010_1000);
+
+    let mut cycles = 0;
+    while read(&mut apu, 0x4015) & 1 != 0 {
+        cycles += 1;
+    }
+    assert_eq!(cycles, 3 + 29830 + 29829 - 1);
+}
+
+#[test]
+pub fn reload_during_length_clock_is_ignored() {
+    assert!(length_after_reload(0));
+    assert!(!length_after_reload(1));
+}
+
+#[test]
+pub fn reload_during_length_clock_applies_to_zero_counter() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b01);
+    write(&mut apu, 0x4017, 0x40);
+    for _ in 0..3 + 14913 - 1 {
+        idle(&mut apu);
+    }
+    write(&mut apu, 0x4003, 0b0000_1000);
+    assert_eq!(read(&mut apu, 0x4015) & 1, 1);
+}
+
+#[test]
+pub fn halt_written_during_length_clock_applies_afterwards() {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b01);
+    // A length of 2 runs out on the second half frame clock.
+    write(&mut apu, 0x4003, 0b0001_1000);
+    write(&mut apu, 0x4017, 0x40);
+    for _ in 0..3 + 14913 - 1 {
+        idle(&mut apu);
+    }
+    write(&mut apu, 0x4000, 0b0010_0000);
+    write(&mut apu, 0x4000, 0b0000_0000);
+    for _ in 0..29830 {
+        idle(&mut apu);
+    }
+    assert_eq!(read(&mut apu, 0x4015) & 1, 0);
+}
+
+#[test]
+pub fn pal_frame_irq_timing() {
+    let mut apu = Apu::new(Region::Pal);
+    write(&mut apu, 0x4017, 0);
+    let mut cycles = 0;
+    loop {
+        cycles += 1;
+        let mut cpu = CpuBus::init();
+        cpu.set_read(true);
+        apu.cycle(&mut cpu);
+        if cpu.irq() {
+            break;
+        }
+    }
+    assert_eq!(cycles, 3 + 33252);
+    assert_eq!(apu.sample_rate(), Region::Pal.cpu_clock_hz());
+}
+
+#[test]
+pub fn pal_noise_periods() {
+    for (region, period) in [(Region::Ntsc, 16), (Region::Pal, 14)] {
+        let mut apu = Apu::new(region);
+        write(&mut apu, 0x4015, 0b1000);
+        write(&mut apu, 0x400C, 0b0011_1111);
+        write(&mut apu, 0x400E, 0x02);
+        write(&mut apu, 0x400F, 0b1000);
+
+        let outputs: Vec<u8> = (0..period * 200)
+            .map(|_| {
+                idle(&mut apu);
+                apu.channel_outputs().noise
+            })
+            .collect();
+        let changes: Vec<usize> = (1..outputs.len())
+            .filter(|&i| outputs[i] != outputs[i - 1])
+            .collect();
+        let shortest = changes.windows(2).map(|w| w[1] - w[0]).min();
+        assert_eq!(shortest, Some(period), "{region:?}");
+    }
+}
+
+#[test]
+pub fn muted_channel_keeps_running() {
+    let mut apu = Apu::init();
+    let mut silence = Vec::new();
+    idle(&mut apu);
+    apu.drain_audio(&mut silence);
+
+    apu.set_channel_enabled(Channel::Pulse1, false);
+    assert!(!apu.channel_enabled(Channel::Pulse1));
+    write(&mut apu, 0x4015, 0b01);
+    write(&mut apu, 0x4000, 0b1011_1111);
+    write(&mut apu, 0x4002, 8);
+    write(&mut apu, 0x4003, 0b1000);
+    let mut samples = Vec::new();
+    let outputs: Vec<u8> = (0..100).map(|_| pulse_output(&mut apu, 0)).collect();
+    apu.drain_audio(&mut samples);
+
+    assert!(outputs.iter().any(|&o| o != 0));
+    assert!(samples.iter().all(|&s| s == silence[0]));
+    assert_eq!(read(&mut apu, 0x4015) & 1, 1);
+
+    apu.set_channel_enabled(Channel::Pulse1, true);
+    samples.clear();
+    for _ in 0..100 {
+        idle(&mut apu);
+    }
+    apu.drain_audio(&mut samples);
+    assert!(samples.iter().any(|&s| s != silence[0]));
+}
+
+#[test]
+pub fn dmc_fetch_during_oam_dma() {
+    // Both buses run the same number of cycles, so the transfers start with the same alignment.
+    // Waiting lets the first sample bytes load, so only one fetch lands mid transfer.
+    let mut plain = dmc_bus(0x4F);
+    plain.write(0x4015, 0x00);
+    let mut with_dmc = dmc_bus(0x4F);
+    with_dmc.write(0x4015, 0x10);
+    for _ in 0..100 {
+        plain.read(0x8000, false, false);
+        with_dmc.read(0x8000, false, false);
+    }
+
+    let plain = oam_dma_stall(&mut plain);
+    assert!(plain == 513 || plain == 514);
+    // The fetch steals one OAM read and costs another cycle to realign.
+    assert_eq!(oam_dma_stall(&mut with_dmc), plain + 2);
+}
+
+#[test]
+pub fn dmc_fetch_at_the_end_of_oam_dma() {
+    // A fetch usually costs two cycles. One that lands on the second to last cycle waits for the
+    // transfer to end and costs one, and one that lands on the last has to realign and costs three.
+    let costs: Vec<usize> = (0..576)
+        .map(|delay| {
+            let mut plain = dmc_bus(0x4E);
+            plain.write(0x4015, 0x00);
+            let mut with_dmc = dmc_bus(0x4E);
+            with_dmc.write(0x4015, 0x10);
+            let mut cycles = 0;
+            loop {
+                let stalled = with_dmc.read(0x8000, false, true).1;
+                cycles += 1;
+                if cycles >= delay && !stalled {
+                    break;
+                };
+            }
+            for _ in 0..cycles {
+                plain.read(0x8000, false, true);
+            }
+            oam_dma_stall(&mut with_dmc) - oam_dma_stall(&mut plain)
+        })
+        .collect();
+
+    let count = |cost| costs.iter().filter(|&&c| c == cost).count();
+    assert_eq!(count(1), 2);
+    assert_eq!(count(3), 2);
+    // The rest either land mid transfer or miss it.
+    assert_eq!(count(0) + count(2), costs.len() - 4);
+    assert!(count(2) > count(0));
+}
+
+#[test]
+pub fn dmc_fetch_during_controller_read_drops_a_bit() {
+    let mut bus = dmc_bus(0x4F);
+    // A, Select and Up, so every bit differs from its neighbours.
+    bus.controllers_mut()[0].0 = 0b0101_0101;
+
+    // Each attempt is one cycle longer, so the fetch drifts across the reads.
+    for attempt in 0.. {
+        assert!(attempt < 1000);
+        for _ in 0..attempt {
+            cpu_read(&mut bus, 0x8000);
+        }
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+
+        let mut bits = Vec::new();
+        let mut stalled = false;
+        for _ in 0..8 {
+            cpu_read(&mut bus, 0x8000);
+            let (data, stall) = cpu_read(&mut bus, 0x4016);
+            bits.push(data & 1);
+            stalled |= stall;
+        }
+
+        if !stalled {
+            assert_eq!(bits, [1, 0, 1, 0, 1, 0, 1, 0]);
+            continue;
+        }
+        // The fetch takes the bus away from $4016 between the halted read and its repeat,
+        // which clocks the shift register an extra time and loses one button.
+        let all = [1, 0, 1, 0, 1, 0, 1, 0, 1];
+        let skipped = (0..8).any(|skip| {
+            let expected = all.iter().enumerate().filter(|&(i, _)| i != skip);
+            expected.map(|(_, &bit)| bit).eq(bits.iter().copied())
+        });
+        assert!(skipped, "{bits:?}");
+        break;
+    }
+}
+
+#[test]
+pub fn oam_dma_length_depends_on_alignment() {
+    // The halt cycle, an alignment cycle if it lands on a get cycle, then 256 reads and writes.
+    let mut lengths: Vec<usize> = (0..2)
+        .map(|delay| {
+            let mut bus = plain_bus();
+            for _ in 0..delay {
+                bus.read(0x8000, false, false);
+            }
+            bus.write(0x4014, 0x02);
+            let mut halt = false;
+            let mut cycles = 0;
+            while bus.read(0x8000, false, halt).1 {
+                halt = true;
+                cycles += 1;
+            }
+            cycles
+        })
+        .collect();
+    lengths.sort();
+    assert_eq!(lengths, [513, 514]);
+}
+
+#[test]
+pub fn oam_dma_starts_at_oam_addr() {
+    let mut bus = plain_bus();
+    for i in 0..256 {
+        bus.write(0x0200 + i, i as u8);
+    }
+    bus.write(0x2003, 0x10);
+    oam_dma_stall(&mut bus);
+
+    // The copy wraps around OAM and leaves the address where it started.
+    // Attribute bytes lose the bits that aren't there.
+    let oam = bus.ppu().debug_oam();
+    for i in 0..256 {
+        let addr = (i + 0x10) % 256;
+        let mask = if addr % 4 == 2 { 0xE3 } else { 0xFF };
+        assert_eq!(oam[addr], i as u8 & mask, "{i}");
+    }
+    assert_eq!(bus.read(0x2004, false, false).0, 0x00);
+}
+
+// Reloads a length of 2 over a length of 254, `delay` cycles after the first half frame clock.
+// Returns whether the channel is still playing two half frames later.
+fn length_after_reload(delay: usize) -> bool {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b01);
+    write(&mut apu, 0x4003, 0b0000_1000);
+    write(&mut apu, 0x4017, 0x40);
+    for _ in 0..3 + 14913 - 1 + delay {
+        idle(&mut apu);
+    }
+    write(&mut apu, 0x4003, 0b0001_1000);
+    for _ in 0..29830 {
+        idle(&mut apu);
+    }
+    read(&mut apu, 0x4015) & 1 != 0
+}
+
+// Counts the idle cycles after a $4017 write until the frame IRQ is raised.
+fn cycles_until_frame_irq() -> usize {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4017, 0);
+    let mut cycles = 0;
+    loop {
+        cycles += 1;
+        let mut cpu = CpuBus::init();
+        cpu.set_read(true);
+        apu.cycle(&mut cpu);
+        if cpu.irq() {
+            return cycles;
+        }
+    }
+}
+// Samples the noise channel once per shift register step at the shortest period.
+fn noise_outputs(short_mode: bool, steps: usize) -> Vec<u8> {
+    let mut apu = Apu::init();
+    write(&mut apu, 0x4015, 0b1000);
+    write(&mut apu, 0x400C, 0b0011_1111);
+    write(&mut apu, 0x400E, if short_mode { 0x80 } else { 0x00 });
+    write(&mut apu, 0x400F, 0b1000);
+
+    (0..steps)
+        .map(|_| {
+            for _ in 0..4 {
+                idle(&mut apu);
+            }
+            apu.channel_outputs().noise
+        })
+        .collect()
+}
+fn sequence_period(outputs: &[u8]) -> usize {
+    (1..outputs.len())
+        .find(|&period| outputs[period..].iter().zip(outputs).all(|(a, b)| a == b))
+        .unwrap()
+}
+
+// Retries a read until it isn't halted by DMA, like the CPU does.
+fn cpu_read(bus: &mut NesBus<Mapper0>, addr: u16) -> (u8, bool) {
+    let mut stalled = false;
+    loop {
+        let (data, not_ready) = bus.read(addr, false, true);
+        if !not_ready {
+            return (data, stalled);
+        }
+        stalled = true;
+    }
+}
+fn oam_dma_stall(bus: &mut NesBus<Mapper0>) -> usize {
+    bus.write(0x4014, 0x02);
+    let mut cycles = 0;
+    while bus.read(0x8000, false, true).1 {
+        cycles += 1;
+    }
+    cycles
+}
+
+fn plain_bus() -> NesBus<Mapper0> {
+    let src = RomBuilder::new().build();
+    let rom = Rom::parse(&src).unwrap();
+    NesBus::new(Mapper0::new(&rom))
+}
+// Plays 17 bytes of $FF from $C000 at the given $4010 setting.
+fn dmc_bus(flags: u8) -> NesBus<Mapper0> {
+    let src = RomBuilder::new().write_cpu(0xC000, &[0xFF; 17]).build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+    bus.write(0x4010, flags);
+    bus.write(0x4012, 0x00);
+    bus.write(0x4013, 0x01);
+    bus.write(0x4015, 0x10);
+    bus
+}
+
+fn write(apu: &mut Apu, addr: u16, data: u8) {
+    let mut cpu = CpuBus::init();
+    cpu.set_address(addr);
+    cpu.set_data(data);
+    cpu.set_read(false);
+    apu.cycle(&mut cpu);
+}
+fn read(apu: &mut Apu, addr: u16) -> u8 {
+    let mut cpu = CpuBus::init();
+    cpu.set_address(addr);
+    cpu.set_read(true);
+    apu.cycle(&mut cpu);
+    cpu.data()
+}
+fn idle(apu: &mut Apu) {
+    let mut cpu = CpuBus::init();
+    cpu.set_read(true);
+    apu.cycle(&mut cpu);
+}
+fn pulse_output(apu: &mut Apu, pulse: usize) -> u8 {
+    idle(apu);
+    apu.channel_outputs().pulse[pulse]
+}
+fn triangle_output(apu: &mut Apu) -> u8 {
+    idle(apu);
+    apu.channel_outputs().triangle
+}
@@ -0,0 +1,99 @@
+use nessy::{
+    rom::builder::RomBuilder,
+    test_rom::{run_blargg_rom, TestFailure},
+};
+use std::{fs, path::Path};
+
+// Blargg's ROMs aren't part of the repository. Put them here and run with --ignored to have them checked.
+const ROM_DIR: &str = "test_roms/blargg";
+const TIMEOUT_FRAMES: u32 = 60 * 60;
+
+#[test]
+#[ignore = "needs blargg's test ROMs in test_roms/blargg"]
+pub fn blargg_test_roms() {
+    let entries = fs::read_dir(ROM_DIR).unwrap_or_else(|err| panic!("can't read {ROM_DIR}: {err}"));
+    let mut paths: Vec<_> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nes"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no test ROMs in {ROM_DIR}");
+
+    let failures: Vec<_> = paths
+        .iter()
+        .filter_map(|path| {
+            let result = run_blargg_rom(path, TIMEOUT_FRAMES);
+            result.err().map(|err| format!("{}: {err}", path.display()))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+// The frame IRQ ROMs from apu_test are named so that a missing one fails instead of being skipped.
+#[test]
+#[ignore = "needs blargg's apu_test ROMs in test_roms/blargg/apu_test"]
+pub fn apu_test_4015_cleared() {
+    run_apu_test("4015_cleared");
+}
+
+#[test]
+#[ignore = "needs blargg's apu_test ROMs in test_roms/blargg/apu_test"]
+pub fn apu_test_irq_flag_timing() {
+    run_apu_test("irq_flag_timing");
+}
+
+fn run_apu_test(name: &str) {
+    let path = format!("{ROM_DIR}/apu_test/{name}.nes");
+    run_blargg_rom(&path, TIMEOUT_FRAMES).unwrap_or_else(|err| panic!("{path}: {err}"));
+}
+
+#[test]
+pub fn reports_the_message() {
+    let path = write_rom("blargg_pass.nes", 0, "Passed\n");
+    assert_eq!(run_blargg_rom(&path, 10).unwrap(), "Passed\n");
+}
+
+#[test]
+pub fn reports_failures() {
+    assert!(matches!(
+        run_blargg_rom("test_roms/missing.nes", 10),
+        Err(TestFailure::Io(_))
+    ));
+    let path = write_rom("blargg_fail.nes", 3, "Failed #3\n");
+    match run_blargg_rom(&path, 10) {
+        Err(TestFailure::Failed { status, message }) => {
+            assert_eq!(status, 3);
+            assert_eq!(message, "Failed #3\n");
+        }
+        result => panic!("unexpected {result:?}"),
+    }
+}
+
+// A ROM that writes its message and signature, then the status last, like the real ones.
+fn write_rom(name: &str, status: u8, message: &str) -> String {
+    let mut stores = vec![(0x6000, 0x80)];
+    stores.extend((0x6004..).zip(message.bytes().chain([0])));
+    stores.extend([
+        (0x6001, 0xDE),
+        (0x6002, 0xB0),
+        (0x6003, 0x61),
+        (0x6000, status),
+    ]);
+
+    let mut program = Vec::new();
+    for (addr, byte) in stores {
+        let [low, high] = u16::to_le_bytes(addr);
+        program.extend([0xA9, byte, 0x8D, low, high]); // LDA #byte; STA addr
+    }
+    let end = 0x8000 + program.len() as u16;
+    let [low, high] = end.to_le_bytes();
+    program.extend([0x4C, low, high]); // JMP end
+
+    let src = RomBuilder::new()
+        .write_cpu(0x8000, &program)
+        .reset_vector(0x8000)
+        .build();
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, src).unwrap();
+    path.to_str().unwrap().to_string()
+}
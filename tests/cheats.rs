@@ -0,0 +1,122 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{
+    cheats::{CheatEngine, CheatError, GameGenieCode},
+    mapper::mapper0::Mapper0,
+    nes::Nes,
+    nesbus::NesBus,
+    rom::builder::RomBuilder,
+};
+
+#[test]
+pub fn decodes_six_letter_codes() {
+    let code = GameGenieCode::decode("SXIOPO").unwrap();
+    assert_eq!(code.address, 0x91D9);
+    assert_eq!(code.value, 0xAD);
+    assert_eq!(code.compare, None);
+
+    let code = GameGenieCode::decode("gossip").unwrap();
+    assert_eq!(code.address, 0xD1DD);
+    assert_eq!(code.value, 0x14);
+}
+
+#[test]
+pub fn decodes_eight_letter_codes() {
+    let code = GameGenieCode::decode("ZEXPYGLA").unwrap();
+    assert_eq!(code.address, 0x94A7);
+    assert_eq!(code.value, 0x02);
+    assert_eq!(code.compare, Some(0x03));
+}
+
+#[test]
+pub fn rejects_malformed_codes() {
+    assert_eq!(GameGenieCode::decode("SXIOP"), Err(CheatError::Length(5)));
+    assert_eq!(
+        GameGenieCode::decode("SXIOPB"),
+        Err(CheatError::Letter('B'))
+    );
+    assert!(CheatEngine::init().add("ZEXPYGL").is_err());
+}
+
+#[test]
+pub fn replaces_cartridge_reads() {
+    let mut nes = console(&[(0x91D9, 0x00), (0x91DA, 0x00)]);
+    nes.cheats_mut().add("SXIOPO").unwrap();
+    assert_eq!(fetch(&mut nes, 0x91D9), 0xAD);
+    assert_eq!(fetch(&mut nes, 0x91DA), 0x00);
+    assert_eq!(nes.bus.peek_cpu(0x91D9), 0x00);
+
+    assert_eq!(nes.cheats_mut().toggle("sxiopo"), Some(false));
+    assert_eq!(fetch(&mut nes, 0x91D9), 0x00);
+    assert_eq!(nes.cheats_mut().toggle("SXIOPO"), Some(true));
+    assert_eq!(fetch(&mut nes, 0x91D9), 0xAD);
+
+    assert!(nes.cheats_mut().remove("SXIOPO"));
+    assert!(nes.cheats_mut().is_empty());
+    assert_eq!(fetch(&mut nes, 0x91D9), 0x00);
+}
+
+#[test]
+pub fn compare_value_must_match() {
+    let mut nes = console(&[(0x94A7, 0x03)]);
+    nes.cheats_mut().add("ZEXPYGLA").unwrap();
+    assert_eq!(fetch(&mut nes, 0x94A7), 0x02);
+
+    let mut nes = console(&[(0x94A7, 0x04)]);
+    nes.cheats_mut().add("ZEXPYGLA").unwrap();
+    assert_eq!(fetch(&mut nes, 0x94A7), 0x04);
+}
+
+#[test]
+pub fn frozen_ram_survives_writes() {
+    let mut nes = console(&[]);
+    assert!(nes.cheats_mut().freeze(0x075A, 9));
+    nes.bus.write(0x075A, 2);
+    assert_eq!(fetch(&mut nes, 0x075A), 9);
+    assert_eq!(nes.peek(0x075A), 9);
+
+    // Mirrors freeze the same byte.
+    assert!(nes.cheats_mut().freeze(0x1F5A, 4));
+    nes.bus.write(0x075A, 3);
+    assert_eq!(nes.bus.ram()[0x75A], 4);
+    assert_eq!(nes.cheats_mut().freezes().len(), 1);
+
+    assert!(nes.cheats_mut().unfreeze(0x075A));
+    nes.bus.write(0x075A, 1);
+    assert_eq!(fetch(&mut nes, 0x075A), 1);
+
+    // Anything past RAM is turned away rather than panicking.
+    assert!(!nes.cheats_mut().freeze(0x6000, 1));
+    assert!(nes.cheats_mut().freezes().is_empty());
+    // Past RAM isn't a mirror of it, so it can't unfreeze a byte either.
+    assert!(nes.cheats_mut().freeze(0x075A, 5));
+    assert!(!nes.cheats_mut().unfreeze(0x275A));
+    assert_eq!(nes.cheats_mut().freezes().len(), 1);
+}
+
+#[test]
+pub fn peek_and_poke_skip_the_bus() {
+    let mut nes = console(&[(0x8000, 0xEA)]);
+    let cycles = nes.bus.cycles();
+    nes.poke(0x0300, 0x12);
+    nes.poke(0x6123, 0x34);
+    nes.poke(0x8000, 0x56);
+    assert_eq!(nes.peek(0x0300), 0x12);
+    assert_eq!(nes.peek(0x6123), 0x34);
+    assert_eq!(nes.peek(0x8000), 0xEA);
+    assert_eq!(nes.bus.cycles(), cycles);
+    assert_eq!(fetch(&mut nes, 0x6123), 0x34);
+}
+
+fn console(bytes: &[(u16, u8)]) -> Nes<Mapper0> {
+    let mut builder = RomBuilder::new();
+    for &(addr, data) in bytes {
+        builder = builder.write_cpu(addr, &[data]);
+    }
+    let src = builder.build();
+    let rom = Rom::parse(&src).unwrap();
+    Nes::new(NesBus::new(Mapper0::new(&rom)))
+}
+fn fetch(nes: &mut Nes<Mapper0>, addr: u16) -> u8 {
+    nes.bus.read(addr, false, false).0
+}
@@ -0,0 +1,221 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{
+    input::{Controller, ControllerState, DpadPolicy},
+    mapper::mapper0::Mapper0,
+    nes::Nes,
+    nesbus::NesBus,
+    rom::builder::RomBuilder,
+};
+
+#[test]
+pub fn strobe_held_keeps_returning_a() {
+    let mut bus = bus();
+    bus.controllers_mut()[0].set_b(true);
+    bus.write(0x4016, 1);
+    for _ in 0..10 {
+        assert_eq!(read_bit(&mut bus, 0x4016), 0);
+    }
+
+    // The register reloads continuously, so A is seen the moment it changes.
+    bus.controllers_mut()[0].set_a(true);
+    assert_eq!(read_bit(&mut bus, 0x4016), 1);
+    bus.controllers_mut()[0].set_a(false);
+    assert_eq!(read_bit(&mut bus, 0x4016), 0);
+}
+
+#[test]
+pub fn strobe_held_applies_to_both_ports() {
+    let mut bus = bus();
+    bus.controllers_mut()[1].set_a(true);
+    bus.write(0x4016, 1);
+    for _ in 0..10 {
+        assert_eq!(read_bit(&mut bus, 0x4017), 1);
+        assert_eq!(read_bit(&mut bus, 0x4016), 0);
+    }
+}
+
+#[test]
+pub fn shifts_once_the_strobe_drops() {
+    let mut bus = bus();
+    *bus.controllers_mut() = [Controller(0b1010_0101), Controller(0)];
+    bus.write(0x4016, 1);
+    read_bit(&mut bus, 0x4016);
+    bus.write(0x4016, 0);
+
+    let bits: Vec<u8> = (0..10).map(|_| read_bit(&mut bus, 0x4016)).collect();
+    assert_eq!(bits, [1, 0, 1, 0, 0, 1, 0, 1, 1, 1]);
+
+    // Strobing again starts over.
+    bus.write(0x4016, 1);
+    bus.write(0x4016, 0);
+    assert_eq!(read_bit(&mut bus, 0x4016), 1);
+    assert_eq!(read_bit(&mut bus, 0x4016), 0);
+}
+
+#[test]
+pub fn only_bit_0_strobes() {
+    let mut bus = bus();
+    bus.controllers_mut()[0].set_a(true);
+    bus.write(0x4016, 1);
+    bus.write(0x4016, 0);
+    assert_eq!(read_bit(&mut bus, 0x4016), 1);
+    bus.write(0x4016, 0xFE);
+    assert_eq!(read_bit(&mut bus, 0x4016), 0);
+}
+
+#[test]
+pub fn states_set_mid_frame_wait_for_the_next_one() {
+    let mut nes = Nes::new(bus());
+    nes.run_scanlines(100);
+    let a = ControllerState {
+        a: true,
+        ..Default::default()
+    };
+    nes.set_controller(0, a);
+    nes.run_scanlines(50);
+    assert_eq!(nes.bus.controllers_mut()[0], Controller(0));
+    nes.bus.write(0x4016, 1);
+    assert_eq!(read_bit(&mut nes.bus, 0x4016), 0);
+
+    // Only the last state sent before the frame ends counts.
+    let b = ControllerState {
+        b: true,
+        ..Default::default()
+    };
+    nes.set_controller(0, b);
+    let line = nes.bus.ppu().dot()[1] as u64;
+    nes.run_scanlines(262 - line);
+    assert_eq!(nes.bus.ppu().dot()[1], 0);
+    assert_eq!(ControllerState::from(nes.bus.controllers_mut()[0]), b);
+    assert_eq!(read_bit(&mut nes.bus, 0x4016), 0);
+    nes.bus.write(0x4016, 0);
+    assert_eq!(read_bit(&mut nes.bus, 0x4016), 0);
+    assert_eq!(read_bit(&mut nes.bus, 0x4016), 1);
+}
+
+#[test]
+pub fn states_set_between_frames_apply_to_the_next() {
+    let mut nes = Nes::new(bus());
+    nes.run_frame();
+    let state = ControllerState {
+        start: true,
+        right: true,
+        ..Default::default()
+    };
+    nes.set_controller(1, state);
+    nes.run_frame();
+    assert_eq!(nes.bus.controllers_mut()[1], Controller(0b1000_1000));
+    assert_eq!(Controller::from(state), Controller(0b1000_1000));
+}
+
+#[test]
+pub fn dpad_policies_resolve_opposite_directions() {
+    let mut up = Controller(0);
+    up.set_up(true);
+    let mut down_right = Controller(0);
+    down_right.set_down(true);
+    down_right.set_right(true);
+    let mut all = Controller(0);
+    all.set_up(true);
+    all.set_down(true);
+    all.set_left(true);
+    all.set_right(true);
+    all.set_a(true);
+
+    let neutral = DpadPolicy::Neutral.apply(all, up, up);
+    assert_eq!(neutral, Controller(0b0000_0001));
+
+    // Down is new next to Up. Neither horizontal direction is, so both are let go.
+    let last_wins = DpadPolicy::LastWins.apply(all, up, up);
+    assert_eq!(
+        ControllerState::from(last_wins),
+        ControllerState {
+            a: true,
+            down: true,
+            ..Default::default()
+        }
+    );
+    let last_wins = DpadPolicy::LastWins.apply(all, down_right, down_right);
+    assert_eq!(
+        ControllerState::from(last_wins),
+        ControllerState {
+            a: true,
+            up: true,
+            left: true,
+            ..Default::default()
+        }
+    );
+
+    assert_eq!(DpadPolicy::Allow.apply(all, up, up), all);
+    for policy in [DpadPolicy::Neutral, DpadPolicy::LastWins, DpadPolicy::Allow] {
+        assert_eq!(policy.apply(down_right, all, Controller(0)), down_right);
+    }
+}
+
+#[test]
+pub fn latched_states_go_through_the_dpad_policy() {
+    let left_right = ControllerState {
+        left: true,
+        right: true,
+        ..Default::default()
+    };
+    let mut nes = Nes::new(bus());
+    nes.set_controller(0, left_right);
+    nes.run_frame();
+    assert_eq!(nes.bus.controllers_mut()[0], Controller(0));
+
+    nes.bus.input_mut().set_dpad_policy(DpadPolicy::Allow);
+    nes.set_controller(0, left_right);
+    nes.run_frame();
+    assert_eq!(nes.bus.controllers_mut()[0], Controller(0b1100_0000));
+}
+
+#[test]
+pub fn last_wins_keeps_its_direction_while_both_are_held() {
+    let right = ControllerState {
+        right: true,
+        ..Default::default()
+    };
+    let both = ControllerState {
+        left: true,
+        ..right
+    };
+    let mut nes = Nes::new(bus());
+    nes.bus.input_mut().set_dpad_policy(DpadPolicy::LastWins);
+    nes.set_controller(0, right);
+    nes.run_frame();
+    nes.set_controller(0, both);
+    nes.run_frame();
+    assert_eq!(nes.bus.controllers_mut()[0], Controller(0b0100_0000));
+
+    // Pressing and letting go of other buttons doesn't make Right new again.
+    nes.set_controller(0, ControllerState { a: true, ..both });
+    nes.run_frame();
+    assert_eq!(nes.bus.controllers_mut()[0], Controller(0b0100_0001));
+    nes.set_controller(0, both);
+    nes.run_frame();
+    assert_eq!(nes.bus.controllers_mut()[0], Controller(0b0100_0000));
+
+    nes.set_controller(
+        0,
+        ControllerState {
+            left: false,
+            ..both
+        },
+    );
+    nes.run_frame();
+    assert_eq!(nes.bus.controllers_mut()[0], Controller(0b1000_0000));
+}
+
+fn bus() -> NesBus<Mapper0> {
+    let src = RomBuilder::new().build();
+    let rom = Rom::parse(&src).unwrap();
+    NesBus::new(Mapper0::new(&rom))
+}
+// A read followed by an unrelated one, the way the CPU's next fetch would clock the register.
+fn read_bit(bus: &mut NesBus<Mapper0>, addr: u16) -> u8 {
+    let bit = bus.read(addr, false, false).0 & 1;
+    bus.read(0x8000, false, false);
+    bit
+}
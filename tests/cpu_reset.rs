@@ -0,0 +1,47 @@
+use cpu_6502::{Bus, Cpu};
+
+// 64K of RAM with nothing else on the bus, logging every access.
+struct FlatBus {
+    memory: Box<[u8; 0x10000]>,
+    accesses: Vec<(u16, bool)>,
+}
+impl Bus for FlatBus {
+    fn rst(&self) -> bool {
+        false
+    }
+    fn nmi(&self) -> bool {
+        false
+    }
+    fn irq(&self) -> bool {
+        false
+    }
+    fn read(&mut self, addr: u16, _sync: bool, _halt: bool) -> (u8, bool) {
+        self.accesses.push((addr, true));
+        (self.memory[addr as usize], false)
+    }
+    fn write(&mut self, addr: u16, data: u8) {
+        self.accesses.push((addr, false));
+        self.memory[addr as usize] = data;
+    }
+}
+
+#[test]
+pub fn reset_takes_seven_cycles_and_pushes_nothing() {
+    let mut memory = Box::new([0xEA; 0x10000]);
+    memory[0xFFFC] = 0x34;
+    memory[0xFFFD] = 0x92;
+    let mut bus = FlatBus {
+        memory,
+        accesses: Vec::new(),
+    };
+    let mut cpu = Cpu::new();
+
+    cpu.exec(&mut bus);
+    assert_eq!(bus.accesses.len(), 7);
+    assert!(bus.accesses.iter().all(|&(_, read)| read));
+    assert_eq!(bus.accesses[5..], [(0xFFFC, true), (0xFFFD, true)]);
+    // The three suppressed pushes still move the stack pointer down from $00.
+    assert_eq!(cpu.sp() & 0xFF, 0xFD);
+    assert_eq!(cpu.pc(), 0x9234);
+    assert!(cpu.flags().irq_disable());
+}
@@ -0,0 +1,209 @@
+use cpu_6502::{Bus, Cpu};
+use nes_rom_parser::Rom;
+use nessy::{
+    debugger::{pending_jam, step, Debugger, Jam, Stop, WatchHit, Watchpoint},
+    mapper::mapper0::Mapper0,
+    nesbus::NesBus,
+    rom::builder::RomBuilder,
+};
+use std::sync::{Arc, Mutex};
+
+#[test]
+pub fn breakpoint_stops_before_the_instruction() {
+    let program = [
+        0x20, 0x10, 0x80, // JSR $8010
+        0x4C, 0x03, 0x80, // JMP $8003
+    ];
+    let subroutine = [
+        0xA9, 0x42, // LDA #$42
+        0x85, 0x10, // STA $10
+        0x60, // RTS
+    ];
+    let src = RomBuilder::new()
+        .write_cpu(0x8000, &program)
+        .write_cpu(0x8010, &subroutine)
+        .reset_vector(0x8000)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut cpu = Cpu::new();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+
+    let mut debugger = Debugger::init();
+    debugger.add_breakpoint(0x8010);
+    assert_eq!(
+        debugger.run_until_break(&mut cpu, &mut bus, 1000),
+        Stop::Breakpoint(0x8010)
+    );
+    assert_eq!(cpu.pc(), 0x8010);
+    assert_eq!(bus.ram()[0x10], 0x00);
+
+    let lda = step(&mut cpu, &mut bus);
+    assert_eq!((lda.pc, lda.cycles), (0x8010, 2));
+    assert_eq!(cpu.a(), 0x42);
+    step(&mut cpu, &mut bus);
+    assert_eq!(bus.ram()[0x10], 0x42);
+
+    // The loop after the subroutine never gets back to the breakpoint.
+    let start = bus.cycles();
+    assert_eq!(
+        debugger.run_until_break(&mut cpu, &mut bus, 100),
+        Stop::CycleBudget
+    );
+    assert!((100..103).contains(&(bus.cycles() - start)));
+    assert_eq!(cpu.pc(), 0x8003);
+}
+
+#[test]
+pub fn step_reports_documented_cycle_counts() {
+    let cases: [(&[u8], u64); 20] = [
+        (&[0xA2, 0x01], 2),       // LDX #$01
+        (&[0xA0, 0x10], 2),       // LDY #$10
+        (&[0xA9, 0x00], 2),       // LDA #$00
+        (&[0xA5, 0x10], 3),       // LDA $10
+        (&[0xB5, 0x10], 4),       // LDA $10,X
+        (&[0xAD, 0x00, 0x02], 4), // LDA $0200
+        (&[0xBD, 0x00, 0x02], 4), // LDA $0200,X
+        (&[0xBD, 0xFF, 0x02], 5), // LDA $02FF,X crosses a page
+        (&[0xB9, 0xF0, 0x02], 5), // LDA $02F0,Y crosses a page
+        (&[0x9D, 0xFF, 0x02], 5), // STA $02FF,X always takes the extra cycle
+        (&[0xA1, 0x10], 6),       // LDA ($10,X)
+        (&[0xB1, 0x20], 5),       // LDA ($20),Y
+        (&[0xE6, 0x10], 5),       // INC $10
+        (&[0xFE, 0x00, 0x02], 7), // INC $0200,X
+        (&[0x0A], 2),             // ASL A
+        (&[0x48], 3),             // PHA
+        (&[0x68], 4),             // PLA
+        (&[0xB6, 0x30], 4),       // LDX $30,Y sets Z
+        (&[0xD0, 0x00], 2),       // BNE not taken
+        (&[0xF0, 0x00], 3),       // BEQ taken
+    ];
+    let program: Vec<u8> = cases.iter().flat_map(|(bytes, _)| bytes.to_vec()).collect();
+    let src = RomBuilder::new()
+        .write_cpu(0x8000, &program)
+        .reset_vector(0x8000)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut cpu = Cpu::new();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+
+    assert_eq!(step(&mut cpu, &mut bus).cycles, 7);
+    for (bytes, cycles) in cases {
+        let result = step(&mut cpu, &mut bus);
+        assert_eq!(result.cycles, cycles, "{bytes:02X?} at {:04X}", result.pc);
+    }
+}
+
+#[test]
+pub fn watchpoints_tell_reads_from_writes() {
+    let src = RomBuilder::new().build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+    bus.watchpoints_mut().add(Watchpoint {
+        range: 0x2000..=0x2000,
+        on_read: false,
+        on_write: true,
+    });
+    bus.watchpoints_mut().add(Watchpoint {
+        range: 0x0300..=0x03FF,
+        on_read: true,
+        on_write: false,
+    });
+
+    bus.read(0x2000, false, false);
+    bus.write(0x0350, 0x77);
+    bus.read(0x0400, false, false);
+    bus.read(0x02FF, false, false);
+    assert_eq!(bus.take_watch_hit(), None);
+
+    let cycle = bus.cycles();
+    bus.write(0x2000, 0x80);
+    let hit = WatchHit {
+        address: 0x2000,
+        data: 0x80,
+        read: false,
+        cycle,
+    };
+    assert_eq!(bus.take_watch_hit(), Some(hit));
+    assert_eq!(bus.take_watch_hit(), None);
+
+    // Only the first hit is kept until it's taken.
+    bus.read(0x0350, false, false);
+    bus.read(0x03FF, false, false);
+    let hit = bus.take_watch_hit().unwrap();
+    assert_eq!((hit.address, hit.data, hit.read), (0x0350, 0x77, true));
+
+    bus.watchpoints_mut().remove(&(0x0300..=0x03FF));
+    bus.read(0x0350, false, false);
+    assert_eq!(bus.take_watch_hit(), None);
+}
+
+#[test]
+pub fn stops_before_a_jam_opcode() {
+    let program = [
+        0xA9, 0x01, // LDA #$01
+        0x85, 0x10, // STA $10
+        0x12, // JAM
+    ];
+    let src = RomBuilder::new()
+        .write_cpu(0x8000, &program)
+        .reset_vector(0x8000)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut cpu = Cpu::new();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+    assert_eq!(pending_jam(&cpu, &bus), None);
+
+    let debugger = Debugger::init();
+    let jam = Jam {
+        opcode: 0x12,
+        pc: 0x8004,
+    };
+    assert_eq!(
+        debugger.run_until_break(&mut cpu, &mut bus, 1000),
+        Stop::Jammed(jam)
+    );
+    assert_eq!(pending_jam(&cpu, &bus), Some(jam));
+    assert_eq!(bus.ram()[0x10], 0x01);
+}
+
+#[test]
+pub fn access_hook_sees_every_cpu_access() {
+    let program = [
+        0xA9, 0x42, // LDA #$42
+        0x8D, 0x10, 0x02, // STA $0210
+        0xAD, 0x10, 0x02, // LDA $0210
+    ];
+    let src = RomBuilder::new()
+        .write_cpu(0x8000, &program)
+        .reset_vector(0x8000)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut cpu = Cpu::new();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+    step(&mut cpu, &mut bus);
+
+    let accesses = Arc::new(Mutex::new(Vec::new()));
+    let log = accesses.clone();
+    bus.set_access_hook(Some(Box::new(move |cycle, addr, data, read| {
+        log.lock().unwrap().push((cycle, addr, data, read));
+    })));
+    for _ in 0..3 {
+        step(&mut cpu, &mut bus);
+    }
+    bus.set_access_hook(None);
+    step(&mut cpu, &mut bus);
+
+    let expected = [
+        (7, 0x8000, 0xA9, true),
+        (8, 0x8001, 0x42, true),
+        (9, 0x8002, 0x8D, true),
+        (10, 0x8003, 0x10, true),
+        (11, 0x8004, 0x02, true),
+        (12, 0x0210, 0x42, false),
+        (13, 0x8005, 0xAD, true),
+        (14, 0x8006, 0x10, true),
+        (15, 0x8007, 0x02, true),
+        (16, 0x0210, 0x42, true),
+    ];
+    assert_eq!(*accesses.lock().unwrap(), expected);
+}
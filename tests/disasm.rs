@@ -0,0 +1,55 @@
+use nessy::disasm::{disassemble, opcode, Mode};
+
+#[test]
+pub fn every_opcode_decodes_to_its_own_bytes() {
+    let mut stream = Vec::new();
+    for byte in 0..=255 {
+        stream.push(byte);
+        let operand = [0x34, 0x12];
+        stream.extend(&operand[..opcode(byte).mode.operand_len() as usize]);
+    }
+
+    let instructions = disassemble(&stream, 0x8000);
+    assert_eq!(instructions.len(), 256);
+    let mut address = 0x8000;
+    for (byte, instruction) in (0..=255).zip(&instructions) {
+        assert_eq!(instruction.address, address);
+        assert_eq!(instruction.bytes[0], byte);
+        assert_eq!(instruction.opcode, opcode(byte));
+        address += instruction.bytes.len() as u16;
+    }
+    let bytes: Vec<u8> = instructions.iter().flat_map(|i| i.bytes.clone()).collect();
+    assert_eq!(bytes, stream);
+    assert_eq!((0..=255).filter(|&byte| opcode(byte).official).count(), 151);
+}
+
+#[test]
+pub fn operands_use_the_usual_syntax() {
+    let program = [
+        0xBD, 0x02, 0x20, // LDA $2002,X
+        0xB6, 0x10, // LDX $10,Y
+        0x6C, 0xFC, 0xFF, // JMP ($FFFC)
+        0x91, 0x00, // STA ($00),Y
+        0xD0, 0xF4, // BNE back to the start
+        0x4A, // LSR A
+        0xA9, 0x07, // LDA #$07
+        0x20, 0x00, // JSR, cut off
+    ];
+    let text: Vec<String> = disassemble(&program, 0xC000)
+        .iter()
+        .map(|instruction| instruction.to_string())
+        .collect();
+    assert_eq!(
+        text,
+        [
+            "LDA $2002,X",
+            "LDX $10,Y",
+            "JMP ($FFFC)",
+            "STA ($00),Y",
+            "BNE $C000",
+            "LSR A",
+            "LDA #$07",
+        ]
+    );
+    assert_eq!(opcode(0x0A).mode, Mode::Accumulator);
+}
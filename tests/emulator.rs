@@ -0,0 +1,51 @@
+use nessy::{
+    emulator::{Emulator, EmulatorError},
+    input::Controller,
+    nesbus::PowerUpState,
+    region::Region,
+    rom::builder::RomBuilder,
+};
+
+#[test]
+pub fn runs_a_rom_from_bytes() {
+    let src = RomBuilder::new().reset_vector(0x8000).build();
+    let mut emulator = Emulator::builder()
+        .rom_bytes(src)
+        .region_override(Region::Pal)
+        .power_up(PowerUpState::Filled(0xFF))
+        .build()
+        .unwrap();
+    assert_eq!(emulator.region(), Region::Pal);
+    assert_eq!(emulator.nes.peek(0x0123), 0xFF);
+
+    emulator.set_controller(0, Controller(0x81));
+    emulator.run_frame();
+    // A frame ends with the instruction it's in, so it can run a few cycles long.
+    let frame_rate = Region::Pal.frames_per_second();
+    let expected = emulator.sample_rate() / frame_rate;
+    assert!((emulator.audio().len() as f64 - expected).abs() < 8.0);
+    assert_eq!(emulator.framebuffer().0.len(), 256 * 240);
+    assert_eq!(emulator.nes.bus.controllers_mut()[0], Controller(0x81));
+
+    let state = emulator.save_state();
+    emulator.run_frame();
+    emulator.load_state(&state).unwrap();
+    assert!(emulator.load_state(&state[1..]).is_err());
+    emulator.reset();
+}
+
+#[test]
+pub fn reports_roms_it_cant_run() {
+    let build = |src: Option<Vec<u8>>| {
+        let builder = Emulator::builder();
+        match src {
+            Some(src) => builder.rom_bytes(src).build(),
+            None => builder.build(),
+        }
+        .err()
+    };
+    assert_eq!(build(None), Some(EmulatorError::NoRom));
+    assert_eq!(build(Some(b"NES".to_vec())), Some(EmulatorError::Invalid));
+    let src = RomBuilder::new().mapper(4).build();
+    assert_eq!(build(Some(src)), Some(EmulatorError::Mapper(4)));
+}
@@ -0,0 +1,53 @@
+use cpu_6502::Bus;
+use nessy::{
+    mapper::{Mapper, MapperBus},
+    nesbus::{CpuBus, NesBus},
+    ppu::PpuBus,
+};
+
+const STEP: f32 = 0.001;
+
+// A cartridge whose audio output rises by a fixed step on every CPU cycle.
+struct RampMapper {
+    level: f32,
+}
+impl Mapper for RampMapper {
+    fn cycle(&mut self, _bus: &mut MapperBus, _cpu: &mut CpuBus, _ppu: &mut PpuBus) {
+        self.level += STEP;
+    }
+    fn cycle_with_ppu(&mut self, _bus: &mut MapperBus, _ppu: &mut PpuBus) {}
+
+    fn audio_output(&self) -> f32 {
+        self.level
+    }
+}
+
+#[test]
+pub fn expansion_audio_is_mixed() {
+    let steps = ramp_steps(None);
+    assert!(steps.iter().all(|&step| (step - 2.0 * STEP).abs() < 1e-5));
+}
+
+#[test]
+pub fn expansion_volume_scales_mix() {
+    let steps = ramp_steps(Some(0.5));
+    assert!(steps.iter().all(|&step| (step - STEP).abs() < 1e-5));
+    let steps = ramp_steps(Some(0.0));
+    assert!(steps.iter().all(|&step| step == 0.0));
+}
+
+// Differences between consecutive samples while the APU itself is silent.
+fn ramp_steps(volume: Option<f32>) -> Vec<f32> {
+    let mut bus = NesBus::new(RampMapper { level: 0.0 });
+    if let Some(volume) = volume {
+        bus.set_expansion_volume(volume);
+    }
+    for _ in 0..100 {
+        bus.read(0x0000, false, false);
+    }
+
+    let mut samples = Vec::new();
+    bus.drain_audio(&mut samples);
+    assert_eq!(samples.len(), 100);
+    samples.windows(2).map(|w| w[1] - w[0]).collect()
+}
@@ -0,0 +1,175 @@
+use nessy::{
+    emulator::Emulator,
+    mapper::{
+        fds::{FdsSystem, BIOS_SIZE},
+        DynMapper, Mapper, MapperBus,
+    },
+    nesbus::CpuBus,
+    ppu::PpuBus,
+    rom::fds::{Disk, FdsError, SIDE_SIZE},
+};
+use std::fs;
+
+const BIOS_FILE: &str = "roms/disksys.rom";
+
+#[test]
+pub fn parses_images_with_and_without_a_header() {
+    let bare = disk_image(2);
+    let mut headered = b"FDS\x1A\x02".to_vec();
+    headered.resize(16, 0);
+    headered.extend_from_slice(&bare);
+
+    let disk = Disk::parse(&bare).unwrap();
+    assert_eq!(disk.sides(), 2);
+    assert_eq!(Disk::parse(&headered).unwrap(), disk);
+    assert_eq!(disk.side(1).len(), SIDE_SIZE);
+
+    // A last side that's cut short gets padded.
+    let short = Disk::parse(&bare[..SIDE_SIZE + 100]).unwrap();
+    assert_eq!(short, disk);
+
+    assert_eq!(Disk::parse(&[]), Err(FdsError::Truncated));
+    assert_eq!(Disk::parse(&[0; 100]), Err(FdsError::NotADisk(0)));
+}
+
+#[test]
+pub fn timer_irq() {
+    let mut fds = FdsSystem::new(&[0; BIOS_SIZE]).unwrap();
+    write(&mut fds, 0x4023, 1);
+    write(&mut fds, 0x4020, 10);
+    write(&mut fds, 0x4021, 0);
+    write(&mut fds, 0x4022, 2);
+
+    let mut cycles = 0;
+    loop {
+        cycles += 1;
+        if idle(&mut fds) {
+            break;
+        }
+        assert!(cycles < 100);
+    }
+    assert_eq!(cycles, 10);
+
+    assert_eq!(read(&mut fds, 0x4030) & 1, 1);
+    assert_eq!(read(&mut fds, 0x4030) & 1, 0);
+    // Without the repeat flag, the timer stops after one IRQ.
+    assert!((0..100).all(|_| !idle(&mut fds)));
+}
+
+#[test]
+pub fn reads_the_disk_after_the_gap() {
+    let mut fds = FdsSystem::new(&[0; BIOS_SIZE]).unwrap();
+    fds.insert_disk(Disk::parse(&disk_image(1)).unwrap());
+    write(&mut fds, 0x4023, 1);
+    while fds.inserted_side().is_none() {
+        idle(&mut fds);
+    }
+
+    // Motor on, read mode, and ready for data.
+    write(&mut fds, 0x4025, 0x45);
+    let mut data = Vec::new();
+    let mut cycles = 0;
+    while data.len() < 16 {
+        cycles += 1;
+        assert!(cycles < 1_000_000);
+        if read(&mut fds, 0x4030) & 2 != 0 {
+            data.push(read(&mut fds, 0x4031));
+        }
+    }
+    assert_eq!(data, b"\x80\x01*NINTENDO-HVC*");
+}
+
+#[test]
+pub fn flipping_the_disk() {
+    let mut mapper = DynMapper::new(FdsSystem::new(&[0; BIOS_SIZE]).unwrap());
+    let fds = mapper.disk_drive().unwrap();
+    fds.insert_disk(Disk::parse(&disk_image(2)).unwrap());
+    write(fds, 0x4023, 1);
+    assert_eq!(read(fds, 0x4032) & 1, 1);
+    while fds.inserted_side().is_none() {
+        idle(fds);
+    }
+    assert_eq!(read(fds, 0x4032) & 1, 0);
+    assert_eq!(fds.inserted_side(), Some(0));
+
+    assert_eq!(fds.flip(), Some(1));
+    assert_eq!(read(fds, 0x4032) & 5, 5);
+    while fds.inserted_side().is_none() {
+        idle(fds);
+    }
+    assert_eq!(fds.inserted_side(), Some(1));
+
+    fds.eject();
+    for _ in 0..10 {
+        idle(fds);
+    }
+    assert_eq!(fds.inserted_side(), None);
+}
+
+#[test]
+pub fn rejects_bios_of_the_wrong_size() {
+    assert!(matches!(
+        FdsSystem::new(&[0; 100]),
+        Err(FdsError::Bios(100))
+    ));
+    assert!(FdsSystem::new(&[0; BIOS_SIZE + 1]).is_err());
+}
+
+// The BIOS isn't part of the repository, so this only runs with one at BIOS_FILE.
+#[test]
+pub fn bios_boots_to_the_license_screen() {
+    let Ok(bios) = fs::read(BIOS_FILE) else {
+        eprintln!("No BIOS at {BIOS_FILE}, skipping");
+        return;
+    };
+    let mut nes = Emulator::builder()
+        .fds_bios(bios)
+        .rom_bytes(disk_image(1))
+        .build_nes()
+        .unwrap();
+    for _ in 0..120 {
+        nes.run_frame();
+    }
+
+    // The Nintendo logo is drawn into the first name table, which starts out empty.
+    assert!((0x2000..0x23C0).any(|addr| nes.peek_ppu(addr) != 0));
+}
+
+// Sides that hold a disk info block, a file count block, and one file of 4 bytes.
+fn disk_image(sides: usize) -> Vec<u8> {
+    let mut image = Vec::new();
+    for _ in 0..sides {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(56, 0);
+        side.extend_from_slice(&[2, 1]);
+        side.extend_from_slice(&[3, 0, 0]);
+        side.extend_from_slice(b"FILE0000");
+        // Loaded at $6000, 4 bytes of PRG.
+        side.extend_from_slice(&[0x00, 0x60, 4, 0, 0]);
+        side.extend_from_slice(&[4, 1, 2, 3, 4]);
+        side.resize(SIDE_SIZE, 0);
+        image.extend_from_slice(&side);
+    }
+    image
+}
+
+fn read(mapper: &mut FdsSystem, addr: u16) -> u8 {
+    let mut cpu = CpuBus::init();
+    cpu.set_address(addr);
+    cpu.set_read(true);
+    mapper.cycle(&mut MapperBus::init(), &mut cpu, &mut PpuBus::init());
+    cpu.data()
+}
+fn write(mapper: &mut FdsSystem, addr: u16, data: u8) {
+    let mut cpu = CpuBus::init();
+    cpu.set_address(addr);
+    cpu.set_data(data);
+    cpu.set_read(false);
+    mapper.cycle(&mut MapperBus::init(), &mut cpu, &mut PpuBus::init());
+}
+fn idle(mapper: &mut FdsSystem) -> bool {
+    let mut cpu = CpuBus::init();
+    cpu.set_read(true);
+    mapper.cycle(&mut MapperBus::init(), &mut cpu, &mut PpuBus::init());
+    cpu.irq()
+}
@@ -0,0 +1,37 @@
+use nessy::{
+    emulator::Emulator,
+    test_rom::{assert_frame_hash, frame_hash},
+};
+
+// The menu, with the cursor on "Run all tests".
+#[test]
+pub fn nestest_goldens() {
+    assert_frame_hash("test_roms/nestest.nes", 60, 0xB821_960A_2B13_A579);
+}
+
+// The text split into its three areas, without any stars marking errors.
+#[test]
+pub fn scanline_goldens() {
+    assert_frame_hash("test_roms/scanline.nes", 60, 0xA482_85A5_3D5E_80A5);
+}
+
+#[test]
+pub fn missing_roms_are_errors() {
+    assert!(frame_hash("test_roms/missing.nes", 1).is_err());
+}
+
+// Every frame drawn with the PPU batched is the same as from the dot loop.
+#[test]
+pub fn batched_ppu_draws_the_same_frames() {
+    for path in ["test_roms/nestest.nes", "test_roms/scanline.nes"] {
+        let [dots, batched] = [false, true].map(|batched| {
+            let src = std::fs::read(path).unwrap();
+            let builder = Emulator::builder().rom_bytes(src).batched_ppu(batched);
+            let mut nes = builder.build_nes().unwrap();
+            (0..120)
+                .map(|_| nes.run_frame().pixels.hash())
+                .collect::<Vec<_>>()
+        });
+        assert!(dots == batched, "{path}");
+    }
+}
@@ -0,0 +1,105 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{
+    input::keyboard::{Key, Keyboard},
+    mapper::mapper0::Mapper0,
+    nesbus::NesBus,
+    rom::builder::RomBuilder,
+};
+
+#[test]
+pub fn scan_finds_pressed_keys() {
+    let mut bus = keyboard_bus();
+    let keyboard = bus.input_mut().keyboard_mut().unwrap();
+    keyboard.set_key(Key::Return, true);
+    keyboard.set_key(Key::Kana, true);
+    keyboard.set_key(Key::A, true);
+    keyboard.set_key(Key::X, true);
+    keyboard.set_key(Key::Space, true);
+
+    let rows = scan(&mut bus);
+    let mut expected = [[0x1E; 2]; 9];
+    expected[0] = [0x1E & !0x08, 0x1E & !0x10];
+    expected[6] = [0x1E & !0x02, 0x1E & !0x10];
+    expected[8][1] = 0x1E & !0x08;
+    assert_eq!(rows, expected);
+
+    bus.input_mut().keyboard_mut().unwrap().release_all();
+    assert_eq!(scan(&mut bus), [[0x1E; 2]; 9]);
+}
+
+#[test]
+pub fn every_key_has_its_own_spot() {
+    let mut bus = keyboard_bus();
+    let keys = [
+        Key::RightBracket,
+        Key::Underscore,
+        Key::M,
+        Key::Ctr,
+        Key::Down,
+    ];
+    for key in keys {
+        let (row, column, bit) = key.position();
+        bus.input_mut().keyboard_mut().unwrap().set_key(key, true);
+        let rows = scan(&mut bus);
+        bus.input_mut().keyboard_mut().unwrap().set_key(key, false);
+        for (i, columns) in rows.iter().enumerate() {
+            for (j, &bits) in columns.iter().enumerate() {
+                let expected = if (i, j) == (row, column) {
+                    0x1E & !bit
+                } else {
+                    0x1E
+                };
+                assert_eq!(bits, expected, "{key:?} at row {i} column {j}");
+            }
+        }
+    }
+}
+
+#[test]
+pub fn disabled_matrix_reads_zero() {
+    let mut bus = keyboard_bus();
+    bus.write(0x4016, 0x05);
+    assert_eq!(read_keys(&mut bus), 0x1E);
+    bus.write(0x4016, 0x00);
+    assert_eq!(read_keys(&mut bus), 0);
+
+    // The tenth row has no keys at all.
+    bus.input_mut()
+        .keyboard_mut()
+        .unwrap()
+        .set_key(Key::Down, true);
+    bus.write(0x4016, 0x05);
+    for _ in 0..8 {
+        bus.write(0x4016, 0x06);
+        bus.write(0x4016, 0x04);
+    }
+    bus.write(0x4016, 0x06);
+    assert_eq!(read_keys(&mut bus), 0x1E & !0x10);
+    bus.write(0x4016, 0x04);
+    bus.write(0x4016, 0x06);
+    assert_eq!(read_keys(&mut bus), 0x1E);
+}
+
+// Resets to the first row, then reads column 0 and column 1 of each row in turn.
+fn scan(bus: &mut NesBus<Mapper0>) -> [[u8; 2]; 9] {
+    let mut rows = [[0; 2]; 9];
+    bus.write(0x4016, 0x05);
+    for row in &mut rows {
+        bus.write(0x4016, 0x04);
+        row[0] = read_keys(bus);
+        bus.write(0x4016, 0x06);
+        row[1] = read_keys(bus);
+    }
+    rows
+}
+fn read_keys(bus: &mut NesBus<Mapper0>) -> u8 {
+    bus.read(0x4017, false, false).0 & 0x1E
+}
+fn keyboard_bus() -> NesBus<Mapper0> {
+    let src = RomBuilder::new().build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+    bus.input_mut().set_keyboard(Some(Keyboard::init()));
+    bus
+}
@@ -0,0 +1,184 @@
+use nes_rom_parser::Rom;
+use nessy::{
+    mapper::{mapper24::Mapper24, Mapper, MapperBus, MapperState, Mirroring},
+    nesbus::CpuBus,
+    ppu::PpuBus,
+    rom::builder::RomBuilder,
+};
+
+#[test]
+pub fn prg_banking() {
+    let src = vrc6_rom(24);
+    let rom = Rom::parse(&src).unwrap();
+    let mut mapper = Mapper24::new(&rom);
+
+    write(&mut mapper, 0x8000, 3);
+    write(&mut mapper, 0xC000, 9);
+    assert_eq!(read(&mut mapper, 0x8000), 6);
+    assert_eq!(read(&mut mapper, 0xA000), 7);
+    assert_eq!(read(&mut mapper, 0xC123), 9);
+    assert_eq!(read(&mut mapper, 0xE000), 31);
+    assert_eq!(read(&mut mapper, 0xFFFF), 31);
+}
+
+#[test]
+pub fn mapper26_swaps_register_lines() {
+    let src = vrc6_rom(26);
+    let rom = Rom::parse(&src).unwrap();
+    let mut mapper = Mapper24::new(&rom);
+
+    // $D001 is CHR register 2 on VRC6b, $D002 is register 1.
+    write(&mut mapper, 0xD001, 20);
+    write(&mut mapper, 0xD002, 10);
+    assert_eq!(read_ppu(&mut mapper, 0x0400), 10);
+    assert_eq!(read_ppu(&mut mapper, 0x0800), 20);
+}
+
+#[test]
+pub fn chr_banking_and_mirroring() {
+    let src = vrc6_rom(24);
+    let rom = Rom::parse(&src).unwrap();
+    let mut mapper = Mapper24::new(&rom);
+
+    for i in 0..4 {
+        write(&mut mapper, 0xD000 + i, 100 + i as u8);
+        write(&mut mapper, 0xE000 + i, 110 + i as u8);
+    }
+    for i in 0..4 {
+        assert_eq!(read_ppu(&mut mapper, i * 0x400), 100 + i as u8);
+        assert_eq!(read_ppu(&mut mapper, 0x1000 + i * 0x400 + 0x3FF), 110 + i as u8);
+    }
+
+    write(&mut mapper, 0xB003, 0b0000);
+    assert!(vram_a10(&mut mapper, 0x2400));
+    assert!(!vram_a10(&mut mapper, 0x2800));
+    write(&mut mapper, 0xB003, 0b0100);
+    assert!(!vram_a10(&mut mapper, 0x2400));
+    assert!(vram_a10(&mut mapper, 0x2800));
+    write(&mut mapper, 0xB003, 0b1000);
+    assert!(!vram_a10(&mut mapper, 0x2C00));
+    write(&mut mapper, 0xB003, 0b1100);
+    assert!(vram_a10(&mut mapper, 0x2000));
+}
+
+#[test]
+pub fn prg_ram_enable() {
+    let src = vrc6_rom(24);
+    let rom = Rom::parse(&src).unwrap();
+    let mut mapper = Mapper24::new(&rom);
+
+    write(&mut mapper, 0x6000, 0x55);
+    assert_ne!(read(&mut mapper, 0x6000), 0x55);
+
+    write(&mut mapper, 0xB003, 0x80);
+    write(&mut mapper, 0x6000, 0x55);
+    assert_eq!(read(&mut mapper, 0x6000), 0x55);
+}
+
+#[test]
+pub fn cycle_mode_irq() {
+    let src = vrc6_rom(24);
+    let rom = Rom::parse(&src).unwrap();
+    let mut mapper = Mapper24::new(&rom);
+
+    write(&mut mapper, 0xF000, 0xF0);
+    write(&mut mapper, 0xF001, 0b110);
+
+    // The write to $F001 already clocked the counter once.
+    let mut cycles = 1;
+    loop {
+        cycles += 1;
+        if idle(&mut mapper) {
+            break;
+        }
+        assert!(cycles < 100);
+    }
+    assert_eq!(cycles, 16);
+
+    write(&mut mapper, 0xF002, 0);
+    assert!(!idle(&mut mapper));
+}
+
+#[test]
+pub fn expansion_audio() {
+    let src = vrc6_rom(24);
+    let rom = Rom::parse(&src).unwrap();
+    let mut mapper = Mapper24::new(&rom);
+    assert_eq!(mapper.audio_output(), 0.0);
+
+    // Pulse 1 in constant-volume mode
+    write(&mut mapper, 0x9000, 0x8F);
+    write(&mut mapper, 0x9002, 0x80);
+    let full = mapper.audio_output();
+    assert!(full > 0.0);
+
+    write(&mut mapper, 0x9000, 0x87);
+    assert!(mapper.audio_output() < full);
+
+    write(&mut mapper, 0x9003, 0x01);
+    write(&mut mapper, 0x9002, 0x00);
+    assert_eq!(mapper.audio_output(), 0.0);
+}
+
+#[test]
+pub fn describe_banks() {
+    let src = vrc6_rom(26);
+    let rom = Rom::parse(&src).unwrap();
+    let mut mapper = Mapper24::new(&rom);
+
+    write(&mut mapper, 0x8000, 2);
+    write(&mut mapper, 0xC000, 17);
+    write(&mut mapper, 0xE002, 42);
+    write(&mut mapper, 0xB003, 0b0100);
+
+    let state = mapper.describe();
+    let expected = MapperState {
+        mapper: 26,
+        prg_banks: [4, 5, 17, 31],
+        chr_banks: [0, 0, 0, 0, 0, 42, 0, 0],
+        mirroring: Mirroring::Horizontal,
+    };
+    assert_eq!(state, expected);
+}
+
+// 256K of PRG and 128K of CHR, each bank filled with its own bank number.
+fn vrc6_rom(mapper: u16) -> Vec<u8> {
+    let prg = (0..32).flat_map(|bank| [bank; 0x2000]).collect();
+    let chr = (0..128).flat_map(|bank| [bank; 0x400]).collect();
+    RomBuilder::new().mapper(mapper).prg(prg).chr(chr).build()
+}
+
+fn read(mapper: &mut Mapper24, addr: u16) -> u8 {
+    let mut cpu = CpuBus::init();
+    cpu.set_address(addr);
+    cpu.set_read(true);
+    mapper.cycle(&mut MapperBus::init(), &mut cpu, &mut PpuBus::init());
+    cpu.data()
+}
+fn write(mapper: &mut Mapper24, addr: u16, data: u8) {
+    let mut cpu = CpuBus::init();
+    cpu.set_address(addr);
+    cpu.set_data(data);
+    cpu.set_read(false);
+    mapper.cycle(&mut MapperBus::init(), &mut cpu, &mut PpuBus::init());
+}
+fn idle(mapper: &mut Mapper24) -> bool {
+    let mut cpu = CpuBus::init();
+    cpu.set_read(true);
+    mapper.cycle(&mut MapperBus::init(), &mut cpu, &mut PpuBus::init());
+    cpu.irq()
+}
+fn read_ppu(mapper: &mut Mapper24, addr: u16) -> u8 {
+    let mut ppu = PpuBus::init();
+    ppu.set_address(addr);
+    ppu.set_read_enable(true);
+    mapper.cycle_with_ppu(&mut MapperBus::init(), &mut ppu);
+    ppu.data()
+}
+fn vram_a10(mapper: &mut Mapper24, addr: u16) -> bool {
+    let mut bus = MapperBus::init();
+    let mut ppu = PpuBus::init();
+    ppu.set_address(addr);
+    mapper.cycle_with_ppu(&mut bus, &mut ppu);
+    bus.vram_a10()
+}
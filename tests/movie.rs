@@ -0,0 +1,131 @@
+use nes_rom_parser::Rom;
+use nessy::{
+    input::Controller,
+    mapper::mapper0::Mapper0,
+    movie::{Movie, MovieError, COMMAND_RESET},
+    nes::Nes,
+    nesbus::{NesBus, PowerUpState},
+    rom::{builder::RomBuilder, hash::crc32},
+};
+
+// Copies controller 1 into the background color every frame.
+#[rustfmt::skip]
+const PROGRAM: [u8; 40] = [
+    0xA9, 0x80,       // LDA #$80
+    0x8D, 0x00, 0x20, // STA $2000
+    0x4C, 0x05, 0x80, // JMP $8005
+    0xA9, 0x01,       // NMI: LDA #$01
+    0x8D, 0x16, 0x40, //      STA $4016
+    0xA9, 0x00,       //      LDA #$00
+    0x8D, 0x16, 0x40, //      STA $4016
+    0xA2, 0x08,       //      LDX #$08
+    0xAD, 0x16, 0x40, // read: LDA $4016
+    0x4A,             //      LSR
+    0x26, 0x00,       //      ROL $00
+    0xCA,             //      DEX
+    0xD0, 0xF7,       //      BNE read
+    0xA9, 0x3F,       //      LDA #$3F
+    0x8D, 0x06, 0x20, //      STA $2006
+    0xA5, 0x00,       //      LDA $00
+    0x8D, 0x06, 0x20, //      STA $2006
+    0x40,             //      RTI
+];
+
+const FM2: &str = "version 3
+emuVersion 22020
+romFilename smb
+palFlag 0
+port0 1
+port1 1
+port2 0
+|0|........|........||
+|1|R..UT..A|.L....B.||
+";
+
+#[test]
+pub fn parses_fm2() {
+    let movie = Movie::parse(FM2).unwrap();
+    assert!(!movie.pal);
+    assert_eq!(movie.power_up, PowerUpState::Zeroed);
+    assert_eq!(movie.frames.len(), 2);
+    assert_eq!(movie.frames[1].commands, COMMAND_RESET);
+    assert_eq!(
+        movie.frames[1].controllers,
+        [Controller(0x99), Controller(0x42)]
+    );
+
+    let written = movie.to_fm2();
+    assert!(written.contains("romFilename smb\n"));
+    assert!(written.contains("|1|R..UT..A|.L....B.||\n"));
+    assert_eq!(Movie::parse(&written).unwrap(), movie);
+}
+
+#[test]
+pub fn rejects_what_it_cant_play() {
+    let four_score = "version 3\nfourscore 1\n";
+    assert!(matches!(
+        Movie::parse(four_score),
+        Err(MovieError::Unsupported(_))
+    ));
+    assert_eq!(
+        Movie::parse("version 3\n|0|...|........||\n"),
+        Err(MovieError::Frame(2))
+    );
+    assert_eq!(
+        Movie::parse("powerUpRam filled XY\n"),
+        Err(MovieError::Header(1))
+    );
+}
+
+#[test]
+pub fn recorded_movies_replay_the_same() {
+    let mut nes = console(PowerUpState::Random(7));
+    nes.record_movie();
+    for frame in 0..20u8 {
+        nes.bus.controllers_mut()[0] = Controller(frame.wrapping_mul(37));
+        if frame == 12 {
+            nes.reset();
+        }
+        nes.run_frame();
+    }
+    let movie = nes.stop_movie().unwrap();
+    assert_eq!(movie.frames.len(), 20);
+    assert_eq!(movie.frames[12].commands, COMMAND_RESET);
+    assert_eq!(movie.power_up, PowerUpState::Random(7));
+
+    let movie = Movie::parse(&movie.to_fm2()).unwrap();
+    let first = play(&movie);
+    let second = play(&movie);
+    assert_eq!(first, second);
+}
+
+fn play(movie: &Movie) -> Vec<u32> {
+    let mut nes = console(PowerUpState::Zeroed);
+    nes.play_movie(movie.clone());
+    let mut hashes = Vec::new();
+    while nes.movie_playing() {
+        let frame = nes.run_frame();
+        let bytes: Vec<u8> = frame
+            .pixels
+            .0
+            .iter()
+            .flat_map(|p| p.to_le_bytes())
+            .collect();
+        hashes.push(crc32(&bytes));
+    }
+    assert_eq!(
+        nes.bus.controllers_mut()[0],
+        Controller(19u8.wrapping_mul(37))
+    );
+    hashes
+}
+
+fn console(power_up: PowerUpState) -> Nes<Mapper0> {
+    let src = RomBuilder::new()
+        .write_cpu(0x8000, &PROGRAM)
+        .reset_vector(0x8000)
+        .nmi_vector(0x8008)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+    Nes::new(NesBus::new_with(Mapper0::new(&rom), &power_up))
+}
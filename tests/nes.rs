@@ -0,0 +1,168 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{
+    debugger::NesObserver,
+    emulator::Emulator,
+    mapper::{mapper0::Mapper0, DynMapper},
+    nes::Nes,
+    nesbus::{NesBus, PowerUpState},
+    rom::{builder::RomBuilder, hash::crc32},
+};
+use std::sync::{Arc, Mutex};
+
+// Counts boots in PRG RAM, leaves a mark in console RAM, then spins at $8007.
+#[rustfmt::skip]
+const PROGRAM: [u8; 10] = [
+    0xEE, 0x00, 0x60, // INC $6000
+    0xA9, 0x05,       // LDA #$05
+    0x85, 0x00,       // STA $00
+    0x4C, 0x07, 0x80, // JMP $8007
+];
+// Starts a pulse tone and turns on the background.
+#[rustfmt::skip]
+const SOUND: [u8; 28] = [
+    0xA9, 0x01,       // LDA #$01
+    0x8D, 0x15, 0x40, // STA $4015
+    0xA9, 0xBF,       // LDA #$BF
+    0x8D, 0x00, 0x40, // STA $4000
+    0xA9, 0x80,       // LDA #$80
+    0x8D, 0x02, 0x40, // STA $4002
+    0xA9, 0x08,       // LDA #$08
+    0x8D, 0x03, 0x40, // STA $4003
+    0xA9, 0x1E,       // LDA #$1E
+    0x8D, 0x01, 0x20, // STA $2001
+    0x4C, 0x19, 0x80, // JMP $8019
+];
+
+#[test]
+pub fn reset_restarts_at_the_reset_vector() {
+    let mut nes = console(&PROGRAM);
+    run_instructions(&mut nes, 20);
+    assert_eq!(nes.cpu.pc(), 0x8007);
+
+    nes.reset();
+    assert!(runs_into(&mut nes, 0x8000));
+    run_instructions(&mut nes, 20);
+    assert_eq!(nes.bus.peek_cpu(0x6000), 2);
+    assert_eq!(nes.bus.ram()[0], 5);
+}
+
+#[test]
+pub fn power_cycle_keeps_cartridge_ram() {
+    let mut nes = console(&PROGRAM);
+    run_instructions(&mut nes, 20);
+
+    nes.power_cycle();
+    assert_eq!(nes.bus.ram()[0], 0xAA);
+    assert!(runs_into(&mut nes, 0x8000));
+    run_instructions(&mut nes, 20);
+    assert_eq!(nes.bus.peek_cpu(0x6000), 2);
+}
+
+#[test]
+pub fn identical_consoles_produce_identical_frames() {
+    let mut first = console(&SOUND);
+    let mut second = console(&SOUND);
+    for _ in 0..5 {
+        assert_eq!(frame_hash(&mut first), frame_hash(&mut second));
+    }
+
+    let frame = first.run_frame();
+    assert_eq!(frame.ppu_dots, frame.cpu_cycles * 3);
+    assert_eq!(frame.samples.len() as u64, frame.cpu_cycles);
+    assert!((29780..29790).contains(&frame.cpu_cycles));
+}
+
+#[test]
+pub fn finer_steps() {
+    let mut nes = console(&SOUND);
+    nes.run_cycles(1000);
+    assert!((1000..1010).contains(&nes.bus.cycles()));
+    let line = nes.bus.ppu().dot()[1];
+    nes.run_scanlines(10);
+    assert_eq!(nes.bus.ppu().dot()[1], line + 10);
+}
+
+// Frontends run the emulator on a thread of its own.
+#[test]
+pub fn consoles_are_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<Nes<DynMapper>>();
+    assert_send::<Nes<Mapper0>>();
+    assert_send::<Emulator>();
+}
+
+#[test]
+pub fn observer_sees_every_scanline() {
+    let mut nes = console(&SOUND);
+    nes.run_frame();
+    let events = Arc::new(Mutex::new(Events::default()));
+    nes.set_observer(Some(Box::new(Recorder(events.clone()))));
+    nes.bus.write(0x2000, 0x80);
+    nes.run_frame();
+    nes.run_frame();
+    nes.set_observer(None);
+    nes.run_frame();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.lines.len(), 2 * 262);
+    assert_eq!(events.lines[..262], (1..262).chain([0]).collect::<Vec<_>>());
+    assert_eq!(events.frames, [2, 3]);
+    assert_eq!(events.nmis, 2);
+}
+
+#[derive(Default)]
+struct Events {
+    lines: Vec<u16>,
+    frames: Vec<u64>,
+    nmis: u32,
+}
+struct Recorder(Arc<Mutex<Events>>);
+impl NesObserver for Recorder {
+    fn on_frame(&mut self, frame: u64) {
+        self.0.lock().unwrap().frames.push(frame);
+    }
+    fn on_scanline(&mut self, _frame: u64, line: u16) {
+        self.0.lock().unwrap().lines.push(line);
+    }
+    fn on_nmi(&mut self, _cycle: u64) {
+        self.0.lock().unwrap().nmis += 1;
+    }
+}
+
+fn frame_hash(nes: &mut Nes<Mapper0>) -> (u32, u64, u64) {
+    let frame = nes.run_frame();
+    let mut bytes = Vec::new();
+    for pixel in &frame.pixels.0 {
+        bytes.extend_from_slice(&pixel.to_le_bytes());
+    }
+    for sample in frame.samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    (crc32(&bytes), frame.cpu_cycles, frame.ppu_dots)
+}
+
+fn console(program: &[u8]) -> Nes<Mapper0> {
+    let src = RomBuilder::new()
+        .write_cpu(0x8000, program)
+        .reset_vector(0x8000)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+    let power_up = PowerUpState::Filled(0xAA);
+    Nes::new(NesBus::new_with(Mapper0::new(&rom), &power_up))
+}
+fn run_instructions(nes: &mut Nes<Mapper0>, count: usize) {
+    for _ in 0..count {
+        nes.cpu.exec(&mut nes.bus);
+    }
+}
+// Whether the CPU gets to `pc` within a few instructions.
+fn runs_into(nes: &mut Nes<Mapper0>, pc: u16) -> bool {
+    for _ in 0..10 {
+        nes.cpu.exec(&mut nes.bus);
+        if nes.cpu.pc() == pc {
+            return true;
+        };
+    }
+    false
+}
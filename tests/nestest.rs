@@ -1,6 +1,8 @@
 use cpu_6502::Cpu;
 use nes_rom_parser::Rom;
-use nessy::{mapper::mapper0::Mapper0, nesbus::NesBus};
+use nessy::{
+    mapper::mapper0::Mapper0, nesbus::NesBus, rom::builder::RomBuilder, trace::nestest_line,
+};
 use std::{
     fs::{self, File},
     io::{BufRead, BufReader},
@@ -12,51 +14,39 @@ pub fn nestest() {
     let log = BufReader::new(log);
     let lines = log.lines();
 
-    let src = fs::read("test_roms/nestest.nes").unwrap();
+    // The automated mode starts at $C000 instead of the reset vector's menu.
+    let dump = fs::read("test_roms/nestest.nes").unwrap();
+    let dump = Rom::parse(&dump).unwrap();
+    let src = RomBuilder::new()
+        .prg(dump.prg_rom.to_vec())
+        .chr(dump.chr_rom.to_vec())
+        .reset_vector(0xC000)
+        .build();
     let rom = Rom::parse(&src).unwrap();
-    let mut mapper = Mapper0::new(&rom);
-    mapper.overwrite(0xFFFC, 0x00);
-    mapper.overwrite(0xFFFD, 0xC0);
 
     let mut cpu = Cpu::new();
-    let mut bus = NesBus::new(mapper);
+    let mut bus = NesBus::new(Mapper0::new(&rom));
 
     // Run reset sequence
     cpu.exec(&mut bus);
 
     for line in lines {
         let line = line.unwrap();
-        compare_state(&line, &cpu, &bus);
+        compare_state(&line, &nestest_line(&cpu, &bus));
         cpu.exec(&mut bus);
     }
 
     println!("Tests are done");
 }
 
-fn compare_state(line: &str, cpu: &Cpu, bus: &NesBus<Mapper0>) {
-    let should_pc = u16::from_str_radix(&line[0..4], 16).unwrap();
-    let should_a = u8::from_str_radix(&line[50..52], 16).unwrap();
-    let should_x = u8::from_str_radix(&line[55..57], 16).unwrap();
-    let should_y = u8::from_str_radix(&line[60..62], 16).unwrap();
-    let should_sp = u8::from_str_radix(&line[71..73], 16).unwrap();
-    let should_dot_y: u16 = line[78..81]
-        .split_whitespace()
-        .next()
-        .unwrap()
-        .parse()
-        .unwrap();
-    let should_dot_x: u16 = line[82..85]
-        .split_whitespace()
-        .next()
-        .unwrap()
-        .parse()
-        .unwrap();
-
-    assert_eq!(should_pc, cpu.pc());
-    assert_eq!(should_a, cpu.a());
-    assert_eq!(should_x, cpu.x());
-    assert_eq!(should_y, cpu.y());
-    assert_eq!(should_sp, cpu.sp() as u8);
-    assert_eq!(should_dot_y, bus.ppu().dot()[1]);
-    assert_eq!(should_dot_x, bus.ppu().dot()[0]);
+// PC, A, X, Y, SP and the PPU position. Flags and the values shown next to operands aren't compared.
+fn compare_state(expected: &str, actual: &str) {
+    let fields = [0..4, 50..52, 55..57, 60..62, 71..73, 78..85];
+    let diverged = fields
+        .into_iter()
+        .any(|range| expected.get(range.clone()) != actual.get(range));
+    assert!(
+        !diverged,
+        "trace diverged\nexpected: {expected}\n  actual: {actual}"
+    );
 }
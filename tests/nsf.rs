@@ -0,0 +1,111 @@
+use nessy::{
+    player::NsfPlayer,
+    region::Region,
+    rom::nsf::{Nsf, NsfError},
+};
+use std::time::Duration;
+
+fn nsf_file(load: u16, init: u16, play: u16, banks: [u8; 8], data: &[u8]) -> Vec<u8> {
+    let mut src = vec![0; 0x80];
+    src[0..5].copy_from_slice(b"NESM\x1A");
+    src[5] = 1;
+    src[6] = 3;
+    src[7] = 2;
+    src[0x08..0x0A].copy_from_slice(&load.to_le_bytes());
+    src[0x0A..0x0C].copy_from_slice(&init.to_le_bytes());
+    src[0x0C..0x0E].copy_from_slice(&play.to_le_bytes());
+    src[0x0E..0x13].copy_from_slice(b"Tune\0");
+    src[0x2E..0x34].copy_from_slice(b"Nessy\0");
+    src[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+    src[0x70..0x78].copy_from_slice(&banks);
+    src.extend_from_slice(data);
+    src
+}
+
+#[test]
+pub fn parses_the_header() {
+    let src = nsf_file(0x8000, 0x8003, 0x8010, [0; 8], &[0xEA; 0x20]);
+    let nsf = Nsf::parse(&src).unwrap();
+    assert_eq!((nsf.songs, nsf.first_song), (3, 1));
+    assert_eq!(
+        (nsf.load_address, nsf.init_address, nsf.play_address),
+        (0x8000, 0x8003, 0x8010)
+    );
+    assert_eq!((nsf.name.as_str(), nsf.artist.as_str()), ("Tune", "Nessy"));
+    assert_eq!(nsf.copyright, "");
+    assert_eq!((nsf.banks, nsf.region), (None, Region::Ntsc));
+    assert_eq!(nsf.speed(Region::Ntsc), 16639);
+    assert_eq!(nsf.speed(Region::Pal), 19997);
+    assert_eq!(nsf.data.len(), 0x20);
+
+    assert_eq!(Nsf::parse(&src[..0x7F]), Err(NsfError::Truncated));
+    assert_eq!(Nsf::parse(b"NES\x1A"), Err(NsfError::Truncated));
+    let low = nsf_file(0x6000, 0x6000, 0x6000, [0; 8], &[]);
+    assert_eq!(Nsf::parse(&low), Err(NsfError::LoadAddress(0x6000)));
+}
+
+#[test]
+pub fn maps_banks_and_vectors() {
+    let mut data = vec![0; 0x3000 - 0x123];
+    for bank in 0..3 {
+        data[bank * 0x1000 + 0xF00 - 0x123] = 0xB0 + bank as u8;
+    }
+    let src = nsf_file(0x8123, 0x8123, 0x8123, [2, 0, 1, 2, 0, 0, 0, 1], &data);
+    let player = NsfPlayer::new(Nsf::parse(&src).unwrap());
+    let bus = player.bus();
+
+    assert_eq!(bus.peek_cpu(0x8F00), 0xB2);
+    assert_eq!(bus.peek_cpu(0x9F00), 0xB0);
+    assert_eq!(bus.peek_cpu(0xAF00), 0xB1);
+    // The player's driver takes over the vectors.
+    assert_eq!(bus.peek_cpu(0xFFFC), 0x00);
+    assert_eq!(bus.peek_cpu(0xFFFD), 0x50);
+}
+
+#[test]
+pub fn calls_init_once_and_play_at_the_header_rate() {
+    let program = [
+        0x85, 0x10, // $8000 STA $10
+        0x86, 0x11, // $8002 STX $11
+        0xE6, 0x12, // $8004 INC $12
+        0x60, // $8006 RTS
+        0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, //
+        0xE6, 0x00, // $8010 INC $00
+        0xD0, 0x02, // $8012 BNE $8016
+        0xE6, 0x01, // $8014 INC $01
+        0x60, // $8016 RTS
+    ];
+    let src = nsf_file(0x8000, 0x8000, 0x8010, [0; 8], &program);
+    let mut player = NsfPlayer::new(Nsf::parse(&src).unwrap());
+    player.select_song(2);
+    player.set_duration_limit(Some(Duration::from_secs(1)));
+
+    let mut samples = Vec::new();
+    while !player.finished() {
+        player.run_cycles(10_000);
+        player.drain_audio(&mut samples);
+    }
+    let ram = player.bus().ram();
+    assert_eq!((ram[0x10], ram[0x11], ram[0x12]), (2, 0, 1));
+    let plays = u16::from_le_bytes([ram[0x00], ram[0x01]]);
+    assert_eq!(plays, 60);
+    let expected = player.sample_rate() as usize;
+    assert!(samples.len().abs_diff(expected) < expected / 100);
+}
+
+#[test]
+pub fn songs_stop_at_the_duration_limit() {
+    let src = nsf_file(0x8000, 0x8000, 0x8000, [0; 8], &[0x60]);
+    let mut player = NsfPlayer::new(Nsf::parse(&src).unwrap());
+    assert_eq!(player.song(), 1);
+    player.set_duration_limit(Some(Duration::from_millis(10)));
+    player.run_cycles(1_000_000);
+    assert!(player.finished());
+    let elapsed = player.elapsed().as_secs_f64();
+    assert!((0.0099..0.0101).contains(&elapsed));
+
+    // Picking a song starts it over.
+    player.select_song(4);
+    assert_eq!(player.song(), 1);
+    assert!(!player.finished());
+}
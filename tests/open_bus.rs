@@ -0,0 +1,87 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{mapper::mapper0::Mapper0, nesbus::NesBus, rom::builder::RomBuilder};
+
+#[test]
+pub fn unmapped_read_returns_last_value() {
+    let mut bus = program_bus(&[0xAD, 0x00, 0x50]); // LDA $5000
+
+    assert_eq!(fetch(&mut bus, 0x8000), 0xAD);
+    assert_eq!(fetch(&mut bus, 0x8001), 0x00);
+    assert_eq!(fetch(&mut bus, 0x8002), 0x50);
+    assert_eq!(fetch(&mut bus, 0x5000), 0x50);
+    assert_eq!(fetch(&mut bus, 0x5FFF), 0x50);
+}
+
+#[test]
+pub fn writes_drive_the_bus() {
+    let mut bus = program_bus(&[]);
+
+    bus.write(0x0010, 0x9C);
+    assert_eq!(fetch(&mut bus, 0x4018), 0x9C);
+}
+
+#[test]
+pub fn controller_upper_bits_are_open_bus() {
+    let mut bus = program_bus(&[0xAD, 0x16, 0x40, 0xFF]); // LDA $4016
+    bus.controllers_mut()[0].set_a(true);
+    bus.write(0x4016, 1);
+    bus.write(0x4016, 0);
+
+    fetch(&mut bus, 0x8002);
+    assert_eq!(fetch(&mut bus, 0x4016), 0x41);
+    fetch(&mut bus, 0x8003);
+    assert_eq!(fetch(&mut bus, 0x4016), 0xE0);
+}
+
+#[test]
+pub fn write_only_apu_registers_are_open_bus() {
+    let mut bus = program_bus(&[]);
+
+    for addr in 0x4000..=0x4014 {
+        let value = addr as u8 ^ 0xA5;
+        bus.write(0x0010, value);
+        assert_eq!(fetch(&mut bus, addr), value, "{addr:04x}");
+    }
+}
+
+#[test]
+pub fn frame_counter_write_shows_above_controller_2() {
+    let mut bus = program_bus(&[]);
+
+    // Reading $4017 returns the second controller, with the write still on the bits above it.
+    bus.write(0x4017, 0xC0);
+    assert_eq!(fetch(&mut bus, 0x4017), 0xC0);
+    bus.controllers_mut()[1].set_a(true);
+    bus.write(0x4016, 1);
+    bus.write(0x4017, 0x40);
+    assert_eq!(fetch(&mut bus, 0x4017), 0x41);
+}
+
+#[test]
+pub fn apu_status_bit_5_is_open_bus() {
+    let mut bus = program_bus(&[]);
+
+    bus.write(0x0010, 0xFF);
+    assert_eq!(fetch(&mut bus, 0x4015), 0x20);
+    bus.write(0x0010, 0x00);
+    assert_eq!(fetch(&mut bus, 0x4015), 0x00);
+}
+
+#[test]
+pub fn apu_status_read_does_not_drive_bus() {
+    let mut bus = program_bus(&[]);
+
+    bus.write(0x0010, 0xC3);
+    fetch(&mut bus, 0x4015);
+    assert_eq!(fetch(&mut bus, 0x5000), 0xC3);
+}
+
+fn program_bus(program: &[u8]) -> NesBus<Mapper0> {
+    let src = RomBuilder::new().write_cpu(0x8000, program).build();
+    let rom = Rom::parse(&src).unwrap();
+    NesBus::new(Mapper0::new(&rom))
+}
+fn fetch(bus: &mut NesBus<Mapper0>, addr: u16) -> u8 {
+    bus.read(addr, false, false).0
+}
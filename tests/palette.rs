@@ -0,0 +1,58 @@
+use nessy::palette::{Palette, PaletteError, EMPHASIS_DIM};
+
+#[test]
+pub fn base_palette_gets_emphasis_rows() {
+    let bytes: Vec<u8> = (0..192).map(|i| (i % 200 + 50) as u8).collect();
+    let palette = Palette::from_bytes(&bytes).unwrap();
+    let base = palette.rgb(0x16, 0);
+    assert_eq!(base, [bytes[0x42], bytes[0x43], bytes[0x44]]);
+
+    // Each emphasis bit dims the channels it doesn't name, once per bit.
+    let dim = |value: u8, bits: i32| (value as f64 * EMPHASIS_DIM.powi(bits)).round() as u8;
+    assert_eq!(
+        palette.rgb(0x16, 0b001),
+        [base[0], dim(base[1], 1), dim(base[2], 1)]
+    );
+    assert_eq!(
+        palette.rgb(0x16, 0b010),
+        [dim(base[0], 1), base[1], dim(base[2], 1)]
+    );
+    assert_eq!(
+        palette.rgb(0x16, 0b110),
+        [dim(base[0], 2), dim(base[1], 1), dim(base[2], 1)]
+    );
+    assert_eq!(palette.rgb(0x16, 0b111), base.map(|value| dim(value, 2)));
+}
+
+#[test]
+pub fn full_palettes_load_as_is() {
+    let bytes: Vec<u8> = (0..1536).map(|i| (i * 7) as u8).collect();
+    let palette = Palette::from_bytes(&bytes).unwrap();
+    let index = (5 * 64 + 0x2A) * 3;
+    assert_eq!(
+        palette.rgb(0x2A, 5),
+        [bytes[index], bytes[index + 1], bytes[index + 2]]
+    );
+
+    assert!(matches!(
+        Palette::from_bytes(&bytes[..100]),
+        Err(PaletteError::Size(100))
+    ));
+}
+
+#[test]
+pub fn generated_palette_has_plausible_hues() {
+    let palette = Palette::generate();
+    let [r, g, b] = palette.rgb(0x16, 0);
+    assert!(r > g && r > b, "red {r} {g} {b}");
+    let [r, g, b] = palette.rgb(0x1A, 0);
+    assert!(g > r && g > b, "green {r} {g} {b}");
+    let [r, g, b] = palette.rgb(0x12, 0);
+    assert!(b > r && b > g, "blue {r} {g} {b}");
+    assert_eq!(palette.rgb(0x0F, 0), [0, 0, 0]);
+    assert!(palette.rgb(0x30, 0).iter().all(|&value| value > 230));
+
+    // Red emphasis darkens white by dimming green and blue.
+    let [r, g, b] = palette.rgb(0x30, 0b001);
+    assert!(r > g && r > b);
+}
@@ -0,0 +1,37 @@
+use nes_rom_parser::Rom;
+use nessy::{
+    mapper::mapper0::Mapper0,
+    nesbus::{NesBus, PowerUpState},
+    rom::builder::RomBuilder,
+};
+
+#[test]
+pub fn ram_is_filled_as_configured() {
+    assert!(ram(&PowerUpState::Zeroed).iter().all(|&byte| byte == 0));
+    assert!(ram(&PowerUpState::Filled(0xFF))
+        .iter()
+        .all(|&byte| byte == 0xFF));
+
+    let pattern = PowerUpState::Pattern(vec![0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+    let ram = ram(&pattern);
+    assert_eq!(ram[0..8], [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+    assert!(ram.chunks(8).all(|chunk| chunk == &ram[0..8]));
+}
+
+#[test]
+pub fn random_fill_depends_only_on_the_seed() {
+    let first = ram(&PowerUpState::Random(1234));
+    assert_eq!(first, ram(&PowerUpState::Random(1234)));
+    assert_ne!(first, ram(&PowerUpState::Random(1235)));
+    // Not stuck on a single value.
+    let zeroes = first.iter().filter(|&&byte| byte == 0).count();
+    assert!(zeroes < 64, "{zeroes}");
+}
+
+fn ram(power_up: &PowerUpState) -> Vec<u8> {
+    let src = RomBuilder::new().build();
+    let rom = Rom::parse(&src).unwrap();
+    NesBus::new_with(Mapper0::new(&rom), power_up)
+        .ram()
+        .to_vec()
+}
@@ -0,0 +1,843 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{
+    mapper::mapper0::Mapper0,
+    nesbus::{CpuBus, NesBus},
+    ppu::{pixel_buffer::WIDTH, Ppu, PpuBus},
+    region::Region,
+    rom::builder::RomBuilder,
+};
+
+const CPU_CYCLES_PER_FRAME: usize = 29781;
+
+#[test]
+pub fn frame_length_follows_the_region() {
+    // CPU cycles over four frames: NTSC runs 3 dots per cycle and drops one dot every other frame,
+    // PAL runs 3.2 dots per cycle over 312 lines.
+    for (region, cycles) in [(Region::Ntsc, 119122), (Region::Pal, 132990)] {
+        let mut bus = ppu_bus(vec![0; 0x2000]);
+        bus.set_region(region);
+        bus.write(0x2001, 0x08);
+        let mut starts = Vec::new();
+        let mut last_line = 0;
+        let mut vblank = false;
+        while starts.len() < 5 {
+            bus.read(0x8000, false, false);
+            last_line = last_line.max(bus.ppu().dot()[1]);
+            if bus.ppu().is_vblank() && !vblank {
+                starts.push(bus.cycles());
+            }
+            vblank = bus.ppu().is_vblank();
+        }
+        assert_eq!(starts[4] - starts[0], cycles, "{region:?}");
+        assert_eq!(last_line, region.ppu_lines() - 1);
+    }
+}
+
+#[test]
+pub fn odd_frame_skip_checks_the_background_on_the_skip_dot() {
+    // The mask for the odd frame, and a write to it on the CPU cycle starting at a dot of line 261.
+    let cases = [
+        (0x08, 338, 0x08, 89341),
+        (0x10, 338, 0x10, 89342),
+        (0x08, 338, 0x00, 89342),
+        (0x08, 339, 0x00, 89341),
+        (0x00, 338, 0x08, 89341),
+    ];
+    for (mask, write_dot, write, length) in cases {
+        let mut ppu = Ppu::init();
+        run_dots_to(&mut ppu, [0, 0]);
+
+        // The two CPU cycles process six dots between them.
+        cpu_cycle(&mut ppu, 0x2001, Some(mask));
+        let mut dots = 6 + run_dots_to(&mut ppu, [write_dot, 261]);
+        cpu_cycle(&mut ppu, 0x2001, Some(write));
+        dots += run_dots_to(&mut ppu, [10, 0]);
+        assert_eq!(
+            dots - 10,
+            length,
+            "{mask:02X} then {write:02X} at {write_dot}"
+        );
+    }
+}
+
+#[test]
+pub fn writes_are_ignored_while_warming_up() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    bus.set_ppu_warm_up(true);
+    bus.write(0x2000, 0x80);
+    set_address(&mut bus, 0x2100);
+    bus.write(0x2007, 0x55);
+    run_to(&mut bus, [100, 241]);
+    assert!(!bus.nmi());
+
+    // Once the first vblank is over, the writes go through.
+    run_to(&mut bus, [100, 0]);
+    assert_eq!(read_vram(&mut bus, 0x2100), 0x00);
+    bus.write(0x2000, 0x80);
+    set_address(&mut bus, 0x2100);
+    bus.write(0x2007, 0x66);
+    assert_eq!(read_vram(&mut bus, 0x2100), 0x66);
+    run_to(&mut bus, [100, 241]);
+    assert!(bus.nmi());
+}
+
+#[test]
+pub fn reset_keeps_oam_but_disables_nmi() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_oam(&mut bus, &[[0x10, 0x20, 0x23, 0x40]]);
+    bus.write(0x2000, 0x80);
+    run_to(&mut bus, [100, 100]);
+    bus.reset();
+    assert!(bus.rst());
+
+    run_to(&mut bus, [100, 241]);
+    assert!(!bus.rst());
+    assert!(!bus.nmi());
+    let oam: Vec<u8> = (0..4)
+        .map(|addr| {
+            bus.write(0x2003, addr);
+            bus.read(0x2004, false, false).0
+        })
+        .collect();
+    assert_eq!(oam, [0x10, 0x20, 0x23, 0x40]);
+
+    bus.write(0x2000, 0x80);
+    assert!(bus.nmi());
+}
+
+#[test]
+pub fn frames_complete_regardless_of_vblank() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    // Reading PPUSTATUS all the time keeps the vblank flag from ever being seen.
+    let run_frame = |bus: &mut NesBus<Mapper0>| {
+        while !bus.take_frame_complete() {
+            bus.read(0x2002, false, false);
+        }
+    };
+    run_frame(&mut bus);
+    assert_eq!(bus.ppu().frame_count(), 1);
+    assert_eq!(bus.ppu().dot()[1], 0);
+
+    let start = bus.cycles();
+    for frame in 2..6 {
+        run_frame(&mut bus);
+        assert_eq!(bus.ppu().frame_count(), frame);
+    }
+    assert!((119122..=119123).contains(&(bus.cycles() - start)));
+    assert!(!bus.take_frame_complete());
+}
+
+#[test]
+pub fn debug_views_have_no_side_effects() {
+    let chr = (0..0x2000).map(|i| i as u8).collect();
+    let mut bus = ppu_bus(chr);
+    set_address(&mut bus, 0x2005);
+    bus.write(0x2007, 0x11);
+    set_address(&mut bus, 0x2805);
+    bus.write(0x2007, 0x22);
+    set_palette(&mut bus, 0x00, &[0x0F, 0x21]);
+    set_oam(&mut bus, &[[0x10, 0x20, 0x23, 0x40]]);
+    set_address(&mut bus, 0x2005);
+    bus.read(0x2007, false, false);
+
+    // The test cartridge mirrors horizontally.
+    for (index, value) in [(0, 0x11), (1, 0x11), (2, 0x22), (3, 0x22)] {
+        assert_eq!(bus.debug_nametable(index)[5], value);
+    }
+    assert_eq!(bus.debug_pattern_table(0)[0xAB], 0xAB);
+    assert_eq!(bus.debug_pattern_table(1)[0x10], 0x10);
+    assert_eq!(bus.debug_palette()[1], 0x21);
+    assert_eq!(bus.debug_oam()[..4], [0x10, 0x20, 0x23, 0x40]);
+
+    // The read buffer and address are where the CPU left them.
+    assert_eq!(bus.read(0x2007, false, false).0, 0x11);
+}
+
+#[test]
+pub fn peeks_have_no_side_effects() {
+    let chr = (0..0x2000).map(|i| i as u8).collect();
+    let mut bus = ppu_bus(chr);
+    set_address(&mut bus, 0x2805);
+    bus.write(0x2007, 0x22);
+    set_palette(&mut bus, 0x00, &[0x0F, 0x21]);
+    run_to(&mut bus, [10, 241]);
+
+    assert_eq!(bus.peek_cpu(0x2002) & 0x80, 0x80);
+    assert_eq!(bus.peek_cpu(0x3FFA) & 0x80, 0x80);
+    assert!(bus.ppu().is_vblank());
+    assert_eq!(bus.read(0x2002, false, false).0 & 0x80, 0x80);
+    assert!(!bus.ppu().is_vblank());
+    assert_eq!(bus.peek_cpu(0x2002) & 0x80, 0);
+
+    assert_eq!(bus.peek_cpu(0x8000), bus.read(0x8000, false, false).0);
+    assert_eq!(bus.peek_cpu(0xFFFC), bus.read(0xFFFC, false, false).0);
+    assert_eq!(bus.peek_ppu(0x00AB), 0xAB);
+    assert_eq!(bus.peek_ppu(0x2C05), 0x22);
+    assert_eq!(bus.peek_ppu(0x3F01), 0x21);
+    assert_eq!(bus.peek_ppu(0x7F21), 0x21);
+}
+
+#[test]
+pub fn backdrop_follows_v_into_palette_ram() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_palette(&mut bus, 0x00, &[0x0F]);
+    set_palette(&mut bus, 0x14, &[0x24]);
+    set_address(&mut bus, 0x3F14);
+    run_frames(&mut bus, 2);
+    assert!(pixels(&bus).iter().all(|&color| color == 0x24));
+
+    set_address(&mut bus, 0x2000);
+    run_frames(&mut bus, 2);
+    assert!(pixels(&bus).iter().all(|&color| color == 0x0F));
+}
+
+#[test]
+pub fn batched_ppu_matches_the_dot_loop() {
+    for region in [Region::Ntsc, Region::Pal] {
+        batched_ppu_matches_the_dot_loop_in(region);
+    }
+}
+fn batched_ppu_matches_the_dot_loop_in(region: Region) {
+    let chr: Vec<u8> = (0..0x2000).map(|i| ((i * 7) >> 3) as u8).collect();
+    let mut buses = [ppu_bus(chr.clone()), ppu_bus(chr)];
+    buses[1].set_batched_ppu(true);
+    let mut traces = [Vec::new(), Vec::new()];
+
+    for (bus, trace) in buses.iter_mut().zip(&mut traces) {
+        bus.set_region(region);
+        set_palette(bus, 0x00, &[0x0F, 0x11, 0x21, 0x31, 0x0F, 0x12, 0x22, 0x32]);
+        set_palette(bus, 0x10, &[0x0F, 0x15, 0x25, 0x35]);
+        set_address(bus, 0x2000);
+        for i in 0..0x400 {
+            bus.write(0x2007, (i * 13) as u8);
+        }
+        set_oam(
+            bus,
+            &[[40, 3, 0, 30], [60, 5, 0x40, 100], [100, 9, 0x20, 200]],
+        );
+        bus.write(0x2000, 0x88);
+        bus.write(0x2001, 0x1E);
+
+        // Mostly RAM traffic, with a raster effect, status polls and cartridge writes mixed in.
+        for cycle in 0..CPU_CYCLES_PER_FRAME * 3 {
+            match cycle % 5000 {
+                1234 => bus.write(0x2005, (cycle / 100) as u8),
+                2500 => trace.push(bus.read(0x2002, false, false).0),
+                3000 => bus.write(0x8000, 0),
+                _ => trace.push(bus.read(0x0000, false, false).0),
+            }
+            trace.push(bus.nmi() as u8);
+        }
+        bus.catch_up_ppu();
+    }
+
+    assert!(traces[0] == traces[1], "{region:?}");
+    assert!(pixels(&buses[0]) == pixels(&buses[1]), "{region:?}");
+    assert_eq!(buses[0].ppu().dot(), buses[1].ppu().dot(), "{region:?}");
+}
+
+#[test]
+pub fn tall_sprites_flip_across_both_tiles() {
+    let mut chr = vec![0; 0x2000];
+    // Tiles $02 and $03 in the right table: the top half uses color 1, the bottom half color 2.
+    chr[0x1020..0x1028].fill(0xFF);
+    chr[0x1038..0x1040].fill(0xFF);
+    // The left table is filled with color 3, so fetching from the wrong table shows up.
+    chr[..0x1000].fill(0xFF);
+    let mut bus = ppu_bus(chr);
+
+    // $3F10 mirrors the backdrop color.
+    set_palette(&mut bus, 0x10, &[0x0F, 0x11, 0x12, 0x13]);
+    // Tile $03 selects the right table even though PPUCTRL selects the left one for 8x8 sprites.
+    set_oam(&mut bus, &[[50, 0x03, 0x00, 100], [50, 0x03, 0x80, 150]]);
+    bus.write(0x2000, 0x20);
+    bus.write(0x2001, 0x14);
+    run_frames(&mut bus, 2);
+
+    // Sprites show up one line below their Y coordinate.
+    for line in 51..59 {
+        assert_eq!(pixel(&bus, 100, line), 0x11);
+        assert_eq!(pixel(&bus, 150, line), 0x12);
+    }
+    for line in 59..67 {
+        assert_eq!(pixel(&bus, 100, line), 0x12);
+        assert_eq!(pixel(&bus, 150, line), 0x11);
+    }
+    for x in [100, 150] {
+        assert_eq!(pixel(&bus, x, 50), 0x0F);
+        assert_eq!(pixel(&bus, x, 67), 0x0F);
+    }
+}
+
+#[test]
+pub fn sprites_parked_offscreen_stay_hidden() {
+    for control in [0x00, 0x20] {
+        let mut bus = ppu_bus(vec![0xFF; 0x2000]);
+        set_palette(&mut bus, 0x10, &[0x0F, 0x11, 0x12, 0x13]);
+        let sprites: Vec<[u8; 4]> = (0..64)
+            .map(|i| if i % 2 == 0 { [0xF0; 4] } else { [0xFF; 4] })
+            .collect();
+        set_oam(&mut bus, &sprites);
+        bus.write(0x2000, control);
+        bus.write(0x2001, 0x14);
+        run_frames(&mut bus, 2);
+
+        assert!(pixels(&bus).iter().all(|&color| color == 0x0F));
+        run_to(&mut bus, [0, 240]);
+        assert!(!bus.ppu().sprite_overflow());
+    }
+}
+
+#[test]
+pub fn rendering_off_mid_line_shows_the_backdrop_and_freezes_v() {
+    // Tile $01 is solid color 1, and the nametable alternates it with the empty tile $00.
+    let mut chr = vec![0; 0x2000];
+    chr[0x10..0x18].fill(0xFF);
+    let mut bus = ppu_bus(chr);
+    set_address(&mut bus, 0x2000);
+    for i in 0..960 {
+        bus.write(0x2007, (i % 2 == 0) as u8);
+    }
+    set_palette(&mut bus, 0x00, &[0x0F, 0x16]);
+    set_address(&mut bus, 0x2000);
+    bus.write(0x2001, 0x0A);
+    run_frames(&mut bus, 2);
+
+    run_to(&mut bus, [100, 50]);
+    bus.write(0x2001, 0x00);
+    run_to(&mut bus, [172, 50]);
+    bus.write(0x2001, 0x0A);
+    run_to(&mut bus, [0, 60]);
+
+    let stripe = |x: usize| if (x / 8) & 1 == 0 { 0x16 } else { 0x0F };
+    for x in 0..256 {
+        assert_eq!(pixel(&bus, x, 49), stripe(x), "{x}");
+        assert_eq!(pixel(&bus, x, 51), stripe(x), "{x}");
+    }
+    // The line picks up with the tile it stopped at, 72 pixels late.
+    for x in (0..96)
+        .chain(190..256)
+        .filter(|x| (2..6).contains(&(x % 8)))
+    {
+        let expected = if x < 96 { stripe(x) } else { stripe(x - 72) };
+        assert_eq!(pixel(&bus, x, 50), expected, "{x}");
+    }
+    for x in 104..172 {
+        assert_eq!(pixel(&bus, x, 50), 0x0F, "{x}");
+    }
+}
+
+#[test]
+pub fn rendering_off_during_evaluation_drops_later_sprites() {
+    let mut chr = vec![0; 0x2000];
+    chr[0x10..0x18].fill(0xFF);
+    let mut bus = ppu_bus(chr);
+    set_palette(&mut bus, 0x10, &[0x0F, 0x11]);
+    let sprites: Vec<[u8; 4]> = (0..8).map(|i| [49, 0x01, 0x00, i * 24 + 8]).collect();
+    set_oam(&mut bus, &sprites);
+    bus.write(0x2001, 0x14);
+    run_frames(&mut bus, 2);
+
+    // Each sprite in range takes 8 dots to copy, so by dot 92 three of them are.
+    run_to(&mut bus, [90, 49]);
+    bus.write(0x2001, 0x00);
+    run_to(&mut bus, [150, 49]);
+    bus.write(0x2001, 0x14);
+    run_to(&mut bus, [0, 60]);
+
+    for i in 0..8 {
+        let x = i * 24 + 12;
+        let expected = if i < 3 { 0x11 } else { 0x0F };
+        assert_eq!(pixel(&bus, x, 50), expected, "{i}");
+        assert_eq!(pixel(&bus, x, 51), 0x11, "{i}");
+    }
+}
+
+#[test]
+pub fn rendering_off_early_in_a_line_corrupts_oam() {
+    let mut ppu = Ppu::init();
+    cpu_cycle(&mut ppu, 0x2003, Some(0));
+    for i in 0..=255 {
+        cpu_cycle(&mut ppu, 0x2004, Some(i));
+    }
+    let before = *ppu.debug_oam();
+    cpu_cycle(&mut ppu, 0x2001, Some(0x18));
+
+    // Turned off for dot 21, when the PPU is clearing the secondary OAM byte matching row 10.
+    run_dots_to(&mut ppu, [20, 49]);
+    cpu_cycle(&mut ppu, 0x2001, Some(0x00));
+    run_dots_to(&mut ppu, [100, 49]);
+    assert_eq!(ppu.debug_oam(), &before);
+
+    // It only shows up once rendering is back on.
+    cpu_cycle(&mut ppu, 0x2001, Some(0x18));
+    run_dots_to(&mut ppu, [0, 50]);
+    let oam = ppu.debug_oam();
+    for row in 0..32 {
+        let expected = if row == 10 { 0 } else { row };
+        assert_eq!(
+            oam[row * 8..row * 8 + 8],
+            before[expected * 8..expected * 8 + 8],
+            "{row}"
+        );
+    }
+}
+
+#[test]
+pub fn lowest_opaque_sprite_wins() {
+    let mut chr = vec![0; 0x2000];
+    // Tile $01 only has its left half opaque, tile $02 is solid color 3.
+    chr[0x10..0x18].fill(0xF0);
+    chr[0x20..0x30].fill(0xFF);
+    // The background uses the right table, where tile $00 is solid color 1.
+    chr[0x1000..0x1008].fill(0xFF);
+
+    for mask in [0x1E, 0x16] {
+        let mut bus = ppu_bus(chr.clone());
+        set_palette(&mut bus, 0x00, &[0x0F, 0x21]);
+        set_palette(
+            &mut bus,
+            0x10,
+            &[0x0F, 0x11, 0x12, 0x13, 0x0F, 0x15, 0x16, 0x17],
+        );
+        set_oam(
+            &mut bus,
+            &[
+                [50, 0x01, 0x00, 100],
+                [50, 0x02, 0x01, 100],
+                [50, 0x02, 0x20, 150],
+                [50, 0x02, 0x01, 150],
+            ],
+        );
+        bus.write(0x2000, 0x10);
+        bus.write(0x2001, mask);
+        run_frames(&mut bus, 2);
+
+        let background = mask & 0x08 != 0;
+        for line in 51..59 {
+            // Transparent pixels of a lower sprite let the next one through.
+            assert_eq!(pixel(&bus, 101, line), 0x11);
+            assert_eq!(pixel(&bus, 105, line), 0x17);
+            // An opaque sprite behind the background still hides the ones after it.
+            let hidden = if background { 0x21 } else { 0x13 };
+            assert_eq!(pixel(&bus, 152, line), hidden);
+        }
+    }
+}
+
+#[test]
+pub fn ninth_sprite_sets_overflow_during_evaluation() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_oam(&mut bus, &[[50, 0, 0, 0]; 9]);
+    bus.write(0x2001, 0x18);
+    run_frames(&mut bus, 1);
+
+    // Eight sprites take 64 dots to copy, so the ninth is found at dot 129.
+    run_to(&mut bus, [126, 50]);
+    assert!(!bus.ppu().sprite_overflow());
+    run_to(&mut bus, [130, 50]);
+    assert!(bus.ppu().sprite_overflow());
+
+    // The flag is cleared at the start of the pre-render line.
+    run_to(&mut bus, [338, 260]);
+    assert!(bus.ppu().sprite_overflow());
+    run_to(&mut bus, [2, 261]);
+    assert!(!bus.ppu().sprite_overflow());
+}
+
+#[test]
+pub fn overflow_check_reads_the_wrong_bytes() {
+    // Sprite 8 is off this line, so sprite 9 is checked by its tile number, sprite 10 by its attributes...
+    let mut sprites = [[50, 0, 0, 0]; 11];
+    sprites[8] = [0xFF; 4];
+    sprites[9] = [0xFF, 50, 0xFF, 0xFF];
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_oam(&mut bus, &sprites);
+    bus.write(0x2001, 0x18);
+    run_frames(&mut bus, 1);
+    run_to(&mut bus, [0, 100]);
+    // Only eight sprites are on line 50, but the tile number of sprite 9 looks like one.
+    assert!(bus.ppu().sprite_overflow());
+
+    // Sprites 9 and 10 are on line 60, but their Y coordinates are never looked at.
+    sprites[9] = [60, 0xFF, 0xFF, 0xFF];
+    sprites[10] = [60, 0xFF, 0xE3, 0xFF];
+    for sprite in &mut sprites[..8] {
+        sprite[0] = 60;
+    }
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_oam(&mut bus, &sprites);
+    bus.write(0x2001, 0x18);
+    run_frames(&mut bus, 1);
+    run_to(&mut bus, [0, 100]);
+    assert!(!bus.ppu().sprite_overflow());
+}
+
+#[test]
+pub fn sprite_zero_hit_exclusions() {
+    // Solid tiles everywhere, for the background and the sprite.
+    let chr = vec![0xFF; 0x2000];
+    let hit_dot = |sprite_x: u8, mask: u8| {
+        let mut bus = ppu_bus(chr.clone());
+        set_oam(&mut bus, &[[50, 0x00, 0x00, sprite_x]]);
+        bus.write(0x2001, mask);
+        run_frames(&mut bus, 1);
+        run_to(&mut bus, [0, 100]);
+        bus.ppu().sprite_zero_hit_dot()
+    };
+
+    // A hit on the first pixel shows up on dot 2.
+    assert_eq!(hit_dot(0, 0x1E), Some([2, 51]));
+    assert_eq!(hit_dot(254, 0x1E), Some([256, 51]));
+    assert_eq!(hit_dot(255, 0x1E), None);
+    // Clipping either layer in the left column delays the hit to x = 8.
+    assert_eq!(hit_dot(4, 0x1A), Some([10, 51]));
+    assert_eq!(hit_dot(4, 0x1C), Some([10, 51]));
+    assert_eq!(hit_dot(0, 0x18), None);
+    assert_eq!(hit_dot(0, 0x16), None);
+    assert_eq!(hit_dot(0, 0x0E), None);
+}
+
+#[test]
+pub fn greyscale_and_emphasis_reach_the_output() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_palette(&mut bus, 0x00, &[0x16]);
+    bus.write(0x2001, 0x08);
+    run_frames(&mut bus, 1);
+    assert_eq!(pixels(&bus)[100 * WIDTH], 0x16);
+
+    // Greyscale keeps the brightness of the color, and blue and red emphasis end up in bits 8 and 6.
+    bus.write(0x2001, 0b1010_1001);
+    run_frames(&mut bus, 1);
+    assert_eq!(pixels(&bus)[100 * WIDTH], 0b101 << 6 | 0x10);
+}
+
+#[test]
+pub fn palette_reads_buffer_the_nametable_underneath() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_address(&mut bus, 0x2F00);
+    bus.write(0x2007, 0xAB);
+    bus.write(0x2007, 0xCD);
+    set_palette(&mut bus, 0x00, &[0x21, 0x16]);
+
+    set_address(&mut bus, 0x3F00);
+    assert_eq!(bus.read(0x2007, false, false).0, 0x21);
+    assert_eq!(bus.read(0x2007, false, false).0, 0x16);
+    // Leaving palette space returns what the last palette read put in the buffer.
+    set_address(&mut bus, 0x2000);
+    assert_eq!(bus.read(0x2007, false, false).0, 0xCD);
+    assert_eq!(bus.read(0x2007, false, false).0, 0x00);
+
+    // The greyscale bit also applies to colors read back.
+    bus.write(0x2001, 0x01);
+    set_address(&mut bus, 0x3F00);
+    assert_eq!(bus.read(0x2007, false, false).0, 0x20);
+    set_address(&mut bus, 0x2000);
+    assert_eq!(bus.read(0x2007, false, false).0, 0xAB);
+}
+
+#[test]
+pub fn palette_space_mirrors_every_address() {
+    // Every fourth sprite entry is the background entry underneath.
+    let mirror = |addr: usize| match addr % 32 {
+        entry @ (0x10 | 0x14 | 0x18 | 0x1C) => entry - 0x10,
+        entry => entry,
+    };
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    let mut expected = [0; 32];
+    for addr in 0..256 {
+        let color = (addr * 37 + 11) as u8;
+        set_palette(&mut bus, addr as u8, &[color]);
+        expected[mirror(addr)] = color & 0x3F;
+    }
+
+    // Only six bits are stored, the top two come from the last write to $2006.
+    for greyscale in [false, true] {
+        bus.write(0x2001, greyscale as u8);
+        let mask = if greyscale { 0x30 } else { 0x3F };
+        for addr in 0..256 {
+            set_address(&mut bus, 0x3F00 | addr as u16);
+            let color = bus.read(0x2007, false, false).0;
+            let open_bus = addr as u8 & 0xC0;
+            assert_eq!(color, open_bus | expected[mirror(addr)] & mask, "{addr:x}");
+        }
+    }
+    assert_eq!(bus.ppu().debug_palette(), &expected);
+}
+
+#[test]
+pub fn status_read_races_vblank() {
+    // The dot the PPU processes on the read cycle, relative to the one that sets vblank,
+    // whether the read sees the flag and whether the CPU gets an NMI that frame.
+    let cases = [
+        (-2, false, true),
+        (-1, false, false),
+        (0, true, false),
+        (1, true, false),
+        (2, true, true),
+    ];
+    let vblank_start = 241 * 341 + 1;
+
+    for (offset, read_set, nmi) in cases {
+        let mut ppu = Ppu::init();
+        cpu_cycle(&mut ppu, 0x2000, Some(0x80));
+        let target = (vblank_start + offset) as u32;
+        let misalignment = (target - ppu_dot_index(&ppu)) % 3;
+        for _ in 0..misalignment {
+            ppu.cycle_alone(&mut PpuBus::init(), &mut CpuBus::init());
+        }
+        let mut nmi_seen = false;
+        while ppu_dot_index(&ppu) < target {
+            nmi_seen |= cpu_cycle(&mut ppu, 0x8000, None).nmi();
+        }
+
+        let status = cpu_cycle(&mut ppu, 0x2002, None).data();
+        assert_eq!(status & 0x80 != 0, read_set, "read at {offset}");
+        for _ in 0..100 {
+            nmi_seen |= cpu_cycle(&mut ppu, 0x8000, None).nmi();
+        }
+        assert_eq!(nmi_seen, nmi, "NMI with a read at {offset}");
+
+        // Only a read before the race window leaves the flag to be set.
+        let status = cpu_cycle(&mut ppu, 0x2002, None).data();
+        assert_eq!(status & 0x80 != 0, offset == -2);
+    }
+}
+
+#[test]
+pub fn toggling_nmi_enable_during_vblank() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    let mut nmis = Nmis::init();
+    nmis.write(&mut bus, 0x2000, 0x80);
+    nmis.run_to(&mut bus, 245);
+    assert_eq!(nmis.count, 1);
+
+    // Dropping and raising the enable bit while the flag is set makes another edge.
+    nmis.write(&mut bus, 0x2000, 0x00);
+    nmis.write(&mut bus, 0x2000, 0x80);
+    assert_eq!(nmis.count, 2);
+    nmis.write(&mut bus, 0x2000, 0x80);
+    assert_eq!(nmis.count, 2);
+    nmis.write(&mut bus, 0x2000, 0x00);
+    nmis.run_to(&mut bus, 246);
+    nmis.write(&mut bus, 0x2000, 0x80);
+    assert_eq!(nmis.count, 3);
+
+    // Once $2002 has cleared the flag there's nothing left to trigger on.
+    bus.read(0x2002, false, false);
+    nmis.write(&mut bus, 0x2000, 0x00);
+    nmis.write(&mut bus, 0x2000, 0x80);
+    assert_eq!(nmis.count, 3);
+
+    // Enabling NMI in the middle of vblank fires right away.
+    nmis.write(&mut bus, 0x2000, 0x00);
+    nmis.run_to(&mut bus, 0);
+    nmis.run_to(&mut bus, 250);
+    assert_eq!(nmis.count, 3);
+    nmis.write(&mut bus, 0x2000, 0x80);
+    assert!(bus.nmi());
+    assert_eq!(nmis.count, 4);
+}
+
+#[test]
+pub fn io_latch_fills_unused_bits() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    bus.write(0x2005, 0xA5);
+    for addr in [0x2000, 0x2001, 0x2003, 0x2005, 0x2006, 0x3FFE] {
+        assert_eq!(bus.read(addr, false, false).0, 0xA5);
+    }
+    assert_eq!(bus.read(0x2002, false, false).0 & 0x1F, 0x05);
+
+    // Palette reads only drive the low six bits.
+    set_palette(&mut bus, 0x00, &[0x16]);
+    set_address(&mut bus, 0x3F00);
+    bus.write(0x2005, 0xFF);
+    assert_eq!(bus.read(0x2007, false, false).0, 0xD6);
+}
+
+#[test]
+pub fn io_latch_decays_per_bit() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_palette(&mut bus, 0x00, &[0x15]);
+    set_address(&mut bus, 0x3F00);
+    bus.write(0x2005, 0xFF);
+    run_frames(&mut bus, 20);
+    assert_eq!(bus.read(0x2000, false, false).0, 0xFF);
+
+    // The palette read refreshes the low bits, the top two keep decaying.
+    bus.read(0x2007, false, false);
+    run_frames(&mut bus, 20);
+    assert_eq!(bus.read(0x2000, false, false).0, 0x15);
+    run_frames(&mut bus, 20);
+    assert_eq!(bus.read(0x2000, false, false).0, 0x00);
+}
+
+#[test]
+pub fn oam_reads_follow_sprite_evaluation() {
+    let mut ppu = Ppu::init();
+    let sprites = [50, 0x11, 0x02, 0x33, 50, 0x22, 0x01, 0x44];
+    cpu_cycle(&mut ppu, 0x2003, Some(0));
+    for byte in sprites.into_iter().chain([0xFF; 248]) {
+        cpu_cycle(&mut ppu, 0x2004, Some(byte));
+    }
+    cpu_cycle(&mut ppu, 0x2001, Some(0x18));
+
+    // The dot on line 50 the read cycle processes, and what it reads.
+    let cases = [
+        (20, 0xFF),
+        (65, 50),
+        (67, 0x11),
+        (71, 0x33),
+        (75, 0x22),
+        (81, 0xFF),
+        (257, 50),
+        (260, 0x33),
+        (266, 0x22),
+        (268, 0x44),
+        (300, 0xFF),
+        (330, 50),
+    ];
+    for (dot, value) in cases {
+        while ppu.dot() != [dot, 50] {
+            ppu.cycle_alone(&mut PpuBus::init(), &mut CpuBus::init());
+        }
+        assert_eq!(cpu_cycle(&mut ppu, 0x2004, None).data(), value, "dot {dot}");
+    }
+}
+
+#[test]
+pub fn oam_writes_during_rendering_only_bump_the_address() {
+    let mut bus = ppu_bus(vec![0; 0x2000]);
+    set_oam(&mut bus, &[[0x10, 0x20, 0x23, 0x40]]);
+    bus.write(0x2001, 0x18);
+    run_to(&mut bus, [100, 50]);
+    bus.write(0x2003, 0x02);
+    bus.write(0x2004, 0x99);
+    bus.write(0x2004, 0x99);
+
+    // Back in vblank, OAM is untouched and the next write lands two sprites on.
+    run_to(&mut bus, [100, 245]);
+    bus.write(0x2004, 0xFF);
+    let oam: Vec<u8> = (0..12)
+        .map(|addr| {
+            bus.write(0x2003, addr);
+            bus.read(0x2004, false, false).0
+        })
+        .collect();
+    assert_eq!(oam[..4], [0x10, 0x20, 0x23, 0x40]);
+    // Bits 2 to 4 of the attribute byte read back as zero.
+    assert_eq!(oam[10], 0xE3);
+}
+
+// Counts rising edges of the NMI line at the end of each CPU cycle, like the CPU does.
+struct Nmis {
+    line: bool,
+    count: u32,
+}
+impl Nmis {
+    fn init() -> Self {
+        Self {
+            line: false,
+            count: 0,
+        }
+    }
+    fn watch(&mut self, bus: &NesBus<Mapper0>) {
+        let line = bus.nmi();
+        if line && !self.line {
+            self.count += 1;
+        }
+        self.line = line;
+    }
+    fn write(&mut self, bus: &mut NesBus<Mapper0>, addr: u16, data: u8) {
+        bus.write(addr, data);
+        self.watch(bus);
+    }
+    fn run_to(&mut self, bus: &mut NesBus<Mapper0>, line: u16) {
+        while bus.ppu().dot()[1] != line {
+            bus.read(0x8000, false, false);
+            self.watch(bus);
+        }
+    }
+}
+
+fn ppu_bus(chr: Vec<u8>) -> NesBus<Mapper0> {
+    let src = RomBuilder::new().chr(chr).build();
+    let rom = Rom::parse(&src).unwrap();
+    NesBus::new(Mapper0::new(&rom))
+}
+fn set_address(bus: &mut NesBus<Mapper0>, addr: u16) {
+    bus.write(0x2006, (addr >> 8) as u8);
+    bus.write(0x2006, addr as u8);
+}
+fn read_vram(bus: &mut NesBus<Mapper0>, addr: u16) -> u8 {
+    set_address(bus, addr);
+    bus.read(0x2007, false, false);
+    bus.read(0x2007, false, false).0
+}
+fn set_palette(bus: &mut NesBus<Mapper0>, start: u8, colors: &[u8]) {
+    set_address(bus, 0x3F00 | start as u16);
+    for &color in colors {
+        bus.write(0x2007, color);
+    }
+}
+// Unused sprites are moved below the screen.
+fn set_oam(bus: &mut NesBus<Mapper0>, sprites: &[[u8; 4]]) {
+    bus.write(0x2003, 0);
+    for i in 0..64 {
+        let sprite = sprites.get(i).copied().unwrap_or([0xFF; 4]);
+        for byte in sprite {
+            bus.write(0x2004, byte);
+        }
+    }
+}
+fn run_frames(bus: &mut NesBus<Mapper0>, frames: usize) {
+    for _ in 0..frames * CPU_CYCLES_PER_FRAME {
+        bus.read(0x8000, false, false);
+    }
+}
+// Runs until the next dot the PPU processes is the given one or at most two after it.
+fn run_to(bus: &mut NesBus<Mapper0>, [x, y]: [u16; 2]) {
+    loop {
+        let [dot_x, dot_y] = bus.ppu().dot();
+        if dot_y == y && (x..x + 3).contains(&dot_x) {
+            return;
+        };
+        bus.read(0x8000, false, false);
+    }
+}
+// One CPU cycle of a PPU on its own, with the CPU access seen on the first of its three dots.
+fn cpu_cycle(ppu: &mut Ppu, addr: u16, write: Option<u8>) -> CpuBus {
+    let mut cpu = CpuBus::init();
+    let mut bus = PpuBus::init();
+    cpu.set_address(addr);
+    cpu.set_read(write.is_none());
+    if let Some(data) = write {
+        cpu.set_data(data);
+    }
+    ppu.cycle(&mut bus, &mut cpu);
+    ppu.cycle_alone(&mut bus, &mut cpu);
+    ppu.cycle_alone(&mut bus, &mut cpu);
+    cpu
+}
+// Runs a PPU on its own until the given dot is next, returning how many dots that took.
+fn run_dots_to(ppu: &mut Ppu, dot: [u16; 2]) -> u32 {
+    let mut dots = 0;
+    loop {
+        ppu.cycle_alone(&mut PpuBus::init(), &mut CpuBus::init());
+        dots += 1;
+        if ppu.dot() == dot {
+            return dots;
+        };
+    }
+}
+fn ppu_dot_index(ppu: &Ppu) -> u32 {
+    let [x, y] = ppu.dot();
+    y as u32 * 341 + x as u32
+}
+fn pixel(bus: &NesBus<Mapper0>, x: usize, y: usize) -> u8 {
+    pixels(bus)[y * WIDTH + x] as u8
+}
+fn pixels(bus: &NesBus<Mapper0>) -> &[u32] {
+    &bus.ppu().pixels().0
+}
@@ -0,0 +1,41 @@
+use nessy::apu::recorder::AudioRecorder;
+use std::io::Cursor;
+
+#[test]
+pub fn wav_header_fields() {
+    let mut recorder = AudioRecorder::from_writer(Cursor::new(Vec::new()), 48000).unwrap();
+    let ramp: Vec<f32> = (0..1000).map(|i| i as f32 / 500.0 - 1.0).collect();
+    recorder.push_samples(&ramp).unwrap();
+    recorder.push_samples(&ramp[..10]).unwrap();
+    assert_eq!(recorder.samples(), 1010);
+    let wav = recorder.finish().unwrap().into_inner();
+
+    let u16_at = |i: usize| u16::from_le_bytes([wav[i], wav[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([wav[i], wav[i + 1], wav[i + 2], wav[i + 3]]);
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u32_at(4) as usize, wav.len() - 8);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(16), 16);
+    assert_eq!(u16_at(20), 1);
+    assert_eq!(u16_at(22), 1);
+    assert_eq!(u32_at(24), 48000);
+    assert_eq!(u32_at(28), 96000);
+    assert_eq!(u16_at(32), 2);
+    assert_eq!(u16_at(34), 16);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32_at(40), 2020);
+    assert_eq!(wav.len(), 44 + 2020);
+}
+
+#[test]
+pub fn samples_are_clamped_pcm() {
+    let mut recorder = AudioRecorder::from_writer(Cursor::new(Vec::new()), 44100).unwrap();
+    recorder.push_samples(&[0.0, 1.0, -1.0, 2.0, -2.0, 0.5]).unwrap();
+    let wav = recorder.finish().unwrap().into_inner();
+
+    let samples: Vec<i16> = wav[44..]
+        .chunks(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(samples, [0, 32767, -32767, 32767, -32767, 16383]);
+}
@@ -0,0 +1,87 @@
+use nessy::{apu::resampler::Resampler, region::Region};
+use std::f64::consts::PI;
+
+const SAMPLE_RATE: f64 = Region::Ntsc.cpu_clock_hz();
+const OUTPUT_RATE: f64 = 48000.0;
+const FRAME_RATE: f64 = 60.0;
+
+#[test]
+pub fn square_wave_is_band_limited() {
+    let mut resampler = Resampler::new(SAMPLE_RATE, OUTPUT_RATE);
+    let mut frames = Vec::new();
+    let mut input = 0u64;
+    for frame in 1..=15 {
+        let end = (frame as f64 * SAMPLE_RATE / FRAME_RATE) as u64;
+        for i in input..end {
+            resampler.push_sample(square(i as f64 / SAMPLE_RATE));
+        }
+        input = end;
+
+        let mut out = Vec::new();
+        resampler.drain(&mut out);
+        frames.push(out);
+    }
+
+    // The first frame is shortened by the filter delay.
+    let per_frame = OUTPUT_RATE / FRAME_RATE;
+    for frame in &frames[1..] {
+        assert!((frame.len() as f64 - per_frame).abs() <= 1.0, "{}", frame.len());
+    }
+
+    // 4800 samples hold exactly 44 periods, so each harmonic lands on one bin.
+    let samples: Vec<f32> = frames.concat();
+    let samples = &samples[samples.len() - 4800..];
+    let spectrum = power_spectrum(samples, 1920);
+    let total: f64 = spectrum.iter().sum();
+    let stray: f64 = spectrum
+        .iter()
+        .enumerate()
+        .filter(|&(bin, _)| bin % 44 != 0)
+        .map(|(_, power)| power)
+        .sum();
+    assert!(stray / total < 1e-4, "{}", stray / total);
+
+    // The fundamental keeps its amplitude of 4/pi.
+    let fundamental = spectrum[44].sqrt() * 2.0 / samples.len() as f64;
+    assert!((fundamental - 4.0 / PI).abs() < 0.01, "{fundamental}");
+}
+
+#[test]
+pub fn constant_input_passes_unchanged() {
+    let mut resampler = Resampler::new(SAMPLE_RATE, 44100.0);
+    for _ in 0..100_000 {
+        resampler.push_sample(0.5);
+    }
+    let mut out = Vec::new();
+    resampler.drain(&mut out);
+    assert!(out.iter().all(|&s| (s - 0.5).abs() < 1e-3));
+}
+
+fn square(time: f64) -> f32 {
+    if (time * 440.0).fract() < 0.5 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+// Power of the first `bins` DFT bins.
+fn power_spectrum(samples: &[f32], bins: usize) -> Vec<f64> {
+    let n = samples.len();
+    let (cos, sin): (Vec<f64>, Vec<f64>) = (0..n)
+        .map(|i| (2.0 * PI * i as f64 / n as f64).sin_cos())
+        .map(|(sin, cos)| (cos, sin))
+        .unzip();
+
+    (0..bins)
+        .map(|k| {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (i, &s) in samples.iter().enumerate() {
+                let t = k * i % n;
+                re += s as f64 * cos[t];
+                im -= s as f64 * sin[t];
+            }
+            re * re + im * im
+        })
+        .collect()
+}
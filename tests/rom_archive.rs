@@ -0,0 +1,46 @@
+use nessy::rom::{
+    archive::{self, ArchiveError},
+    builder::RomBuilder,
+};
+use std::io::{Cursor, Write};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in files {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+pub fn extracts_the_first_nes_file() {
+    let rom = RomBuilder::new().reset_vector(0x8000).build();
+    let other = RomBuilder::new().reset_vector(0x9000).build();
+    let src = zip(&[
+        ("readme.txt", b"not a rom"),
+        ("games/Game (U).NES", &rom),
+        ("other.nes", &other),
+    ]);
+    assert!(archive::is_zip(&src));
+    assert!(!archive::is_zip(&rom));
+
+    let (name, data) = archive::extract_nes(&src).unwrap();
+    assert_eq!(name, "games/Game (U).NES");
+    assert_eq!(data, rom);
+}
+
+#[test]
+pub fn reports_archives_without_roms() {
+    let src = zip(&[("readme.txt", b"not a rom")]);
+    assert!(matches!(
+        archive::extract_nes(&src),
+        Err(ArchiveError::NoRom)
+    ));
+    assert!(matches!(
+        archive::extract_nes(b"PK\x03\x04 broken"),
+        Err(ArchiveError::Zip(_))
+    ));
+}
@@ -0,0 +1,99 @@
+use cpu_6502::{Bus, Cpu};
+use nes_rom_parser::Rom;
+use nessy::{
+    mapper::{mapper0::Mapper0, try_get_mapper},
+    nesbus::NesBus,
+    region::Region,
+    rom::{self, builder::RomBuilder},
+};
+
+#[test]
+pub fn header_fields() {
+    let src = RomBuilder::new()
+        .mapper(66)
+        .prg_size(0x4000 * 3)
+        .chr_size(0x2000 * 5)
+        .vertical_mirroring(true)
+        .battery(true)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+
+    assert_eq!(rom.header.mapper, 66);
+    assert!(rom.header.vertical_mirroring);
+    assert_eq!(rom.prg_rom.len(), 0x4000 * 3);
+    assert_eq!(rom.chr_rom.len(), 0x2000 * 5);
+}
+
+#[test]
+pub fn region_from_header() {
+    let pal = RomBuilder::new().region(Region::Pal).build();
+    assert_eq!(rom::region(&pal), Region::Pal);
+    let ntsc = RomBuilder::new().build();
+    assert_eq!(rom::region(&ntsc), Region::Ntsc);
+
+    // iNES 1.0 images flag PAL in bit 0 of byte 9.
+    let mut ines = ntsc.clone();
+    ines[7] = 0;
+    ines[9] = 1;
+    assert_eq!(rom::region(&ines), Region::Pal);
+}
+
+#[test]
+pub fn cpu_addresses() {
+    let small = RomBuilder::new()
+        .prg_size(0x4000)
+        .write_cpu(0xC000, &[1, 2, 3])
+        .reset_vector(0xC000)
+        .build();
+    let rom = Rom::parse(&small).unwrap();
+    assert_eq!(&rom.prg_rom[0..3], &[1, 2, 3]);
+    assert_eq!(&rom.prg_rom[0x3FFC..], &[0x00, 0xC0, 0, 0]);
+
+    let large = RomBuilder::new()
+        .prg_size(0x20000)
+        .write_cpu(0x8000, &[4, 5])
+        .build();
+    let rom = Rom::parse(&large).unwrap();
+    assert_eq!(&rom.prg_rom[0x18000..0x18002], &[4, 5]);
+}
+
+#[test]
+pub fn generated_program() {
+    let program = [
+        0xA9, 0x42, // LDA #$42
+        0x85, 0x10, // STA $10
+        0x4C, 0x04, 0x80, // JMP $8004
+    ];
+    let src = RomBuilder::new()
+        .write_cpu(0x8000, &program)
+        .reset_vector(0x8000)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+
+    let mut cpu = Cpu::new();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+
+    // Run reset sequence
+    cpu.exec(&mut bus);
+    for _ in 0..3 {
+        cpu.exec(&mut bus);
+    }
+
+    assert_eq!(cpu.pc(), 0x8004);
+    assert_eq!(bus.ram()[0x10], 0x42);
+}
+
+#[test]
+pub fn trainer_is_loaded_at_7000() {
+    let trainer: Vec<u8> = (0..512).map(|i| (i * 7) as u8).collect();
+    let src = RomBuilder::new().trainer(&trainer).build();
+    let rom = Rom::parse(&src).unwrap();
+    assert_eq!(rom::trainer(&src), Some(&trainer[..]));
+
+    let mut bus = NesBus::new(try_get_mapper(&rom, rom::trainer(&src)).unwrap());
+
+    for (i, &byte) in trainer.iter().enumerate() {
+        assert_eq!(bus.read(0x7000 + i as u16, false, false).0, byte);
+    }
+    assert_eq!(bus.read(0x6FFF, false, false).0, 0);
+}
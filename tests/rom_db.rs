@@ -0,0 +1,63 @@
+use nes_rom_parser::Rom;
+use nessy::rom::{
+    builder::RomBuilder,
+    db::{Db, DbError},
+    header::Header,
+};
+
+#[test]
+pub fn fixes_misheadered_images() {
+    let db = Db::load("test_roms/nes20db_fixture.xml").unwrap();
+    let mut src = RomBuilder::new()
+        .write_cpu(0x8000, &[0xEA])
+        .reset_vector(0x8000)
+        .build();
+    // An iNES 1.0 header claiming NROM with horizontal mirroring.
+    src[6] = 0;
+    src[7] = 0;
+    src[8..16].fill(0);
+
+    let changes = db.apply(&mut src).unwrap();
+    assert_eq!(
+        changes,
+        [
+            "mapper: 0 -> 1",
+            "submapper: 0 -> 5",
+            "PRG RAM size: 8192 -> 0",
+            "PRG NVRAM size: 0 -> 8192",
+            "vertical mirroring: 0 -> 1",
+            "battery: 0 -> 1",
+            "timing: 0 -> 1",
+            "expansion device: 0 -> 1",
+        ]
+    );
+    let rom = Rom::parse(&src).unwrap();
+    assert_eq!(rom.header.mapper, 1);
+    assert!(rom.header.vertical_mirroring);
+
+    // Applying it again finds nothing left to fix.
+    assert_eq!(db.apply(&mut src), Some(vec![]));
+    let mut unknown = RomBuilder::new().build();
+    assert_eq!(db.apply(&mut unknown), None);
+}
+
+#[test]
+pub fn reads_vs_system_entries() {
+    let db = Db::load("test_roms/nes20db_fixture.xml").unwrap();
+    let header = db.get(0x12345678).unwrap();
+    assert_eq!((header.mapper, header.four_screen), (99, true));
+    assert_eq!((header.console_type, header.console_info), (1, 0x02));
+    assert_eq!((header.chr_rom_size, header.chr_ram_size), (0, 0x2000));
+    assert_eq!(Header::parse(&header.to_bytes()).as_ref(), Ok(header));
+}
+
+#[test]
+pub fn rejects_malformed_entries() {
+    let xml = "<nes20db>\n<game>\n<rom crc32=\"XYZ\"/>\n</game>\n</nes20db>";
+    assert!(matches!(Db::parse(xml), Err(DbError::Invalid(17))));
+    let no_hash = "<game><pcb mapper=\"1\" submapper=\"0\" mirroring=\"H\" battery=\"0\"/></game>";
+    let end = no_hash.find("</game>").unwrap();
+    assert!(matches!(Db::parse(no_hash), Err(DbError::Invalid(offset)) if offset == end));
+    let huge_mapper = "<game><rom crc32=\"1\"/><pcb mapper=\"4096\" submapper=\"0\" mirroring=\"H\" battery=\"0\"/></game>";
+    assert!(matches!(Db::parse(huge_mapper), Err(DbError::Invalid(_))));
+}
@@ -0,0 +1,58 @@
+use nes_rom_parser::Rom;
+use nessy::rom::{
+    builder::RomBuilder,
+    hash::{crc32, sha1, RomHashes},
+};
+
+#[test]
+pub fn hashes_match_reference_values() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32(&[]), 0);
+    assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    assert_eq!(
+        hex(&sha1(b"abc")),
+        "a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+    // Long enough to take several blocks.
+    assert_eq!(
+        hex(&sha1(&[b'a'; 1000])),
+        "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+    );
+}
+
+#[test]
+pub fn hashes_leave_out_header_and_trainer() {
+    let chr: Vec<u8> = (0..0x2000).map(|i| i as u8).collect();
+    let builder = || {
+        RomBuilder::new()
+            .chr(chr.clone())
+            .write_cpu(0x8000, &[0xA9, 0x42])
+            .reset_vector(0x8000)
+    };
+    let src = builder().build();
+    let rom = Rom::parse(&src).unwrap();
+
+    assert_eq!(rom.prg_crc32(), 0xD7354354);
+    assert_eq!(rom.chr_crc32(), 0xB6675307);
+    assert_eq!(rom.rom_crc32(), 0x66C6A862);
+    assert_eq!(
+        hex(&rom.prg_sha1()),
+        "edd683264314a048f0d2b1798f15e3495df583cb"
+    );
+    assert_eq!(
+        hex(&rom.chr_sha1()),
+        "ecca46e1a1d0a6012713b09a870d84f695b6d9b0"
+    );
+    assert_eq!(
+        hex(&rom.rom_sha1()),
+        "13ab1acc94c337dc0c4e42ea829b527a7b0c68d4"
+    );
+
+    let src = builder().mapper(1).trainer(&[0xFF; 512]).build();
+    let rom = Rom::parse(&src).unwrap();
+    assert_eq!(rom.rom_crc32(), 0x66C6A862);
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
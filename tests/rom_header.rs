@@ -0,0 +1,196 @@
+use nes_rom_parser::Rom;
+use nessy::rom::{
+    builder::RomBuilder,
+    expansion::ExpansionDevice,
+    header::{assemble, Header, HeaderError, Layout, PLAYCHOICE},
+};
+
+fn header() -> Header {
+    Header {
+        mapper: 0,
+        submapper: 0,
+        prg_rom_size: 0x8000,
+        chr_rom_size: 0x2000,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+        vertical_mirroring: false,
+        battery: false,
+        trainer: false,
+        four_screen: false,
+        console_type: 0,
+        timing: 0,
+        console_info: 0,
+        misc_roms: 0,
+        expansion_device: ExpansionDevice::Unspecified,
+    }
+}
+
+#[test]
+pub fn round_trips_through_bytes() {
+    let rom_sizes = [(0, 0), (0x4000, 0x2000), (0x4000 * 0xEFF, 0x2000 * 0x123)];
+    // These need exponent-multiplier form.
+    let odd_sizes = [(0x4000 * 0x1000, 0x3000), (0x200, 1), (7 << 20, 5 << 40)];
+    let ram_sizes = [0, 128, 0x2000, 0x10000 << 5];
+    for (prg_rom_size, chr_rom_size) in rom_sizes.into_iter().chain(odd_sizes) {
+        for (i, ram) in ram_sizes.into_iter().enumerate() {
+            for flags in 0..16u8 {
+                let header = Header {
+                    mapper: 0x111 * flags as u16,
+                    submapper: flags,
+                    prg_rom_size,
+                    chr_rom_size,
+                    prg_ram_size: ram,
+                    prg_nvram_size: ram_sizes[(i + 1) % 4],
+                    chr_ram_size: ram_sizes[(i + 2) % 4],
+                    chr_nvram_size: ram_sizes[(i + 3) % 4],
+                    vertical_mirroring: flags & 1 != 0,
+                    battery: flags & 2 != 0,
+                    trainer: flags & 4 != 0,
+                    four_screen: flags & 8 != 0,
+                    console_type: flags % 4,
+                    timing: flags / 4,
+                    console_info: flags * 17,
+                    misc_roms: flags % 3,
+                    expansion_device: ExpansionDevice::from_byte(flags * 3 + 1).unwrap(),
+                };
+                assert_eq!(Header::parse(&header.to_bytes()), Ok(header));
+            }
+        }
+    }
+}
+
+#[test]
+pub fn sizes_use_the_simplest_encoding() {
+    let bytes = Header {
+        prg_rom_size: 0x4000 * 0x234,
+        chr_rom_size: 0x3000,
+        prg_ram_size: 0x2000,
+        chr_nvram_size: 0x8000,
+        ..header()
+    }
+    .to_bytes();
+    assert_eq!(bytes[4], 0x34);
+    // 0x3000 is 2^12 * 3, which only the exponent form can express.
+    assert_eq!((bytes[5], bytes[9]), (12 << 2 | 1, 0xF2));
+    assert_eq!((bytes[10], bytes[11]), (0x07, 0x90));
+}
+
+#[test]
+pub fn reads_ines_headers() {
+    let mut src = RomBuilder::new().mapper(0x42).battery(true).build();
+    src[7] &= 0xF3;
+    src[8..16].copy_from_slice(b"Dude\0\0\0\0");
+    src[9] = 1;
+
+    let header = Header::parse(&src).unwrap();
+    assert_eq!(
+        (header.mapper, header.submapper, header.timing),
+        (0x42, 0, 1)
+    );
+    assert_eq!((header.prg_ram_size, header.prg_nvram_size), (0, 0x2000));
+    assert_eq!(header.console_info, 0);
+
+    assert_eq!(Header::parse(&src[..15]), Err(HeaderError::Truncated));
+    assert_eq!(
+        Header::parse(b"NES\0 padded to sixteen"),
+        Err(HeaderError::Magic)
+    );
+}
+
+#[test]
+pub fn assembles_images() {
+    let prg: Vec<u8> = (0..0x4000).map(|i| i as u8).collect();
+    let chr = [0xAA; 0x2000];
+    let trainer = [0x55; 512];
+    let misc = [1, 2, 3];
+    let header = Header {
+        mapper: 3,
+        vertical_mirroring: true,
+        ..header()
+    };
+
+    let src = assemble(&header, &prg, &chr, Some(&trainer), &misc);
+    assert_eq!(src.len(), 16 + 512 + 0x4000 + 0x2000 + 3);
+    assert_eq!(&src[src.len() - 3..], &misc);
+    let parsed = Header::parse(&src).unwrap();
+    assert!(parsed.trainer);
+    assert_eq!((parsed.prg_rom_size, parsed.chr_rom_size), (0x4000, 0x2000));
+
+    let rom = Rom::parse(&src).unwrap();
+    assert_eq!(rom.header.mapper, 3);
+    assert_eq!(rom.prg_rom, &prg[..]);
+    assert_eq!(rom.chr_rom, &chr[..]);
+}
+
+#[test]
+pub fn lays_out_misc_roms() {
+    let prg = [0; 0x8000];
+    let chr = [0; 0x2000];
+    let header = Header {
+        misc_roms: 1,
+        ..header()
+    };
+    let src = assemble(&header, &prg, &chr, None, &[7; 100]);
+    let layout = Layout::of(&src).unwrap();
+    assert_eq!(layout.trainer, None);
+    assert_eq!(layout.prg_rom, 16..0x8010);
+    assert_eq!(layout.chr_rom, 0x8010..0xA010);
+    assert_eq!(layout.misc_rom, Some(0xA010..0xA074));
+    assert_eq!((layout.pc10_inst_rom, layout.pc10_prom), (None, None));
+
+    let src = assemble(&header, &prg, &chr, None, &[]);
+    assert_eq!(Layout::of(&src).unwrap().misc_rom, None);
+    assert_eq!(Layout::of(&src[..0x9000]), Err(HeaderError::ImageSize));
+
+    // $F size nibbles with the largest exponent: 2^63 bytes each, which can't both fit.
+    let mut src = src;
+    src[4] = 0xFC;
+    src[5] = 0xFC;
+    src[9] = 0xFF;
+    assert_eq!(Layout::of(&src), Err(HeaderError::Size));
+}
+
+#[test]
+pub fn lays_out_playchoice_roms() {
+    let prg = [0; 0x8000];
+    let chr = [0; 0x2000];
+    let header = Header {
+        console_type: PLAYCHOICE,
+        ..header()
+    };
+    let src = assemble(&header, &prg, &chr, None, &[0; 0x2000 + 32]);
+    let layout = Layout::of(&src).unwrap();
+    assert_eq!(layout.pc10_inst_rom, Some(0xA010..0xC010));
+    assert_eq!(layout.pc10_prom, Some(0xC010..0xC020));
+    assert_eq!(layout.misc_rom, None);
+
+    // Without the PROM's CounterOut half.
+    let src = assemble(&header, &prg, &chr, None, &[0; 0x2000 + 16]);
+    assert_eq!(Layout::of(&src).unwrap().pc10_prom, Some(0xC010..0xC020));
+
+    let src = assemble(&header, &prg, &chr, None, &[0; 0x2000]);
+    assert_eq!(Layout::of(&src), Err(HeaderError::Pc10Size));
+}
+
+#[test]
+pub fn reads_expansion_devices() {
+    let mut src = header().to_bytes();
+    for (byte, device) in [
+        (0x00, ExpansionDevice::Unspecified),
+        (0x01, ExpansionDevice::StandardControllers),
+        (0x08, ExpansionDevice::Zapper),
+        (0x0F, ExpansionDevice::ArkanoidNes),
+        (0x23, ExpansionDevice::FamilyBasicKeyboard),
+        // The top two bits aren't part of the field.
+        (0xC7, ExpansionDevice::VsZapper),
+    ] {
+        src[15] = byte;
+        assert_eq!(Header::parse(&src).unwrap().expansion_device, device);
+        assert_eq!(device.to_byte(), byte & 0x3F);
+    }
+
+    src[15] = 0x06;
+    assert_eq!(Header::parse(&src), Err(HeaderError::ExpansionDevice(6)));
+}
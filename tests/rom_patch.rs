@@ -0,0 +1,137 @@
+use nessy::rom::{
+    hash::crc32,
+    patch::{self, apply_bps, apply_ips, PatchError},
+};
+
+fn ips(records: &[(u32, &[u8])], truncate: Option<u32>) -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+    for (offset, data) in records {
+        patch.extend_from_slice(&offset.to_be_bytes()[1..]);
+        patch.extend_from_slice(data);
+    }
+    patch.extend_from_slice(b"EOF");
+    if let Some(len) = truncate {
+        patch.extend_from_slice(&len.to_be_bytes()[1..]);
+    }
+    patch
+}
+
+#[test]
+pub fn ips_records_and_runs() {
+    let mut rom = vec![0; 16];
+    let patch = ips(
+        &[
+            (2, &[0, 3, 1, 2, 3]),
+            // A run of four $AA bytes.
+            (8, &[0, 0, 0, 4, 0xAA]),
+            (18, &[0, 2, 5, 6]),
+        ],
+        None,
+    );
+    apply_ips(&mut rom, &patch).unwrap();
+
+    let mut expected = vec![0, 0, 1, 2, 3, 0, 0, 0, 0xAA, 0xAA, 0xAA, 0xAA];
+    expected.resize(18, 0);
+    expected.extend_from_slice(&[5, 6]);
+    assert_eq!(rom, expected);
+}
+
+#[test]
+pub fn ips_truncation_and_eof_offset() {
+    let mut rom = vec![0; 16];
+    apply_ips(&mut rom, &ips(&[(0, &[0, 1, 9])], Some(10))).unwrap();
+    assert_eq!(rom, [&[9], &[0; 9][..]].concat());
+
+    // A record at $454F46 starts with the same bytes as EOF.
+    let mut rom = vec![0; 0x454F50];
+    apply_ips(&mut rom, &ips(&[(0x454F46, &[0, 1, 7])], None)).unwrap();
+    assert_eq!(rom[0x454F46], 7);
+    assert_eq!(rom.len(), 0x454F50);
+}
+
+#[test]
+pub fn ips_errors() {
+    let mut rom = vec![0; 16];
+    assert_eq!(
+        apply_ips(&mut rom, b"PATCH\0\0\x01\0\x05\x01"),
+        Err(PatchError::Truncated)
+    );
+    assert_eq!(
+        apply_ips(&mut rom, b"PATCH\0\0\x01"),
+        Err(PatchError::Truncated)
+    );
+    assert_eq!(apply_ips(&mut rom, b"BPATCH"), Err(PatchError::Magic));
+    assert_eq!(patch::apply(&mut rom, b"nothing"), Err(PatchError::Magic));
+}
+
+fn number(out: &mut Vec<u8>, mut number: usize) {
+    loop {
+        let byte = (number & 0x7F) as u8;
+        number >>= 7;
+        if number == 0 {
+            out.push(byte | 0x80);
+            return;
+        };
+        out.push(byte);
+        number -= 1;
+    }
+}
+fn action(out: &mut Vec<u8>, kind: usize, len: usize) {
+    number(out, (len - 1) << 2 | kind);
+}
+fn finish_bps(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&crc32(target).to_le_bytes());
+    let checksum = crc32(&patch);
+    patch.extend_from_slice(&checksum.to_le_bytes());
+    patch
+}
+
+// Turns "hello world" into "hello there worldhello", using each of the four actions.
+fn hello_bps(target: &[u8]) -> Vec<u8> {
+    let source = b"hello world";
+    let mut patch = b"BPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, target.len());
+    number(&mut patch, 4);
+    patch.extend_from_slice(b"meta");
+    action(&mut patch, 0, 6);
+    action(&mut patch, 1, 6);
+    patch.extend_from_slice(b"there ");
+    action(&mut patch, 2, 5);
+    number(&mut patch, 6 << 1);
+    action(&mut patch, 3, 5);
+    number(&mut patch, 0);
+    finish_bps(patch, source, target)
+}
+
+#[test]
+pub fn bps_actions() {
+    let target = b"hello there worldhello";
+    let mut rom = b"hello world".to_vec();
+    patch::apply(&mut rom, &hello_bps(target)).unwrap();
+    assert_eq!(rom, target);
+}
+
+#[test]
+pub fn bps_checksum_mismatch() {
+    let target = b"hello there worldhello";
+    let patch = hello_bps(target);
+
+    let mut rom = b"hello World".to_vec();
+    assert_eq!(apply_bps(&mut rom, &patch), Err(PatchError::SourceChecksum));
+    assert_eq!(rom, b"hello World");
+
+    let mut corrupted = patch.clone();
+    corrupted[20] ^= 1;
+    let mut rom = b"hello world".to_vec();
+    assert_eq!(
+        apply_bps(&mut rom, &corrupted),
+        Err(PatchError::PatchChecksum)
+    );
+
+    // A patch that claims a different result than it produces.
+    let wrong = hello_bps(b"hello there worldhellO");
+    assert_eq!(apply_bps(&mut rom, &wrong), Err(PatchError::TargetChecksum));
+    assert_eq!(rom, b"hello world");
+}
@@ -0,0 +1,78 @@
+use nes_rom_parser::Rom;
+use nessy::rom::{
+    header::Header,
+    unif::{self, UnifError},
+};
+
+fn unif(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut src = b"UNIF".to_vec();
+    src.extend_from_slice(&7u32.to_le_bytes());
+    src.resize(32, 0);
+    for (id, data) in chunks {
+        src.extend_from_slice(*id);
+        src.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        src.extend_from_slice(data);
+    }
+    src
+}
+
+#[test]
+pub fn joins_prg_chunks_in_order() {
+    let prg0 = [0x11; 0x4000];
+    let prg1 = [0x22; 0x4000];
+    let prg2 = [0x33; 0x8000];
+    let chr0 = [0x44; 0x2000];
+    let src = unif(&[
+        (b"NAME", b"Test\0"),
+        (b"MAPR", b"NES-SNROM\0"),
+        (b"PRG2", &prg2),
+        (b"PRG0", &prg0),
+        (b"CHR0", &chr0),
+        (b"PRG1", &prg1),
+        (b"MIRR", &[1]),
+        (b"BATR", &[0]),
+    ]);
+
+    let image = unif::parse(&src).unwrap();
+    let header = Header::parse(&image).unwrap();
+    assert_eq!(header.mapper, 1);
+    assert!(header.vertical_mirroring && header.battery);
+    assert_eq!((header.prg_ram_size, header.prg_nvram_size), (0, 0x2000));
+
+    let rom = Rom::parse(&image).unwrap();
+    assert_eq!(rom.prg_rom.len(), 0x10000);
+    assert_eq!(rom.prg_rom[0x3FFF], 0x11);
+    assert_eq!(rom.prg_rom[0x4000], 0x22);
+    assert_eq!(rom.prg_rom[0x8000], 0x33);
+    assert_eq!(rom.chr_rom, &chr0[..]);
+}
+
+#[test]
+pub fn chr_ram_boards() {
+    let prg = [0xEA; 0x20000];
+    let src = unif(&[(b"PRG0", &prg), (b"MIRR", &[4]), (b"MAPR", b"UNL-UNROM")]);
+    let header = Header::parse(&unif::parse(&src).unwrap()).unwrap();
+    assert_eq!((header.mapper, header.four_screen), (2, true));
+    assert_eq!((header.chr_rom_size, header.chr_ram_size), (0, 0x2000));
+}
+
+#[test]
+pub fn rejects_unknown_boards() {
+    let src = unif(&[(b"MAPR", b"BMC-Mystery64in1\0"), (b"PRG0", &[0; 0x4000])]);
+    let err = unif::parse(&src).unwrap_err();
+    assert_eq!(err, UnifError::UnknownBoard("BMC-Mystery64in1".into()));
+    assert_eq!(
+        err.to_string(),
+        "UNIF board BMC-Mystery64in1 isn't supported"
+    );
+
+    assert_eq!(
+        unif::parse(&unif(&[(b"PRG0", &[0; 16])])),
+        Err(UnifError::NoBoard)
+    );
+    let mut truncated = unif(&[(b"MAPR", b"NES-NROM-256\0")]);
+    truncated.pop();
+    assert_eq!(unif::parse(&truncated), Err(UnifError::Truncated));
+    assert_eq!(unif::parse(b"NES\x1A"), Err(UnifError::Truncated));
+    assert_eq!(unif::parse(&[0; 32]), Err(UnifError::Magic));
+}
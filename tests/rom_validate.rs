@@ -0,0 +1,60 @@
+use nessy::rom::{
+    builder::RomBuilder,
+    header::Header,
+    validate::{clean_header, validate, RomWarning},
+};
+
+// An iNES 1.0 image for mapper 4 with 32K of PRG and 8K of CHR, and the given bytes 7-15.
+fn ines(tail: &[u8; 9]) -> Vec<u8> {
+    let mut src = b"NES\x1A\x02\x01\x41".to_vec();
+    src.extend_from_slice(tail);
+    src.resize(16 + 0x8000 + 0x2000, 0);
+    src
+}
+
+#[test]
+pub fn diskdude() {
+    let mut src = ines(b"DiskDude!");
+    let header = Header::parse(&src).unwrap();
+    assert_eq!(header.mapper, 4);
+    assert!(header.vertical_mirroring);
+    assert_eq!(validate(&src), [RomWarning::DirtyHeader]);
+
+    assert!(clean_header(&mut src));
+    assert_eq!(src[7..16], [0; 9]);
+    assert_eq!(Header::parse(&src).unwrap(), header);
+    assert_eq!(validate(&src), []);
+}
+
+#[test]
+pub fn garbage_that_looks_like_nes2() {
+    // '(' has the NES 2.0 identifier bits set, and ')' makes PRG ROM 2.3M.
+    let mut src = ines(b"(c)Ripper");
+    assert_ne!(Header::parse(&src).unwrap().mapper, 4);
+    assert_eq!(validate(&src), [RomWarning::FalseNes2]);
+
+    assert!(clean_header(&mut src));
+    let header = Header::parse(&src).unwrap();
+    assert_eq!(header.mapper, 4);
+    assert_eq!(header.prg_rom_size, 0x8000);
+}
+
+#[test]
+pub fn size_warnings() {
+    let mut src = ines(&[0; 9]);
+    src[4] = 3;
+    src.resize(16 + 0xC000 + 0x2000 + 100, 0);
+    assert_eq!(
+        validate(&src),
+        [RomWarning::PrgSize(0xC000), RomWarning::TrailingBytes(100)]
+    );
+
+    let mut src = ines(&[0; 9]);
+    src[8] = 0x01;
+    assert_eq!(validate(&src), [RomWarning::ExtendedMapper(0x104)]);
+
+    // Real NES 2.0 headers are left alone.
+    let mut src = RomBuilder::new().mapper(0x104).build();
+    assert_eq!(validate(&src), []);
+    assert!(!clean_header(&mut src));
+}
@@ -0,0 +1,221 @@
+use nes_rom_parser::Rom;
+use nessy::{
+    apu::Apu,
+    mapper::mapper0::Mapper0,
+    nes::{Nes, STATE_VERSION},
+    nesbus::{CpuBus, NesBus},
+    rom::builder::RomBuilder,
+    state::{SaveState, StateError, StateReader, StateWriter},
+};
+
+// Turns on rendering, then changes the backdrop color and scroll every frame.
+#[rustfmt::skip]
+const RESET: [u8; 29] = [
+    0x78,             // SEI
+    0xA2, 0xFF,       // LDX #$FF
+    0x9A,             // TXS
+    0x2C, 0x02, 0x20, // BIT $2002
+    0x10, 0xFB,       // BPL -5
+    0x2C, 0x02, 0x20, // BIT $2002
+    0x10, 0xFB,       // BPL -5
+    0xA9, 0x80,       // LDA #$80
+    0x8D, 0x00, 0x20, // STA $2000
+    0xA9, 0x1E,       // LDA #$1E
+    0x8D, 0x01, 0x20, // STA $2001
+    0xE6, 0x00,       // INC $00
+    0x4C, 0x18, 0x80, // JMP $8018
+];
+#[rustfmt::skip]
+const NMI: [u8; 33] = [
+    0xE6, 0x10,       // INC $10
+    0xA9, 0x3F,       // LDA #$3F
+    0x8D, 0x06, 0x20, // STA $2006
+    0xA9, 0x00,       // LDA #$00
+    0x8D, 0x06, 0x20, // STA $2006
+    0xA5, 0x10,       // LDA $10
+    0x8D, 0x07, 0x20, // STA $2007
+    0xA9, 0x80,       // LDA #$80
+    0x8D, 0x00, 0x20, // STA $2000
+    0xA5, 0x10,       // LDA $10
+    0x8D, 0x05, 0x20, // STA $2005
+    0xA9, 0x00,       // LDA #$00
+    0x8D, 0x05, 0x20, // STA $2005
+    0x40,             // RTI
+];
+
+#[test]
+pub fn console_restores_mid_frame() {
+    let mut nes = console();
+    run_frames(&mut nes, 10);
+    for _ in 0..1000 {
+        nes.cpu.exec(&mut nes.bus);
+    }
+
+    let state = nes.save_state();
+    run_frames(&mut nes, 5);
+    let expected = nes.bus.ppu().pixels().0;
+    let expected_state = nes.save_state();
+
+    nes.load_state(&state).unwrap();
+    assert!(nes.save_state() == state);
+    run_frames(&mut nes, 5);
+    assert!(nes.bus.ppu().pixels().0 == expected);
+    assert!(nes.save_state() == expected_state);
+}
+
+#[test]
+pub fn console_rejects_foreign_states() {
+    let mut nes = console();
+    run_frames(&mut nes, 1);
+    let state = nes.save_state();
+
+    assert_eq!(nes.load_state(b"hello"), Err(StateError::NotAState));
+    let mut old = state.clone();
+    old[4..6].copy_from_slice(&(STATE_VERSION - 1).to_le_bytes());
+    assert_eq!(
+        nes.load_state(&old),
+        Err(StateError::Version(STATE_VERSION - 1))
+    );
+    // A state that fails partway leaves the console alone.
+    assert_eq!(
+        nes.load_state(&state[..state.len() - 1]),
+        Err(StateError::Truncated)
+    );
+    assert!(nes.save_state() == state);
+
+    // So does one that is whole but holds a pulse duty the APU can't play.
+    let apu = save(nes.bus.apu());
+    let at = state
+        .windows(apu.len())
+        .position(|window| window == apu)
+        .unwrap();
+    let mut bad = state.clone();
+    bad[at + 1] = 4;
+    assert_eq!(nes.load_state(&bad), Err(StateError::Invalid));
+    assert!(nes.save_state() == state);
+}
+
+fn console() -> Nes<Mapper0> {
+    let mut builder = RomBuilder::new()
+        .write_cpu(0x8000, &RESET)
+        .write_cpu(0x8100, &NMI)
+        .reset_vector(0x8000)
+        .nmi_vector(0x8100);
+    // Stripes in every tile, so scrolling shows.
+    for (i, byte) in builder.chr_mut().iter_mut().enumerate() {
+        *byte = if i % 16 < 8 { 0x33 } else { 0x0F };
+    }
+    let src = builder.build();
+    let rom = Rom::parse(&src).unwrap();
+    Nes::new(NesBus::new(Mapper0::new(&rom)))
+}
+fn run_frames(nes: &mut Nes<Mapper0>, frames: usize) {
+    for _ in 0..frames {
+        nes.bus.take_frame_complete();
+        while !nes.bus.take_frame_complete() {
+            nes.cpu.exec(&mut nes.bus);
+        }
+    }
+    nes.bus.catch_up_ppu();
+}
+
+#[test]
+pub fn apu_restores_mid_dma() {
+    let mut apu = Apu::init();
+    // A looping sample keeps DMC fetches landing in the middle of the transfer.
+    write(&mut apu, 0x4010, 0x4F);
+    write(&mut apu, 0x4013, 0x01);
+    write(&mut apu, 0x4015, 0x10);
+    write(&mut apu, 0x4014, 0x02);
+    for _ in 0..301 {
+        halted_read(&mut apu);
+    }
+
+    let state = save(&apu);
+    let mut restored = Apu::init();
+    load(&mut restored, &state).unwrap();
+
+    let expected: Vec<_> = (0..400).map(|_| halted_read(&mut apu)).collect();
+    let actual: Vec<_> = (0..400).map(|_| halted_read(&mut restored)).collect();
+    assert_eq!(expected, actual);
+
+    // The transfer picks up where it left off and still ends with $02FF.
+    let oam_reads: Vec<u16> = actual
+        .iter()
+        .filter(|&&(addr, read, _)| read && addr & 0xFF00 == 0x0200)
+        .map(|&(addr, _, _)| addr)
+        .collect();
+    assert!(oam_reads[0] > 0x0200);
+    assert!(oam_reads.windows(2).all(|pair| pair[1] == pair[0] + 1));
+    assert_eq!(oam_reads.last(), Some(&0x02FF));
+    assert!(!actual.last().unwrap().2);
+}
+
+#[test]
+pub fn rejects_bad_states() {
+    let state = save(&Apu::init());
+    let mut apu = Apu::init();
+
+    assert_eq!(load(&mut apu, &state[..10]), Err(StateError::Truncated));
+    let mut long = state.clone();
+    long.push(0);
+    assert_eq!(load(&mut apu, &long), Err(StateError::TrailingBytes));
+    let mut region = state.clone();
+    region[0] = 7;
+    assert_eq!(load(&mut apu, &region), Err(StateError::Invalid));
+
+    // The largest value each field can hold and the first one that would crash the APU.
+    let end = state.len();
+    let fields: [(&str, usize, u16, u16); 8] = [
+        ("pulse duty", 1, 3, 4),
+        ("pulse step", 2, 7, 8),
+        ("sweep shift", 19, 7, 8),
+        ("triangle step", 47, 31, 32),
+        ("noise period", 56, 4068, 0),
+        ("noise period", 56, 4068, 4069),
+        ("frame counter cycle", end - 3, 29829, 29830),
+        ("frame counter reset delay", end - 1, 4, 5),
+    ];
+    for (field, offset, good, bad) in fields {
+        let set = |value: u16| {
+            let mut state = state.clone();
+            if field.ends_with("period") || field.ends_with("cycle") {
+                state[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+            } else {
+                state[offset] = value as u8;
+            }
+            state
+        };
+        assert_eq!(load(&mut apu, &set(good)), Ok(()), "{field} {good}");
+        let loaded = load(&mut apu, &set(bad));
+        assert_eq!(loaded, Err(StateError::Invalid), "{field} {bad}");
+    }
+}
+
+fn save(apu: &Apu) -> Vec<u8> {
+    let mut out = StateWriter::init();
+    apu.save_state(&mut out);
+    out.finish()
+}
+fn load(apu: &mut Apu, state: &[u8]) -> Result<(), StateError> {
+    let mut reader = StateReader::new(state);
+    apu.load_state(&mut reader)?;
+    reader.finish()
+}
+
+fn write(apu: &mut Apu, addr: u16, data: u8) {
+    let mut cpu = CpuBus::init();
+    cpu.set_address(addr);
+    cpu.set_data(data);
+    cpu.set_read(false);
+    apu.cycle(&mut cpu);
+}
+// A CPU read that lets DMA take the bus, returning what the bus ended up doing.
+fn halted_read(apu: &mut Apu) -> (u16, bool, bool) {
+    let mut cpu = CpuBus::init();
+    cpu.set_address(0x8000);
+    cpu.set_read(true);
+    cpu.set_halt(true);
+    apu.cycle(&mut cpu);
+    (cpu.address(), cpu.read(), cpu.not_ready())
+}
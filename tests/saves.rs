@@ -0,0 +1,73 @@
+use nes_rom_parser::Rom;
+use nessy::{
+    emulator::Emulator,
+    rom::builder::RomBuilder,
+    saves::{game_id, FileSaveStore, MemorySaveStore, SaveStore},
+};
+
+#[test]
+pub fn battery_ram_survives_the_emulator() {
+    let src = RomBuilder::new().battery(true).build();
+    let id = game_id(&Rom::parse(&src).unwrap());
+    let store = MemorySaveStore::init();
+
+    let mut emulator = console(&src, &store);
+    emulator.nes.poke(0x6000, 0x42);
+    emulator.nes.poke(0x7FFF, 0x99);
+    emulator.run_frame();
+    assert_eq!(store.get(&id), None);
+    drop(emulator);
+    let save = store.get(&id).unwrap();
+    assert_eq!((save[0], save[0x1FFF]), (0x42, 0x99));
+
+    let emulator = console(&src, &store);
+    assert_eq!(emulator.nes.peek(0x6000), 0x42);
+    assert_eq!(emulator.nes.peek(0x7FFF), 0x99);
+}
+
+#[test]
+pub fn changes_are_flushed_while_running() {
+    let src = RomBuilder::new().battery(true).build();
+    let id = game_id(&Rom::parse(&src).unwrap());
+    let store = MemorySaveStore::init();
+    let mut emulator = console(&src, &store);
+    emulator.nes.poke(0x6123, 7);
+    for _ in 0..300 {
+        emulator.run_frame();
+    }
+    assert_eq!(store.get(&id).unwrap()[0x123], 7);
+
+    emulator.nes.poke(0x6123, 8);
+    emulator.flush_saves().unwrap();
+    assert_eq!(store.get(&id).unwrap()[0x123], 8);
+}
+
+#[test]
+pub fn carts_without_a_battery_keep_nothing() {
+    let src = RomBuilder::new().build();
+    let id = game_id(&Rom::parse(&src).unwrap());
+    let store = MemorySaveStore::init();
+    let mut emulator = console(&src, &store);
+    emulator.nes.poke(0x6000, 0x42);
+    drop(emulator);
+    assert_eq!(store.get(&id), None);
+}
+
+#[test]
+pub fn file_store_round_trips() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("saves");
+    let mut store = FileSaveStore::new(&dir);
+    store.save("game", &[1, 2, 3]).unwrap();
+    store.save("game", &[4, 5]).unwrap();
+    assert_eq!(store.load("game"), Some(vec![4, 5]));
+    assert_eq!(store.load("other"), None);
+    assert!(dir.join("game.sav").exists());
+}
+
+fn console(src: &[u8], store: &MemorySaveStore) -> Emulator {
+    Emulator::builder()
+        .rom_bytes(src.to_vec())
+        .save_store(store.clone())
+        .build()
+        .unwrap()
+}
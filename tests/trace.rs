@@ -0,0 +1,48 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{
+    disasm::opcode, mapper::mapper0::Mapper0, nesbus::NesBus, rom::builder::RomBuilder,
+    trace::instruction_text,
+};
+
+#[test]
+pub fn operands_are_resolved_like_nestest() {
+    let program = [
+        0xA1, 0x80, // LDA ($80,X)
+        0xB1, 0x89, // LDA ($89),Y
+        0x6C, 0xFF, 0x02, // JMP ($02FF)
+        0xB0, 0xFC, // BCS
+        0x20, 0x2D, 0xC7, // JSR $C72D
+        0xB4, 0xFF, // LDY $FF,X
+        0xBE, 0xFF, 0xFF, // LDX $FFFF,Y
+        0x0A, // ASL A
+        0x04, 0xA9, // NOP $A9
+    ];
+    let src = RomBuilder::new().write_cpu(0x8000, &program).build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+    for (addr, data) in [(0x82, 0x00), (0x83, 0x02), (0x0200, 0x03), (0x0203, 0x5A)] {
+        bus.write(addr, data);
+    }
+    for (addr, data) in [(0x89, 0xFF), (0x8A, 0xFF), (0x33, 0xA3), (0x02FF, 0x80)] {
+        bus.write(addr, data);
+    }
+
+    let cases = [
+        (0x8000, "LDA ($80,X) @ 82 = 0200 = 03"),
+        (0x8002, "LDA ($89),Y = FFFF @ 0033 = A3"),
+        // The pointer wraps within its page.
+        (0x8004, "JMP ($02FF) = 0380"),
+        (0x8007, "BCS $8005"),
+        (0x8009, "JSR $C72D"),
+        (0x800C, "LDY $FF,X @ 01 = 00"),
+        (0x800E, "LDX $FFFF,Y @ 0033 = A3"),
+        (0x8011, "ASL A"),
+        (0x8012, "NOP $A9 = 00"),
+    ];
+    for (pc, expected) in cases {
+        assert_eq!(instruction_text(&bus, pc, 0x02, 0x34), expected);
+    }
+    assert!(opcode(0xA1).official);
+    assert!(!opcode(0x04).official);
+}
@@ -0,0 +1,58 @@
+use nes_rom_parser::Rom;
+use nessy::{
+    input::Controller, mapper::mapper0::Mapper0, nes::Nes, nesbus::NesBus, rom::builder::RomBuilder,
+};
+
+#[test]
+pub fn follows_the_duty_cycle() {
+    let mut nes = console();
+    let mut turbo = Controller(0);
+    turbo.set_a(true);
+    nes.bus.input_mut().turbo_mut()[0] = turbo;
+    assert_eq!(pattern(&mut nes, 0x01), "11001100110011001100");
+
+    nes.bus.input_mut().set_turbo_rate(3, 1);
+    nes.bus.controllers_mut()[0].set_b(true);
+    assert_eq!(pattern(&mut nes, 0x01), "11101110111011101110");
+    assert_eq!(pattern(&mut nes, 0x02), "11111111111111111111");
+
+    // Letting go of the turbo key releases the button.
+    nes.bus.input_mut().turbo_mut()[0] = Controller(0);
+    assert_eq!(pattern(&mut nes, 0x01), "00000000000000000000");
+}
+
+#[test]
+pub fn movies_record_the_toggling() {
+    let mut nes = console();
+    let mut turbo = Controller(0);
+    turbo.set_b(true);
+    nes.bus.input_mut().turbo_mut()[1] = turbo;
+    nes.record_movie();
+    for _ in 0..8 {
+        nes.run_frame();
+    }
+    let movie = nes.stop_movie().unwrap();
+    let recorded: Vec<u8> = movie.frames.iter().map(|f| f.controllers[1].0).collect();
+    assert_eq!(recorded, [2, 2, 0, 0, 2, 2, 0, 0]);
+}
+
+// Which frames had the given buttons pressed, over the next 20.
+fn pattern(nes: &mut Nes<Mapper0>, buttons: u8) -> String {
+    (0..20)
+        .map(|_| {
+            nes.run_frame();
+            let pressed = nes.bus.controllers_mut()[0].0 & buttons != 0;
+            if pressed {
+                '1'
+            } else {
+                '0'
+            }
+        })
+        .collect()
+}
+
+fn console() -> Nes<Mapper0> {
+    let src = RomBuilder::new().build();
+    let rom = Rom::parse(&src).unwrap();
+    Nes::new(NesBus::new(Mapper0::new(&rom)))
+}
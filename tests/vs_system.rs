@@ -0,0 +1,108 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{
+    mapper::{mapper99::Mapper99, Mapper, MapperBus},
+    nesbus::NesBus,
+    ppu::{vs_ppu::VsPpu, PpuBus},
+    rom::{self, builder::RomBuilder, expansion::ExpansionDevice, header::Header},
+};
+
+#[test]
+pub fn bank_switch_through_4016() {
+    // 48K of PRG and 16K of CHR, each 8K bank filled with its own bank number.
+    let prg = (0..6).flat_map(|bank| [bank; 0x2000]).collect();
+    let chr = (0..2).flat_map(|bank| [bank; 0x2000]).collect();
+    let src = RomBuilder::new().mapper(99).prg(prg).chr(chr).build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut bus = NesBus::new(Mapper99::new(&rom));
+
+    assert_eq!(bus.read(0x8000, false, false).0, 0);
+    assert_eq!(bus.read(0xE000, false, false).0, 3);
+
+    bus.write(0x4016, 0b100);
+    assert_eq!(bus.read(0x8000, false, false).0, 4);
+    assert_eq!(bus.read(0xA000, false, false).0, 1);
+    assert_eq!(bus.mapper_state().chr_banks[0], 8);
+
+    bus.write(0x4016, 0);
+    assert_eq!(bus.read(0x9FFF, false, false).0, 0);
+    assert_eq!(bus.mapper_state().chr_banks[7], 7);
+}
+
+#[test]
+pub fn chr_reads_follow_bank() {
+    let chr = (0..2).flat_map(|bank| [bank + 10; 0x2000]).collect();
+    let src = RomBuilder::new().mapper(99).chr(chr).build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut mapper = Mapper99::new(&rom);
+    assert_eq!(read_ppu(&mut mapper, 0x1234), 10);
+
+    let mut bus = NesBus::new(mapper);
+    bus.write(0x4016, 0b100);
+    assert_eq!(bus.mapper_state().chr_banks, [8, 9, 10, 11, 12, 13, 14, 15]);
+}
+
+#[test]
+pub fn coin_and_dip_switches() {
+    let src = RomBuilder::new().mapper(99).build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut bus = NesBus::new(Mapper99::new(&rom));
+    bus.enable_vs_system(VsPpu::Rp2c03);
+
+    let switches = bus.input_mut().vs_switches_mut().unwrap();
+    switches.dip = 0b1010_0110;
+    switches.coins[0] = true;
+    switches.service = true;
+
+    bus.write(0x4016, 1);
+    bus.write(0x4016, 0);
+    assert_eq!(bus.read(0x4016, false, false).0 & 0x7E, 0b0011_0100);
+    assert_eq!(bus.read(0x4017, false, false).0 & 0xFE, 0b1010_0100);
+}
+
+#[test]
+pub fn ppu_type_from_header() {
+    let mut src = RomBuilder::new().mapper(99).build();
+    assert_eq!(rom::vs_ppu(&src), None);
+
+    src[7] |= 1;
+    src[13] = 0x5;
+    let vs_ppu = rom::vs_ppu(&src).unwrap();
+    assert_eq!(vs_ppu, VsPpu::Rp2c04(4));
+    assert_eq!(vs_ppu.palette_lut().unwrap()[0x00], 0x18);
+    assert_eq!(vs_ppu.palette_lut().unwrap()[0x3F], 0x09);
+
+    src[13] = 0x9;
+    let vs_ppu = rom::vs_ppu(&src).unwrap();
+    assert_eq!(vs_ppu, VsPpu::Rc2c05(2));
+    assert_eq!(vs_ppu.ppu_id(), Some(0x3D));
+    assert!(vs_ppu.swaps_ctrl_and_mask());
+    assert!(vs_ppu.palette_lut().is_none());
+}
+
+#[test]
+pub fn player_one_on_4017() {
+    let src = RomBuilder::new()
+        .mapper(99)
+        .expansion_device(ExpansionDevice::VsSystem4017)
+        .build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut bus = NesBus::new(Mapper99::new(&rom));
+    let device = Header::parse(&src).unwrap().expansion_device;
+    assert!(device.swaps_ports());
+    bus.input_mut().set_swapped_ports(device.swaps_ports());
+
+    bus.input_mut().controller_mut(0).set_a(true);
+    bus.write(0x4016, 1);
+    bus.write(0x4016, 0);
+    assert_eq!(bus.read(0x4016, false, false).0 & 1, 0);
+    assert_eq!(bus.read(0x4017, false, false).0 & 1, 1);
+}
+
+fn read_ppu(mapper: &mut Mapper99, addr: u16) -> u8 {
+    let mut ppu = PpuBus::init();
+    ppu.set_address(addr);
+    ppu.set_read_enable(true);
+    mapper.cycle_with_ppu(&mut MapperBus::init(), &mut ppu);
+    ppu.data()
+}
@@ -0,0 +1,82 @@
+use cpu_6502::Bus;
+use nes_rom_parser::Rom;
+use nessy::{input::Zapper, mapper::mapper0::Mapper0, nesbus::NesBus, rom::builder::RomBuilder};
+
+#[test]
+pub fn light_follows_the_beam() {
+    let mut bus = zapper_bus(0x30);
+    bus.input_mut().zapper_mut().unwrap().set_position(100, 50);
+    run_to(&mut bus, [0, 0]);
+
+    // Not drawn yet this frame.
+    run_to(&mut bus, [90, 50]);
+    assert_eq!(read_zapper(&mut bus) & 0x18, 0x08);
+    run_to(&mut bus, [110, 50]);
+    assert_eq!(read_zapper(&mut bus) & 0x18, 0x00);
+    run_to(&mut bus, [0, 65]);
+    assert_eq!(read_zapper(&mut bus) & 0x18, 0x00);
+    // The photodiode has gone dark again.
+    run_to(&mut bus, [0, 75]);
+    assert_eq!(read_zapper(&mut bus) & 0x18, 0x08);
+
+    bus.input_mut().zapper_mut().unwrap().set_trigger(true);
+    assert_eq!(read_zapper(&mut bus) & 0x18, 0x18);
+}
+
+#[test]
+pub fn dark_pixels_and_aiming_away_see_nothing() {
+    let mut bus = zapper_bus(0x0F);
+    bus.input_mut().zapper_mut().unwrap().set_position(100, 50);
+    run_to(&mut bus, [110, 50]);
+    assert_eq!(read_zapper(&mut bus) & 0x08, 0x08);
+
+    let mut bus = zapper_bus(0x30);
+    bus.input_mut().zapper_mut().unwrap().aim_away();
+    run_to(&mut bus, [110, 50]);
+    assert_eq!(read_zapper(&mut bus) & 0x08, 0x08);
+
+    // Below the picture, read while the PPU is past it.
+    let mut bus = zapper_bus(0x30);
+    bus.input_mut().zapper_mut().unwrap().set_position(100, 250);
+    run_to(&mut bus, [110, 255]);
+    assert_eq!(read_zapper(&mut bus) & 0x08, 0x08);
+}
+
+#[test]
+pub fn zapper_replaces_controller_2() {
+    let mut bus = zapper_bus(0x30);
+    bus.controllers_mut()[1].set_a(true);
+    bus.write(0x4016, 1);
+    bus.write(0x4016, 0);
+    assert_eq!(read_zapper(&mut bus) & 0x01, 0);
+
+    bus.input_mut().set_zapper(None);
+    assert_eq!(read_zapper(&mut bus) & 0x01, 1);
+}
+
+// With rendering off, the whole picture is the backdrop color.
+fn zapper_bus(backdrop: u8) -> NesBus<Mapper0> {
+    let src = RomBuilder::new().build();
+    let rom = Rom::parse(&src).unwrap();
+    let mut bus = NesBus::new(Mapper0::new(&rom));
+    bus.input_mut().set_zapper(Some(Zapper::init()));
+    bus.write(0x2006, 0x3F);
+    bus.write(0x2006, 0x00);
+    bus.write(0x2007, backdrop);
+    bus.write(0x2006, 0x20);
+    bus.write(0x2006, 0x00);
+    bus
+}
+fn read_zapper(bus: &mut NesBus<Mapper0>) -> u8 {
+    bus.read(0x4017, false, false).0
+}
+// Runs until the next dot the PPU processes is the given one or at most two after it.
+fn run_to(bus: &mut NesBus<Mapper0>, [x, y]: [u16; 2]) {
+    loop {
+        let [dot_x, dot_y] = bus.ppu().dot();
+        if dot_y == y && (x..x + 3).contains(&dot_x) {
+            return;
+        };
+        bus.read(0x8000, false, false);
+    }
+}